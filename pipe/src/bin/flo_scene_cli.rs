@@ -0,0 +1,308 @@
+//!
+//! `flo_scene_cli` connects to the command socket of a running scene, and runs commands either interactively or from the
+//! command line:
+//!
+//! ```text
+//! flo_scene_cli --unix ./example_unix_socket
+//! flo_scene_cli --tcp localhost:3000 -c 'list_subprograms' --raw
+//! ```
+//!
+//! The exit code is 0 if all the commands succeeded, 1 if any command generated an error, and 2 if the connection could not be
+//! made. Output from background streams is written with a `[n]` prefix, where `n` identifies the stream.
+//!
+
+use flo_scene_pipe::commands::*;
+
+use futures::executor;
+use serde_json::{json};
+
+use std::collections::{HashSet};
+use std::env;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::net::{TcpStream};
+use std::process;
+use std::sync::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixStream};
+
+/// Exit code when all of the commands succeeded
+const EXIT_SUCCESS: i32             = 0;
+
+/// Exit code when a command generated an error
+const EXIT_COMMAND_ERROR: i32       = 1;
+
+/// Exit code when the command line was invalid or the connection failed
+const EXIT_CONNECTION_ERROR: i32    = 2;
+
+const USAGE: &str = "Usage: flo_scene_cli (--unix <path> | --tcp <host:port>) [-c <command>]... [--raw] [--follow]
+
+    --unix <path>       Connect to the command socket at the specified path
+    --tcp <host:port>   Connect to the command socket at the specified address
+    -c <command>        Run a command and exit instead of reading commands from stdin (can be repeated)
+    --raw               Write JSON values on a single line instead of pretty-printing them
+    --follow            After running the -c commands, wait for any background streams to finish";
+
+///
+/// The address of the command socket
+///
+enum Address {
+    Unix(String),
+    Tcp(String),
+}
+
+///
+/// The options passed on the command line
+///
+struct Options {
+    address:    Address,
+    commands:   Vec<String>,
+    raw:        bool,
+    follow:     bool,
+}
+
+///
+/// Events sent from the thread that reads from the socket
+///
+enum SocketEvent {
+    /// The request with the specified ID finished (true if it succeeded)
+    Response(u64, bool),
+
+    /// All of the background streams have finished
+    StreamsFinished,
+
+    /// The connection was closed
+    Closed,
+}
+
+///
+/// Reads the options from the command line
+///
+fn parse_options() -> Result<Options, String> {
+    let mut args        = env::args().skip(1);
+    let mut address     = None;
+    let mut commands    = vec![];
+    let mut raw         = false;
+    let mut follow      = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--unix"            => { address = Some(Address::Unix(args.next().ok_or("--unix requires a path")?)); }
+            "--tcp"             => { address = Some(Address::Tcp(args.next().ok_or("--tcp requires an address")?)); }
+            "-c"                => { commands.push(args.next().ok_or("-c requires a command")?); }
+            "--raw"             => { raw = true; }
+            "--follow"          => { follow = true; }
+            "-h" | "--help"     => { println!("{}", USAGE); process::exit(EXIT_SUCCESS); }
+            other               => { return Err(format!("Unknown option `{}`", other)); }
+        }
+    }
+
+    let address = address.ok_or("Either --unix or --tcp must be specified")?;
+
+    Ok(Options { address, commands, raw, follow })
+}
+
+///
+/// Connects to the command socket, returning a reader and a writer
+///
+fn connect(address: &Address) -> io::Result<(Box<dyn Send + Read>, Box<dyn Send + Write>)> {
+    match address {
+        Address::Tcp(address) => {
+            let stream = TcpStream::connect(address)?;
+            Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+        }
+
+        #[cfg(unix)]
+        Address::Unix(path) => {
+            let stream = UnixStream::connect(path)?;
+            Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+        }
+
+        #[cfg(not(unix))]
+        Address::Unix(_) => {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "Unix domain sockets are not supported on this platform"))
+        }
+    }
+}
+
+///
+/// Writes a JSON value to stdout, with a prefix on every line
+///
+fn print_json(value: &serde_json::Value, raw: bool, prefix: &str) {
+    let text = if raw { value.to_string() } else { serde_json::to_string_pretty(value).unwrap_or_default() };
+
+    for line in text.lines() {
+        println!("{}{}", prefix, line);
+    }
+}
+
+///
+/// Reads the JSON-RPC messages from the socket, displays them, and reports when requests finish
+///
+fn read_socket(input: impl Read, raw: bool, open_streams: Arc<AtomicUsize>, events: mpsc::Sender<SocketEvent>) {
+    let mut streams = HashSet::new();
+
+    for line in BufReader::new(input).lines() {
+        let line = if let Ok(line) = line { line } else { break; };
+
+        // Anything that's not a JSON object is ignored (this will be the prompt sent before we switch to JSON-RPC mode)
+        let message = if let Ok(message @ serde_json::Value::Object(_)) = serde_json::from_str(line.trim()) { message } else { continue; };
+        let params  = &message["params"];
+
+        match message["method"].as_str() {
+            Some("message")         => { println!("{}", params["message"].as_str().unwrap_or_default()); }
+            Some("error")           => { eprintln!("!!! {}", params["message"].as_str().unwrap_or_default()); }
+            Some("json")            => { print_json(&params["value"], raw, ""); }
+            Some("stream")          => { print_json(&params["value"], raw, &format!("[{}] ", params["stream"])); }
+
+            Some("stream_start")    => {
+                streams.insert(params["stream"].to_string());
+                open_streams.store(streams.len(), Ordering::SeqCst);
+            }
+
+            Some("stream_end")      => {
+                streams.remove(&params["stream"].to_string());
+                open_streams.store(streams.len(), Ordering::SeqCst);
+
+                if streams.is_empty() {
+                    events.send(SocketEvent::StreamsFinished).ok();
+                }
+            }
+
+            Some(_)                 => { }
+
+            None                    => {
+                // Response to a request
+                let id = message["id"].as_u64().unwrap_or(u64::MAX);
+
+                if let Some(results) = message["result"].as_array() {
+                    results.iter().for_each(|result| print_json(result, raw, ""));
+                    events.send(SocketEvent::Response(id, true)).ok();
+                } else {
+                    eprintln!("!!! {}", message["error"]["message"].as_str().unwrap_or_default());
+                    events.send(SocketEvent::Response(id, false)).ok();
+                }
+            }
+        }
+    }
+
+    events.send(SocketEvent::Closed).ok();
+}
+
+///
+/// Sends a command to the socket and waits for it to finish. Returns None if the connection is closed
+///
+fn run_command(command: &str, id: u64, output: &mut impl Write, events: &mpsc::Receiver<SocketEvent>) -> Option<bool> {
+    let request = json!({ "jsonrpc": "2.0", "id": id, "method": JSON_RPC_EVAL_METHOD, "params": command });
+
+    writeln!(output, "{}", request).ok()?;
+    output.flush().ok()?;
+
+    loop {
+        match events.recv() {
+            Ok(SocketEvent::Response(response_id, success)) if response_id == id    => { return Some(success); }
+            Ok(SocketEvent::Response(_, _)) | Ok(SocketEvent::StreamsFinished)      => { }
+            Ok(SocketEvent::Closed) | Err(_)                                        => { return None; }
+        }
+    }
+}
+
+///
+/// True if a command is incomplete (eg, has an unclosed '{' block) and more lines should be read before running it
+///
+fn is_incomplete(command: &str) -> bool {
+    matches!(executor::block_on(CommandRequest::parse(command)), Err(CommandParseError::UnclosedBlock) | Err(CommandParseError::ExpectedMoreInput))
+}
+
+fn main() {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(err)    => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(EXIT_CONNECTION_ERROR);
+        }
+    };
+
+    // Connect to the socket
+    let (input, mut output) = match connect(&options.address) {
+        Ok(connection)  => connection,
+        Err(err)        => {
+            eprintln!("Could not connect: {}", err);
+            process::exit(EXIT_CONNECTION_ERROR);
+        }
+    };
+
+    // Read the responses on a separate thread, so that background streams are displayed while we're waiting for input
+    let (send_events, events)   = mpsc::channel();
+    let open_streams            = Arc::new(AtomicUsize::new(0));
+    let raw                     = options.raw;
+    let reader_open_streams     = Arc::clone(&open_streams);
+
+    thread::spawn(move || read_socket(input, raw, reader_open_streams, send_events));
+
+    let mut next_id = 0;
+    let mut failed  = false;
+
+    if !options.commands.is_empty() {
+        // Run the commands from the command line, stopping at the first one that fails
+        for command in options.commands.iter() {
+            next_id += 1;
+
+            match run_command(command, next_id, &mut output, &events) {
+                Some(true)  => { }
+                Some(false) => { failed = true; break; }
+                None        => { eprintln!("Connection closed"); process::exit(EXIT_CONNECTION_ERROR); }
+            }
+        }
+
+        // Wait for any background streams to finish if --follow was specified
+        if options.follow && !failed {
+            while open_streams.load(Ordering::SeqCst) > 0 {
+                match events.recv() {
+                    Ok(SocketEvent::StreamsFinished) | Ok(SocketEvent::Closed) | Err(_)    => { break; }
+                    Ok(SocketEvent::Response(_, _))                                         => { }
+                }
+            }
+        }
+    } else {
+        // Read commands from stdin
+        let stdin       = io::stdin();
+        let interactive = stdin.is_terminal();
+        let mut command = String::new();
+        let mut lines   = stdin.lock().lines();
+
+        loop {
+            if interactive {
+                print!("{}", if command.is_empty() { "> " } else { "... " });
+                io::stdout().flush().ok();
+            }
+
+            let line = if let Some(Ok(line)) = lines.next() { line } else { break; };
+
+            command.push_str(&line);
+            command.push('\n');
+
+            if command.trim().is_empty() {
+                command.clear();
+                continue;
+            }
+
+            if is_incomplete(&command) {
+                continue;
+            }
+
+            next_id += 1;
+            match run_command(&command, next_id, &mut output, &events) {
+                Some(true)  => { }
+                Some(false) => { failed = true; }
+                None        => { eprintln!("Connection closed"); process::exit(EXIT_CONNECTION_ERROR); }
+            }
+
+            command.clear();
+        }
+    }
+
+    process::exit(if failed { EXIT_COMMAND_ERROR } else { EXIT_SUCCESS });
+}
@@ -0,0 +1,69 @@
+//!
+//! `flo_scene_script` runs a command script in a new scene with the standard JSON commands, writing the output to stdout:
+//!
+//! ```text
+//! flo_scene_script ./setup.cmd
+//! ```
+//!
+//! The script stops at the first command that fails. The exit code is 0 if the whole script ran, 1 if a command generated an
+//! error, 2 if the script could not be read or parsed, and 3 if the output was closed before the script finished.
+//!
+
+use flo_scene::*;
+use flo_scene_pipe::*;
+use flo_scene_pipe::commands::*;
+
+use futures::prelude::*;
+use futures::executor;
+
+use std::env;
+use std::fs;
+use std::io::{Write};
+use std::process;
+
+/// Exit code when the script file could not be read
+const EXIT_READ_ERROR: i32 = 2;
+
+const USAGE: &str = "Usage: flo_scene_script <script_file>";
+
+fn main() {
+    // Read the script from the file named on the command line
+    let path = if let Some(path) = env::args().nth(1) { path } else {
+        eprintln!("{}", USAGE);
+        process::exit(EXIT_READ_ERROR);
+    };
+
+    let script = match fs::read(&path) {
+        Ok(script)  => script,
+        Err(err)    => {
+            eprintln!("Could not read '{}': {}", path, err);
+            process::exit(EXIT_READ_ERROR);
+        }
+    };
+
+    // Create a default scene
+    let scene = Scene::default()
+        .with_standard_json_commands();
+
+    // Run the script from a subprogram, writing the output to stdout
+    scene.add_subprogram(SubProgramId::new(), move |_: InputStream<()>, context| async move {
+        let stdout = sink::unfold((), |_, CommandData(data)| async move {
+            let mut stdout = std::io::stdout();
+            stdout.write_all(&data)?;
+            stdout.flush()
+        });
+
+        let result = run_command_script(&context, stream::iter(vec![script]), (), stdout).await;
+
+        // Exit with a non-zero status if the script failed
+        match result {
+            Ok(())      => process::exit(0),
+            Err(err)    => {
+                eprintln!("{:?}", err);
+                process::exit(err.exit_code());
+            }
+        }
+    }, 0);
+
+    executor::block_on(scene.run_scene());
+}
@@ -0,0 +1,280 @@
+use super::socket::*;
+#[cfg(unix)]
+use super::unix_socket::*;
+
+use flo_scene::*;
+
+use futures::prelude::{Stream, Future};
+use futures::channel::oneshot;
+use futures::stream;
+use futures::stream::{BoxStream, StreamExt};
+use futures::task::{Poll};
+use futures_timer::{Delay};
+
+use tokio::io::*;
+use tokio::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use tokio::net::{UnixStream};
+
+use std::path::*;
+use std::result::{Result};
+use std::sync::*;
+use std::time::{Duration};
+
+///
+/// Describes how long a client socket waits before trying to connect again after a connection fails or is closed
+///
+/// The first retry happens after the initial delay, and each following retry waits for the previous delay multiplied by the multiplier,
+/// up to the maximum delay. The delay is reset once a connection succeeds.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReconnectBackoff {
+    /// The delay before the first attempt to reconnect
+    initial_delay: Duration,
+
+    /// The longest delay between attempts to reconnect
+    max_delay: Duration,
+
+    /// The amount the delay is multiplied by after each failed attempt
+    multiplier: f64,
+
+    /// The number of times to try to connect before giving up (None to keep trying forever)
+    max_attempts: Option<usize>,
+}
+
+impl Default for ReconnectBackoff {
+    fn default() -> Self {
+        ReconnectBackoff {
+            initial_delay:  Duration::from_millis(100),
+            max_delay:      Duration::from_secs(30),
+            multiplier:     2.0,
+            max_attempts:   None,
+        }
+    }
+}
+
+impl ReconnectBackoff {
+    ///
+    /// Creates a backoff that starts at the initial delay, and doubles after every failed attempt up to the maximum delay
+    ///
+    pub fn new(initial_delay: Duration, max_delay: Duration) -> Self {
+        ReconnectBackoff { initial_delay, max_delay, ..Default::default() }
+    }
+
+    ///
+    /// Sets the amount the delay is multiplied by after each failed attempt
+    ///
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    ///
+    /// Sets the number of consecutive failed attempts to connect before the client program gives up and stops
+    ///
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    ///
+    /// Returns the delay before the specified retry (0 is the first retry), or None if no more attempts should be made
+    ///
+    pub fn delay(&self, attempt: usize) -> Option<Duration> {
+        if let Some(max_attempts) = self.max_attempts {
+            if attempt + 1 >= max_attempts {
+                return None;
+            }
+        }
+
+        let delay = self.initial_delay.as_secs_f64() * self.multiplier.powi(attempt.min(i32::MAX as usize) as i32);
+        let delay = delay.min(self.max_delay.as_secs_f64());
+
+        Some(Duration::from_secs_f64(delay))
+    }
+}
+
+///
+/// Runs a socket client subprogram. This calls the 'connect' function to make an outgoing connection, and sends a `SocketMessage::Connection`
+/// message when it succeeds, in the same way as the listener programs do for incoming connections
+///
+/// When the connection is closed (the input stream reaches its end or is dropped), or if the connection could not be made, the program
+/// waits for a delay chosen by the backoff and then connects again. The program stops if the backoff runs out of attempts.
+///
+pub async fn socket_client_subprogram<TFutureStream, TReadStream, TWriteStream, TInputStream, TOutputMessage>(
+    context:                SceneContext,
+    connect:                impl 'static + Send + Fn() -> TFutureStream,
+    backoff:                ReconnectBackoff,
+    create_input_messages:  impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> TInputStream,
+    create_output_messages: impl 'static + Send + Sync + Fn(BoxStream<'static, TOutputMessage>) -> BoxStream<'static, Vec<u8>>)
+where
+    TFutureStream:  Send + Future<Output=Result<(TReadStream, TWriteStream, SocketPeer), ConnectionError>>,
+    TReadStream:    'static + Send + AsyncRead,
+    TWriteStream:   'static + Send + AsyncWrite,
+    TInputStream:   'static + Send + Stream,
+    TOutputMessage: 'static + Send,
+{
+    let create_output_messages  = Arc::new(create_output_messages);
+    let mut attempt             = 0;
+
+    loop {
+        let delay = match connect().await {
+            Ok((async_reader, async_writer, peer)) => {
+                // The 'closed' sender is dropped when the reader stream finishes or is dropped, which tells us to reconnect
+                let (closed, when_closed)   = oneshot::channel::<()>();
+                let mut closed              = Some(closed);
+                let reader_stream           = create_reader_stream(async_reader, &SocketOptions::default())
+                    .chain(stream::poll_fn(move |_| { closed.take(); Poll::Ready(None) }));
+
+                let socket_connection = create_socket_connection(&context, reader_stream.boxed(), async_writer, peer, &SocketOptions::default(), None, &create_input_messages, Arc::clone(&create_output_messages));
+
+                // Send the connection to whoever is connected to this program, then wait for it to close
+                if context.send_message(SocketMessage::Connection(socket_connection)).await.is_err() {
+                    break;
+                }
+
+                when_closed.await.ok();
+
+                // The connection succeeded, so the backoff starts again from the beginning
+                attempt = 0;
+                backoff.delay(0)
+            }
+
+            Err(_) => {
+                attempt += 1;
+                backoff.delay(attempt - 1)
+            }
+        };
+
+        // Wait before trying again (or give up if we've run out of attempts)
+        if let Some(delay) = delay {
+            Delay::new(delay).await;
+        } else {
+            break;
+        }
+    }
+}
+
+///
+/// Starts a sub-program that connects to a unix domain socket at the specified path, and reconnects if the connection is closed
+///
+/// To use this subprogram, the scene must be running inside a tokio runtime. The program will make no connections if this crate was not
+/// compiled for UNIX.
+///
+/// The program sends a `SocketMessage<TInputStream::Item, TOutputStream::Item>` to its subscribers every time it connects, so it can be
+/// used in the same way as the listener created by `start_unix_socket_program()` (for example, to talk to a command socket in another
+/// process). If the socket isn't available, the program will keep trying to connect, waiting for the delays specified by the backoff.
+///
+pub fn start_unix_socket_client_program<TInputStream, TOutputMessage>(
+        scene:                  &Scene,
+        program_id:             SubProgramId,
+        path:                   impl AsRef<Path>,
+        backoff:                ReconnectBackoff,
+        create_input_messages:  impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> TInputStream,
+        create_output_messages: impl 'static + Send + Sync + Fn(BoxStream<'static, TOutputMessage>) -> BoxStream<'static, Vec<u8>>
+    ) -> Result<(), ConnectionError>
+where
+    TInputStream:   'static + Send + Stream,
+    TOutputMessage: 'static + Send,
+{
+    #[cfg(unix)]
+    {
+        let path = path.as_ref().to_path_buf();
+
+        scene.add_subprogram(program_id, move |_input: InputStream<()>, context| socket_client_subprogram(context, move || {
+                let path = path.clone();
+
+                async move {
+                    let socket              = UnixStream::connect(path).await?;
+                    let peer                = unix_socket_peer(&socket);
+                    let (reader, writer)    = socket.into_split();
+
+                    Ok((reader, writer, peer))
+                }
+            },
+            backoff,
+            create_input_messages,
+            create_output_messages), 0);
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    {
+        // If we're not on Unix, this creates a program that ignores its messages (we can't connect to any UNIX sockets)
+        let _ = (path, backoff, create_input_messages, create_output_messages);
+
+        scene.add_subprogram(program_id, move |input: InputStream<()>, _context| async move {
+            let mut input = input;
+            while let Some(_) = input.next().await {
+            }
+        }, 0);
+
+        Ok(())
+    }
+}
+
+///
+/// Starts a sub-program that makes an unencrypted connection to a TCP socket at the specified address, and reconnects if the connection
+/// is closed
+///
+/// The program sends a `SocketMessage<TInputStream::Item, TOutputStream::Item>` to its subscribers every time it connects, so it can be
+/// used in the same way as the listener created by `start_unencrpted_tcp_socket()`.  If the socket isn't available, the program will
+/// keep trying to connect, waiting for the delays specified by the backoff.
+///
+pub fn start_tcp_socket_client_program<TInputStream, TOutputMessage>(
+        scene:                  &Scene,
+        program_id:             SubProgramId,
+        address:                impl 'static + Send + Sync + Clone + ToSocketAddrs,
+        backoff:                ReconnectBackoff,
+        create_input_messages:  impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> TInputStream,
+        create_output_messages: impl 'static + Send + Sync + Fn(BoxStream<'static, TOutputMessage>) -> BoxStream<'static, Vec<u8>>
+    ) -> Result<(), ConnectionError>
+where
+    TInputStream:   'static + Send + Stream,
+    TOutputMessage: 'static + Send,
+{
+    scene.add_subprogram(program_id, move |_input: InputStream<()>, context| socket_client_subprogram(context, move || {
+            let address = address.clone();
+
+            async move {
+                let socket = TcpStream::connect(address).await?;
+                socket.set_nodelay(true).ok();
+
+                let peer                = SocketPeer { address: socket.peer_addr().ok().map(|addr| addr.to_string()), ..SocketPeer::default() };
+                let (reader, writer)    = socket.into_split();
+
+                Ok((reader, writer, peer))
+            }
+        },
+        backoff,
+        create_input_messages,
+        create_output_messages), 0);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff_delays() {
+        let backoff = ReconnectBackoff::new(Duration::from_millis(100), Duration::from_millis(500));
+
+        assert!(backoff.delay(0) == Some(Duration::from_millis(100)));
+        assert!(backoff.delay(1) == Some(Duration::from_millis(200)));
+        assert!(backoff.delay(2) == Some(Duration::from_millis(400)));
+        assert!(backoff.delay(3) == Some(Duration::from_millis(500)));
+        assert!(backoff.delay(1000) == Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn backoff_max_attempts() {
+        let backoff = ReconnectBackoff::new(Duration::from_millis(100), Duration::from_millis(500)).with_max_attempts(3);
+
+        assert!(backoff.delay(0).is_some());
+        assert!(backoff.delay(1).is_some());
+        assert!(backoff.delay(2).is_none());
+    }
+}
@@ -0,0 +1,247 @@
+use crate::socket::*;
+
+use flo_scene::*;
+
+use std::collections::{HashSet};
+
+/// The command that a session uses to supply a token to its authenticator
+pub const AUTHENTICATE_COMMAND: &str = "authenticate";
+
+///
+/// The commands and subprograms that a command session is allowed to use
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandPermissions {
+    /// The names of the commands that can be run, or None if any command can be run
+    commands: Option<HashSet<String>>,
+
+    /// The subprograms that commands can be sent to with the `@program` syntax or name in their arguments, or None if commands can reach any subprogram
+    targets: Option<HashSet<SubProgramId>>,
+}
+
+///
+/// The credentials supplied by the client of a command session
+///
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct CommandCredentials {
+    /// Information about the process at the other end of the socket
+    pub peer: SocketPeer,
+
+    /// The token supplied with the `authenticate` command, or None if the client hasn't authenticated yet
+    pub token: Option<String>,
+}
+
+///
+/// A command authenticator decides what a command session is allowed to do based on the credentials supplied by its client
+///
+/// Sessions are authenticated when they are created (with no token), and again whenever the client uses the `authenticate` command.
+/// Sessions that are not authenticated can only use the `authenticate` command.
+///
+pub trait CommandAuthenticator : Send + Sync {
+    ///
+    /// Returns the permissions for a session with the specified credentials, or `ConnectionError::TargetPermissionRefused` if the
+    /// credentials are not accepted
+    ///
+    fn authenticate(&self, credentials: &CommandCredentials) -> Result<CommandPermissions, ConnectionError>;
+}
+
+///
+/// Authenticator that grants permissions to clients that supply a shared token using the `authenticate` command
+///
+#[derive(Clone, Debug)]
+pub struct SharedTokenAuthenticator {
+    token:          String,
+    permissions:    CommandPermissions,
+}
+
+///
+/// Authenticator that grants permissions to clients that are running as one of a set of user IDs (this requires a socket that can
+/// supply peer credentials, such as the one created by `start_unix_socket_program()`)
+///
+#[derive(Clone, Debug)]
+pub struct PeerUserAuthenticator {
+    user_ids:       HashSet<u32>,
+    permissions:    CommandPermissions,
+}
+
+impl CommandPermissions {
+    ///
+    /// Permissions that allow any command to be sent to any subprogram
+    ///
+    pub fn all() -> Self {
+        CommandPermissions { commands: None, targets: None }
+    }
+
+    ///
+    /// Permissions that allow no commands to be run
+    ///
+    pub fn none() -> Self {
+        CommandPermissions { commands: Some(HashSet::new()), targets: Some(HashSet::new()) }
+    }
+
+    ///
+    /// Restricts these permissions so that only the specified commands can be run (this adds to the list if it's already restricted)
+    ///
+    pub fn with_commands(mut self, commands: impl IntoIterator<Item=impl Into<String>>) -> Self {
+        self.commands.get_or_insert_with(HashSet::new).extend(commands.into_iter().map(|command| command.into()));
+        self
+    }
+
+    ///
+    /// Restricts these permissions so that commands can only be sent directly to the specified subprograms (this adds to the list if it's
+    /// already restricted)
+    ///
+    /// Commands sent to the default command target are not affected by this restriction, but the subprograms named in the arguments
+    /// of the standard commands (such as `send` and `connect`) are checked against it.
+    ///
+    pub fn with_targets(mut self, targets: impl IntoIterator<Item=SubProgramId>) -> Self {
+        self.targets.get_or_insert_with(HashSet::new).extend(targets);
+        self
+    }
+
+    ///
+    /// Returns true if these permissions allow the specified command to be run
+    ///
+    pub fn allows_command(&self, command: &str) -> bool {
+        self.commands.as_ref().map(|commands| commands.contains(command)).unwrap_or(true)
+    }
+
+    ///
+    /// Returns true if these permissions allow commands to be sent to the specified target
+    ///
+    pub fn allows_target(&self, target: &StreamTarget) -> bool {
+        match (target, &self.targets) {
+            (_, None)                                                           => true,
+            (StreamTarget::Program(program_id), Some(targets))                  |
+            (StreamTarget::Filtered(_, program_id), Some(targets))              => targets.contains(program_id),
+            (StreamTarget::None, _) | (StreamTarget::Any, _)                    => true,
+        }
+    }
+
+    ///
+    /// Checks that a command can reach each of a set of targets (eg, the subprograms named in its arguments), returning
+    /// `ConnectionError::TargetPermissionRefused` if it can't
+    ///
+    pub fn check_targets(&self, targets: impl IntoIterator<Item=StreamTarget>) -> Result<(), ConnectionError> {
+        if targets.into_iter().all(|target| self.allows_target(&target)) {
+            Ok(())
+        } else {
+            Err(ConnectionError::TargetPermissionRefused)
+        }
+    }
+
+    ///
+    /// Checks that a command can be sent to a target, returning `ConnectionError::TargetPermissionRefused` if it can't
+    ///
+    pub fn check(&self, command: &str, target: &StreamTarget) -> Result<(), ConnectionError> {
+        if self.allows_command(command) && self.allows_target(target) {
+            Ok(())
+        } else {
+            Err(ConnectionError::TargetPermissionRefused)
+        }
+    }
+}
+
+impl<TFn> CommandAuthenticator for TFn
+where
+    TFn: Send + Sync + Fn(&CommandCredentials) -> Result<CommandPermissions, ConnectionError>,
+{
+    #[inline]
+    fn authenticate(&self, credentials: &CommandCredentials) -> Result<CommandPermissions, ConnectionError> {
+        (self)(credentials)
+    }
+}
+
+impl SharedTokenAuthenticator {
+    ///
+    /// Creates an authenticator that grants the specified permissions to clients that supply a token
+    ///
+    pub fn new(token: impl Into<String>, permissions: CommandPermissions) -> Self {
+        SharedTokenAuthenticator { token: token.into(), permissions }
+    }
+}
+
+impl CommandAuthenticator for SharedTokenAuthenticator {
+    fn authenticate(&self, credentials: &CommandCredentials) -> Result<CommandPermissions, ConnectionError> {
+        match &credentials.token {
+            Some(token) if tokens_match(token, &self.token) => Ok(self.permissions.clone()),
+            _                                               => Err(ConnectionError::TargetPermissionRefused),
+        }
+    }
+}
+
+impl PeerUserAuthenticator {
+    ///
+    /// Creates an authenticator that grants the specified permissions to clients running as one of the specified users
+    ///
+    pub fn new(user_ids: impl IntoIterator<Item=u32>, permissions: CommandPermissions) -> Self {
+        PeerUserAuthenticator { user_ids: user_ids.into_iter().collect(), permissions }
+    }
+}
+
+impl CommandAuthenticator for PeerUserAuthenticator {
+    fn authenticate(&self, credentials: &CommandCredentials) -> Result<CommandPermissions, ConnectionError> {
+        match credentials.peer.user_id {
+            Some(user_id) if self.user_ids.contains(&user_id)   => Ok(self.permissions.clone()),
+            _                                                   => Err(ConnectionError::TargetPermissionRefused),
+        }
+    }
+}
+
+///
+/// Compares two tokens, taking the same amount of time regardless of where they differ
+///
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn all_permissions_allow_everything() {
+        let permissions = CommandPermissions::all();
+
+        assert!(permissions.check("send", &StreamTarget::Any).is_ok());
+        assert!(permissions.check("send", &StreamTarget::Program(SubProgramId::called("test"))).is_ok());
+    }
+
+    #[test]
+    fn restrict_commands() {
+        let permissions = CommandPermissions::all().with_commands(["echo"]);
+
+        assert!(permissions.check("echo", &StreamTarget::Any).is_ok());
+        assert!(permissions.check("send", &StreamTarget::Any) == Err(ConnectionError::TargetPermissionRefused));
+        assert!(permissions.check("echo", &StreamTarget::Program(SubProgramId::called("test"))).is_ok());
+    }
+
+    #[test]
+    fn restrict_targets() {
+        let permissions = CommandPermissions::all().with_targets([SubProgramId::called("allowed")]);
+
+        assert!(permissions.check("echo", &StreamTarget::Any).is_ok());
+        assert!(permissions.check("echo", &StreamTarget::Program(SubProgramId::called("allowed"))).is_ok());
+        assert!(permissions.check("echo", &StreamTarget::Program(SubProgramId::called("not_allowed"))) == Err(ConnectionError::TargetPermissionRefused));
+    }
+
+    #[test]
+    fn shared_token() {
+        let authenticator = SharedTokenAuthenticator::new("secret", CommandPermissions::all());
+
+        assert!(authenticator.authenticate(&CommandCredentials::default()).is_err());
+        assert!(authenticator.authenticate(&CommandCredentials { token: Some("secre".into()), ..Default::default() }).is_err());
+        assert!(authenticator.authenticate(&CommandCredentials { token: Some("secrets".into()), ..Default::default() }).is_err());
+        assert!(authenticator.authenticate(&CommandCredentials { token: Some("secret".into()), ..Default::default() }) == Ok(CommandPermissions::all()));
+    }
+
+    #[test]
+    fn peer_user() {
+        let authenticator   = PeerUserAuthenticator::new([1000], CommandPermissions::all());
+        let peer            = |user_id| CommandCredentials { peer: SocketPeer { user_id, ..Default::default() }, token: None };
+
+        assert!(authenticator.authenticate(&peer(None)).is_err());
+        assert!(authenticator.authenticate(&peer(Some(0))).is_err());
+        assert!(authenticator.authenticate(&peer(Some(1000))) == Ok(CommandPermissions::all()));
+    }
+}
@@ -0,0 +1,242 @@
+use crate::socket::*;
+
+use flo_scene::*;
+
+use serde::*;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The command that lists, exports, clears, saves and loads the variables for a session
+pub const VARS_COMMAND: &str = "vars";
+
+///
+/// The requests that can be made with the `vars` command
+///
+/// The parameter for the command is one of `"list"` (the default if there's no parameter), `"export"`, `"clear"`, `{ "save": "<profile>" }`
+/// or `{ "load": "<profile>" }`
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VarsRequest {
+    /// Generates a message describing each variable
+    List,
+
+    /// Returns the variables as a single JSON object
+    Export,
+
+    /// Removes all of the variables from the session
+    Clear,
+
+    /// Saves the variables to a profile (which becomes the active profile for the session)
+    Save(String),
+
+    /// Replaces the variables with those stored in a profile (which becomes the active profile for the session)
+    Load(String),
+}
+
+///
+/// Describes where command sessions save and restore their variables
+///
+/// Profiles are stored as JSON files in a directory, so a session's variables can be kept after its socket disconnects. A session
+/// that has loaded or saved a profile will save its variables back to that profile when it finishes.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandProfiles {
+    /// The directory where the profiles are stored
+    directory: PathBuf,
+
+    /// A command script that's run at the start of every session
+    startup_file: Option<PathBuf>,
+
+    /// True if sessions with a peer user ID should automatically use a profile for that user
+    peer_profiles: bool,
+}
+
+impl CommandProfiles {
+    ///
+    /// Creates a set of profiles stored in the specified directory (the directory is created when the first profile is saved)
+    ///
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        CommandProfiles {
+            directory:      directory.into(),
+            startup_file:   None,
+            peer_profiles:  false,
+        }
+    }
+
+    ///
+    /// Sets a command script that's run at the start of every session, like the rc file for a shell
+    ///
+    /// The startup file is run after the session's profile has been loaded. Errors are reported to the session but don't stop it
+    /// from starting, and any other output from the script is discarded.
+    ///
+    pub fn with_startup_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.startup_file = Some(path.into());
+        self
+    }
+
+    ///
+    /// Sets whether or not sessions use a profile for the user at the other end of the socket
+    ///
+    /// When this is set, a session whose `SocketPeer` has a user ID (for example, a session on a unix domain socket) will load
+    /// the profile called `user-<uid>` when it starts (if it exists), and save its variables back to it when it finishes. Sessions
+    /// can't save or load the `user-<uid>` profile for any other user.
+    ///
+    pub fn with_peer_profiles(mut self, peer_profiles: bool) -> Self {
+        self.peer_profiles = peer_profiles;
+        self
+    }
+
+    ///
+    /// Returns the directory where the profiles are stored
+    ///
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    ///
+    /// Returns the startup file that's run at the start of every session, if there is one
+    ///
+    pub fn startup_file(&self) -> Option<&Path> {
+        self.startup_file.as_deref()
+    }
+
+    ///
+    /// Returns the name of the profile that a session connected to the specified peer should use
+    ///
+    pub fn peer_profile_name(&self, peer: &SocketPeer) -> Option<String> {
+        if self.peer_profiles {
+            peer.user_id.map(|user_id| format!("user-{}", user_id))
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// Checks that a session connected to the specified peer is allowed to save or load a profile
+    ///
+    /// When peer profiles are in use, the profiles called `user-<uid>` belong to the user with that ID, so only a session whose peer
+    /// has that user ID can use them. Other profiles can be used by any session.
+    ///
+    pub fn check_profile_access(&self, name: &str, peer: &SocketPeer) -> Result<(), ConnectionError> {
+        if self.peer_profiles && name.starts_with("user-") && self.peer_profile_name(peer).as_deref() != Some(name) {
+            Err(ConnectionError::TargetPermissionRefused)
+        } else {
+            Ok(())
+        }
+    }
+
+    ///
+    /// Returns the path of the file used to store a profile
+    ///
+    /// Profile names can only contain letters, numbers, '-' and '_', so a profile can't refer to a file outside of the profile directory
+    ///
+    pub fn profile_path(&self, name: &str) -> Result<PathBuf, ConnectionError> {
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            return Err(ConnectionError::InvalidConfiguration(format!("`{}` is not a valid profile name", name)));
+        }
+
+        Ok(self.directory.join(format!("{}.json", name)))
+    }
+
+    ///
+    /// True if the profile with the specified name has been saved
+    ///
+    pub fn profile_exists(&self, name: &str) -> bool {
+        self.profile_path(name).map(|path| path.exists()).unwrap_or(false)
+    }
+
+    ///
+    /// Reads the variables stored in a profile
+    ///
+    pub fn load_profile(&self, name: &str) -> Result<HashMap<String, serde_json::Value>, ConnectionError> {
+        let path    = self.profile_path(name)?;
+        let profile = fs::read_to_string(path)
+            .map_err(|io_err| ConnectionError::IoError(format!("{}", io_err)))?;
+
+        serde_json::from_str(&profile)
+            .map_err(|err| ConnectionError::InvalidConfiguration(format!("{}", err)))
+    }
+
+    ///
+    /// Writes a set of variables to a profile, replacing its previous contents
+    ///
+    pub fn save_profile(&self, name: &str, variables: &HashMap<String, serde_json::Value>) -> Result<(), ConnectionError> {
+        let path = self.profile_path(name)?;
+
+        // Sort the variables so the file is easier to read and compare
+        let variables   = variables.iter().collect::<BTreeMap<_, _>>();
+        let profile     = serde_json::to_string_pretty(&variables)
+            .map_err(|err| ConnectionError::InvalidConfiguration(format!("{}", err)))?;
+
+        fs::create_dir_all(&self.directory)
+            .and_then(|_| fs::write(path, profile))
+            .map_err(|io_err| ConnectionError::IoError(format!("{}", io_err)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+
+    #[test]
+    fn save_and_load_profile() {
+        let directory   = env::temp_dir().join(format!("flo_scene_profiles_{}", std::process::id()));
+        let profiles    = CommandProfiles::new(&directory);
+
+        let mut variables = HashMap::new();
+        variables.insert("one".to_string(), serde_json::json!(1));
+        variables.insert("two".to_string(), serde_json::json!({ "value": [2] }));
+
+        profiles.save_profile("test", &variables).unwrap();
+        assert!(profiles.profile_exists("test"));
+
+        let loaded = profiles.load_profile("test").unwrap();
+        assert!(loaded == variables, "{:?}", loaded);
+
+        fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn reject_invalid_profile_names() {
+        let profiles = CommandProfiles::new("profiles");
+
+        assert!(profiles.profile_path("valid-name_1").is_ok());
+        assert!(profiles.profile_path("").is_err());
+        assert!(profiles.profile_path("../outside").is_err());
+        assert!(profiles.profile_path("sub/dir").is_err());
+    }
+
+    #[test]
+    fn profile_name_for_peer() {
+        let peer = SocketPeer { user_id: Some(501), ..SocketPeer::default() };
+
+        assert!(CommandProfiles::new("profiles").peer_profile_name(&peer).is_none());
+        assert!(CommandProfiles::new("profiles").with_peer_profiles(true).peer_profile_name(&peer) == Some("user-501".into()));
+        assert!(CommandProfiles::new("profiles").with_peer_profiles(true).peer_profile_name(&SocketPeer::default()).is_none());
+    }
+
+    #[test]
+    fn peers_can_only_use_their_own_user_profile() {
+        let peer        = SocketPeer { user_id: Some(501), ..SocketPeer::default() };
+        let profiles    = CommandProfiles::new("profiles").with_peer_profiles(true);
+
+        assert!(profiles.check_profile_access("user-501", &peer).is_ok());
+        assert!(profiles.check_profile_access("shared", &peer).is_ok());
+        assert!(profiles.check_profile_access("user-0", &peer).is_err());
+        assert!(profiles.check_profile_access("user-501", &SocketPeer::default()).is_err());
+        assert!(CommandProfiles::new("profiles").check_profile_access("user-0", &peer).is_ok());
+    }
+
+    #[test]
+    fn parse_vars_requests() {
+        assert!(serde_json::from_value::<VarsRequest>(serde_json::json!("list")).unwrap() == VarsRequest::List);
+        assert!(serde_json::from_value::<VarsRequest>(serde_json::json!("clear")).unwrap() == VarsRequest::Clear);
+        assert!(serde_json::from_value::<VarsRequest>(serde_json::json!({ "save": "name" })).unwrap() == VarsRequest::Save("name".into()));
+    }
+}
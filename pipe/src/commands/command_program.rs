@@ -1,8 +1,14 @@
 use super::command_stream::*;
 use super::command_socket::*;
+use super::command_script::*;
+use super::parse_command::*;
 use super::json_command::*;
+use super::line_editor::*;
+use super::command_permissions::*;
+use super::command_profiles::*;
 use crate::socket::*;
 use crate::parse_json::*;
+use crate::standard_json_commands::*;
 
 use flo_scene::*;
 use flo_scene::commands::*;
@@ -12,18 +18,28 @@ use flo_stream::*;
 
 use futures::prelude::*;
 use futures::{pin_mut};
-use futures::future::{BoxFuture};
+use futures::channel::mpsc;
+use futures::future::{AbortHandle, Abortable, BoxFuture, Either};
 use futures::stream::{BoxStream};
 use once_cell::sync::{Lazy};
 use serde::*;
 
-use std::collections::{HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
 use std::iter;
+use std::path::{PathBuf};
 use std::sync::*;
 
 /// Filter that maps the 'Query' message to a CommandSessionRequest message
 static COMMAND_SESSION_VARIABLE_QUERY_FILTER: Lazy<FilterHandle> = Lazy::new(|| FilterHandle::for_filter(|stream: InputStream<Query<CommandVariable>>| stream.map(|msg| CommandSessionRequest::QueryAllVariables(msg.target()))));
 
+/// Filter that maps the 'Subscribe' message to a CommandSessionRequest message
+static COMMAND_SESSION_VARIABLE_SUBSCRIBE_FILTER: Lazy<FilterHandle> = Lazy::new(|| FilterHandle::for_filter(|stream: InputStream<Subscribe<CommandVariable>>| stream.map(|msg| CommandSessionRequest::Subscribe(msg.target()))));
+
+/// The command that generates a background stream of the value of an expression every time one of the variables it uses changes
+pub const WATCH_COMMAND: &str = "watch";
+
 ///
 /// A connection to a simple command program
 ///
@@ -47,6 +63,9 @@ pub enum CommandSessionRequest {
 
     /// As for QueryVariable, except sends the values of all of the variables to the specified target as `QueryResponse<CommandVariable>` messages
     QueryAllVariables(StreamTarget),
+
+    /// Sends a `CommandVariable` message to the specified target whenever a variable in this session changes
+    Subscribe(StreamTarget),
 }
 
 ///
@@ -59,6 +78,7 @@ pub struct CommandVariable(pub String, pub serde_json::Value);
 impl SceneMessage for CommandSessionRequest {
     fn initialise(scene: &Scene) {
         scene.connect_programs(StreamSource::Filtered(*COMMAND_SESSION_VARIABLE_QUERY_FILTER), (), StreamId::with_message_type::<Query<CommandVariable>>()).unwrap();
+        scene.connect_programs(StreamSource::Filtered(*COMMAND_SESSION_VARIABLE_SUBSCRIBE_FILTER), (), StreamId::with_message_type::<Subscribe<CommandVariable>>()).unwrap();
     }
 
     #[inline]
@@ -106,6 +126,148 @@ pub async fn command_connection_program(input: InputStream<CommandProgramSocketM
     }
 }
 
+///
+/// As for `command_connection_program()`, except the connections use the JSON-RPC protocol from the start
+///
+/// (Connections made to `command_connection_program()` can also switch to JSON-RPC by sending a JSON-RPC request as their first
+/// request, but will receive a prompt before doing so)
+///
+pub async fn json_rpc_connection_program(input: InputStream<CommandProgramSocketMessage>, context: SceneContext, command_target: impl Into<StreamTarget>) {
+    let command_target = command_target.into();
+
+    // Spawn session tasks for each connection
+    let mut input = input;
+    while let Some(connection) = input.next().await {
+        match connection {
+            SocketMessage::Connection(connection) => {
+                // Connect the command socket using the JSON-RPC protocol
+                let mut socket      = CommandSocket::connect(connection);
+                let command_target  = command_target.clone();
+
+                socket.set_protocol(CommandProtocol::JsonRpc);
+
+                // Spawn a subprogram to handle running the commands using the CommandSession
+                let command_session_id = SubProgramId::new();
+                context.send_message(SceneControl::start_program(
+                    command_session_id,
+                    move |input, context| async move {
+                        let command_session = CommandSession::new(socket, command_target);
+                        command_session.run(input, context).await;
+                    },
+                    0)).await.ok();
+            }
+        }
+    }
+}
+
+///
+/// As for `command_connection_program()`, except the permissions for each connection are decided by an authenticator
+///
+/// Connections are authenticated when they are made, using the `SocketPeer` information for the socket (which contains the user ID
+/// of the connecting process for Unix domain sockets). If this is refused, the connection can only run the `authenticate "<token>"`
+/// command until the authenticator accepts its token. Commands that are not permitted generate an error response.
+///
+pub async fn authenticated_command_connection_program(input: InputStream<CommandProgramSocketMessage>, context: SceneContext, command_target: impl Into<StreamTarget>, authenticator: impl 'static + CommandAuthenticator) {
+    let command_target  = command_target.into();
+    let authenticator   = Arc::new(authenticator);
+
+    // Spawn session tasks for each connection
+    let mut input = input;
+    while let Some(connection) = input.next().await {
+        match connection {
+            SocketMessage::Connection(connection) => {
+                // Connect the command socket
+                let socket          = CommandSocket::connect(connection);
+                let command_target  = command_target.clone();
+                let authenticator   = Arc::clone(&authenticator);
+
+                // Spawn a subprogram to handle running the commands using the CommandSession
+                let command_session_id = SubProgramId::new();
+                context.send_message(SceneControl::start_program(
+                    command_session_id,
+                    move |input, context| async move {
+                        let command_session = CommandSession::new(socket, command_target).with_authenticator(authenticator);
+                        command_session.run(input, context).await;
+                    },
+                    0)).await.ok();
+            }
+        }
+    }
+}
+
+///
+/// As for `command_connection_program()`, except the sessions can save and restore their variables using a set of profiles
+///
+/// The startup file for the profiles (if there is one) is run at the start of each session, and sessions with a peer user ID will
+/// use the profile for that user if the profiles were created with `with_peer_profiles(true)`.
+///
+pub async fn profile_command_connection_program(input: InputStream<CommandProgramSocketMessage>, context: SceneContext, command_target: impl Into<StreamTarget>, profiles: CommandProfiles) {
+    let command_target = command_target.into();
+
+    // Spawn session tasks for each connection
+    let mut input = input;
+    while let Some(connection) = input.next().await {
+        match connection {
+            SocketMessage::Connection(connection) => {
+                // Connect the command socket
+                let socket          = CommandSocket::connect(connection);
+                let command_target  = command_target.clone();
+                let profiles        = profiles.clone();
+
+                // Spawn a subprogram to handle running the commands using the CommandSession
+                let command_session_id = SubProgramId::new();
+                context.send_message(SceneControl::start_program(
+                    command_session_id,
+                    move |input, context| async move {
+                        let command_session = CommandSession::new(socket, command_target).with_profiles(profiles);
+                        command_session.run(input, context).await;
+                    },
+                    0)).await.ok();
+            }
+        }
+    }
+}
+
+///
+/// As for `command_connection_program()`, except the connections are expected to be from a terminal in raw mode, and the socket
+/// will perform line editing with history and tab completion
+///
+/// If a history file is supplied, the history is loaded from and saved to that file.
+///
+pub async fn line_editing_connection_program(input: InputStream<CommandProgramSocketMessage>, context: SceneContext, command_target: impl Into<StreamTarget>, history_file: Option<PathBuf>) {
+    let command_target = command_target.into();
+
+    // Spawn session tasks for each connection
+    let mut input = input;
+    while let Some(connection) = input.next().await {
+        match connection {
+            SocketMessage::Connection(connection) => {
+                // Connect the command socket
+                let socket          = CommandSocket::connect(connection);
+                let command_target  = command_target.clone();
+                let history_file    = history_file.clone();
+
+                // Spawn a subprogram to handle running the commands using the CommandSession
+                let command_session_id = SubProgramId::new();
+                context.send_message(SceneControl::start_program(
+                    command_session_id,
+                    move |input, context| async move {
+                        // Each session has its own line editor (sessions will share the history file if there is one)
+                        let mut socket      = socket;
+                        let line_editor     = LineEditor::new();
+                        let line_editor     = if let Some(history_file) = history_file { line_editor.with_history_file(history_file) } else { line_editor };
+
+                        socket.start_line_editing(line_editor).await;
+
+                        let command_session = CommandSession::new(socket, command_target);
+                        command_session.run(input, context).await;
+                    },
+                    0)).await.ok();
+            }
+        }
+    }
+}
+
 ///
 /// The command session reads commands from a socket and evaluates them
 ///
@@ -119,6 +281,27 @@ pub struct CommandSession {
 
     /// The variables for this command session
     variables: Arc<Mutex<HashMap<String, serde_json::Value>>>,
+
+    /// The commands and targets that this session is allowed to use
+    permissions: Arc<Mutex<CommandPermissions>>,
+
+    /// The authenticator that decides the permissions for this session, if it has one
+    authenticator: Option<Arc<dyn CommandAuthenticator>>,
+
+    /// Information about the process at the other end of the socket for this session
+    peer: SocketPeer,
+
+    /// Where this session can save and load its variables, if it has profiles
+    profiles: Option<Arc<CommandProfiles>>,
+
+    /// The profile that the variables are saved to when this session finishes
+    active_profile: Arc<Mutex<Option<String>>>,
+
+    /// Channels that are sent the new value of a variable whenever one changes
+    variable_watchers: Arc<Mutex<Vec<mpsc::UnboundedSender<CommandVariable>>>>,
+
+    /// Stops the background streams that are updating variables
+    reactive_variables: Arc<Mutex<HashMap<String, AbortHandle>>>,
 }
 
 impl CommandSession {
@@ -126,18 +309,293 @@ impl CommandSession {
     /// Creates a new command processor that will send commands to the specified target
     ///
     pub fn new(socket: CommandSocket, target: StreamTarget) -> Self {
-        let socket = Arc::new(Mutex::new(Some(socket)));
-        let variables = Arc::new(Mutex::new(HashMap::new()));
-        CommandSession { socket, target, variables }
+        let peer        = socket.peer().clone();
+        let socket      = Arc::new(Mutex::new(Some(socket)));
+        let variables   = Arc::new(Mutex::new(HashMap::new()));
+        let permissions = Arc::new(Mutex::new(CommandPermissions::all()));
+        CommandSession {
+            socket,
+            target,
+            variables,
+            permissions,
+            authenticator:      None,
+            peer,
+            profiles:           None,
+            active_profile:     Arc::new(Mutex::new(None)),
+            variable_watchers:  Arc::new(Mutex::new(vec![])),
+            reactive_variables: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    ///
+    /// Sets the value of a variable in this session, stopping any background stream that was updating it
+    ///
+    pub fn set_variable(&self, name: impl Into<String>, value: serde_json::Value) {
+        let name = name.into();
+
+        if let Some(reactive_variable) = self.reactive_variables.lock().unwrap().remove(&name) {
+            reactive_variable.abort();
+        }
+
+        self.update_variable(name, value);
+    }
+
+    ///
+    /// Changes the value of a variable and notifies anything that's watching the variables
+    ///
+    fn update_variable(&self, name: String, value: serde_json::Value) {
+        self.variables.lock().unwrap().insert(name.clone(), value.clone());
+        self.notify_variable_changed(CommandVariable(name, value));
+    }
+
+    ///
+    /// Replaces all of the variables in this session (used when they're cleared or loaded from a profile)
+    ///
+    /// Variables that are removed are reported to the watchers as being changed to `null`
+    ///
+    fn replace_variables(&self, new_variables: HashMap<String, serde_json::Value>) {
+        self.stop_reactive_variables();
+
+        let old_variables = std::mem::replace(&mut *self.variables.lock().unwrap(), new_variables.clone());
+
+        for name in old_variables.into_keys().filter(|name| !new_variables.contains_key(name)) {
+            self.notify_variable_changed(CommandVariable(name, serde_json::Value::Null));
+        }
+
+        for (name, value) in new_variables {
+            self.notify_variable_changed(CommandVariable(name, value));
+        }
+    }
+
+    ///
+    /// Sends a changed variable to anything that's watching the variables in this session
+    ///
+    fn notify_variable_changed(&self, variable: CommandVariable) {
+        self.variable_watchers.lock().unwrap()
+            .retain(|watcher| watcher.unbounded_send(variable.clone()).is_ok());
+    }
+
+    ///
+    /// Returns a stream of the changes to the variables in this session
+    ///
+    fn watch_variables(&self) -> mpsc::UnboundedReceiver<CommandVariable> {
+        let (send_changes, recv_changes) = mpsc::unbounded();
+        self.variable_watchers.lock().unwrap().push(send_changes);
+
+        recv_changes
+    }
+
+    ///
+    /// Stops all of the background streams that are updating variables in this session
+    ///
+    fn stop_reactive_variables(&self) {
+        for (_, reactive_variable) in self.reactive_variables.lock().unwrap().drain() {
+            reactive_variable.abort();
+        }
+    }
+
+    ///
+    /// Allows this session to save and load its variables using a set of profiles
+    ///
+    /// If the profiles use peer profiles and the socket for this session has a user ID, the profile for that user is loaded
+    /// immediately, and will be saved again when the session finishes.
+    ///
+    pub fn with_profiles(mut self, profiles: CommandProfiles) -> Self {
+        if let Some(profile_name) = profiles.peer_profile_name(&self.peer) {
+            // A new user starts with no variables
+            let variables = if profiles.profile_exists(&profile_name) { profiles.load_profile(&profile_name).map(Some) } else { Ok(None) };
+
+            // The profile isn't made active if it can't be loaded, so it won't be overwritten when the session finishes
+            if let Ok(variables) = variables {
+                if let Some(variables) = variables {
+                    *self.variables.lock().unwrap() = variables;
+                }
+
+                *self.active_profile.lock().unwrap() = Some(profile_name);
+            }
+        }
+
+        self.profiles = Some(Arc::new(profiles));
+        self
+    }
+
+    ///
+    /// Runs the `vars` command, which lists, exports, clears, saves or loads the variables for this session
+    ///
+    fn vars(&self, parameter: serde_json::Value) -> BoxStream<'static, CommandResponse> {
+        use serde_json::Value;
+
+        let request = if parameter.is_null() { Ok(VarsRequest::List) } else { serde_json::from_value(parameter) };
+        let request = match request {
+            Ok(request) => request,
+            Err(err)    => { return stream::iter(iter::once(CommandResponse::Error(format!("`{}` requires \"list\", \"export\", \"clear\", {{ \"save\": \"<profile>\" }} or {{ \"load\": \"<profile>\" }} ({})", VARS_COMMAND, err)))).boxed(); }
+        };
+
+        let responses = match request {
+            VarsRequest::List => {
+                let variables = self.variables.lock().unwrap().iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect::<BTreeMap<_, _>>();
+
+                if variables.is_empty() {
+                    vec![CommandResponse::Message("No variables are defined".into())]
+                } else {
+                    variables.into_iter()
+                        .map(|(name, value)| CommandResponse::Message(format!("{} = {}", name, value)))
+                        .collect()
+                }
+            }
+
+            VarsRequest::Export => {
+                let variables = self.variables.lock().unwrap().iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect::<serde_json::Map<_, _>>();
+
+                vec![CommandResponse::Json(Value::Object(variables))]
+            }
+
+            VarsRequest::Clear => {
+                let num_variables = self.variables.lock().unwrap().len();
+                self.replace_variables(HashMap::new());
+
+                vec![CommandResponse::Message(format!("Cleared {} variables", num_variables))]
+            }
+
+            VarsRequest::Save(profile_name) => {
+                if let Some(profiles) = &self.profiles {
+                    let variables = self.variables.lock().unwrap().clone();
+
+                    match profiles.check_profile_access(&profile_name, &self.peer).and_then(|_| profiles.save_profile(&profile_name, &variables)) {
+                        Ok(()) => {
+                            *self.active_profile.lock().unwrap() = Some(profile_name.clone());
+                            vec![CommandResponse::Message(format!("Variables saved to profile `{}`", profile_name))]
+                        }
+
+                        Err(err) => vec![CommandResponse::Error(format!("Could not save profile `{}`: {:?}", profile_name, err))],
+                    }
+                } else {
+                    vec![CommandResponse::Error("This session cannot save profiles".into())]
+                }
+            }
+
+            VarsRequest::Load(profile_name) => {
+                if let Some(profiles) = &self.profiles {
+                    match profiles.check_profile_access(&profile_name, &self.peer).and_then(|_| profiles.load_profile(&profile_name)) {
+                        Ok(variables) => {
+                            self.replace_variables(variables);
+                            *self.active_profile.lock().unwrap() = Some(profile_name.clone());
+                            vec![CommandResponse::Message(format!("Variables loaded from profile `{}`", profile_name))]
+                        }
+
+                        Err(err) => vec![CommandResponse::Error(format!("Could not load profile `{}`: {:?}", profile_name, err))],
+                    }
+                } else {
+                    vec![CommandResponse::Error("This session cannot load profiles".into())]
+                }
+            }
+        };
+
+        stream::iter(responses).boxed()
+    }
+
+    ///
+    /// Saves the variables for this session to its active profile, if it has one
+    ///
+    fn save_active_profile(&self) {
+        let profile_name = self.active_profile.lock().unwrap().clone();
+
+        if let (Some(profiles), Some(profile_name)) = (&self.profiles, profile_name) {
+            let variables = self.variables.lock().unwrap().clone();
+            profiles.save_profile(&profile_name, &variables).ok();
+        }
+    }
+
+    ///
+    /// Runs the startup file for this session's profiles, if there is one
+    ///
+    /// Errors are sent to the socket as notifications, and other responses are discarded
+    ///
+    async fn run_startup_file(&self, socket: &mut CommandSocket, context: &SceneContext) {
+        let startup_file = if let Some(startup_file) = self.profiles.as_ref().and_then(|profiles| profiles.startup_file()) { startup_file } else { return; };
+
+        // It's not an error for the startup file to not exist
+        let script = match fs::read_to_string(startup_file) {
+            Ok(script)                                          => script,
+            Err(err) if err.kind() == io::ErrorKind::NotFound   => { return; }
+            Err(err)                                            => {
+                socket.notify(CommandNotification::Error(format!("Could not read startup file: {}", err))).await.ok();
+                return;
+            }
+        };
+
+        let requests = match CommandRequest::parse_script(&script).await {
+            Ok(requests)    => requests,
+            Err(err)        => {
+                socket.notify(CommandNotification::Error(format!("Could not parse startup file: {:?}", err))).await.ok();
+                return;
+            }
+        };
+
+        // Run the requests in order
+        for request in requests {
+            let mut responses = self.evaluate_request(request, context).await;
+
+            while let Some(response) = responses.next().await {
+                if let CommandResponse::Error(err) = response {
+                    socket.notify(CommandNotification::Error(format!("Startup file: {}", err))).await.ok();
+                }
+            }
+        }
+    }
+
+    ///
+    /// Uses an authenticator to decide what this session is allowed to do
+    ///
+    /// The session is authenticated immediately using the peer information for its socket. If that's refused, the session can only
+    /// run the `authenticate` command until the client supplies a token that the authenticator accepts.
+    ///
+    pub fn with_authenticator(mut self, authenticator: Arc<dyn CommandAuthenticator>) -> Self {
+        let credentials = CommandCredentials { peer: self.peer.clone(), token: None };
+        let permissions = authenticator.authenticate(&credentials).unwrap_or_else(|_| CommandPermissions::none());
+
+        *self.permissions.lock().unwrap()   = permissions;
+        self.authenticator                  = Some(authenticator);
+
+        self
+    }
+
+    ///
+    /// Runs the `authenticate` command, which updates the permissions for this session using a token supplied by the client
+    ///
+    fn authenticate(&self, authenticator: &Arc<dyn CommandAuthenticator>, token: serde_json::Value) -> CommandResponse {
+        let token = if let serde_json::Value::String(token) = token { token } else {
+            return CommandResponse::Error(format!("`{}` requires a string token", AUTHENTICATE_COMMAND));
+        };
+
+        let credentials = CommandCredentials { peer: self.peer.clone(), token: Some(token) };
+
+        match authenticator.authenticate(&credentials) {
+            Ok(permissions) => {
+                *self.permissions.lock().unwrap() = permissions;
+                CommandResponse::Message("Authenticated".into())
+            }
+
+            Err(err) => {
+                // A failed attempt removes any permissions the session already had
+                *self.permissions.lock().unwrap() = CommandPermissions::none();
+                CommandResponse::Error(format!("Authentication failed ({:?})", err))
+            }
+        }
     }
 
     ///
     /// Substitutes any variables found in a `ParsedJson` structure.
     ///
+    /// `(command)` substitutions are also evaluated here: the command is run and replaced with the first JSON value that it returns.
+    ///
     /// Return value is the substituted variable or an error response
     ///
     pub fn substitute_variables<'a>(&'a self, parsed_json: ParsedJson, context: &'a SceneContext) -> BoxFuture<'a, Result<serde_json::Value, CommandResponse>> {
-        // TODO: a way to have `command { }` type substitutions
         async move {
             use ParsedJson::*;
             use serde_json::Value;
@@ -176,6 +634,24 @@ impl CommandSession {
                         Err(CommandResponse::Error(format!("Variable '{}' is not defined", variable)))
                     }
                 }
+
+                Command(command) => {
+                    let request = CommandRequest::parse(&command).await
+                        .map_err(|err| CommandResponse::Error(format!("Could not parse `({})`: {:?}", command, err)))?;
+
+                    // The first JSON value generated by the command is used as the result
+                    let mut responses = self.evaluate_request(request, context).await;
+
+                    while let Some(response) = responses.next().await {
+                        match response {
+                            CommandResponse::Json(value)    => { return Ok(value); }
+                            CommandResponse::Error(err)     => { return Err(CommandResponse::Error(format!("`({})`: {}", command, err))); }
+                            _                               => { }
+                        }
+                    }
+
+                    Err(CommandResponse::Error(format!("`({})` did not generate a value", command)))
+                }
             }
         }.boxed()
     }
@@ -188,18 +664,248 @@ impl CommandSession {
             use CommandRequest::*;
 
             match request {
-                Command     { command, argument } => { self.run_command(command, argument, &context).await }
-                RawJson     { value }             => { self.raw_json(value, context).await }
-                Pipe        { from, to }          => { stream::iter(iter::once(CommandResponse::Error("Not implemented yet".into()))).boxed() }
-                Assign      { variable, from }    => {
+                Command         { command, argument }               => { self.run_command(command, argument, &context).await }
+                RawJson         { value }                           => { self.raw_json(value, context).await }
+                Pipe            { from, to }                        => { self.pipeline(Pipe { from, to }, context).await }
+                Assign          { variable, from }                  => {
                     let request_responses = self.evaluate_request(*from, context).await;
-                    self.assign(variable, request_responses).await
+                    self.assign(variable, request_responses, context).await
+                }
+                ForTarget       { target, request }                 => { self.for_target(target, *request, context).await }
+                Block           { requests }                        => { self.block(requests, context).await }
+                If              { condition, then, otherwise }      => { self.if_request(condition, *then, otherwise.map(|otherwise| *otherwise), context).await }
+                ForEach         { variable, values, body }          => { self.foreach(variable, values, *body, context).await }
+                ContinueOnError { request }                         => {
+                    // Errors are reported as messages so they don't stop the block or script that this request is in
+                    self.evaluate_request(*request, context).await
+                        .map(|response| match response {
+                            CommandResponse::Error(err) => CommandResponse::Message(format!("Ignored error: {}", err)),
+                            other                       => other,
+                        })
+                        .boxed()
                 }
-                ForTarget   { target, request }   => { stream::iter(iter::once(CommandResponse::Error("Not implemented yet".into()))).boxed() }
             }
         }.boxed()
     }
 
+    ///
+    /// Evaluates a pipeline of commands (`a | b | c`)
+    ///
+    /// Each stage after the first must open an `IoStream`, which will receive the JSON values generated by the preceding stage.
+    /// The values from the final stage are returned as JSON responses. Messages from any stage are passed on directly, and
+    /// errors indicate which stage of the pipeline they came from.
+    ///
+    pub async fn pipeline<'a>(&'a self, pipeline: CommandRequest, context: &'a SceneContext) -> BoxStream<'a, CommandResponse> {
+        // Flatten the pipeline into a list of stages
+        let mut stages = vec![];
+        Self::pipeline_stages(pipeline, &mut stages);
+
+        // Each stage is a stream of the responses that should go to the user, which also sends its values to the next stage
+        let mut stage_streams   = Vec::with_capacity(stages.len() + 1);
+        let mut input           = stream::empty().boxed();
+
+        for (stage_idx, stage) in stages.into_iter().enumerate() {
+            let stage_name                  = Self::pipeline_stage_name(stage_idx, &stage);
+            let responses                   = self.evaluate_request(stage, context).await;
+            let (send_values, recv_values)  = mpsc::channel(0);
+
+            stage_streams.push(Self::pipeline_stage(stage_idx, stage_name, responses, input, send_values));
+            input = recv_values.boxed();
+        }
+
+        // The values from the last stage are returned to the user
+        stage_streams.push(input.map(CommandResponse::Json).boxed());
+
+        stream::select_all(stage_streams).boxed()
+    }
+
+    ///
+    /// Flattens a pipe request into a list of stages
+    ///
+    fn pipeline_stages(request: CommandRequest, stages: &mut Vec<CommandRequest>) {
+        match request {
+            CommandRequest::Pipe { from, to } => {
+                Self::pipeline_stages(*from, stages);
+                Self::pipeline_stages(*to, stages);
+            }
+
+            other => { stages.push(other); }
+        }
+    }
+
+    ///
+    /// Returns the description of a stage of a pipeline that's used when reporting errors
+    ///
+    fn pipeline_stage_name(stage_idx: usize, stage: &CommandRequest) -> String {
+        match stage {
+            CommandRequest::Command { command: CommandName(name), .. }  => format!("Pipeline stage {} (`{}`)", stage_idx+1, name),
+            CommandRequest::Assign { variable: VariableName(name), .. } => format!("Pipeline stage {} (`{} = ...`)", stage_idx+1, name),
+            CommandRequest::ForTarget { request, .. }                   => Self::pipeline_stage_name(stage_idx, request),
+            _                                                           => format!("Pipeline stage {}", stage_idx+1),
+        }
+    }
+
+    ///
+    /// Runs a single stage of a pipeline, sending its output values to the next stage and returning the responses that should be sent to the user
+    ///
+    fn pipeline_stage<'a>(stage_idx: usize, stage_name: String, responses: BoxStream<'a, CommandResponse>, input: BoxStream<'static, serde_json::Value>, send_values: mpsc::Sender<serde_json::Value>) -> BoxStream<'a, CommandResponse> {
+        generator_stream(move |yield_value| async move {
+            let mut responses   = responses;
+            let mut input       = Some(input);
+            let mut send_values = send_values;
+
+            while let Some(response) = responses.next().await {
+                match response {
+                    CommandResponse::Json(value) => {
+                        if send_values.send(value).await.is_err() { return; }
+                    }
+
+                    CommandResponse::BackgroundStream(values) => {
+                        let mut values = values;
+                        while let Some(value) = values.next().await {
+                            if send_values.send(value).await.is_err() { return; }
+                        }
+                    }
+
+                    CommandResponse::IoStream(create_stream) => {
+                        if let Some(input) = input.take() {
+                            let mut values = create_stream(input);
+                            while let Some(value) = values.next().await {
+                                if send_values.send(value).await.is_err() { return; }
+                            }
+                        } else {
+                            yield_value(CommandResponse::Error(format!("{}: command tried to read its input more than once", stage_name))).await;
+                            return;
+                        }
+                    }
+
+                    CommandResponse::InteractiveStream(_) => {
+                        yield_value(CommandResponse::Error(format!("{}: interactive commands cannot be used in a pipeline", stage_name))).await;
+                        return;
+                    }
+
+                    CommandResponse::Error(err) => {
+                        // Errors stop the pipeline at this stage
+                        yield_value(CommandResponse::Error(format!("{}: {}", stage_name, err))).await;
+                        return;
+                    }
+
+                    CommandResponse::Message(msg) => {
+                        yield_value(CommandResponse::Message(msg)).await;
+                    }
+                }
+            }
+
+            // Every stage after the first must read from the preceding stage
+            if stage_idx > 0 && input.is_some() {
+                yield_value(CommandResponse::Error(format!("{}: command does not accept piped input", stage_name))).await;
+            }
+        }).boxed()
+    }
+
+    ///
+    /// Evaluates a request, sending any commands in it directly to the specified target instead of the session's usual target
+    ///
+    /// This is used for the `@program command` syntax, and bypasses the command dispatcher so that a particular program can be
+    /// chosen when more than one program implements a command with the same name.
+    ///
+    pub async fn for_target<'a>(&'a self, target: StreamTarget, request: CommandRequest, context: &'a SceneContext) -> BoxStream<'a, CommandResponse> {
+        // Commands sent to a specific program are converted so that they can be read by a launcher directly
+        let target = match target {
+            StreamTarget::Program(program_id)   => JsonCommand::direct_target(program_id),
+            other                               => other,
+        };
+
+        // The session for the target shares its state with this session
+        let target_session = CommandSession { target, ..self.clone() };
+
+        generator_stream(move |yield_value| async move {
+            let mut responses = target_session.evaluate_request(request, context).await;
+
+            while let Some(response) = responses.next().await {
+                yield_value(response).await;
+            }
+        }).boxed()
+    }
+
+    ///
+    /// Evaluates a block of requests in order, stopping at the first request that generates an error
+    ///
+    pub async fn block<'a>(&'a self, requests: Vec<CommandRequest>, context: &'a SceneContext) -> BoxStream<'a, CommandResponse> {
+        generator_stream(move |yield_value| async move {
+            for request in requests {
+                let mut responses = self.evaluate_request(request, context).await;
+
+                while let Some(response) = responses.next().await {
+                    let is_error = matches!(response, CommandResponse::Error(_));
+                    yield_value(response).await;
+
+                    if is_error { return; }
+                }
+            }
+        }).boxed()
+    }
+
+    ///
+    /// Evaluates an `if` request, running the `then` request if the condition is 'truthy' and the `otherwise` request if it is not
+    ///
+    /// `null`, `false`, `0`, and empty strings, arrays and objects are all treated as false, and any other value is treated as true.
+    ///
+    pub async fn if_request<'a>(&'a self, condition: ParsedJson, then: CommandRequest, otherwise: Option<CommandRequest>, context: &'a SceneContext) -> BoxStream<'a, CommandResponse> {
+        use serde_json::Value;
+
+        let condition = match self.substitute_variables(condition, context).await {
+            Ok(condition)   => condition,
+            Err(err)        => { return stream::iter(iter::once(err)).boxed(); }
+        };
+
+        let is_true = match condition {
+            Value::Null         => false,
+            Value::Bool(val)    => val,
+            Value::Number(num)  => num.as_f64() != Some(0.0),
+            Value::String(val)  => !val.is_empty(),
+            Value::Array(val)   => !val.is_empty(),
+            Value::Object(val)  => !val.is_empty(),
+        };
+
+        if is_true {
+            self.evaluate_request(then, context).await
+        } else if let Some(otherwise) = otherwise {
+            self.evaluate_request(otherwise, context).await
+        } else {
+            stream::empty().boxed()
+        }
+    }
+
+    ///
+    /// Evaluates a `foreach` request, which assigns each of the values in an array to a variable in turn and runs a request for each one
+    ///
+    /// The loop stops at the first error generated by the body.
+    ///
+    pub async fn foreach<'a>(&'a self, variable: VariableName, values: ParsedJson, body: CommandRequest, context: &'a SceneContext) -> BoxStream<'a, CommandResponse> {
+        let values = match self.substitute_variables(values, context).await {
+            Ok(serde_json::Value::Array(values))    => values,
+            Ok(other)                               => { return stream::iter(iter::once(CommandResponse::Error(format!("foreach can only iterate over arrays (not {})", other)))).boxed(); }
+            Err(err)                                => { return stream::iter(iter::once(err)).boxed(); }
+        };
+
+        generator_stream(move |yield_value| async move {
+            let VariableName(variable) = variable;
+
+            for value in values {
+                self.set_variable(variable.clone(), value);
+                let mut responses = self.evaluate_request(body.clone(), context).await;
+
+                while let Some(response) = responses.next().await {
+                    let is_error = matches!(response, CommandResponse::Error(_));
+                    yield_value(response).await;
+
+                    if is_error { return; }
+                }
+            }
+        }).boxed()
+    }
+
     ///
     /// Evaluates a raw JSON request
     ///
@@ -214,24 +920,44 @@ impl CommandSession {
     /// Runs a command, returning the response
     ///
     pub async fn run_command<'a>(&'a self, command: CommandName, parameter: ParsedJson, context: &SceneContext) -> BoxStream<'a, CommandResponse> {
+        // Retrieve the target for the commands
+        let target = self.target.clone();
+
+        // Check that this session is allowed to run this command
+        let CommandName(command_name)   = &command;
+        let permission                  = self.permissions.lock().unwrap().check(command_name, &target);
+
+        // `watch` needs to know which variables its parameter uses, so it's run before the variables are substituted
+        if command_name == WATCH_COMMAND {
+            return match permission {
+                Ok(_)       => self.watch(parameter, context),
+                Err(err)    => stream::iter(iter::once(CommandResponse::Error(format!("Cannot run `{}` ({:?})", command_name, err)))).boxed(),
+            };
+        }
+
         let parameter = match self.substitute_variables(parameter, context).await {
             Ok(json) => json,
             Err(err) => { return stream::iter(iter::once(err)).boxed(); }
         };
 
-        // Retrieve the target for the commands
-        let target = self.target.clone();
+        // Commands like `send` and `connect` can reach the subprograms named in their arguments, so the session needs permission for those too
+        let permission = permission.and_then(|_| self.permissions.lock().unwrap().check_targets(standard_command_targets(command_name, &parameter)));
 
         // Check for a variable matching this command name
-        let variable_value = {
-            let variables                   = self.variables.lock().unwrap();
-            let CommandName(command_name)   = &command;
-            variables.get(command_name).cloned()
-        };
+        let variable_value              = self.variables.lock().unwrap().get(command_name).cloned();
 
         if let Some(variable_value) = variable_value {
             // Variables replace commands (even with parameters), so if a variable is defined, this is the value
             stream::iter(iter::once(CommandResponse::Json(variable_value))).boxed()
+        } else if let (Some(authenticator), true) = (&self.authenticator, command_name == AUTHENTICATE_COMMAND) {
+            // Sessions with an authenticator can supply a token to change their permissions
+            stream::iter(iter::once(self.authenticate(authenticator, parameter))).boxed()
+        } else if let Err(err) = permission {
+            // The session does not have permission to run this command
+            stream::iter(iter::once(CommandResponse::Error(format!("Cannot run `{}` ({:?})", command_name, err)))).boxed()
+        } else if command_name == VARS_COMMAND {
+            // The variables belong to the session, so the session runs this command itself
+            self.vars(parameter)
         } else {
             // Create the command query
             let command = JsonCommand::new((), command, parameter, context.current_program_id());
@@ -255,9 +981,11 @@ impl CommandSession {
     ///   * A JSON stream will initially assign 'null' to the variable and then assign whatever is the most recent message to the variable (so this can be used to 
     ///     represent an updating state). A message is generated to indicate that this has happened.
     ///
+    /// The stream in the second case is read by a subprogram, which stops when the variable is assigned again or the session finishes.
+    ///
     /// Errors will short-circuit the assignment (ie, we'll display the error and any results will be left out)
     ///
-    pub async fn assign<'a>(&'a self, variable: impl Into<String>, response: BoxStream<'a, CommandResponse>) -> BoxStream<'a, CommandResponse> {
+    pub async fn assign<'a>(&'a self, variable: impl Into<String>, response: BoxStream<'a, CommandResponse>, context: &'a SceneContext) -> BoxStream<'a, CommandResponse> {
         let variable = variable.into();
 
         // The assignment happens when the response reader reaches the appropriate point
@@ -272,7 +1000,18 @@ impl CommandSession {
                     Some(CommandResponse::Json(value)) => {
                         // Assign this value to the variable
                         yield_value(CommandResponse::Message(format!("Result assigned to `{}`", variable))).await;
-                        self.variables.lock().unwrap().insert(variable, value);
+                        self.set_variable(variable, value);
+                        break;
+                    }
+
+                    Some(CommandResponse::BackgroundStream(values)) => {
+                        // The variable is null until the first value arrives
+                        self.set_variable(variable.clone(), serde_json::Value::Null);
+
+                        match self.start_reactive_variable(variable.clone(), values, context).await {
+                            Ok(())  => { yield_value(CommandResponse::Message(format!("`{}` will be updated as new values arrive", variable))).await; }
+                            Err(err) => { yield_value(CommandResponse::Error(format!("Could not update `{}` from a background stream: {:?}", variable, err))).await; }
+                        }
                         break;
                     }
 
@@ -302,6 +1041,158 @@ impl CommandSession {
         }).boxed()
     }
 
+    ///
+    /// Starts a subprogram that sets a variable to each of the values from a background stream in turn
+    ///
+    async fn start_reactive_variable(&self, variable: String, values: BoxStream<'static, serde_json::Value>, context: &SceneContext) -> Result<(), ConnectionError> {
+        let (abort_handle, abort_registration)  = AbortHandle::new_pair();
+        let session                             = self.clone();
+        let name                                = variable.clone();
+
+        context.send_message(SceneControl::start_program(SubProgramId::new(), move |_: InputStream<()>, _context| async move {
+            let update_variable = async {
+                let mut values = values;
+
+                while let Some(value) = values.next().await {
+                    session.update_variable(name.clone(), value);
+                }
+            };
+
+            Abortable::new(update_variable, abort_registration).await.ok();
+        }, 0)).await?;
+
+        // Any stream that was already updating this variable was stopped when it was set to null
+        self.reactive_variables.lock().unwrap().insert(variable, abort_handle);
+
+        Ok(())
+    }
+
+    ///
+    /// Runs the `watch` command, which returns a background stream that evaluates an expression every time one of the variables it uses changes
+    ///
+    /// For example, `watch :state` will send the new value of `:state` every time it's updated, and `watch { "a": :a, "b": :b }`
+    /// will send a new object whenever either `:a` or `:b` changes. The current value of the expression is sent immediately.
+    ///
+    fn watch(&self, expression: ParsedJson, context: &SceneContext) -> BoxStream<'static, CommandResponse> {
+        // Find the variables that the expression depends on
+        let mut dependencies = HashSet::new();
+        Self::find_variables(&expression, &mut dependencies);
+
+        if dependencies.is_empty() {
+            return stream::iter(iter::once(CommandResponse::Error(format!("`{}` requires an expression that uses at least one variable", WATCH_COMMAND)))).boxed();
+        }
+
+        // Start watching before the first value is evaluated, so no changes are missed
+        let changes = self.watch_variables();
+        let session = self.clone();
+        let context = context.clone();
+
+        let values = generator_stream(move |yield_value| async move {
+            let mut changes = changes
+                .filter(move |CommandVariable(name, _)| future::ready(dependencies.contains(name)));
+
+            loop {
+                // Expressions that can't be evaluated (for example, because a variable has been removed) are skipped
+                if let Ok(value) = session.substitute_variables(expression.clone(), &context).await {
+                    yield_value(value).await;
+                }
+
+                if changes.next().await.is_none() { break; }
+            }
+        });
+
+        stream::iter(iter::once(CommandResponse::BackgroundStream(values.boxed()))).boxed()
+    }
+
+    ///
+    /// Finds the names of the variables used in a JSON expression
+    ///
+    fn find_variables(expression: &ParsedJson, variables: &mut HashSet<String>) {
+        match expression {
+            ParsedJson::Variable(name)  => { variables.insert(name.clone()); }
+            ParsedJson::Array(values)   => { values.iter().for_each(|value| Self::find_variables(value, variables)); }
+            ParsedJson::Object(values)  => { values.values().for_each(|value| Self::find_variables(value, variables)); }
+            _                           => { }
+        }
+    }
+
+    ///
+    /// Runs the commands from the socket as a script
+    ///
+    /// No prompts are displayed, and the script stops at the first command that generates an error (commands that start with '-' can
+    /// generate errors without stopping the script). The script finishes successfully when the end of the input is reached between
+    /// commands: a script that ends partway through a command is a parse error.
+    ///
+    pub async fn run_script(&self, context: SceneContext) -> Result<(), CommandScriptError> {
+        // Take the socket from inside the object
+        let mut socket = self.socket.lock().unwrap().take().unwrap();
+        socket.set_interactive(false);
+
+        loop {
+            let next_command = match socket.next_request().await {
+                Ok(next_command)                                => next_command,
+                Err(CommandParseError::EndOfInput)              => { return Ok(()); }
+                Err(err)                                        => {
+                    socket.notify(CommandNotification::Error(format!("Could not parse command: {:?}", err))).await.ok();
+                    return Err(CommandScriptError::ParseError(err));
+                }
+            };
+
+            // Run the command, and stop if it generates an error
+            let mut first_error         = None;
+            let command_responses       = self.evaluate_request(next_command, &context).await
+                .inspect(|response| {
+                    if let (CommandResponse::Error(err), None) = (response, &first_error) {
+                        first_error = Some(err.clone());
+                    }
+                });
+
+            if socket.send_responses(command_responses).await.is_err() {
+                return Err(CommandScriptError::OutputClosed);
+            }
+
+            if let Some(error) = first_error {
+                return Err(CommandScriptError::CommandFailed(error));
+            }
+        }
+    }
+
+    ///
+    /// Returns the words that can be used for tab completion in this session
+    ///
+    /// These are the commands supported by the command target, the names of the variables in this session, and the names of the
+    /// subprograms and message types in the scene.
+    ///
+    pub async fn completions(&self, context: &SceneContext) -> Vec<String> {
+        let mut completions = vec![];
+
+        // Ask the command target for the commands that it supports
+        let mut commands = self.run_command(CommandName(LIST_COMMANDS.into()), ParsedJson::Null, context).await;
+        while let Some(response) = commands.next().await {
+            if let Ok(ListCommandResponse(commands)) = response.try_into() {
+                completions.extend(commands.into_iter().map(|command| command.name));
+            }
+        }
+
+        // Variables, and the command that manages them
+        completions.extend(self.variables.lock().unwrap().keys().cloned());
+        completions.push(VARS_COMMAND.into());
+
+        // Subprograms and the message types that they accept
+        if let Ok(updates) = context.spawn_query(ReadCommand::default(), Query::<SceneUpdate>::with_no_target(), *SCENE_CONTROL_PROGRAM) {
+            let mut updates = updates;
+
+            while let Some(update) = updates.next().await {
+                if let SceneUpdate::Started(program_id, input_stream_id) = update {
+                    completions.extend(program_id.name());
+                    completions.extend(input_stream_id.serialization_type_name());
+                }
+            }
+        }
+
+        completions
+    }
+
     ///
     /// Runs the command session program
     ///
@@ -310,6 +1201,7 @@ impl CommandSession {
         let input_variables = Arc::clone(&self.variables);
         let run_context     = context;
         let input_context   = run_context.clone();
+        let changes         = self.watch_variables();
 
         // Take the socket from inside the object
         let mut socket = self.socket.lock().unwrap().take().unwrap();
@@ -318,9 +1210,18 @@ impl CommandSession {
         let run_commands = async move {
             let context     = run_context;
 
-            while let Ok(next_command) = socket.next_request().await {
+            // The startup file is run before the first command is read
+            self.run_startup_file(&mut socket, &context).await;
+
+            loop {
+                // Update the tab completions if the socket is editing lines for a terminal
+                if socket.is_line_editing() {
+                    socket.set_completions(self.completions(&context).await);
+                }
+
                 // Read the next command and decide on the response
-                let command_responses = self.evaluate_request(next_command, &context).await;
+                let next_command        = if let Ok(next_command) = socket.next_request().await { next_command } else { break; };
+                let command_responses   = self.evaluate_request(next_command, &context).await;
 
                 // Send the responses to the socket
                 if socket.send_responses(command_responses).await.is_err() {
@@ -331,15 +1232,33 @@ impl CommandSession {
 
         // Create another future that processes command requests
         let process_input = async move {
-            let variables   = input_variables;
-            let context     = input_context;
+            let variables       = input_variables;
+            let context         = input_context;
+            let mut subscribers = EventSubscribers::<CommandVariable>::new();
+
+            // The changes to the variables are sent to the subscribers, and the session stops when the input stream ends (the `None` item)
+            let input   = input.map(|request| Either::Left(Some(request))).chain(stream::once(future::ready(Either::Left(None))));
+            let input   = stream::select(input, changes.map(Either::Right));
 
             pin_mut!(input);
             while let Some(request) = input.next().await {
+                let request = match request {
+                    Either::Left(Some(request)) => request,
+                    Either::Left(None)          => { break; }
+                    Either::Right(variable)     => {
+                        subscribers.send(variable).await;
+                        continue;
+                    }
+                };
+
                 match request {
                     CommandSessionRequest::SetVariable(name, value) => {
                         // Just set the variable immediately
-                        variables.lock().unwrap().insert(name, value);
+                        self.set_variable(name, value);
+                    }
+
+                    CommandSessionRequest::Subscribe(target) => {
+                        subscribers.subscribe(&context, target);
                     }
 
                     CommandSessionRequest::QueryVariable(name, target) => {
@@ -368,8 +1287,11 @@ impl CommandSession {
             }
         };
 
-        // The session runs until either of the two futures terminates
+        // The session runs until either of the two futures terminates, and then saves its variables if it has a profile
         future::select(Box::pin(run_commands), Box::pin(process_input))
-            .map(|_| ())
+            .map(move |_| {
+                self.stop_reactive_variables();
+                self.save_active_profile();
+            })
     }
 }
@@ -0,0 +1,93 @@
+use super::command_program::*;
+use super::command_socket::*;
+use super::parse_command::*;
+use crate::socket::*;
+
+use flo_scene::*;
+use flo_scene::programs::*;
+
+use futures::prelude::*;
+use futures::channel::oneshot;
+
+///
+/// The ways that a command script can fail
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CommandScriptError {
+    /// The script could not be parsed
+    ParseError(CommandParseError),
+
+    /// A command in the script generated an error
+    CommandFailed(String),
+
+    /// The output for the script was closed before it finished
+    OutputClosed,
+}
+
+impl CommandScriptError {
+    ///
+    /// The status code that a process should exit with if a script stops with this error
+    ///
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CommandScriptError::CommandFailed(_)    => 1,
+            CommandScriptError::ParseError(_)       => 2,
+            CommandScriptError::OutputClosed        => 3,
+        }
+    }
+}
+
+///
+/// Runs a command script in a scene, sending its output to the specified sink
+///
+/// The script is run by a `CommandSession` using the same syntax as the interactive command socket, except that no prompts are
+/// displayed, and the script will stop at the first command that fails.
+///
+pub async fn run_command_script(context: &SceneContext, script: impl 'static + Send + Stream<Item=Vec<u8>>, command_target: impl Into<StreamTarget>, output: impl Send + Sink<CommandData>) -> Result<(), CommandScriptError> {
+    // Create a socket connection that reads from the script
+    let (send_output, recv_output)  = oneshot::channel();
+    let connection                  = SocketConnection::new(context, script.map(CommandData), move |_context, output| { send_output.send(output).ok(); });
+    let socket                      = CommandSocket::connect(connection);
+    let output_stream               = recv_output.await.map_err(|_| CommandScriptError::OutputClosed)?;
+
+    // Run the script and write the output at the same time
+    let session         = CommandSession::new(socket, command_target.into());
+    let run_script      = session.run_script(context.clone());
+    let write_output    = output_stream.map(Ok).forward(output);
+
+    let (result, _) = future::join(run_script, write_output).await;
+
+    result
+}
+
+///
+/// A program that accepts connections from a socket and runs whatever is sent to them as a script
+///
+/// This works like `command_connection_program()`, except that no prompts are displayed, and the connection is closed after the
+/// script finishes or the first command that fails.
+///
+pub async fn script_connection_program(input: InputStream<CommandProgramSocketMessage>, context: SceneContext, command_target: impl Into<StreamTarget>) {
+    let command_target = command_target.into();
+
+    // Spawn session tasks for each connection
+    let mut input = input;
+    while let Some(connection) = input.next().await {
+        match connection {
+            SocketMessage::Connection(connection) => {
+                // Connect the command socket
+                let socket          = CommandSocket::connect(connection);
+                let command_target  = command_target.clone();
+
+                // Spawn a subprogram to run the script
+                let script_session_id = SubProgramId::new();
+                context.send_message(SceneControl::start_program(
+                    script_session_id,
+                    move |_: InputStream<()>, context| async move {
+                        let command_session = CommandSession::new(socket, command_target);
+                        command_session.run_script(context).await.ok();
+                    },
+                    0)).await.ok();
+            }
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use crate::parser::*;
 use crate::socket::*;
 use crate::commands::command_stream::*;
+use crate::commands::json_rpc::*;
+use crate::commands::line_editor::*;
 
 use futures::prelude::*;
 use futures::future::{BoxFuture};
@@ -14,11 +16,12 @@ use serde_json;
 use std::collections::{HashMap};
 use std::iter;
 use std::sync::*;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 ///
 /// Data intended to be sent to a command socket (a command socket sends and receives the bytes directly)
 ///
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
 pub struct CommandData(pub Vec<u8>);
 
 impl From<&str> for CommandData {
@@ -68,6 +71,18 @@ pub enum CommandNotification {
     EndMode(String),
 }
 
+///
+/// The protocols that a command socket can use to talk to its client
+///
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CommandProtocol {
+    /// Human-readable protocol, with prompts and formatted responses
+    Text,
+
+    /// Machine-readable protocol, where every request, response and notification is a JSON-RPC 2.0 object on a single line
+    JsonRpc,
+}
+
 
 ///
 /// A command socket manages the socket connection for a command
@@ -91,6 +106,27 @@ pub struct CommandSocket {
 
     /// The next handle to apply to a background stream
     next_background_stream_handle: usize,
+
+    /// True if this socket is being used interactively (false if it's running a script, in which case no prompts are displayed)
+    interactive: bool,
+
+    /// The protocol used to communicate with the client
+    protocol: CommandProtocol,
+
+    /// True once the first request has been read from this socket (the protocol can only be negotiated by the first request)
+    received_request: bool,
+
+    /// In JSON-RPC mode, the ID of the request that is currently being processed
+    request_id: Option<serde_json::Value>,
+
+    /// The line editor, if the socket is performing line editing for a terminal
+    line_editor: Option<LineEditor>,
+
+    /// Set to true if '\n' characters should be sent as '\r\n' (needed when the client terminal is in raw mode)
+    translate_newlines: Arc<AtomicBool>,
+
+    /// Information about the process at the other end of the socket
+    peer: SocketPeer,
 }
 
 impl CommandSocket {
@@ -100,7 +136,14 @@ impl CommandSocket {
     pub fn connect(connection: SocketConnection<CommandData, CommandData>) -> Self {
         // Finish the connection to create the CommandSocket structure
         let (send_output, recv_output) = mpsc::channel(0);
-        let input_stream = connection.connect(recv_output);
+
+        // Terminals in raw mode need '\r\n' to start a new line, which we supply while line editing is turned on
+        let translate_output    = Arc::new(AtomicBool::new(false));
+        let translate           = Arc::clone(&translate_output);
+        let recv_output         = recv_output.map(move |data| if translate.load(Ordering::Relaxed) { Self::crlf(data) } else { data });
+
+        let socket_peer     = connection.peer().clone();
+        let input_stream    = connection.connect(recv_output);
 
         Self {
             buffer:                         vec![],
@@ -108,28 +151,163 @@ impl CommandSocket {
             output_stream:                  send_output,
             background_json_streams:        HashMap::new(),
             next_background_stream_handle:  0,
+            interactive:                    true,
+            protocol:                       CommandProtocol::Text,
+            received_request:               false,
+            request_id:                     None,
+            line_editor:                    None,
+            translate_newlines:             translate_output,
+            peer:                           socket_peer,
+        }
+    }
+
+    ///
+    /// Returns the information about the process at the other end of this socket
+    ///
+    pub fn peer(&self) -> &SocketPeer {
+        &self.peer
+    }
+
+    ///
+    /// Converts any bare '\n' characters in some command data to '\r\n'
+    ///
+    fn crlf(CommandData(data): CommandData) -> CommandData {
+        let mut result  = Vec::with_capacity(data.len());
+        let mut last    = 0;
+
+        for byte in data {
+            if byte == b'\n' && last != b'\r' {
+                result.push(b'\r');
+            }
+
+            result.push(byte);
+            last = byte;
+        }
+
+        CommandData(result)
+    }
+
+    ///
+    /// Sets whether or not this socket is interactive. Sockets are interactive by default: non-interactive sockets do not display
+    /// a prompt when waiting for the next command, which is useful when running scripts.
+    ///
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    ///
+    /// Sets the protocol that this socket uses to talk to its client
+    ///
+    /// Sockets use the text protocol by default. Clients can also switch to the JSON-RPC protocol by sending a JSON-RPC request
+    /// as the first request on the socket.
+    ///
+    pub fn set_protocol(&mut self, protocol: CommandProtocol) {
+        self.protocol = protocol;
+    }
+
+    ///
+    /// Returns the protocol that this socket is using to talk to its client
+    ///
+    pub fn protocol(&self) -> CommandProtocol {
+        self.protocol
+    }
+
+    ///
+    /// Starts editing lines for a client that is a terminal in raw mode
+    ///
+    /// The socket will echo what the user types, with support for history and tab completion. The client is notified with the 'EDIT' mode
+    /// so it can switch its terminal into raw mode.
+    ///
+    pub async fn start_line_editing(&mut self, line_editor: LineEditor) {
+        self.notify(CommandNotification::StartMode("EDIT".into())).await.ok();
+
+        self.line_editor = Some(line_editor);
+        self.translate_newlines.store(true, Ordering::Relaxed);
+    }
+
+    ///
+    /// Stops editing lines, returning the line editor that was in use
+    ///
+    pub async fn stop_line_editing(&mut self) -> Option<LineEditor> {
+        let line_editor = self.line_editor.take();
+
+        if line_editor.is_some() {
+            self.translate_newlines.store(false, Ordering::Relaxed);
+            self.notify(CommandNotification::EndMode("EDIT".into())).await.ok();
         }
+
+        line_editor
+    }
+
+    ///
+    /// True if this socket is editing lines for the client
+    ///
+    pub fn is_line_editing(&self) -> bool {
+        self.line_editor.is_some()
+    }
+
+    ///
+    /// Sets the words that the line editor can use for tab completion
+    ///
+    pub fn set_completions(&mut self, completions: impl IntoIterator<Item=String>) {
+        if let Some(line_editor) = &mut self.line_editor {
+            line_editor.set_completions(completions);
+        }
+    }
+
+    ///
+    /// Creates a stream that passes the input through a line editor, echoing the results to the output
+    ///
+    fn edited_input<'a>(input: &'a mut BoxStream<'static, CommandData>, line_editor: &'a mut LineEditor, echo: mpsc::Sender<CommandData>) -> impl 'a + Send + Stream<Item=Vec<u8>> {
+        stream::unfold((input, line_editor, echo), |(input, line_editor, mut echo)| async move {
+            loop {
+                if line_editor.is_closed() { return None; }
+
+                let CommandData(data)   = input.next().await?;
+                let output              = line_editor.input(&data);
+
+                if !output.echo.is_empty() {
+                    echo.send(CommandData(output.echo)).await.ok();
+                }
+
+                if !output.lines.is_empty() {
+                    let lines = output.lines.into_iter()
+                        .flat_map(|line| line.into_bytes().into_iter().chain(iter::once(b'\n')))
+                        .collect::<Vec<_>>();
+
+                    return Some((lines, (input, line_editor, echo)));
+                }
+            }
+        })
     }
 
     ///
     /// Reads the next request from the input stream
     ///
+    /// Returns `CommandParseError::EndOfInput` once the input has finished and there are no more requests to read.
+    ///
     pub async fn next_request(&mut self) -> Result<CommandRequest, CommandParseError> {
         use std::mem;
 
-        // Before a request is made, we always generate a prompt
-        self.notify(CommandNotification::Prompt).await.ok();
+        if self.protocol == CommandProtocol::JsonRpc {
+            return self.next_json_rpc_request().await;
+        }
 
-        // Borrow the background streams so we can monitor them
-        let background_json_streams = &mut self.background_json_streams;
-        let output_stream           = &mut self.output_stream;
+        // Before a request is made, we generate a prompt if this is an interactive socket
+        if self.interactive {
+            self.notify(CommandNotification::Prompt).await.ok();
+        }
 
-        // The input is whatever we have in the buffer + what we can read from the input stream
+        // The input is whatever we have in the buffer + what we can read from the input stream (via the line editor if there is one)
         let mut buffer      = vec![];
         mem::swap(&mut buffer, &mut self.buffer);
 
         let input           = &mut self.input_stream;
-        let input           = stream::iter(iter::once(buffer)).chain(input.map(|CommandData(data)| data));
+        let input           = if let Some(line_editor) = &mut self.line_editor {
+            stream::iter(iter::once(buffer)).chain(Self::edited_input(input, line_editor, self.output_stream.clone())).boxed()
+        } else {
+            stream::iter(iter::once(buffer)).chain(input.map(|CommandData(data)| data)).boxed()
+        };
 
         // Set up a tokenizer and parser for the input
         let mut tokenizer   = Tokenizer::new(input);
@@ -137,19 +315,123 @@ impl CommandSocket {
 
         tokenizer.with_command_matchers();
 
-        // Start reading the next command
-        let next_command = command_parse(&mut parser, &mut tokenizer);
+        // Read the next command while monitoring the background streams
+        let next_command = command_parse_next(&mut parser, &mut tokenizer);
+        let next_command = Self::monitor_background_streams(self.protocol, &mut self.background_json_streams, &mut self.output_stream, next_command).await;
 
+        // Convert the tokenizer back to a buffer
+        let buffer  = tokenizer.to_u8_lookahead();
+        self.buffer = buffer;
+
+        // Fetch the matched command from the parser
+        let command = match next_command {
+            Ok(())      => parser.finish()?,
+            Err(err)    => { return Err(err); }
+        };
+
+        // If the first request is a JSON-RPC request, then the client is switching to the JSON-RPC protocol
+        let is_first_request    = !self.received_request;
+        self.received_request   = true;
+
+        match command {
+            CommandRequest::RawJson { value: ParsedJson::Object(fields) } if is_first_request && fields.contains_key("jsonrpc") => {
+                self.protocol = CommandProtocol::JsonRpc;
+
+                // The prompt has already been written, so end that line so that every JSON-RPC object is on a line of its own
+                self.output_stream.send("\n".into()).await.ok();
+
+                match JsonRpcRequest::from_json(ParsedJson::Object(fields).into()).await {
+                    Ok(request) => {
+                        self.request_id = request.id;
+                        Ok(request.request)
+                    }
+
+                    Err(err) => {
+                        self.output_stream.send(json_rpc_line(&err.to_json())).await.ok();
+                        self.next_json_rpc_request().await
+                    }
+                }
+            }
+
+            command => Ok(command)
+        }
+    }
+
+    ///
+    /// Reads the next request in JSON-RPC format from the input stream
+    ///
+    /// Invalid requests are reported to the client and skipped. The input stream cannot be recovered after a JSON syntax error,
+    /// so those are returned as errors after they have been reported.
+    ///
+    async fn next_json_rpc_request(&mut self) -> Result<CommandRequest, CommandParseError> {
+        use std::mem;
+
+        self.received_request = true;
+
+        loop {
+            // The input is whatever we have in the buffer + what we can read from the input stream
+            let mut buffer      = vec![];
+            mem::swap(&mut buffer, &mut self.buffer);
+
+            let input           = &mut self.input_stream;
+            let input           = stream::iter(iter::once(buffer)).chain(input.map(|CommandData(data)| data));
+
+            // Requests are read as JSON values
+            let mut tokenizer   = Tokenizer::<JsonToken, _>::new(input);
+            let mut parser      = Parser::new();
+
+            tokenizer.with_json_matchers();
+
+            let next_value = json_parse_value(&mut parser, &mut tokenizer);
+            let next_value = Self::monitor_background_streams(self.protocol, &mut self.background_json_streams, &mut self.output_stream, next_value).await;
+
+            self.buffer = tokenizer.to_u8_lookahead();
+
+            let next_value = match next_value {
+                Ok(())                                                              => parser.finish()?,
+                Err(JsonParseError::ExpectedMoreInput(JsonInputType::StartOfValue)) => { return Err(CommandParseError::EndOfInput); },
+                Err(err)                                                            => {
+                    let error = JsonRpcError { id: serde_json::Value::Null, code: JSON_RPC_PARSE_ERROR, message: format!("{:?}", err) };
+                    self.output_stream.send(json_rpc_line(&error.to_json())).await.ok();
+
+                    return Err(err.into());
+                }
+            };
+
+            // Convert to a request
+            match JsonRpcRequest::from_json(next_value.into()).await {
+                Ok(request) => {
+                    self.request_id = request.id;
+                    return Ok(request.request);
+                }
+
+                Err(err) => {
+                    if self.output_stream.send(json_rpc_line(&err.to_json())).await.is_err() {
+                        return Err(CommandParseError::ExpectedMoreInput);
+                    }
+                }
+            }
+        }
+    }
+
+    ///
+    /// Waits for a future to complete (usually one that's reading the input stream), and sends notifications for any activity
+    /// on the background streams while it's running
+    ///
+    async fn monitor_background_streams<TFuture>(protocol: CommandProtocol, background_json_streams: &mut HashMap<usize, BoxStream<'static, serde_json::Value>>, output_stream: &mut mpsc::Sender<CommandData>, future: TFuture) -> TFuture::Output
+    where
+        TFuture: Future,
+    {
         // Wait for the result while monitoring background streams (via a fairly involved poll function)
         //  - if we're sending a notification to the output stream it must complete before we finish
         //  - we must monitor for background streams and notify when they close or produce messages
-        //  - we must finish when the 'next_command' future completes
+        //  - we must finish when the future we're monitoring completes
         let mut notify_background: Option<BoxFuture<'_, ()>> = None;
 
         let output_stream       = Arc::new(Mutex::new(Some(output_stream)));
-        let mut next_command    = Box::pin(next_command);
+        let mut future          = Box::pin(future);
 
-        let next_command = future::poll_fn(move |context| {
+        future::poll_fn(move |context| {
             // Loop while there is activity on the background streams
             loop {
                 // If we're sending a background stream notification, that has priority
@@ -204,25 +486,32 @@ impl CommandSocket {
                         // The notification takes priority, so the output stream will be returned by the time it's done
                         let in_use_output_stream    = output_stream.lock().unwrap().take().unwrap();
                         let output_stream           = Arc::clone(&output_stream);
+                        let notification            = match protocol {
+                            CommandProtocol::Text       => format!("\n=== {}\n\n", stream_id).into(),
+                            CommandProtocol::JsonRpc    => json_rpc_notification(CommandNotification::EndStream(stream_id), None).map(|json| json_rpc_line(&json)).unwrap_or_default(),
+                        };
 
                         notify_background = Some(async move {
-                            in_use_output_stream.send(format!("\n=== {}\n\n", stream_id).into()).await.ok();
+                            in_use_output_stream.send(notification).await.ok();
                             *output_stream.lock().unwrap() = Some(in_use_output_stream);
                         }.boxed());
                     }
 
                     StreamActivity::Message(stream_id, json) => {
                         // Format the JSON as a pretty-printed string (TODO: the to_writer_pretty version would be better for very long JSON)
-                        let json_string = serde_json::to_string_pretty(&json);
+                        let notification = match protocol {
+                            CommandProtocol::Text       => serde_json::to_string_pretty(&json).ok().map(|json_string| format!("\n<{} {}\n\n", stream_id, json_string).into()),
+                            CommandProtocol::JsonRpc    => json_rpc_notification(CommandNotification::StreamJson(stream_id, json), None).map(|json| json_rpc_line(&json)),
+                        };
 
-                        if let Ok(json_string) = json_string {
+                        if let Some(notification) = notification {
                             // Start notifying about the background stream activity (as stuff like the input is borrowed we can't call .notify() here)
                             // The output stream belongs to the notifier while we're notifying, will be returned because the notifier always takes priority
                             let in_use_output_stream    = output_stream.lock().unwrap().take().unwrap();
                             let output_stream           = Arc::clone(&output_stream);
 
                             notify_background = Some(async move {
-                                in_use_output_stream.send(format!("\n<{} {}\n\n", stream_id, json_string).into()).await.ok();
+                                in_use_output_stream.send(notification).await.ok();
                                 *output_stream.lock().unwrap() = Some(in_use_output_stream);
                             }.boxed());
                         }
@@ -235,25 +524,9 @@ impl CommandSocket {
                 }
             }
 
-            // Poll the next command
-            next_command.poll_unpin(context)
-        }).await;
-
-        // Convert the tokenizer back to a buffer
-        let buffer  = tokenizer.to_u8_lookahead();
-        self.buffer = buffer;
-
-        // Fetch the matched command from the parser
-        match next_command {
-            Ok(()) => {
-                let command = parser.finish()?;
-                Ok(command)
-            }
-
-            Err(err) => {
-                Err(err)
-            }
-        }
+            // Poll the future that we're monitoring
+            future.poll_unpin(context)
+        }).await
     }
 
     ///
@@ -262,8 +535,21 @@ impl CommandSocket {
     pub async fn notify(&mut self, notification: CommandNotification) -> Result<(), mpsc::SendError> {
         use CommandNotification::*;
 
+        if self.protocol == CommandProtocol::JsonRpc {
+            // Notifications are sent as JSON-RPC notification objects, tagged with the ID of the request that is being processed
+            if let Some(json) = json_rpc_notification(notification, self.request_id.as_ref()) {
+                self.output_stream.send(json_rpc_line(&json)).await?;
+            }
+
+            return Ok(());
+        }
+
         match notification {
             Prompt                      => {
+                if let Some(line_editor) = &mut self.line_editor {
+                    line_editor.set_prompt("> ");
+                }
+
                 self.output_stream.send("\n\n> ".into()).await?;
             },
 
@@ -339,6 +625,11 @@ impl CommandSocket {
                 self.notify(CommandNotification::NewStream(stream_id)).await.map_err(|_| ())
             },
 
+            CommandResponse::IoStream(_) | CommandResponse::InteractiveStream(_) if self.protocol == CommandProtocol::JsonRpc => {
+                // The socket can't be taken over by a stream when it's sending JSON-RPC messages
+                self.notify(CommandNotification::Error("Commands that read from the socket cannot be used in JSON-RPC mode".into())).await.map_err(|_| ())
+            }
+
             CommandResponse::IoStream(create_stream) => {
                 self.notify(CommandNotification::StartMode("JSON".into())).await.map_err(|_| ())?;
 
@@ -465,6 +756,10 @@ impl CommandSocket {
     pub async fn send_responses(&mut self, responses: impl Send + Stream<Item=CommandResponse>) -> Result<(), ()> {
         pin_mut!(responses);
 
+        if self.protocol == CommandProtocol::JsonRpc {
+            return self.send_json_rpc_responses(responses).await;
+        }
+
         while let Some(response) = responses.next().await {
             self.send_response(response).await?;
         }
@@ -472,6 +767,40 @@ impl CommandSocket {
         Ok(())
     }
 
+    ///
+    /// Sends the responses to a JSON-RPC request
+    ///
+    /// The JSON values generated by the command are sent as the result of the request, and the first error is sent as the error for
+    /// the request. Other responses are sent as notifications.
+    ///
+    async fn send_json_rpc_responses(&mut self, responses: impl Send + Stream<Item=CommandResponse>) -> Result<(), ()> {
+        pin_mut!(responses);
+
+        let mut results     = vec![];
+        let mut first_error = None;
+
+        while let Some(response) = responses.next().await {
+            match response {
+                CommandResponse::Json(json)                             => { results.push(json); }
+                CommandResponse::Error(error) if first_error.is_none()  => { first_error = Some(error); }
+                response                                                => { self.send_response(response).await?; }
+            }
+        }
+
+        // Requests without an ID are notifications, which don't get a response
+        if let Some(request_id) = self.request_id.take() {
+            let response = if let Some(error) = first_error {
+                JsonRpcError { id: request_id, code: JSON_RPC_COMMAND_ERROR, message: error }.to_json()
+            } else {
+                json_rpc_result(request_id, results)
+            };
+
+            self.output_stream.send(json_rpc_line(&response)).await.map_err(|_| ())?;
+        }
+
+        Ok(())
+    }
+
     ///
     /// Takes over the socket to send a stream of raw JSON data
     ///
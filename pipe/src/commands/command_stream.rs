@@ -12,6 +12,7 @@ use serde::{Deserialize, Serialize};
 use serde_json;
 
 use std::fmt;
+use std::iter;
 use std::fmt::{Debug, Formatter};
 
 ///
@@ -40,13 +41,20 @@ pub enum CommandArgument {
 /// Commands have the format `<CommandName> <Argument>`, where the command name is an identifier and the arguments is a single
 /// JSON value (multiple values can be passed by chained together commands using '|' operator)
 ///
+/// Scripts can also use `if <value> { ... } else { ... }` and `foreach :variable in <value> { ... }` to control which
+/// commands are run. A command that starts with '-' will not stop a script or a block if it fails.
+///
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CommandRequest {
-    Command     { command: CommandName, argument: ParsedJson },
-    RawJson     { value: ParsedJson },
-    Pipe        { from: Box<CommandRequest>, to: Box<CommandRequest> },
-    Assign      { variable: VariableName, from: Box<CommandRequest> },
-    ForTarget   { target: StreamTarget, request: Box<CommandRequest> },
+    Command         { command: CommandName, argument: ParsedJson },
+    RawJson         { value: ParsedJson },
+    Pipe            { from: Box<CommandRequest>, to: Box<CommandRequest> },
+    Assign          { variable: VariableName, from: Box<CommandRequest> },
+    ForTarget       { target: StreamTarget, request: Box<CommandRequest> },
+    Block           { requests: Vec<CommandRequest> },
+    If              { condition: ParsedJson, then: Box<CommandRequest>, otherwise: Option<Box<CommandRequest>> },
+    ForEach         { variable: VariableName, values: ParsedJson, body: Box<CommandRequest> },
+    ContinueOnError { request: Box<CommandRequest> },
 }
 
 ///
@@ -238,6 +246,32 @@ impl CommandRequest {
 
         Ok(parser.finish()?)
     }
+
+    ///
+    /// Parses all of the commands in a script
+    ///
+    /// A script that ends partway through a command will return an error.
+    ///
+    pub async fn parse_script(script: &str) -> Result<Vec<CommandRequest>, CommandParseError> {
+        let mut requests    = vec![];
+        let mut buffer      = script.as_bytes().to_vec();
+
+        loop {
+            // Each request is parsed from whatever was left over from the previous request
+            let mut parser      = Parser::new();
+            let mut tokenizer   = Tokenizer::new(stream::iter(iter::once(buffer)));
+
+            tokenizer.with_command_matchers();
+
+            match command_parse_next(&mut parser, &mut tokenizer).await {
+                Ok(())                                  => { requests.push(parser.finish()?); }
+                Err(CommandParseError::EndOfInput)      => { return Ok(requests); }
+                Err(err)                                => { return Err(err); }
+            }
+
+            buffer = tokenizer.to_u8_lookahead();
+        }
+    }
 }
 
 impl Into<String> for VariableName {
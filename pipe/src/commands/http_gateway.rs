@@ -0,0 +1,513 @@
+use super::command_stream::*;
+use super::json_command::*;
+use crate::socket::*;
+
+use flo_scene::*;
+use flo_scene::commands::*;
+use flo_scene::programs::*;
+
+use futures::prelude::*;
+use futures::channel::mpsc;
+use futures::stream::{BoxStream};
+
+/// The largest amount of header data that's accepted for a single HTTP request
+const MAX_HEADER_LENGTH: usize = 64 * 1024;
+
+/// The largest request body that's accepted for a single HTTP request
+const MAX_BODY_LENGTH: usize = 16 * 1024 * 1024;
+
+/// The path that `GET` requests are sent to in order to list the available commands
+pub const HTTP_COMMANDS_PATH: &str = "/commands";
+
+///
+/// A HTTP request received by the HTTP command gateway
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HttpRequest {
+    /// The request method (eg, `GET` or `POST`)
+    pub method: String,
+
+    /// The path that was requested (the query string is not included)
+    pub path: String,
+
+    /// The HTTP version from the request line (eg, `HTTP/1.1`)
+    pub version: String,
+
+    /// The headers for this request, with their names in lower case
+    pub headers: Vec<(String, String)>,
+
+    /// The body of the request
+    pub body: Vec<u8>,
+}
+
+///
+/// An error that prevented a HTTP request from being read
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HttpRequestError {
+    /// The status code to send back to the client (eg, 400 for a request that can't be parsed)
+    pub status: u16,
+
+    /// A description of what was wrong with the request
+    pub message: String,
+}
+
+///
+/// A response to send to a HTTP client
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum HttpResponse {
+    /// A complete response, with a status code, a content type and a body. The connection is closed afterwards if `close` is true
+    Complete { status: u16, content_type: String, body: Vec<u8>, close: bool },
+
+    /// Starts a stream of server-sent events (the connection is closed once the events are finished)
+    StartEvents,
+
+    /// A server-sent event, with an optional event name and some data
+    Event { event: Option<String>, data: String },
+}
+
+/// Socket message received by the HTTP command gateway program
+pub type HttpSocketMessage = SocketMessage<Result<HttpRequest, HttpRequestError>, HttpResponse>;
+
+impl HttpRequestError {
+    ///
+    /// Creates an error for a request that could not be understood (status 400)
+    ///
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        HttpRequestError { status: 400, message: message.into() }
+    }
+}
+
+impl HttpRequest {
+    ///
+    /// Returns the value of the header with the specified name (which should be in lower case)
+    ///
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header_name, _)| header_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    ///
+    /// True if the connection should be closed after the response to this request has been sent
+    ///
+    pub fn closes_connection(&self) -> bool {
+        match self.header("connection").map(|connection| connection.to_ascii_lowercase()) {
+            Some(connection) if connection == "close"       => true,
+            Some(connection) if connection == "keep-alive"  => false,
+            _                                               => self.version != "HTTP/1.1",
+        }
+    }
+
+    ///
+    /// Parses the headers of a HTTP request (everything up to the blank line), returning the request with an empty body
+    ///
+    fn parse_headers(header: &[u8]) -> Result<HttpRequest, String> {
+        let header      = String::from_utf8_lossy(header);
+        let mut lines   = header.split("\r\n");
+
+        // The first line is the request line, eg 'POST /commands/echo HTTP/1.1'
+        let request_line    = lines.next().unwrap_or_default();
+        let mut parts       = request_line.split(' ').filter(|part| !part.is_empty());
+
+        let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version), None) if version.starts_with("HTTP/1.") => (method, target, version),
+            _                                                                                   => { return Err(format!("Invalid request line `{}`", request_line)); }
+        };
+
+        // The remaining lines are the headers
+        let mut headers = vec![];
+        for line in lines.filter(|line| !line.is_empty()) {
+            let (name, value) = line.split_once(':').ok_or_else(|| format!("Invalid header `{}`", line))?;
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+
+        let path = target.split('?').next().unwrap_or_default();
+
+        Ok(HttpRequest {
+            method:     method.to_string(),
+            path:       percent_decode(path),
+            version:    version.to_string(),
+            headers,
+            body:       vec![],
+        })
+    }
+}
+
+impl HttpResponse {
+    ///
+    /// Creates a complete response containing a JSON value
+    ///
+    pub fn json(status: u16, json: &serde_json::Value, close: bool) -> Self {
+        HttpResponse::Complete { status, content_type: "application/json".into(), body: json.to_string().into_bytes(), close }
+    }
+
+    ///
+    /// Creates a complete response describing an error
+    ///
+    pub fn error(status: u16, message: impl Into<String>, close: bool) -> Self {
+        Self::json(status, &serde_json::json!({ "Error": message.into() }), close)
+    }
+
+    ///
+    /// Formats this response as bytes to send to the client
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            HttpResponse::Complete { status, content_type, body, close } => {
+                let connection  = if *close { "close" } else { "keep-alive" };
+                let mut bytes   = format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: {}\r\n\r\n", status, status_reason(*status), content_type, body.len(), connection).into_bytes();
+
+                bytes.extend(body);
+                bytes
+            }
+
+            HttpResponse::StartEvents => {
+                "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n".into()
+            }
+
+            HttpResponse::Event { event, data } => {
+                let mut text = String::new();
+
+                if let Some(event) = event {
+                    text.push_str(&format!("event: {}\n", event));
+                }
+                for line in data.split('\n') {
+                    text.push_str(&format!("data: {}\n", line));
+                }
+                text.push('\n');
+
+                text.into_bytes()
+            }
+        }
+    }
+}
+
+///
+/// Returns the reason phrase for a HTTP status code
+///
+fn status_reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        _   => "Unknown",
+    }
+}
+
+///
+/// Decodes the `%xx` escape sequences in a URL path
+///
+fn percent_decode(path: &str) -> String {
+    let bytes       = path.as_bytes();
+    let mut decoded = vec![];
+    let mut pos     = 0;
+
+    while pos < bytes.len() {
+        let escaped = if bytes[pos] == b'%' && pos + 2 < bytes.len() {
+            std::str::from_utf8(&bytes[(pos + 1)..(pos + 3)]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+
+        if let Some(escaped) = escaped {
+            decoded.push(escaped);
+            pos += 3;
+        } else {
+            decoded.push(bytes[pos]);
+            pos += 1;
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+///
+/// Reads HTTP requests from a stream of bytes
+///
+/// Request bodies must have a `Content-Length` header, and can be at most 16MB long. If a request can't be read, an error is generated
+/// and the stream ends (the connection can't be recovered after an invalid request). Often used with a socket, for example
+/// `start_unencrpted_tcp_socket(&scene, socket_program, address, read_http_requests, write_http_responses)`
+///
+pub fn read_http_requests(input: BoxStream<'static, Vec<u8>>) -> BoxStream<'static, Result<HttpRequest, HttpRequestError>> {
+    stream::unfold(Some((input, vec![])), |state| async move {
+        let (mut input, mut buffer) = state?;
+
+        // Read until the end of the headers
+        let header_length = loop {
+            if let Some(pos) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+                break pos + 4;
+            }
+
+            if buffer.len() > MAX_HEADER_LENGTH {
+                return Some((Err(HttpRequestError::bad_request("The request headers are too long")), None));
+            }
+
+            // The stream ends without an error if the client closes the connection between requests
+            let more = input.next().await?;
+            buffer.extend(more);
+        };
+
+        let mut request = match HttpRequest::parse_headers(&buffer[0..header_length]) {
+            Ok(request) => request,
+            Err(err)    => { return Some((Err(HttpRequestError::bad_request(err)), None)); }
+        };
+
+        if request.header("transfer-encoding").is_some() {
+            return Some((Err(HttpRequestError::bad_request("Request bodies must have a Content-Length")), None));
+        }
+
+        let body_length = match request.header("content-length").map(|length| length.parse::<usize>()) {
+            None                => 0,
+            Some(Ok(length))    => length,
+            Some(Err(_))        => { return Some((Err(HttpRequestError::bad_request("Invalid Content-Length")), None)); }
+        };
+
+        if body_length > MAX_BODY_LENGTH {
+            return Some((Err(HttpRequestError { status: 413, message: format!("Request bodies can be at most {} bytes long", MAX_BODY_LENGTH) }), None));
+        }
+
+        // Read the body
+        while buffer.len() < header_length + body_length {
+            if let Some(more) = input.next().await {
+                buffer.extend(more);
+            } else {
+                return Some((Err(HttpRequestError::bad_request("The connection was closed before the request body was received")), None));
+            }
+        }
+
+        request.body = buffer.drain(0..(header_length + body_length)).skip(header_length).collect();
+
+        Some((Ok(request), Some((input, buffer))))
+    }).boxed()
+}
+
+///
+/// Converts HTTP responses to bytes ready to be sent to a socket stream
+///
+/// The output stream is closed after a response that closes the connection is written.
+///
+pub fn write_http_responses(output: BoxStream<'static, HttpResponse>) -> BoxStream<'static, Vec<u8>> {
+    output
+        .scan(false, |closed, response| {
+            if *closed {
+                return future::ready(None);
+            }
+
+            *closed = matches!(response, HttpResponse::Complete { close: true, .. });
+            future::ready(Some(response.to_bytes()))
+        })
+        .boxed()
+}
+
+///
+/// The HTTP command gateway program accepts connections from a socket that reads HTTP requests, and runs them as commands
+///
+/// The socket should use `read_http_requests` and `write_http_responses`. These requests are supported:
+///
+///  * `POST /commands/<name>` runs the named command, with the JSON body of the request as its parameter (or null if there's no body)
+///  * `GET /commands` runs the `list_commands` command
+///
+/// Commands are sent to the command target as `JsonCommand` requests (use `()` to send them via the default dispatcher). The response
+/// is a JSON array containing each `CommandResponse` generated by the command: for example, `[{"Json":42}]`. The status code is 404 if
+/// the command could not be found, and 500 if the command generated any other error. If the command returns a background stream, the
+/// response is instead sent as server-sent events: each value from the stream is sent as an event whose data is the JSON value, and any
+/// other responses are sent as `response` events.
+///
+/// A typical set up for the gateway is:
+///
+/// ```text
+/// start_unencrpted_tcp_socket(&scene, socket_program, ("127.0.0.1", 8080), read_http_requests, write_http_responses)?;
+/// scene.add_subprogram(gateway_program, |input, context| http_command_gateway_program(input, context, ()), 0);
+/// scene.connect_programs(socket_program, gateway_program, StreamId::with_message_type::<HttpSocketMessage>())?;
+/// ```
+///
+pub async fn http_command_gateway_program(input: InputStream<HttpSocketMessage>, context: SceneContext, command_target: impl Into<StreamTarget>) {
+    let command_target = command_target.into();
+
+    // Spawn a subprogram to handle the requests for each connection
+    let mut input = input;
+    while let Some(connection) = input.next().await {
+        match connection {
+            SocketMessage::Connection(connection) => {
+                let command_target = command_target.clone();
+
+                let connection_id = SubProgramId::new();
+                context.send_message(SceneControl::start_program(
+                    connection_id,
+                    move |_: InputStream<()>, context| async move {
+                        let (mut send_responses, recv_responses)    = mpsc::channel(16);
+                        let mut requests                            = connection.connect(recv_responses);
+
+                        while let Some(request) = requests.next().await {
+                            let request = match request {
+                                Ok(request) => request,
+                                Err(err)    => {
+                                    send_responses.send(HttpResponse::error(err.status, err.message, true)).await.ok();
+                                    break;
+                                }
+                            };
+
+                            // Each request is handled in turn (the connection stays open after most responses unless the client closes it)
+                            if !http_command_request(request, &command_target, &context, &mut send_responses).await {
+                                break;
+                            }
+                        }
+                    },
+                    0)).await.ok();
+            }
+        }
+    }
+}
+
+///
+/// Runs the command for a HTTP request, and sends the responses. Returns false if the connection should be closed afterwards
+///
+async fn http_command_request(request: HttpRequest, command_target: &StreamTarget, context: &SceneContext, responses: &mut mpsc::Sender<HttpResponse>) -> bool {
+    let close = request.closes_connection();
+
+    // Decide which command to run
+    let command_name = if request.path == HTTP_COMMANDS_PATH {
+        if request.method == "GET" {
+            LIST_COMMANDS.to_string()
+        } else {
+            return responses.send(HttpResponse::error(405, "Use GET to list the commands", close)).await.is_ok() && !close;
+        }
+    } else if let Some(command_name) = request.path.strip_prefix(HTTP_COMMANDS_PATH).and_then(|path| path.strip_prefix('/')).filter(|name| !name.is_empty()) {
+        if request.method == "POST" {
+            command_name.to_string()
+        } else {
+            return responses.send(HttpResponse::error(405, "Use POST to run a command", close)).await.is_ok() && !close;
+        }
+    } else {
+        return responses.send(HttpResponse::error(404, format!("`{}` is not a command path", request.path), close)).await.is_ok() && !close;
+    };
+
+    // The body is the parameter for the command
+    let parameter = if request.body.iter().all(|byte| byte.is_ascii_whitespace()) {
+        serde_json::Value::Null
+    } else {
+        match serde_json::from_slice::<serde_json::Value>(&request.body) {
+            Ok(parameter)   => parameter,
+            Err(err)        => { return responses.send(HttpResponse::error(400, format!("The request body is not valid JSON ({})", err), close)).await.is_ok() && !close; }
+        }
+    };
+
+    // Run the command
+    let command         = JsonCommand::new((), command_name.clone(), parameter, None);
+    let command_results = match context.spawn_query(ReadCommand::default(), command, command_target.clone()) {
+        Ok(command_results) => command_results,
+        Err(err)            => { return responses.send(HttpResponse::error(500, format!("Could not send command: {:?}", err), close)).await.is_ok() && !close; }
+    };
+
+    // Gather the responses, switching to server-sent events if there's a background stream
+    let not_found           = format!("{:?}", CommandError::CommandNotFound(command_name));
+    let mut results         = Vec::<serde_json::Value>::new();
+    let mut status          = 200;
+    let mut sending_events  = false;
+
+    let mut command_results = Box::pin(command_results);
+    while let Some(response) = command_results.next().await {
+        match response {
+            CommandResponse::BackgroundStream(stream) => {
+                if !sending_events {
+                    // Anything we received before the stream is sent as the first events
+                    sending_events = true;
+                    if responses.send(HttpResponse::StartEvents).await.is_err() { return false; }
+
+                    for result in results.drain(..) {
+                        if responses.send(HttpResponse::Event { event: Some("response".into()), data: result.to_string() }).await.is_err() { return false; }
+                    }
+                }
+
+                let mut stream = stream;
+                while let Some(value) = stream.next().await {
+                    if responses.send(HttpResponse::Event { event: None, data: value.to_string() }).await.is_err() { return false; }
+                }
+            }
+
+            CommandResponse::IoStream(_) | CommandResponse::InteractiveStream(_) => {
+                status = 500;
+                results.push(serde_json::json!({ "Error": "Commands that read from their input cannot be run over HTTP" }));
+            }
+
+            response => {
+                if let CommandResponse::Error(err) = &response {
+                    status = if *err == not_found { 404 } else { 500 };
+                }
+
+                let result = serde_json::to_value(&response).unwrap_or(serde_json::Value::Null);
+
+                if sending_events {
+                    if responses.send(HttpResponse::Event { event: Some("response".into()), data: result.to_string() }).await.is_err() { return false; }
+                } else {
+                    results.push(result);
+                }
+            }
+        }
+    }
+
+    if sending_events {
+        // The event stream ends when the connection is closed
+        false
+    } else {
+        responses.send(HttpResponse::json(status, &serde_json::Value::Array(results), close)).await.is_ok() && !close
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor;
+
+    fn read_requests(blocks: Vec<&[u8]>) -> Vec<Result<HttpRequest, HttpRequestError>> {
+        let blocks = blocks.into_iter().map(|block| block.to_vec()).collect::<Vec<_>>();
+        executor::block_on(read_http_requests(stream::iter(blocks).boxed()).collect())
+    }
+
+    #[test]
+    fn read_request_with_body() {
+        let requests = read_requests(vec![b"POST /commands/test%3A%3Aadd HTTP/1.1\r\nContent-Length: 2\r\nHost: localhost\r\n\r\n41"]);
+
+        assert!(requests.len() == 1, "{:?}", requests);
+        let request = requests[0].clone().unwrap();
+
+        assert!(request.method == "POST");
+        assert!(request.path == "/commands/test::add", "{:?}", request.path);
+        assert!(request.header("host") == Some("localhost"));
+        assert!(request.body == b"41");
+        assert!(!request.closes_connection());
+    }
+
+    #[test]
+    fn read_requests_split_across_blocks() {
+        let requests = read_requests(vec![b"GET /commands HT", b"TP/1.1\r\n\r", b"\nPOST /commands/echo HTTP/1.0\r\nContent-Length: 7\r\n\r\n\"Hel", b"lo\""]);
+
+        assert!(requests.len() == 2, "{:?}", requests);
+        assert!(requests[0].as_ref().unwrap().path == "/commands");
+        assert!(requests[1].as_ref().unwrap().body == b"\"Hello\"");
+        assert!(requests[1].as_ref().unwrap().closes_connection());
+    }
+
+    #[test]
+    fn invalid_request_ends_stream() {
+        let requests = read_requests(vec![b"Not a request\r\n\r\nGET /commands HTTP/1.1\r\n\r\n"]);
+
+        assert!(requests.len() == 1, "{:?}", requests);
+        assert!(requests[0].is_err());
+    }
+
+    #[test]
+    fn body_too_large() {
+        let requests = read_requests(vec![b"POST /commands/echo HTTP/1.1\r\nContent-Length: 1000000000\r\n\r\n\"Hello\""]);
+
+        assert!(requests.len() == 1, "{:?}", requests);
+        assert!(requests[0].as_ref().unwrap_err().status == 413, "{:?}", requests);
+    }
+}
@@ -75,6 +75,13 @@ impl JsonCommand {
     pub fn new(target: impl Into<StreamTarget>, name: impl Into<String>, parameter: impl Into<serde_json::Value>, command_processor: Option<SubProgramId>) -> Self {
         Self(RunCommand::new(target, name, JsonParameter { value: parameter.into(), processor: command_processor }))
     }
+
+    ///
+    /// Returns a stream target that will send a `JsonCommand` directly to a program that runs commands (such as a command launcher), bypassing the dispatcher
+    ///
+    pub fn direct_target(program_id: SubProgramId) -> StreamTarget {
+        StreamTarget::Filtered(*FILTER_CONVERT_JSON_COMMAND, program_id)
+    }
 }
 
 ///
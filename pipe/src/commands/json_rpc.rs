@@ -0,0 +1,151 @@
+use super::command_socket::*;
+use super::command_stream::*;
+
+use serde_json::{json};
+
+/// Error code indicating that the input was not valid JSON
+pub const JSON_RPC_PARSE_ERROR: i64         = -32700;
+
+/// Error code indicating that a JSON value was not a valid request object
+pub const JSON_RPC_INVALID_REQUEST: i64     = -32600;
+
+/// Error code indicating that the parameters for a request were not valid
+pub const JSON_RPC_INVALID_PARAMS: i64      = -32602;
+
+/// Error code indicating that a command generated an error
+pub const JSON_RPC_COMMAND_ERROR: i64       = -32000;
+
+/// Method that evaluates its parameter (a string) as a command, using the same syntax as the text protocol
+pub const JSON_RPC_EVAL_METHOD: &str        = "flo_scene::eval";
+
+///
+/// A command request that was received in JSON-RPC format
+///
+/// Requests are objects like `{ "jsonrpc": "2.0", "id": 1, "method": "echo", "params": "Hello" }`: the method is the name of the
+/// command to run and the params are its argument. Requests without an `id` are notifications, and will not receive a final response.
+///
+/// The `flo_scene::eval` method can be used to run anything that can be typed at the command prompt (including pipelines, variable
+/// assignments and scripts): its params should be a string containing the command.
+///
+#[derive(Clone, PartialEq, Debug)]
+pub struct JsonRpcRequest {
+    /// The ID of this request, or None if this is a notification
+    pub id: Option<serde_json::Value>,
+
+    /// The command request that this corresponds to
+    pub request: CommandRequest,
+}
+
+///
+/// An error that should be sent as a JSON-RPC error response
+///
+#[derive(Clone, PartialEq, Debug)]
+pub struct JsonRpcError {
+    /// The ID of the request that caused the error (null if it could not be determined)
+    pub id: serde_json::Value,
+
+    /// The error code
+    pub code: i64,
+
+    /// Description of the error
+    pub message: String,
+}
+
+impl JsonRpcRequest {
+    ///
+    /// Converts a JSON value into a request
+    ///
+    pub async fn from_json(value: serde_json::Value) -> Result<JsonRpcRequest, JsonRpcError> {
+        use serde_json::Value;
+
+        let mut fields = if let Value::Object(fields) = value { fields } else {
+            return Err(JsonRpcError::invalid_request(Value::Null, "Requests must be JSON objects"));
+        };
+
+        let id = fields.remove("id");
+
+        if fields.get("jsonrpc") != Some(&Value::String("2.0".into())) {
+            return Err(JsonRpcError::invalid_request(id.unwrap_or(Value::Null), "Requests must have a `jsonrpc` field with the value \"2.0\""));
+        }
+
+        let method = if let Some(Value::String(method)) = fields.remove("method") { method } else {
+            return Err(JsonRpcError::invalid_request(id.unwrap_or(Value::Null), "Requests must have a `method` field containing a command name"));
+        };
+
+        let argument = fields.remove("params").unwrap_or(Value::Null);
+
+        let request = if method == JSON_RPC_EVAL_METHOD {
+            // The argument is parsed as a command
+            let command = if let Value::String(command) = argument { command } else {
+                return Err(JsonRpcError { id: id.unwrap_or(Value::Null), code: JSON_RPC_INVALID_PARAMS, message: format!("The params for `{}` must be a string", JSON_RPC_EVAL_METHOD) });
+            };
+
+            match CommandRequest::parse(&command).await {
+                Ok(request) => request,
+                Err(err)    => { return Err(JsonRpcError { id: id.unwrap_or(Value::Null), code: JSON_RPC_INVALID_PARAMS, message: format!("Could not parse command: {:?}", err) }); }
+            }
+        } else {
+            CommandRequest::Command { command: CommandName(method), argument: argument.into() }
+        };
+
+        Ok(JsonRpcRequest { id, request })
+    }
+}
+
+impl JsonRpcError {
+    ///
+    /// Creates an 'invalid request' error
+    ///
+    pub fn invalid_request(id: serde_json::Value, message: impl Into<String>) -> Self {
+        JsonRpcError { id, code: JSON_RPC_INVALID_REQUEST, message: message.into() }
+    }
+
+    ///
+    /// Converts this error to a JSON-RPC response object
+    ///
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({ "jsonrpc": "2.0", "id": self.id, "error": { "code": self.code, "message": self.message } })
+    }
+}
+
+///
+/// Creates the JSON-RPC response object for a request that succeeded
+///
+/// The result is always an array containing the JSON values generated by the command (in the order they were generated)
+///
+pub fn json_rpc_result(id: serde_json::Value, results: Vec<serde_json::Value>) -> serde_json::Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": results })
+}
+
+///
+/// Converts a command notification to a JSON-RPC notification object
+///
+/// Notifications relating to a request include its ID as the `id` parameter. Background streams are identified by the `stream`
+/// parameter, which matches the value supplied in the `stream_start` notification for the request that started the stream.
+///
+/// Prompts have no JSON-RPC representation, so this returns None for them.
+///
+pub fn json_rpc_notification(notification: CommandNotification, request_id: Option<&serde_json::Value>) -> Option<serde_json::Value> {
+    use CommandNotification::*;
+
+    let (method, params) = match notification {
+        Prompt                      => { return None; }
+        JsonResponse(json)          => ("json",         json!({ "id": request_id, "value": json })),
+        Message(message)            => ("message",      json!({ "id": request_id, "message": message })),
+        Error(message)              => ("error",        json!({ "id": request_id, "message": message })),
+        NewStream(stream_id)        => ("stream_start", json!({ "id": request_id, "stream": stream_id })),
+        EndStream(stream_id)        => ("stream_end",   json!({ "stream": stream_id })),
+        StreamJson(stream_id, json) => ("stream",       json!({ "stream": stream_id, "value": json })),
+        StartMode(mode)             => ("mode_start",   json!({ "id": request_id, "mode": mode })),
+        EndMode(mode)               => ("mode_end",     json!({ "id": request_id, "mode": mode })),
+    };
+
+    Some(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+}
+
+///
+/// Formats a JSON-RPC object as a line of data to send to a command socket
+///
+pub fn json_rpc_line(json: &serde_json::Value) -> CommandData {
+    format!("{}\n", json).into()
+}
@@ -0,0 +1,547 @@
+use std::fs;
+use std::io::{Write};
+use std::path::{PathBuf};
+
+///
+/// The result of sending some input to a line editor
+///
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct LineEditorOutput {
+    /// Bytes that should be sent back to the terminal to display the effect of the input
+    pub echo: Vec<u8>,
+
+    /// Lines that were completed by the input (without the newline characters)
+    pub lines: Vec<String>,
+}
+
+///
+/// Line editor for command sockets that are connected to a terminal
+///
+/// The terminal should be in 'raw' mode (eg: `socat -,raw,echo=0 UNIX-CONNECT:./socket`): the line editor receives the bytes sent
+/// by the terminal and generates the text to echo back to it, along with the lines that are completed by the user. The cursor keys
+/// move around the line and through the history, tab completes the word before the cursor, and ^A, ^E, ^U, ^C and ^D have their
+/// usual meanings.
+///
+pub struct LineEditor {
+    /// The line that is being edited
+    line: Vec<char>,
+
+    /// The position of the cursor within the line
+    cursor: usize,
+
+    /// The prompt that is displayed before the line (used when the line needs to be displayed again)
+    prompt: String,
+
+    /// The lines that have been entered previously
+    history: Vec<String>,
+
+    /// The history entry being displayed, if the user is browsing the history
+    history_pos: Option<usize>,
+
+    /// The line that was being edited before the user started browsing the history
+    edited_line: Vec<char>,
+
+    /// The file where the history is stored
+    history_file: Option<PathBuf>,
+
+    /// The words that can be used to complete the word before the cursor
+    completions: Vec<String>,
+
+    /// Bytes that form an incomplete character or escape sequence
+    pending: Vec<u8>,
+
+    /// True if the last byte processed was a '\r' (so a following '\n' should be ignored)
+    after_cr: bool,
+
+    /// True if the user has closed the input (by pressing ^D on an empty line)
+    closed: bool,
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        LineEditor::new()
+    }
+}
+
+impl LineEditor {
+    ///
+    /// Creates a new line editor with an empty history
+    ///
+    pub fn new() -> Self {
+        LineEditor {
+            line:           vec![],
+            cursor:         0,
+            prompt:         String::new(),
+            history:        vec![],
+            history_pos:    None,
+            edited_line:    vec![],
+            history_file:   None,
+            completions:    vec![],
+            pending:        vec![],
+            after_cr:       false,
+            closed:         false,
+        }
+    }
+
+    ///
+    /// Loads the history from a file, and appends any new lines to that file as they're entered
+    ///
+    /// The file is created if it does not exist. Errors reading or writing the history are ignored.
+    ///
+    pub fn with_history_file(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+
+        if let Ok(history) = fs::read_to_string(&path) {
+            self.history.extend(history.lines().filter(|line| !line.is_empty()).map(|line| line.to_string()));
+        }
+
+        self.history_file = Some(path);
+        self
+    }
+
+    ///
+    /// The lines that have been entered into this editor (oldest first)
+    ///
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    ///
+    /// Sets the prompt that is displayed before the current line
+    ///
+    pub fn set_prompt(&mut self, prompt: impl Into<String>) {
+        self.prompt = prompt.into();
+    }
+
+    ///
+    /// Sets the words that are used when the user presses tab
+    ///
+    pub fn set_completions(&mut self, completions: impl IntoIterator<Item=String>) {
+        let mut completions = completions.into_iter().collect::<Vec<_>>();
+        completions.sort();
+        completions.dedup();
+
+        self.completions = completions;
+    }
+
+    ///
+    /// True if the user has closed the input
+    ///
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    ///
+    /// Processes some bytes received from the terminal
+    ///
+    pub fn input(&mut self, bytes: &[u8]) -> LineEditorOutput {
+        let mut output = LineEditorOutput::default();
+
+        if self.closed {
+            return output;
+        }
+
+        self.pending.extend(bytes);
+
+        let pending = std::mem::take(&mut self.pending);
+        let mut pos = 0;
+
+        while pos < pending.len() && !self.closed {
+            let byte        = pending[pos];
+            let after_cr    = self.after_cr;
+            self.after_cr   = false;
+
+            match byte {
+                0x1b => {
+                    // Escape sequence: the cursor keys send sequences like ESC [ A
+                    if pos+1 >= pending.len() { break; }
+
+                    if pending[pos+1] == b'[' || pending[pos+1] == b'O' {
+                        // Sequences end with a byte in the range 0x40-0x7e
+                        let mut end = pos+2;
+                        while end < pending.len() && !(0x40..=0x7e).contains(&pending[end]) {
+                            end += 1;
+                        }
+
+                        if end >= pending.len() { break; }
+
+                        self.escape_sequence(&pending[pos+2..end], pending[end], &mut output);
+                        pos = end+1;
+                    } else {
+                        // Alt+key, which we ignore
+                        pos += 2;
+                    }
+                }
+
+                b'\r'           => { self.finish_line(&mut output); self.after_cr = true; pos += 1; }
+                b'\n'           => { if !after_cr { self.finish_line(&mut output); } pos += 1; }
+                0x7f | 0x08     => { self.backspace(&mut output); pos += 1; }
+                b'\t'           => { self.complete(&mut output); pos += 1; }
+                0x01            => { self.move_cursor(0, &mut output); pos += 1; }
+                0x05            => { self.move_cursor(self.line.len(), &mut output); pos += 1; }
+                0x15            => { self.replace_line(vec![], &mut output); pos += 1; }
+                0x03            => { self.cancel_line(&mut output); pos += 1; }
+                0x04            => { self.end_of_input(&mut output); pos += 1; }
+                0x00..=0x1f     => { pos += 1; }
+
+                _ => {
+                    // UTF-8 character
+                    let len = match byte {
+                        0xc0..=0xdf => 2,
+                        0xe0..=0xef => 3,
+                        0xf0..=0xf7 => 4,
+                        _           => 1,
+                    };
+
+                    if pos+len > pending.len() { break; }
+
+                    if let Ok(chr) = std::str::from_utf8(&pending[pos..pos+len]) {
+                        for chr in chr.chars() {
+                            self.insert(chr, &mut output);
+                        }
+                    }
+
+                    pos += len;
+                }
+            }
+        }
+
+        // Keep any incomplete sequences for the next call
+        if !self.closed {
+            self.pending = pending[pos..].to_vec();
+        }
+
+        output
+    }
+
+    ///
+    /// Handles an escape sequence (ESC [ <parameters> <final>)
+    ///
+    fn escape_sequence(&mut self, parameters: &[u8], final_byte: u8, output: &mut LineEditorOutput) {
+        match (parameters, final_byte) {
+            (_, b'A')               => self.history_previous(output),
+            (_, b'B')               => self.history_next(output),
+            (_, b'C')               => self.move_cursor((self.cursor+1).min(self.line.len()), output),
+            (_, b'D')               => self.move_cursor(self.cursor.saturating_sub(1), output),
+            (_, b'H')               |
+            (b"1", b'~')            |
+            (b"7", b'~')            => self.move_cursor(0, output),
+            (_, b'F')               |
+            (b"4", b'~')            |
+            (b"8", b'~')            => self.move_cursor(self.line.len(), output),
+            (b"3", b'~')            => self.delete(output),
+            _                       => { }
+        }
+    }
+
+    ///
+    /// Writes the sequence to move the cursor left by a number of characters
+    ///
+    fn cursor_left(count: usize, output: &mut LineEditorOutput) {
+        if count > 0 {
+            write!(output.echo, "\x1b[{}D", count).ok();
+        }
+    }
+
+    ///
+    /// Displays the line again after it has been edited, assuming that the terminal cursor is at `old_cursor`
+    ///
+    fn redraw(&self, old_cursor: usize, output: &mut LineEditorOutput) {
+        Self::cursor_left(old_cursor, output);
+        output.echo.extend(b"\x1b[K");
+        output.echo.extend(self.line.iter().collect::<String>().bytes());
+        Self::cursor_left(self.line.len() - self.cursor, output);
+    }
+
+    ///
+    /// Inserts a character at the cursor position
+    ///
+    fn insert(&mut self, chr: char, output: &mut LineEditorOutput) {
+        if self.cursor == self.line.len() {
+            // Typing at the end of the line just needs the character to be echoed
+            self.line.push(chr);
+            self.cursor += 1;
+
+            let mut buf = [0u8; 4];
+            output.echo.extend(chr.encode_utf8(&mut buf).bytes());
+        } else {
+            let old_cursor = self.cursor;
+
+            self.line.insert(self.cursor, chr);
+            self.cursor += 1;
+            self.redraw(old_cursor, output);
+        }
+    }
+
+    ///
+    /// Removes the character before the cursor
+    ///
+    fn backspace(&mut self, output: &mut LineEditorOutput) {
+        if self.cursor > 0 {
+            let old_cursor = self.cursor;
+
+            self.cursor -= 1;
+            self.line.remove(self.cursor);
+            self.redraw(old_cursor, output);
+        }
+    }
+
+    ///
+    /// Removes the character under the cursor
+    ///
+    fn delete(&mut self, output: &mut LineEditorOutput) {
+        if self.cursor < self.line.len() {
+            self.line.remove(self.cursor);
+            self.redraw(self.cursor, output);
+        }
+    }
+
+    ///
+    /// Moves the cursor to a new position
+    ///
+    fn move_cursor(&mut self, new_cursor: usize, output: &mut LineEditorOutput) {
+        if new_cursor < self.cursor {
+            Self::cursor_left(self.cursor - new_cursor, output);
+        } else if new_cursor > self.cursor {
+            write!(output.echo, "\x1b[{}C", new_cursor - self.cursor).ok();
+        }
+
+        self.cursor = new_cursor;
+    }
+
+    ///
+    /// Replaces the line being edited with a new line, with the cursor at the end
+    ///
+    fn replace_line(&mut self, new_line: Vec<char>, output: &mut LineEditorOutput) {
+        let old_cursor = self.cursor;
+
+        self.line   = new_line;
+        self.cursor = self.line.len();
+        self.redraw(old_cursor, output);
+    }
+
+    ///
+    /// Shows the previous line from the history
+    ///
+    fn history_previous(&mut self, output: &mut LineEditorOutput) {
+        let new_pos = match self.history_pos {
+            None if !self.history.is_empty()    => { self.edited_line = self.line.clone(); self.history.len()-1 },
+            Some(pos) if pos > 0                => pos-1,
+            _                                   => { return; }
+        };
+
+        self.history_pos = Some(new_pos);
+        self.replace_line(self.history[new_pos].chars().collect(), output);
+    }
+
+    ///
+    /// Shows the next line from the history (or the line that was being edited before the user started browsing the history)
+    ///
+    fn history_next(&mut self, output: &mut LineEditorOutput) {
+        match self.history_pos {
+            None => { }
+
+            Some(pos) if pos+1 < self.history.len() => {
+                self.history_pos = Some(pos+1);
+                self.replace_line(self.history[pos+1].chars().collect(), output);
+            }
+
+            Some(_) => {
+                let edited_line = std::mem::take(&mut self.edited_line);
+
+                self.history_pos = None;
+                self.replace_line(edited_line, output);
+            }
+        }
+    }
+
+    ///
+    /// Completes the word before the cursor using the list of completions
+    ///
+    fn complete(&mut self, output: &mut LineEditorOutput) {
+        // Find the start of the word before the cursor
+        let is_word_break   = |chr: &char| chr.is_whitespace() || "\"'[]{}(),|;=@".contains(*chr);
+        let word_start      = self.line[0..self.cursor].iter().rposition(is_word_break).map(|pos| pos+1).unwrap_or(0);
+        let word            = self.line[word_start..self.cursor].iter().collect::<String>();
+
+        // Find the completions that match this word
+        let matches = self.completions.iter()
+            .filter(|completion| completion.starts_with(&word))
+            .collect::<Vec<_>>();
+
+        if matches.is_empty() {
+            // Ring the bell if there's nothing that can complete this word
+            output.echo.push(0x07);
+            return;
+        }
+
+        // Extend the word as far as all of the matches agree
+        let common_prefix = matches.iter().skip(1)
+            .fold(matches[0].chars().collect::<Vec<_>>(), |prefix, completion| {
+                prefix.into_iter().zip(completion.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a).collect()
+            });
+
+        let word_len = word.chars().count();
+
+        if common_prefix.len() > word_len {
+            let old_cursor = self.cursor;
+
+            for chr in common_prefix[word_len..].iter() {
+                self.line.insert(self.cursor, *chr);
+                self.cursor += 1;
+            }
+
+            self.redraw(old_cursor, output);
+        } else if matches.len() > 1 {
+            // The word can't be extended any further, so show the possible completions
+            output.echo.extend(b"\r\n");
+            output.echo.extend(matches.iter().map(|completion| completion.as_str()).collect::<Vec<_>>().join("  ").bytes());
+            output.echo.extend(b"\r\n");
+            output.echo.extend(self.prompt.bytes());
+
+            self.redraw(0, output);
+        }
+    }
+
+    ///
+    /// Finishes the current line and adds it to the history
+    ///
+    fn finish_line(&mut self, output: &mut LineEditorOutput) {
+        let line = self.line.drain(..).collect::<String>();
+
+        output.echo.extend(b"\r\n");
+
+        // Add to the history (we don't store blank lines or repeats)
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+
+            if let Some(history_file) = &self.history_file {
+                fs::OpenOptions::new().create(true).append(true).open(history_file)
+                    .and_then(|mut file| writeln!(file, "{}", line))
+                    .ok();
+            }
+        }
+
+        // Any following lines have no prompt until the socket asks for one
+        self.cursor         = 0;
+        self.history_pos    = None;
+        self.prompt         = String::new();
+
+        output.lines.push(line);
+    }
+
+    ///
+    /// Abandons the current line (^C)
+    ///
+    fn cancel_line(&mut self, output: &mut LineEditorOutput) {
+        self.line.clear();
+        self.cursor         = 0;
+        self.history_pos    = None;
+
+        output.echo.extend(b"^C\r\n");
+        output.echo.extend(self.prompt.bytes());
+    }
+
+    ///
+    /// Closes the input if the line is empty, or deletes the character under the cursor otherwise (^D)
+    ///
+    fn end_of_input(&mut self, output: &mut LineEditorOutput) {
+        if self.line.is_empty() {
+            self.closed = true;
+            output.echo.extend(b"\r\n");
+        } else {
+            self.delete(output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn type_line() {
+        let mut editor  = LineEditor::new();
+        let output      = editor.input(b"echo \"Hello\"\r\n");
+
+        assert!(output.lines == vec!["echo \"Hello\"".to_string()], "{:?}", output);
+        assert!(output.echo == b"echo \"Hello\"\r\n".to_vec(), "{:?}", String::from_utf8_lossy(&output.echo));
+    }
+
+    #[test]
+    fn line_split_across_input() {
+        let mut editor  = LineEditor::new();
+
+        assert!(editor.input(b"ech").lines.is_empty());
+        assert!(editor.input(b"o\x1b").lines.is_empty());
+        assert!(editor.input(b"[D\x1b[C\n").lines == vec!["echo".to_string()]);
+    }
+
+    #[test]
+    fn edit_line() {
+        let mut editor  = LineEditor::new();
+
+        // Type 'ehox', backspace, move left twice, insert 'c'
+        let output      = editor.input(b"ehox\x7f\x1b[D\x1b[Dc\r");
+
+        assert!(output.lines == vec!["echo".to_string()], "{:?}", output);
+    }
+
+    #[test]
+    fn browse_history() {
+        let mut editor  = LineEditor::new();
+
+        editor.input(b"first\rsecond\r");
+        let output      = editor.input(b"thi\x1b[A\x1b[A\x1b[B\x1b[B\x1b[Ard\r");
+
+        assert!(output.lines == vec!["secondrd".to_string()], "{:?}", output);
+        assert!(editor.history() == ["first".to_string(), "second".to_string(), "secondrd".to_string()]);
+    }
+
+    #[test]
+    fn complete_unique_word() {
+        let mut editor  = LineEditor::new();
+        editor.set_completions(vec!["echo".to_string(), "list_subprograms".to_string(), "list_connections".to_string()]);
+
+        let output      = editor.input(b"ec\t \"Hello\"\r");
+
+        assert!(output.lines == vec!["echo \"Hello\"".to_string()], "{:?}", output);
+    }
+
+    #[test]
+    fn complete_common_prefix() {
+        let mut editor  = LineEditor::new();
+        editor.set_completions(vec!["echo".to_string(), "list_subprograms".to_string(), "list_connections".to_string()]);
+        editor.set_prompt("> ");
+
+        let output      = editor.input(b"l\t");
+        assert!(output.echo.ends_with(b"list_"), "{:?}", String::from_utf8_lossy(&output.echo));
+
+        // Pressing tab again lists the options
+        let output      = editor.input(b"\t");
+        assert!(String::from_utf8_lossy(&output.echo).contains("list_connections  list_subprograms\r\n> "), "{:?}", String::from_utf8_lossy(&output.echo));
+
+        let output      = editor.input(b"s\t\r");
+        assert!(output.lines == vec!["list_subprograms".to_string()], "{:?}", output);
+    }
+
+    #[test]
+    fn complete_after_target() {
+        let mut editor  = LineEditor::new();
+        editor.set_completions(vec!["launcher_a".to_string()]);
+
+        let output      = editor.input(b"@lau\t\r");
+
+        assert!(output.lines == vec!["@launcher_a".to_string()], "{:?}", output);
+    }
+
+    #[test]
+    fn close_with_ctrl_d() {
+        let mut editor  = LineEditor::new();
+
+        let output      = editor.input(b"abc\x04\x01\x04\x04\x04\x03\x04more");
+
+        assert!(output.lines.is_empty());
+        assert!(editor.is_closed());
+    }
+}
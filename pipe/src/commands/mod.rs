@@ -1,13 +1,25 @@
 mod command_socket;
 mod command_program;
+mod command_script;
 mod command_stream;
 pub (crate) mod parse_command;
 mod json_command;
 mod json_command_launcher;
+mod json_rpc;
+mod line_editor;
+mod command_permissions;
+mod command_profiles;
+mod http_gateway;
 
 pub use command_program::*;
+pub use command_script::*;
 pub use command_stream::*;
 pub use command_socket::*;
 pub use parse_command::*;
 pub use json_command::*;
 pub use json_command_launcher::*;
+pub use json_rpc::*;
+pub use line_editor::*;
+pub use command_permissions::*;
+pub use command_profiles::*;
+pub use http_gateway::*;
@@ -1,6 +1,8 @@
 use super::command_stream::*;
 use crate::parser::*;
 
+use flo_scene::*;
+
 use futures::prelude::*;
 use futures::future::{BoxFuture};
 
@@ -15,6 +17,12 @@ pub enum CommandToken {
     /// A variable name
     Variable,
 
+    /// An '@program' target, which sends the following command to a specific subprogram
+    Target,
+
+    /// A '(command)' substitution, which is replaced by the result of the command when used in a JSON value
+    Substitution,
+
     /// The '|' symbol, used to send the output of one command to another
     Pipe,
 
@@ -48,11 +56,17 @@ pub enum CommandParseError {
     /// Ran out of input while parsing the command
     ExpectedMoreInput,
 
+    /// The input finished before the start of the next command (ie, there were no more commands to read)
+    EndOfInput,
+
     /// Usually an error in the parser, we tried to 'reduce' a token when we hadn't previously accepted enough input 
     ParserStackTooSmall,
 
     /// Usually indicates an error with the parser, we failed to 'converge' to a single value
     ParserDidNotConverge,
+
+    /// The input ended before a '{' block was closed
+    UnclosedBlock,
 }
 
 impl<'a, TToken> From<&'a TokenMatch<TToken>> for CommandParseError 
@@ -108,6 +122,7 @@ impl TryInto<JsonToken> for CommandToken {
         match self {
             CommandToken::Json(token)   => Ok(token),
             CommandToken::Variable      => Ok(JsonToken::Variable),
+            CommandToken::Substitution  => Ok(JsonToken::Substitution),
             CommandToken::Comment       => Ok(JsonToken::Whitespace),
             CommandToken::Newline       => Ok(JsonToken::Whitespace),
             other                       => Err(other),
@@ -118,13 +133,15 @@ impl TryInto<JsonToken> for CommandToken {
 impl TokenMatcher<CommandToken> for CommandToken {
     fn try_match(&self, lookahead: &'_ str, eof: bool) -> TokenMatchResult<CommandToken> {
         match self {
-            CommandToken::Command   => match_command(lookahead, eof),
-            CommandToken::Variable  => match_variable(lookahead, eof),
-            CommandToken::Comment   => match_command_comment(lookahead, eof),
-            CommandToken::Pipe      => if lookahead.starts_with("|") { TokenMatchResult::Matches(CommandToken::Pipe, 1) } else { TokenMatchResult::LookaheadCannotMatch },
-            CommandToken::SemiColon => if lookahead.starts_with(";") { TokenMatchResult::Matches(CommandToken::SemiColon, 1) } else { TokenMatchResult::LookaheadCannotMatch },
-            CommandToken::Equals    => if lookahead.starts_with("=") { TokenMatchResult::Matches(CommandToken::Equals, 1) } else { TokenMatchResult::LookaheadCannotMatch },
-            CommandToken::Newline   => {
+            CommandToken::Command      => match_command(lookahead, eof),
+            CommandToken::Variable     => match_variable(lookahead, eof),
+            CommandToken::Target       => match_target(lookahead, eof),
+            CommandToken::Substitution => match_substitution(lookahead, eof),
+            CommandToken::Comment      => match_command_comment(lookahead, eof),
+            CommandToken::Pipe         => if lookahead.starts_with("|") { TokenMatchResult::Matches(CommandToken::Pipe, 1) } else { TokenMatchResult::LookaheadCannotMatch },
+            CommandToken::SemiColon    => if lookahead.starts_with(";") { TokenMatchResult::Matches(CommandToken::SemiColon, 1) } else { TokenMatchResult::LookaheadCannotMatch },
+            CommandToken::Equals       => if lookahead.starts_with("=") { TokenMatchResult::Matches(CommandToken::Equals, 1) } else { TokenMatchResult::LookaheadCannotMatch },
+            CommandToken::Newline      => {
                 match match_whitespace(lookahead, eof) {
                     TokenMatchResult::Matches(JsonToken::Whitespace, count) => {
                         if lookahead.as_bytes()[count-1] == b'\n' || lookahead.as_bytes()[count-1] == b'\r' {
@@ -147,7 +164,7 @@ impl TokenMatcher<CommandToken> for CommandToken {
                     }
                 }
             }
-            CommandToken::Json(_)   => TokenMatchResult::LookaheadCannotMatch
+            CommandToken::Json(_)      => TokenMatchResult::LookaheadCannotMatch
         }
     }
 }
@@ -161,6 +178,8 @@ impl<TStream> Tokenizer<CommandToken, TStream> {
             .with_json_matchers()
             .with_matcher(CommandToken::Command)
             .with_matcher(CommandToken::Variable)
+            .with_matcher(CommandToken::Target)
+            .with_matcher(CommandToken::Substitution)
             .with_matcher(CommandToken::Comment)
             .with_matcher(CommandToken::Pipe)
             .with_matcher(CommandToken::SemiColon)
@@ -171,6 +190,22 @@ impl<TStream> Tokenizer<CommandToken, TStream> {
     }
 }
 
+///
+/// True if a word is one of the JSON keywords, which are always treated as JSON values instead of commands
+///
+#[inline]
+fn is_json_keyword(word: &str) -> bool {
+    word == "true" || word == "false" || word == "null"
+}
+
+///
+/// True if a word following a ':' is a JSON value in an object without whitespace (eg `{"key":1}` or `{"key":true}`) rather than a variable name
+///
+#[inline]
+fn is_json_value_after_colon(first_chr: char, word: &str) -> bool {
+    first_chr == ':' && (word.starts_with(|chr: char| chr.is_ascii_digit()) || is_json_keyword(word))
+}
+
 ///
 /// Matches against the command token
 ///
@@ -187,7 +222,7 @@ fn match_command(lookahead: &str, eof: bool) -> TokenMatchResult<CommandToken> {
                 if next_chr.is_alphabetic() || next_chr.is_digit(10) || next_chr == '_' || next_chr == ':' {
                     // Is a valid continuation
                 } else {
-                    if first_chr != ':' || len > 1 {
+                    if (first_chr != ':' || len > 1) && !is_json_keyword(&lookahead[0..len]) && !is_json_value_after_colon(first_chr, &lookahead[1..len]) {
                         return TokenMatchResult::Matches(CommandToken::Command, len);
                     } else {
                         return TokenMatchResult::LookaheadCannotMatch;
@@ -197,7 +232,9 @@ fn match_command(lookahead: &str, eof: bool) -> TokenMatchResult<CommandToken> {
                 len += 1;
             }
 
-            if eof {
+            if eof && (is_json_keyword(lookahead) || is_json_value_after_colon(first_chr, &lookahead[1..])) {
+                TokenMatchResult::LookaheadCannotMatch
+            } else if eof {
                 TokenMatchResult::Matches(CommandToken::Command, len)
             } else {
                 TokenMatchResult::LookaheadIsPrefix
@@ -229,7 +266,7 @@ fn match_variable(lookahead: &str, eof: bool) -> TokenMatchResult<CommandToken>
                 if next_chr.is_alphabetic() || next_chr.is_digit(10) || next_chr == '_' || next_chr == ':' {
                     // Is a valid continuation
                 } else {
-                    if len > 1 {
+                    if len > 1 && !is_json_value_after_colon(first_chr, &lookahead[1..len]) {
                         return TokenMatchResult::Matches(CommandToken::Variable, len);
                     } else {
                         return TokenMatchResult::LookaheadCannotMatch;
@@ -239,7 +276,9 @@ fn match_variable(lookahead: &str, eof: bool) -> TokenMatchResult<CommandToken>
                 len += 1;
             }
 
-            if eof {
+            if eof && is_json_value_after_colon(first_chr, &lookahead[1..len]) {
+                TokenMatchResult::LookaheadCannotMatch
+            } else if eof {
                 TokenMatchResult::Matches(CommandToken::Variable, len)
             } else {
                 TokenMatchResult::LookaheadIsPrefix
@@ -253,6 +292,91 @@ fn match_variable(lookahead: &str, eof: bool) -> TokenMatchResult<CommandToken>
     }
 }
 
+///
+/// Matches against the target token
+///
+/// Targets are `@name`, where the name is the name of a subprogram (as used by `SubProgramId::called()`)
+///
+fn match_target(lookahead: &str, eof: bool) -> TokenMatchResult<CommandToken> {
+    let mut characters = lookahead.chars();
+
+    if characters.next() != Some('@') {
+        // Not a target
+        return TokenMatchResult::LookaheadCannotMatch;
+    }
+
+    // The name is made up of the same characters as a command, and can also contain '-'
+    let mut len = 1;
+    for next_chr in characters {
+        if next_chr.is_alphanumeric() || next_chr == '_' || next_chr == ':' || next_chr == '-' {
+            len += 1;
+        } else if len > 1 {
+            return TokenMatchResult::Matches(CommandToken::Target, len);
+        } else {
+            return TokenMatchResult::LookaheadCannotMatch;
+        }
+    }
+
+    if !eof {
+        TokenMatchResult::LookaheadIsPrefix
+    } else if len > 1 {
+        TokenMatchResult::Matches(CommandToken::Target, len)
+    } else {
+        TokenMatchResult::LookaheadCannotMatch
+    }
+}
+
+///
+/// Matches against a command substitution, which is a command in brackets: `(command)`
+///
+/// Brackets can be nested, and brackets inside strings are ignored
+///
+fn match_substitution(lookahead: &str, eof: bool) -> TokenMatchResult<CommandToken> {
+    let mut characters = lookahead.chars();
+
+    if characters.next() != Some('(') {
+        // Not a substitution
+        return TokenMatchResult::LookaheadCannotMatch;
+    }
+
+    let mut depth       = 1;
+    let mut in_string   = false;
+    let mut escaped     = false;
+
+    for (idx, chr) in characters.enumerate() {
+        if in_string {
+            // Skip to the end of the string
+            if escaped {
+                escaped = false;
+            } else if chr == '\\' {
+                escaped = true;
+            } else if chr == '"' {
+                in_string = false;
+            }
+        } else {
+            match chr {
+                '"' => { in_string = true; }
+                '(' => { depth += 1; }
+                ')' => {
+                    depth -= 1;
+
+                    if depth == 0 {
+                        // Length includes the opening and closing brackets
+                        return TokenMatchResult::Matches(CommandToken::Substitution, idx + 2);
+                    }
+                }
+                _   => { }
+            }
+        }
+    }
+
+    if eof {
+        TokenMatchResult::LookaheadCannotMatch
+    } else {
+        TokenMatchResult::LookaheadIsPrefix
+    }
+}
+
 ///
 /// Matches against the comment syntax
 ///
@@ -325,7 +449,11 @@ where
             if let Some(lookahead) = lookahead {
                 match lookahead.token {
                     Some(CommandToken::Newline)  => { parser.skip_token(); }
+                    Some(CommandToken::Comment)  => { parser.skip_token(); }
+                    Some(CommandToken::Command) if lookahead.fragment == "if"       => { command_parse_if(parser, tokenizer).await?; break Ok(()); }
+                    Some(CommandToken::Command) if lookahead.fragment == "foreach"  => { command_parse_foreach(parser, tokenizer).await?; break Ok(()); }
                     Some(CommandToken::Command)  => { command_parse_command(parser, tokenizer).await?; break Ok(()); }
+                    Some(CommandToken::Target)   => { command_parse_command(parser, tokenizer).await?; break Ok(()); }
                     Some(CommandToken::Variable) => { 
                         let maybe_equals = parser.lookahead(1, tokenizer, |tokenizer| command_read_token(tokenizer).boxed()).await;
 
@@ -338,7 +466,21 @@ where
                         }
                     }
 
-                    Some(CommandToken::Json(_)) => {
+                    Some(CommandToken::Json(JsonToken::Character('-'))) => {
+                        // '-command' will continue a script even if the command fails
+                        parser.accept_token()?;
+                        command_parse(parser, tokenizer).await?;
+
+                        parser.reduce(2, |mut continue_on_error| {
+                            let request = continue_on_error.pop().unwrap();
+                            CommandRequest::ContinueOnError { request: Box::new(request.to_node().unwrap()) }
+                        })?;
+
+                        break Ok(());
+                    }
+
+                    Some(CommandToken::Json(_))      |
+                    Some(CommandToken::Substitution) => {
                         // Convert to a JSON parser
                         let mut json_parser = Parser::with_lookahead_from(parser);
                         json_parse_value(&mut json_parser, tokenizer).await?;
@@ -350,6 +492,9 @@ where
                         let json_value = json_parser.finish()?;
                         parser.reduce(0, |_| CommandRequest::RawJson { value: json_value.into() })?;
 
+                        // JSON values can be piped into a command
+                        command_parse_pipe(parser, tokenizer).await?;
+
                         break Ok(());
                     }
 
@@ -364,12 +509,45 @@ where
 }
 
 ///
-/// Parses a command, at the point where the lookahead contains the 'Command' token
+/// Parses the next command from an input stream, skipping any blank lines or comments before it
+///
+/// Unlike `command_parse`, this will distinguish between input that ends between commands, which returns `EndOfInput`, and input that
+/// ends partway through a command, which returns `ExpectedMoreInput`.
+///
+pub async fn command_parse_next<TStream>(parser: &mut Parser<TokenMatch<CommandToken>, CommandRequest>, tokenizer: &mut Tokenizer<CommandToken, TStream>) -> Result<(), CommandParseError>
+where
+    TStream: Send + Stream<Item=Vec<u8>>,
+{
+    loop {
+        let lookahead = parser.lookahead(0, tokenizer, |tokenizer| command_read_token(tokenizer).boxed()).await;
+
+        match lookahead.map(|lookahead| lookahead.token) {
+            None                                => { return Err(CommandParseError::EndOfInput); }
+            Some(Some(CommandToken::Newline))   |
+            Some(Some(CommandToken::Comment))   => { parser.skip_token(); }
+            Some(_)                             => { return command_parse(parser, tokenizer).await; }
+        }
+    }
+}
+
+///
+/// Parses a command, at the point where the lookahead contains the 'Command' token (or a 'Target' token followed by a command)
 ///
 async fn command_parse_command<TStream>(parser: &mut Parser<TokenMatch<CommandToken>, CommandRequest>, tokenizer: &mut Tokenizer<CommandToken, TStream>) -> Result<(), CommandParseError>
 where
     TStream: Send + Stream<Item=Vec<u8>>,
  {
+    // Commands can be prefixed by an '@target' to send them to a specific subprogram
+    let target = parser.lookahead(0, tokenizer, |tokenizer| command_read_token(tokenizer).boxed()).await.ok_or(CommandParseError::ExpectedMoreInput)?;
+    let target = if target.token == Some(CommandToken::Target) {
+        let target_name = target.fragment[1..].to_string();
+        parser.skip_token();
+
+        Some(StreamTarget::Program(SubProgramId::called(&target_name)))
+    } else {
+        None
+    };
+
     // Lookahead must be a 'Command'
     let command_name = parser.lookahead(0, tokenizer, |tokenizer| command_read_token(tokenizer).boxed()).await.ok_or(CommandParseError::ExpectedMoreInput)?;
     if !matches!(command_name.token, Some(CommandToken::Command) | Some(CommandToken::Variable)) { return Err(command_name.into()); }
@@ -380,7 +558,16 @@ where
     let maybe_argument = parser.lookahead(0, tokenizer, |tokenizer| command_read_token(tokenizer).boxed()).await;
     if let Some(maybe_argument) = maybe_argument {
         match maybe_argument.token {
-            Some(CommandToken::Json(_)) | Some(CommandToken::Variable) => {
+            Some(CommandToken::Json(JsonToken::Character('}'))) => {
+                // Command has no argument and is at the end of a block (the '}' is left in the lookahead for the block to process)
+                parser.reduce(1, |cmd| {
+                    let name = cmd[0].token().unwrap().fragment.clone();
+                    CommandRequest::Command { command: CommandName(name), argument: ParsedJson::Null }
+                })?;
+                command_reduce_target(parser, target)?;
+            }
+
+            Some(CommandToken::Json(_)) | Some(CommandToken::Variable) | Some(CommandToken::Substitution) => {
                 // Argument is a JSON value which may be followed by a pipe or an equals
                 command_parse_argument(parser, tokenizer).await?;
 
@@ -393,8 +580,10 @@ where
                         _                                           => { unreachable!() }
                     }
                 })?;
+                command_reduce_target(parser, target)?;
 
-                // TODO: command can use pipe or an equals here
+                // Command can be followed by a pipe
+                command_parse_pipe(parser, tokenizer).await?;
             }
 
             Some(CommandToken::Newline)     |
@@ -406,9 +595,19 @@ where
                     let name = cmd[0].token().unwrap().fragment.clone();
                     CommandRequest::Command { command: CommandName(name), argument: ParsedJson::Null }
                 })?;
+                command_reduce_target(parser, target)?;
             }
 
-            // TODO: command can have no arguments and be followed by a pipe
+            Some(CommandToken::Pipe) => {
+                // Command has no argument and is followed by a pipe
+                parser.reduce(1, |cmd| {
+                    let name = cmd[0].token().unwrap().fragment.clone();
+                    CommandRequest::Command { command: CommandName(name), argument: ParsedJson::Null }
+                })?;
+                command_reduce_target(parser, target)?;
+
+                command_parse_pipe(parser, tokenizer).await?;
+            }
 
             _ => { return Err(maybe_argument.into()); }
         }
@@ -418,8 +617,184 @@ where
             let name = cmd[0].token().unwrap().fragment.clone();
             CommandRequest::Command { command: CommandName(name), argument: ParsedJson::Null }
         })?;
+        command_reduce_target(parser, target)?;
+    }
+
+    Ok(())
+}
+
+///
+/// If a command had an '@target' prefix, replaces the command on top of the stack with a `ForTarget` request
+///
+fn command_reduce_target(parser: &mut Parser<TokenMatch<CommandToken>, CommandRequest>, target: Option<StreamTarget>) -> Result<(), CommandParseError> {
+    if let Some(target) = target {
+        parser.reduce(1, move |mut cmd| {
+            let command = cmd.pop().unwrap();
+
+            CommandRequest::ForTarget { target, request: Box::new(command.to_node().unwrap()) }
+        })?;
+    }
+
+    Ok(())
+}
+
+///
+/// Parses the rest of a pipeline (`| command`) if the lookahead is a pipe, when the stack has the command that the pipe is reading from
+///
+async fn command_parse_pipe<TStream>(parser: &mut Parser<TokenMatch<CommandToken>, CommandRequest>, tokenizer: &mut Tokenizer<CommandToken, TStream>) -> Result<(), CommandParseError>
+where
+    TStream: Send + Stream<Item=Vec<u8>>,
+{
+    let maybe_pipe = parser.lookahead(0, tokenizer, |tokenizer| command_read_token(tokenizer).boxed()).await;
+    if maybe_pipe.and_then(|pipe| pipe.token) != Some(CommandToken::Pipe) {
+        // Not a pipeline
+        return Ok(());
     }
 
+    // Accept the pipe, and parse the command that it's sending to (which may be another pipeline)
+    parser.accept_token()?;
+    command_parse(parser, tokenizer).await?;
+
+    // Reduce to a pipe request
+    parser.reduce(3, |mut pipe| {
+        let to      = pipe.pop().unwrap();
+        let _pipe   = pipe.pop().unwrap();
+        let from    = pipe.pop().unwrap();
+
+        CommandRequest::Pipe {
+            from:   Box::new(from.to_node().unwrap()),
+            to:     Box::new(to.to_node().unwrap()),
+        }
+    })?;
+
+    Ok(())
+}
+
+///
+/// Parses a block of commands surrounded by '{' and '}'
+///
+async fn command_parse_block<TStream>(parser: &mut Parser<TokenMatch<CommandToken>, CommandRequest>, tokenizer: &mut Tokenizer<CommandToken, TStream>) -> Result<(), CommandParseError>
+where
+    TStream: Send + Stream<Item=Vec<u8>>,
+{
+    // Lookahead must be a '{'
+    let open_block = parser.lookahead(0, tokenizer, |tokenizer| command_read_token(tokenizer).boxed()).await.ok_or(CommandParseError::UnclosedBlock)?;
+    if open_block.token != Some(CommandToken::Json(JsonToken::Character('{'))) { return Err(open_block.into()); }
+
+    parser.accept_token()?;
+
+    // Read commands until the '}'
+    let mut num_requests = 0;
+
+    loop {
+        let lookahead = parser.lookahead(0, tokenizer, |tokenizer| command_read_token(tokenizer).boxed()).await;
+        let lookahead = if let Some(lookahead) = lookahead { lookahead } else { return Err(CommandParseError::UnclosedBlock); };
+
+        match lookahead.token {
+            Some(CommandToken::Newline)                         |
+            Some(CommandToken::SemiColon)                       |
+            Some(CommandToken::Comment)                         => { parser.skip_token(); }
+            Some(CommandToken::Json(JsonToken::Character('}'))) => { parser.accept_token()?; break; }
+            _                                                   => { command_parse(parser, tokenizer).await?; num_requests += 1; }
+        }
+    }
+
+    // Reduce to a block (the first and last entries are the brackets)
+    parser.reduce(num_requests + 2, |block| {
+        let requests = block.into_iter()
+            .skip(1)
+            .take(num_requests)
+            .map(|request| request.to_node().unwrap())
+            .collect();
+
+        CommandRequest::Block { requests }
+    })?;
+
+    Ok(())
+}
+
+///
+/// Parses an 'if <value> { commands } else { commands }' request, at the point where the lookahead contains the 'if' token
+///
+fn command_parse_if<'a, TStream>(parser: &'a mut Parser<TokenMatch<CommandToken>, CommandRequest>, tokenizer: &'a mut Tokenizer<CommandToken, TStream>) -> BoxFuture<'a, Result<(), CommandParseError>>
+where
+    TStream: Send + Stream<Item=Vec<u8>>,
+{
+    async move {
+        // 'if <condition> { block }'
+        parser.accept_token()?;
+        command_parse_argument(parser, tokenizer).await?;
+        command_parse_block(parser, tokenizer).await?;
+
+        // Can be followed by 'else { block }' or 'else if ...'
+        let maybe_else = parser.lookahead(0, tokenizer, |tokenizer| command_read_token(tokenizer).boxed()).await;
+        let has_else   = maybe_else.map(|maybe_else| maybe_else.token == Some(CommandToken::Command) && maybe_else.fragment == "else").unwrap_or(false);
+
+        if has_else {
+            parser.accept_token()?;
+
+            let else_if = parser.lookahead(0, tokenizer, |tokenizer| command_read_token(tokenizer).boxed()).await;
+            let else_if = else_if.map(|else_if| else_if.token == Some(CommandToken::Command) && else_if.fragment == "if").unwrap_or(false);
+
+            if else_if {
+                command_parse_if(parser, tokenizer).await?;
+            } else {
+                command_parse_block(parser, tokenizer).await?;
+            }
+        }
+
+        // Reduce to an 'if' request
+        let num_entries = if has_else { 5 } else { 3 };
+        parser.reduce(num_entries, |mut if_request| {
+            let otherwise   = if has_else { Some(Box::new(if_request.pop().unwrap().to_node().unwrap())) } else { None };
+            let _else       = if has_else { if_request.pop() } else { None };
+            let then        = if_request.pop().unwrap().to_node().unwrap();
+            let condition   = if_request.pop().unwrap().to_node().unwrap();
+
+            match condition {
+                CommandRequest::Command { argument, .. }    => CommandRequest::If { condition: argument, then: Box::new(then), otherwise },
+                _                                           => { unreachable!() }
+            }
+        })?;
+
+        Ok(())
+    }.boxed()
+}
+
+///
+/// Parses a 'foreach :variable in <value> { commands }' request, at the point where the lookahead contains the 'foreach' token
+///
+async fn command_parse_foreach<TStream>(parser: &mut Parser<TokenMatch<CommandToken>, CommandRequest>, tokenizer: &mut Tokenizer<CommandToken, TStream>) -> Result<(), CommandParseError>
+where
+    TStream: Send + Stream<Item=Vec<u8>>,
+{
+    parser.accept_token()?;
+
+    // 'foreach' is followed by a variable name and 'in'
+    let variable = parser.lookahead(0, tokenizer, |tokenizer| command_read_token(tokenizer).boxed()).await.ok_or(CommandParseError::ExpectedMoreInput)?;
+    if variable.token != Some(CommandToken::Variable) { return Err(variable.into()); }
+    parser.accept_token()?;
+
+    let in_keyword = parser.lookahead(0, tokenizer, |tokenizer| command_read_token(tokenizer).boxed()).await.ok_or(CommandParseError::ExpectedMoreInput)?;
+    if in_keyword.token != Some(CommandToken::Command) || in_keyword.fragment != "in" { return Err(in_keyword.into()); }
+    parser.accept_token()?;
+
+    // Then the values to iterate over and the block to run
+    command_parse_argument(parser, tokenizer).await?;
+    command_parse_block(parser, tokenizer).await?;
+
+    parser.reduce(5, |mut foreach| {
+        let body        = foreach.pop().unwrap().to_node().unwrap();
+        let values      = foreach.pop().unwrap().to_node().unwrap();
+        let _in         = foreach.pop();
+        let variable    = foreach.pop().unwrap().to_token().unwrap();
+
+        match values {
+            CommandRequest::Command { argument, .. }    => CommandRequest::ForEach { variable: VariableName(variable.fragment), values: argument, body: Box::new(body) },
+            _                                           => { unreachable!() }
+        }
+    })?;
+
     Ok(())
 }
 
@@ -759,6 +1134,227 @@ mod test {
         });
     }
 
+    #[test]
+    fn parse_pipe() {
+        let pipe            = stream::iter("some::command [ 1, 2 ] | another::command\n".bytes()).ready_chunks(2);
+        let mut tokenizer   = Tokenizer::new(pipe);
+        let mut parser      = Parser::new();
+
+        tokenizer.with_command_matchers();
+
+        executor::block_on(async {
+            command_parse(&mut parser, &mut tokenizer).await.unwrap();
+            let result = parser.finish().unwrap();
+
+            assert!(result == CommandRequest::Pipe {
+                from:   Box::new(CommandRequest::Command { command: CommandName("some::command".to_string()), argument: json!{[1, 2]}.into() }),
+                to:     Box::new(CommandRequest::Command { command: CommandName("another::command".to_string()), argument: serde_json::Value::Null.into() }),
+            }, "{:?}", result);
+        });
+    }
+
+    #[test]
+    fn parse_pipe_chain() {
+        let pipe            = stream::iter("42 | first | second { } | third\nfourth".bytes()).ready_chunks(2);
+        let mut tokenizer   = Tokenizer::new(pipe);
+        let mut parser      = Parser::new();
+
+        tokenizer.with_command_matchers();
+
+        executor::block_on(async {
+            command_parse(&mut parser, &mut tokenizer).await.unwrap();
+            let result = parser.finish().unwrap();
+
+            assert!(result == CommandRequest::Pipe {
+                from:   Box::new(CommandRequest::RawJson { value: json!{42}.into() }),
+                to:     Box::new(CommandRequest::Pipe {
+                    from:   Box::new(CommandRequest::Command { command: CommandName("first".to_string()), argument: serde_json::Value::Null.into() }),
+                    to:     Box::new(CommandRequest::Pipe {
+                        from:   Box::new(CommandRequest::Command { command: CommandName("second".to_string()), argument: json!{{}}.into() }),
+                        to:     Box::new(CommandRequest::Command { command: CommandName("third".to_string()), argument: serde_json::Value::Null.into() }),
+                    }),
+                }),
+            }, "{:?}", result);
+
+            command_parse(&mut parser, &mut tokenizer).await.unwrap();
+            let result = parser.finish().unwrap();
+            assert!(result == CommandRequest::Command { command: CommandName("fourth".to_string()), argument: serde_json::Value::Null.into() });
+        });
+    }
+
+    #[test]
+    fn tokenize_target() {
+        let target          = "@some_program command";
+        let mut tokenizer   = Tokenizer::new(stream::iter(target.bytes()).ready_chunks(2));
+
+        tokenizer.with_command_matchers();
+        let target_token = executor::block_on(async { command_read_token(&mut tokenizer).await });
+
+        assert!(target_token.is_some());
+        let target_token = target_token.unwrap();
+
+        assert!(target_token.token == Some(CommandToken::Target), "{:?}", target_token);
+        assert!(target_token.fragment == "@some_program", "{:?}", target_token);
+    }
+
+    #[test]
+    fn parse_command_for_target() {
+        let command         = stream::iter("@some_program some::command { }\n".bytes()).ready_chunks(2);
+        let mut tokenizer   = Tokenizer::new(command);
+        let mut parser      = Parser::new();
+
+        tokenizer.with_command_matchers();
+
+        executor::block_on(async {
+            command_parse(&mut parser, &mut tokenizer).await.unwrap();
+            let result = parser.finish().unwrap();
+
+            assert!(result == CommandRequest::ForTarget {
+                target:     StreamTarget::Program(SubProgramId::called("some_program")),
+                request:    Box::new(CommandRequest::Command { command: CommandName("some::command".to_string()), argument: json!({}).into() }),
+            }, "{:?}", result);
+        });
+    }
+
+    #[test]
+    fn parse_pipe_to_target() {
+        let command         = stream::iter("@first one | @second two\n".bytes()).ready_chunks(2);
+        let mut tokenizer   = Tokenizer::new(command);
+        let mut parser      = Parser::new();
+
+        tokenizer.with_command_matchers();
+
+        executor::block_on(async {
+            command_parse(&mut parser, &mut tokenizer).await.unwrap();
+            let result = parser.finish().unwrap();
+
+            assert!(result == CommandRequest::Pipe {
+                from:   Box::new(CommandRequest::ForTarget {
+                    target:     StreamTarget::Program(SubProgramId::called("first")),
+                    request:    Box::new(CommandRequest::Command { command: CommandName("one".to_string()), argument: serde_json::Value::Null.into() }),
+                }),
+                to:     Box::new(CommandRequest::ForTarget {
+                    target:     StreamTarget::Program(SubProgramId::called("second")),
+                    request:    Box::new(CommandRequest::Command { command: CommandName("two".to_string()), argument: serde_json::Value::Null.into() }),
+                }),
+            }, "{:?}", result);
+        });
+    }
+
+    #[test]
+    fn match_nested_substitution() {
+        let match_result = match_substitution(r#"(first (second ")") [ 1 ]) trailing"#, false);
+        assert!(match_result == TokenMatchResult::Matches(CommandToken::Substitution, r#"(first (second ")") [ 1 ])"#.chars().count()), "{:?}", match_result);
+    }
+
+    #[test]
+    fn match_substitution_prefix() {
+        let match_result = match_substitution(r#"(first "#, false);
+        assert!(match_result == TokenMatchResult::LookaheadIsPrefix, "{:?}", match_result);
+    }
+
+    #[test]
+    fn parse_command_with_substitution() {
+        let command         = stream::iter(r#"send { "SubProgram": (find_program "logger") }"#.bytes()).ready_chunks(2);
+        let mut tokenizer   = Tokenizer::new(command);
+        let mut parser      = Parser::new();
+
+        tokenizer.with_command_matchers();
+
+        executor::block_on(async {
+            command_parse(&mut parser, &mut tokenizer).await.unwrap();
+            let result = parser.finish().unwrap();
+
+            let mut expected_argument = std::collections::HashMap::new();
+            expected_argument.insert("SubProgram".to_string(), ParsedJson::Command(r#"find_program "logger""#.to_string()));
+
+            assert!(result == CommandRequest::Command { command: CommandName("send".to_string()), argument: ParsedJson::Object(expected_argument) }, "{:?}", result);
+        });
+    }
+
+    #[test]
+    fn parse_if_else() {
+        let command         = stream::iter("if :flag {\n    first ; second [ 1 ]\n} else { third }\nfourth".bytes()).ready_chunks(2);
+        let mut tokenizer   = Tokenizer::new(command);
+        let mut parser      = Parser::new();
+
+        tokenizer.with_command_matchers();
+
+        executor::block_on(async {
+            command_parse(&mut parser, &mut tokenizer).await.unwrap();
+            let result = parser.finish().unwrap();
+
+            assert!(result == CommandRequest::If {
+                condition:  ParsedJson::Variable(":flag".into()),
+                then:       Box::new(CommandRequest::Block { requests: vec![
+                    CommandRequest::Command { command: CommandName("first".to_string()), argument: ParsedJson::Null },
+                    CommandRequest::Command { command: CommandName("second".to_string()), argument: json!{[1]}.into() },
+                ] }),
+                otherwise:  Some(Box::new(CommandRequest::Block { requests: vec![
+                    CommandRequest::Command { command: CommandName("third".to_string()), argument: ParsedJson::Null },
+                ] })),
+            }, "{:?}", result);
+
+            command_parse(&mut parser, &mut tokenizer).await.unwrap();
+            let result = parser.finish().unwrap();
+            assert!(result == CommandRequest::Command { command: CommandName("fourth".to_string()), argument: ParsedJson::Null });
+        });
+    }
+
+    #[test]
+    fn parse_foreach() {
+        let command         = stream::iter("foreach :item in :list {\n    // Comment\n    echo :item\n}\n".bytes()).ready_chunks(2);
+        let mut tokenizer   = Tokenizer::new(command);
+        let mut parser      = Parser::new();
+
+        tokenizer.with_command_matchers();
+
+        executor::block_on(async {
+            command_parse(&mut parser, &mut tokenizer).await.unwrap();
+            let result = parser.finish().unwrap();
+
+            assert!(result == CommandRequest::ForEach {
+                variable:   VariableName(":item".into()),
+                values:     ParsedJson::Variable(":list".into()),
+                body:       Box::new(CommandRequest::Block { requests: vec![
+                    CommandRequest::Command { command: CommandName("echo".to_string()), argument: ParsedJson::Variable(":item".into()) },
+                ] }),
+            }, "{:?}", result);
+        });
+    }
+
+    #[test]
+    fn parse_continue_on_error() {
+        let command         = stream::iter("-some::command { }\n".bytes()).ready_chunks(2);
+        let mut tokenizer   = Tokenizer::new(command);
+        let mut parser      = Parser::new();
+
+        tokenizer.with_command_matchers();
+
+        executor::block_on(async {
+            command_parse(&mut parser, &mut tokenizer).await.unwrap();
+            let result = parser.finish().unwrap();
+
+            assert!(result == CommandRequest::ContinueOnError {
+                request: Box::new(CommandRequest::Command { command: CommandName("some::command".to_string()), argument: json!({}).into() }),
+            }, "{:?}", result);
+        });
+    }
+
+    #[test]
+    fn parse_unclosed_block() {
+        let command         = stream::iter("if true {\n    first\n".bytes()).ready_chunks(2);
+        let mut tokenizer   = Tokenizer::new(command);
+        let mut parser      = Parser::new();
+
+        tokenizer.with_command_matchers();
+
+        executor::block_on(async {
+            let result = command_parse(&mut parser, &mut tokenizer).await;
+            assert!(result == Err(CommandParseError::UnclosedBlock), "{:?}", result);
+        });
+    }
+
     #[test]
     fn parse_variable_command() {
         let variable        = stream::iter(r#"$variable"#.bytes()).ready_chunks(2);
@@ -822,4 +1418,38 @@ mod test {
             assert!(result == CommandRequest::RawJson { value: json!{{"test": 1}}.into() }, "{:?}", result);
         });
     }
+
+    #[test]
+    fn parse_raw_json_without_whitespace() {
+        let json            = stream::iter(r#"{"a":1,"b":true,"c":null,"d":[false]}"#.bytes()).ready_chunks(2);
+        let mut tokenizer   = Tokenizer::new(json);
+        let mut parser      = Parser::new();
+
+        tokenizer.with_command_matchers();
+
+        executor::block_on(async {
+            command_parse(&mut parser, &mut tokenizer).await.unwrap();
+            let result = parser.finish().unwrap();
+
+            assert!(result == CommandRequest::RawJson { value: json!{{"a": 1, "b": true, "c": null, "d": [false]}}.into() }, "{:?}", result);
+        });
+    }
+
+    #[test]
+    fn parse_script_with_trailing_comment() {
+        executor::block_on(async {
+            let result = CommandRequest::parse_script("first\nsecond\n\n// Nothing else\n").await.unwrap();
+
+            assert!(result.len() == 2, "{:?}", result);
+        });
+    }
+
+    #[test]
+    fn parse_truncated_script() {
+        executor::block_on(async {
+            let result = CommandRequest::parse_script("first\nforeach :item").await;
+
+            assert!(result == Err(CommandParseError::ExpectedMoreInput), "{:?}", result);
+        });
+    }
 }
@@ -3,7 +3,7 @@ use super::socket::*;
 use flo_scene::*;
 use flo_scene::commands::*;
 
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use futures::prelude::*;
 use futures::stream::{BoxStream, ReadyChunks};
 
@@ -241,37 +241,17 @@ where
             match request {
                 InternalSocketMessage::CreateInternalSocket(async_reader, async_writer) => {
                     // Create the socket connection from the reader
-                    let reader_stream = create_reader_stream(Box::into_pin(async_reader));
+                    let reader_stream = create_reader_stream(Box::into_pin(async_reader), &SocketOptions::default());
                     let reader_stream = create_input_messages(reader_stream.boxed());
 
                     let create_output_messages  = Arc::clone(&create_output_messages);
                     let socket_connection       = SocketConnection::new(&context, reader_stream, move |context, output_stream| {
                         // Create a stream that converts to bytes
-                        let mut output_byte_stream = create_output_messages(output_stream);
+                        let output_byte_stream = create_output_messages(output_stream);
 
                         // Future to write the bytes
                         let async_writer = Box::into_pin(async_writer);
-                        let byte_writer  = async move {
-                            // Write each block as it arrives from the output byte stream to the socket target
-                            let mut async_writer = async_writer;
-                            while let Some(bytes) = output_byte_stream.next().await {
-                                // Loop until we've written all of the bytes
-                                let mut write_pos = 0;
-
-                                while write_pos < bytes.len() {
-                                    match async_writer.write(&bytes[write_pos..(bytes.len())]).await {
-                                        Ok(0)           => break,
-                                        Err(_)          => break,
-                                        Ok(num_written) => {
-                                            write_pos += num_written;
-                                            if write_pos >= bytes.len() {
-                                                break;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        };
+                        let byte_writer  = async move { write_byte_stream(output_byte_stream, async_writer, &SocketOptions::default()).await; };
                         let byte_writer = Mutex::new(Some(byte_writer));
 
                         // Ask the scene to create a subprogram that writes the output (won't work if the main 'scene' program isn't running)
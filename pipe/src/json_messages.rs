@@ -0,0 +1,172 @@
+use crate::parser::*;
+use crate::tokenizer::*;
+use crate::parse_json::*;
+
+use flo_scene::*;
+
+use futures::prelude::*;
+use futures::stream::{BoxStream};
+
+use std::any::{TypeId};
+
+///
+/// Reads tokens from a tokenizer until the end of the current line is reached (used to recover after invalid JSON is received)
+///
+async fn skip_to_end_of_line(tokenizer: &mut Tokenizer<JsonToken, BoxStream<'static, Vec<u8>>>) {
+    while let Some(token) = tokenizer.match_token().await {
+        if token.token == Some(JsonToken::Whitespace) && token.fragment.contains('\n') {
+            break;
+        }
+    }
+}
+
+///
+/// Parses the JSON values in a stream of bytes, returning them as serialized messages with the specified type ID
+///
+fn read_json_messages_with_type(input: BoxStream<'static, Vec<u8>>, message_type: TypeId) -> BoxStream<'static, SerializedMessage<serde_json::Value>> {
+    let mut tokenizer = Tokenizer::new(input);
+    tokenizer.with_json_matchers();
+
+    stream::unfold((tokenizer, Parser::new()), move |(mut tokenizer, mut parser)| async move {
+        loop {
+            // Skip any whitespace before the next value (the stream ends when the input does)
+            let next_token = tokenizer.match_token().await?;
+
+            match next_token.token {
+                Some(JsonToken::Whitespace) => { continue; }
+                None                        => { skip_to_end_of_line(&mut tokenizer).await; continue; }
+                Some(_)                     => { tokenizer.return_characters(next_token.fragment); }
+            }
+
+            // Parse the value, and send it as a message if it's valid
+            let parse_result = json_parse_value(&mut parser, &mut tokenizer).await;
+
+            match (parse_result, parser.finish()) {
+                (Ok(()), Ok(value)) => {
+                    return Some((SerializedMessage(value.to_serde(), message_type), (tokenizer, parser)));
+                }
+
+                _ => {
+                    // Discard the rest of the line if the JSON couldn't be parsed
+                    parser.abort();
+                    parser.return_lookahead().for_each(|_| { });
+
+                    skip_to_end_of_line(&mut tokenizer).await;
+                }
+            }
+        }
+    }).boxed()
+}
+
+///
+/// Reads a stream of newline-delimited JSON values from a socket as serialized messages of the specified type
+///
+/// This can be used as the `create_input_messages` function for any of the socket programs: for example,
+/// `start_unix_socket_program(&scene, program_id, path, read_json_messages::<MyMessage>, write_json_messages)`.
+/// Each JSON value that's received is passed on as a `SerializedMessage`, which can be sent on to a program that accepts
+/// `MyMessage` using `send_serialized()`. Lines that don't contain valid JSON are skipped.
+///
+pub fn read_json_messages<TMessage>(input: BoxStream<'static, Vec<u8>>) -> BoxStream<'static, SerializedMessage<serde_json::Value>>
+where
+    TMessage: 'static + SceneMessage,
+{
+    read_json_messages_with_type(input, TypeId::of::<TMessage>())
+}
+
+///
+/// Returns a function that reads newline-delimited JSON values from a socket as serialized messages, for the message type with the
+/// specified serialization name
+///
+/// This is the same as `read_json_messages()`, except the message type is chosen at runtime. The result is `None` if there's
+/// no serializable message type with the specified name.
+///
+pub fn read_json_messages_for_type(type_name: impl Into<String>) -> Option<impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> BoxStream<'static, SerializedMessage<serde_json::Value>>> {
+    let message_type = StreamId::with_serialization_type(type_name)?.message_type();
+
+    Some(move |input| read_json_messages_with_type(input, message_type))
+}
+
+///
+/// Writes serialized messages to a socket as newline-delimited JSON
+///
+/// This is the reverse of `read_json_messages()`: each message is written as a single line of JSON
+///
+pub fn write_json_messages(input: BoxStream<'static, SerializedMessage<serde_json::Value>>) -> BoxStream<'static, Vec<u8>> {
+    input.map(|SerializedMessage(value, _)| {
+        let mut bytes = value.to_string().into_bytes();
+        bytes.push(b'\n');
+
+        bytes
+    }).boxed()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor;
+    use serde::*;
+    use serde_json::json;
+
+    #[derive(Serialize, Deserialize)]
+    struct TestMessage;
+    impl SceneMessage for TestMessage { }
+
+    fn read_values(chunks: Vec<&'static str>) -> Vec<serde_json::Value> {
+        let input = stream::iter(chunks.into_iter().map(|chunk| chunk.bytes().collect::<Vec<_>>())).boxed();
+
+        executor::block_on(async move {
+            read_json_messages::<TestMessage>(input)
+                .map(|SerializedMessage(value, type_id)| {
+                    assert!(type_id == TypeId::of::<TestMessage>());
+                    value
+                })
+                .collect::<Vec<_>>()
+                .await
+        })
+    }
+
+    #[test]
+    fn read_single_value() {
+        let values = read_values(vec!["{ \"Field\": 42 }\n"]);
+        assert!(values == vec![json!({ "Field": 42 })], "{:?}", values);
+    }
+
+    #[test]
+    fn read_several_values() {
+        let values = read_values(vec!["1\n\"two\"\n[3]\n{ \"four\": 4 }\nnull\n"]);
+        assert!(values == vec![json!(1), json!("two"), json!([3]), json!({ "four": 4 }), json!(null)], "{:?}", values);
+    }
+
+    #[test]
+    fn read_values_split_across_chunks() {
+        let values = read_values(vec!["{ \"Fi", "eld\": 4", "2 }\n[1, ", "2]", "\n12", "34"]);
+        assert!(values == vec![json!({ "Field": 42 }), json!([1, 2]), json!(1234)], "{:?}", values);
+    }
+
+    #[test]
+    fn skip_invalid_lines() {
+        let values = read_values(vec!["1\n{ not json }\n2\n@@@\n3\n"]);
+        assert!(values == vec![json!(1), json!(2), json!(3)], "{:?}", values);
+    }
+
+    #[test]
+    fn read_value_before_more_input_arrives() {
+        let input = stream::iter(vec!["{ \"Field\": 42 }\n".bytes().collect::<Vec<_>>()]).chain(stream::pending()).boxed();
+
+        executor::block_on(async move {
+            let mut messages    = read_json_messages::<TestMessage>(input);
+            let first_message   = messages.next().await.unwrap();
+
+            assert!(first_message.0 == json!({ "Field": 42 }));
+        });
+    }
+
+    #[test]
+    fn write_values() {
+        let messages = stream::iter(vec![SerializedMessage(json!({ "Field": 42 }), TypeId::of::<TestMessage>()), SerializedMessage(json!([1, 2]), TypeId::of::<TestMessage>())]).boxed();
+        let bytes    = executor::block_on(write_json_messages(messages).concat());
+
+        assert!(String::from_utf8(bytes).unwrap() == "{\"Field\":42}\n[1,2]\n");
+    }
+}
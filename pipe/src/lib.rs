@@ -2,8 +2,15 @@ mod socket;
 mod unix_socket;
 mod internal_socket;
 mod tcp_socket;
+mod client_socket;
+mod scene_bridge;
+mod json_messages;
+mod socket_frames;
+#[cfg(feature="tls")] mod tls_socket;
+#[cfg(feature="websocket")] mod websocket;
 mod tokenizer;
 mod parse_json;
+mod scene_config;
 
 pub mod main_scene;
 pub mod sub_scene;
@@ -15,6 +22,13 @@ pub use socket::*;
 pub use unix_socket::*;
 pub use internal_socket::*;
 pub use tcp_socket::*;
+pub use client_socket::*;
+pub use scene_bridge::*;
+pub use json_messages::*;
+pub use socket_frames::*;
+#[cfg(feature="tls")] pub use tls_socket::*;
+#[cfg(feature="websocket")] pub use websocket::*;
+pub use scene_config::*;
 
 pub use commands::{JsonCommandLauncherExt};
 pub use standard_json_commands::{StandardCommandsLauncherExt, StandardCommandsSceneExt};
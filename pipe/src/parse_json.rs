@@ -16,6 +16,7 @@ pub enum JsonToken {
     Number,
     String,
     Variable,
+    Substitution,
     True,
     False,
     Null,
@@ -55,6 +56,9 @@ pub enum ParsedJson {
     Array(Vec<ParsedJson>),
     Object(HashMap<String, ParsedJson>),
     Variable(String),
+
+    /// A command whose result should be substituted for this value (the `(command)` syntax)
+    Command(String),
 }
 
 ///
@@ -396,6 +400,7 @@ where
             False           => match_false(lookahead, eof).into(),
             Null            => match_null(lookahead, eof).into(),
             Variable        => TokenMatchResult::LookaheadCannotMatch, // These are generated externally, so there's no matcher here
+            Substitution    => TokenMatchResult::LookaheadCannotMatch,
         }
     }
 }
@@ -466,6 +471,7 @@ where
                 Some(Ok(JsonToken::False))          => { parser.accept_token()?.reduce(1, |_| ParsedJson::Bool(false))?; Ok(()) },
                 Some(Ok(JsonToken::Null))           => { parser.accept_token()?.reduce(1, |_| ParsedJson::Null)?; Ok(()) },
                 Some(Ok(JsonToken::Variable))       => { let fragment = lookahead.fragment.clone(); parser.accept_token()?.reduce(1, |_| ParsedJson::Variable(fragment))?; Ok(()) },
+                Some(Ok(JsonToken::Substitution))   => { let command = lookahead.fragment[1..lookahead.fragment.len()-1].to_string(); parser.accept_token()?.reduce(1, |_| ParsedJson::Command(command))?; Ok(()) },
                 _                                   => Err(lookahead.into())
             }
         } else {
@@ -736,7 +742,7 @@ where
 
 impl From<ParsedJson> for serde_json::Value {
     ///
-    /// Converts a parsed JSON structure into a serde value. All variables are replaced with 'null':
+    /// Converts a parsed JSON structure into a serde value. All variables and commands are replaced with 'null':
     /// if you want to substitute these you will need to implement a separate converter.
     ///
     fn from(json: ParsedJson) -> serde_json::Value {
@@ -745,6 +751,7 @@ impl From<ParsedJson> for serde_json::Value {
 
         match json {
             Variable(_)     => Value::Null,
+            Command(_)      => Value::Null,
 
             Null            => Value::Null,
             Bool(val)       => Value::Bool(val),
@@ -0,0 +1,489 @@
+use super::socket::*;
+use super::socket_frames::*;
+use super::client_socket::*;
+#[cfg(unix)]
+use super::unix_socket::*;
+
+use flo_scene::*;
+use flo_scene::programs::*;
+
+use futures::prelude::*;
+use futures::channel::mpsc;
+use futures::future::{Either};
+use futures::stream::{BoxStream};
+use futures_timer::{Delay};
+use serde::*;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use tokio::net::{UnixStream};
+
+use std::collections::{VecDeque};
+use std::path::*;
+use std::sync::*;
+
+///
+/// A frame sent between the two sides of a scene bridge
+///
+/// Frames are sent as JSON, one per line. The proxy side sends `Message` frames, and the publishing side replies with a `Delivered` or
+/// an `Error` frame for each message once it has been passed on to its target.
+///
+#[derive(Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum BridgeFrame {
+    /// A serialized message, along with a sequence number that's used to acknowledge it
+    Message(u64, serde_json::Value),
+
+    /// The message with the specified sequence number has been accepted by the input stream of its target
+    Delivered(u64),
+
+    /// The message with the specified sequence number could not be delivered
+    Error(u64, String),
+}
+
+/// Socket message received by the programs that publish a stream for a scene bridge
+pub type BridgeSocketMessage = SocketMessage<Result<BridgeFrame, ConnectionError>, BridgeFrame>;
+
+///
+/// Events sent by a scene bridge proxy when its messages can't be passed on to the published target
+///
+/// These events are discarded unless a program is connected to receive them, for example with
+/// `scene.connect_programs(proxy_program, event_program, StreamId::with_message_type::<SceneBridgeEvent>())`.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SceneBridgeEvent {
+    /// A message sent to the proxy could not be serialized as JSON, so it was discarded
+    NotSerialized { error: String },
+
+    /// The publisher could not deliver the message with the specified sequence number to its target
+    NotDelivered { sequence: u64, error: String },
+
+    /// The frames sent by the publisher could not be read, so the proxy disconnected
+    InvalidFrames { error: ConnectionError },
+}
+
+impl SceneMessage for SceneBridgeEvent {
+    fn message_type_name() -> String { "flo_scene_pipe::SceneBridgeEvent".into() }
+
+    fn default_target() -> StreamTarget { StreamTarget::None }
+}
+
+///
+/// Returns a function that reads the frames sent over a scene bridge, rejecting any frame that's longer than the specified size
+///
+/// The stream ends after the first error, as the rest of the data from a connection that's sending an over-long frame can't be trusted
+///
+pub fn read_bridge_frames_with_max_size(max_frame_size: usize) -> impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> BoxStream<'static, Result<BridgeFrame, ConnectionError>> {
+    move |input| {
+        // State is the input stream, the buffered bytes, the position of the start of the next line and how far the buffer has been searched for a newline
+        stream::unfold(Some((input, vec![], 0, 0)), move |state| async move {
+            let (mut input, mut buffer, mut pos, mut scanned): (BoxStream<'static, Vec<u8>>, Vec<u8>, usize, usize) = state?;
+
+            loop {
+                // Return the next line from the buffer if there is one (only the bytes that weren't searched last time need to be checked)
+                if let Some(offset) = buffer[scanned..].iter().position(|byte| *byte == b'\n') {
+                    let line_start  = pos;
+                    let line_end    = scanned + offset;
+
+                    pos     = line_end + 1;
+                    scanned = pos;
+
+                    if line_end - line_start > max_frame_size {
+                        return Some((Err(ConnectionError::IoError(format!("Frame of {} bytes is larger than the maximum size of {} bytes", line_end - line_start, max_frame_size))), None));
+                    }
+
+                    if let Ok(frame) = serde_json::from_slice::<BridgeFrame>(&buffer[line_start..line_end]) {
+                        return Some((Ok(frame), Some((input, buffer, pos, scanned))));
+                    } else {
+                        continue;
+                    }
+                }
+
+                scanned = buffer.len();
+
+                if buffer.len() - pos > max_frame_size {
+                    return Some((Err(ConnectionError::IoError(format!("Frame is larger than the maximum size of {} bytes", max_frame_size))), None));
+                }
+
+                // Need to read more data: discard the lines that have already been read from the buffer first
+                buffer.drain(0..pos);
+                scanned -= pos;
+                pos     = 0;
+
+                // The stream ends when the input does
+                buffer.extend(input.next().await?);
+            }
+        }).boxed()
+    }
+}
+
+///
+/// Reads the frames sent over a scene bridge from a stream of bytes
+///
+/// Lines that are not valid frames are ignored. Lines longer than `DEFAULT_MAX_FRAME_SIZE` are reported as `ConnectionError::IoError`
+/// and end the stream: use `read_bridge_frames_with_max_size()` to choose a different limit. Often used with a socket, for example
+/// `start_unix_socket_program(&scene, socket_program, path, read_bridge_frames, write_bridge_frames)`
+///
+pub fn read_bridge_frames(input: BoxStream<'static, Vec<u8>>) -> BoxStream<'static, Result<BridgeFrame, ConnectionError>> {
+    read_bridge_frames_with_max_size(DEFAULT_MAX_FRAME_SIZE)(input)
+}
+
+///
+/// Converts the frames for a scene bridge to bytes ready to be sent to a socket stream
+///
+pub fn write_bridge_frames(output: BoxStream<'static, BridgeFrame>) -> BoxStream<'static, Vec<u8>> {
+    output.map(|frame| {
+        let mut bytes = serde_json::to_vec(&frame).unwrap_or_default();
+        bytes.push(b'\n');
+        bytes
+    }).boxed()
+}
+
+///
+/// Publishes a target in this scene so that messages can be sent to it from a proxy in another scene
+///
+/// The input is the connections from a socket (which should use `read_bridge_frames` and `write_bridge_frames`). Messages received
+/// on each connection are deserialized and sent to the target, which can either be a subprogram or the default target for a stream.
+/// The target's message type must be serializable as JSON. Messages are acknowledged once the target's input stream has accepted
+/// them, so proxies will wait when the target is busy.
+///
+/// A typical set up for publishing a stream is:
+///
+/// ```text
+/// start_unix_socket_program(&scene, socket_program, path, read_bridge_frames, write_bridge_frames)?;
+/// scene.add_subprogram(publisher_program, |input, context| scene_bridge_publisher_program(input, context, StreamId::with_message_type::<MyMessage>()), 0);
+/// scene.connect_programs(socket_program, publisher_program, StreamId::with_message_type::<BridgeSocketMessage>())?;
+/// ```
+///
+pub async fn scene_bridge_publisher_program(input: InputStream<BridgeSocketMessage>, context: SceneContext, target: impl Into<SerializedStreamTarget>) {
+    let target = target.into();
+
+    // Spawn a subprogram to deliver the messages for each connection
+    let mut input = input;
+    while let Some(connection) = input.next().await {
+        match connection {
+            SocketMessage::Connection(connection) => {
+                let target = target.clone();
+
+                let connection_id = SubProgramId::new();
+                context.send_message(SceneControl::start_program(
+                    connection_id,
+                    move |_: InputStream<()>, context| async move {
+                        // The responses are sent back over the connection as each message is delivered
+                        let (mut send_responses, recv_responses)    = mpsc::channel(16);
+                        let mut frames                              = connection.connect(recv_responses);
+                        let mut target_sink                         = context.send_serialized::<serde_json::Value>(target).map(Box::pin);
+
+                        while let Some(frame) = frames.next().await {
+                            let (sequence, message) = match frame {
+                                Ok(BridgeFrame::Message(sequence, message)) => (sequence, message),
+                                Ok(_)                                       => { continue; }
+                                Err(_)                                      => { break; }
+                            };
+
+                            let response = match &mut target_sink {
+                                Ok(target_sink) => match target_sink.send(message).await {
+                                    Ok(())      => BridgeFrame::Delivered(sequence),
+                                    Err(err)    => BridgeFrame::Error(sequence, format!("{:?}", err)),
+                                },
+                                Err(err)        => BridgeFrame::Error(sequence, format!("{:?}", err)),
+                            };
+
+                            if send_responses.send(response).await.is_err() {
+                                break;
+                            }
+                        }
+                    },
+                    0)).await.ok();
+            }
+        }
+    }
+}
+
+///
+/// An event received by a scene bridge proxy
+///
+enum ProxyEvent<TMessage> {
+    /// A frame was received from the publisher (None if the connection was closed)
+    Frame(Option<Result<BridgeFrame, ConnectionError>>),
+
+    /// A message was received from the proxy's input stream (None if the input stream was closed)
+    Message(Option<TMessage>),
+}
+
+///
+/// Runs a proxy for a target that was published in another scene using `scene_bridge_publisher_program()`
+///
+/// Messages sent to the proxy are serialized as JSON and forwarded to the publisher over the connections made by the `connect`
+/// function. At most `max_input_waiting` messages (or 1 if this is 0) can be waiting to be acknowledged by the publisher: the proxy
+/// stops reading its input until the publisher catches up, so senders wait in the same way as they would when sending to a busy
+/// local subprogram.
+///
+/// If the connection is lost, the proxy waits for the delay chosen by the backoff and then reconnects, sending any messages that
+/// were not acknowledged again (this means that a message can occasionally be delivered twice). The proxy stops if the backoff runs
+/// out of attempts. Messages that can't be serialized or that the publisher could not deliver are discarded, and reported with a
+/// `SceneBridgeEvent`.
+///
+pub async fn scene_bridge_proxy_program<TMessage, TFutureStream, TReadStream, TWriteStream>(
+    input:              InputStream<TMessage>,
+    context:            SceneContext,
+    connect:            impl 'static + Send + Fn() -> TFutureStream,
+    backoff:            ReconnectBackoff,
+    max_input_waiting:  usize)
+where
+    TMessage:       'static + SceneMessage,
+    TFutureStream:  Send + Future<Output=Result<(TReadStream, TWriteStream, SocketPeer), ConnectionError>>,
+    TReadStream:    'static + Send + AsyncRead,
+    TWriteStream:   'static + Send + AsyncWrite,
+{
+    let max_waiting         = max_input_waiting.max(1);
+    let write_frames        = Arc::new(write_bridge_frames);
+    let mut input           = input;
+    let mut input_closed    = false;
+    let mut waiting         = VecDeque::<(u64, serde_json::Value)>::new();
+    let mut next_sequence   = 0;
+    let mut attempt         = 0;
+
+    // Events are discarded unless something is connected to receive them
+    let mut events          = context.send::<SceneBridgeEvent>(StreamTarget::None).unwrap();
+
+    loop {
+        let delay = match connect().await {
+            Ok((async_reader, async_writer, peer)) => {
+                // Connect to the publisher
+                let reader_stream                   = create_reader_stream(async_reader, &SocketOptions::default()).boxed();
+                let connection                      = create_socket_connection(&context, reader_stream, async_writer, peer, &SocketOptions::default(), None, &read_bridge_frames, Arc::clone(&write_frames));
+                let (mut send_frames, recv_frames)  = mpsc::channel(max_waiting);
+                let mut frames                      = connection.connect(recv_frames);
+
+                attempt = 0;
+
+                // Anything that wasn't acknowledged on the last connection is sent again
+                for (sequence, message) in waiting.iter() {
+                    send_frames.send(BridgeFrame::Message(*sequence, message.clone())).await.ok();
+                }
+
+                loop {
+                    // Stop once everything has been delivered and there are no more messages to send
+                    if input_closed && waiting.is_empty() {
+                        return;
+                    }
+
+                    // Only read from the input when there's space for another message to be waiting
+                    let event = if !input_closed && waiting.len() < max_waiting {
+                        match future::select(frames.next(), input.next()).await {
+                            Either::Left((frame, _))    => ProxyEvent::Frame(frame),
+                            Either::Right((message, _)) => ProxyEvent::Message(message),
+                        }
+                    } else {
+                        ProxyEvent::Frame(frames.next().await)
+                    };
+
+                    match event {
+                        ProxyEvent::Frame(Some(Ok(BridgeFrame::Delivered(sequence)))) => {
+                            // Messages are delivered in order, so this acknowledges everything up to the sequence number
+                            while waiting.front().map(|(waiting_sequence, _)| *waiting_sequence <= sequence).unwrap_or(false) {
+                                waiting.pop_front();
+                            }
+                        }
+
+                        ProxyEvent::Frame(Some(Ok(BridgeFrame::Error(sequence, error)))) => {
+                            // The message is acknowledged in the same way as a delivered one, but is reported as an error
+                            while waiting.front().map(|(waiting_sequence, _)| *waiting_sequence <= sequence).unwrap_or(false) {
+                                waiting.pop_front();
+                            }
+
+                            events.send(SceneBridgeEvent::NotDelivered { sequence, error }).await.ok();
+                        }
+
+                        ProxyEvent::Frame(Some(Ok(BridgeFrame::Message(_, _))))    => { }
+                        ProxyEvent::Frame(None)                                     => { break; }
+
+                        ProxyEvent::Frame(Some(Err(error))) => {
+                            // The connection can't be used once its framing is lost, so disconnect (and reconnect after the backoff)
+                            events.send(SceneBridgeEvent::InvalidFrames { error }).await.ok();
+                            break;
+                        }
+
+                        ProxyEvent::Message(Some(message)) => {
+                            // Messages that can't be serialized are reported and then discarded
+                            match MessageSerializeAs::<serde_json::Value>::to_serialized(&message) {
+                                Ok(message) => {
+                                    let sequence = next_sequence;
+                                    next_sequence += 1;
+
+                                    waiting.push_back((sequence, message.clone()));
+                                    send_frames.send(BridgeFrame::Message(sequence, message)).await.ok();
+                                }
+
+                                Err(err) => {
+                                    events.send(SceneBridgeEvent::NotSerialized { error: format!("{:?}", err) }).await.ok();
+                                }
+                            }
+                        }
+
+                        ProxyEvent::Message(None) => { input_closed = true; }
+                    }
+                }
+
+                backoff.delay(0)
+            }
+
+            Err(_) => {
+                attempt += 1;
+                backoff.delay(attempt - 1)
+            }
+        };
+
+        // Wait before trying again (or give up if we've run out of attempts)
+        if let Some(delay) = delay {
+            Delay::new(delay).await;
+        } else {
+            break;
+        }
+    }
+}
+
+///
+/// Starts a proxy subprogram for a target published by another scene at the unix domain socket with the specified path
+///
+/// The proxy appears in this scene as a normal subprogram accepting messages of type `TMessage`, which are forwarded to the published
+/// target. See `scene_bridge_proxy_program()` for details of how flow control and reconnection work. To use this subprogram, the
+/// scene must be running inside a tokio runtime. The proxy will discard its messages if this crate was not compiled for UNIX.
+///
+pub fn start_unix_scene_bridge_proxy<TMessage>(
+        scene:              &Scene,
+        program_id:         SubProgramId,
+        path:               impl AsRef<Path>,
+        backoff:            ReconnectBackoff,
+        max_input_waiting:  usize
+    ) -> Result<(), ConnectionError>
+where
+    TMessage: 'static + SceneMessage,
+{
+    #[cfg(unix)]
+    {
+        let path = path.as_ref().to_path_buf();
+
+        scene.add_subprogram(program_id, move |input: InputStream<TMessage>, context| scene_bridge_proxy_program(input, context, move || {
+                let path = path.clone();
+
+                async move {
+                    let socket              = UnixStream::connect(path).await?;
+                    let peer                = unix_socket_peer(&socket);
+                    let (reader, writer)    = socket.into_split();
+
+                    Ok((reader, writer, peer))
+                }
+            },
+            backoff,
+            max_input_waiting), max_input_waiting);
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    {
+        // If we're not on Unix, this creates a program that ignores its messages (we can't connect to any UNIX sockets)
+        let _ = (path, backoff);
+
+        scene.add_subprogram(program_id, move |input: InputStream<TMessage>, _context| async move {
+            let mut input = input;
+            while let Some(_) = input.next().await {
+            }
+        }, max_input_waiting);
+
+        Ok(())
+    }
+}
+
+///
+/// Starts a proxy subprogram for a target published by another scene on an unencrypted TCP socket at the specified address
+///
+/// The proxy appears in this scene as a normal subprogram accepting messages of type `TMessage`, which are forwarded to the published
+/// target. See `scene_bridge_proxy_program()` for details of how flow control and reconnection work.
+///
+pub fn start_tcp_scene_bridge_proxy<TMessage>(
+        scene:              &Scene,
+        program_id:         SubProgramId,
+        address:            impl 'static + Send + Sync + Clone + ToSocketAddrs,
+        backoff:            ReconnectBackoff,
+        max_input_waiting:  usize
+    ) -> Result<(), ConnectionError>
+where
+    TMessage: 'static + SceneMessage,
+{
+    scene.add_subprogram(program_id, move |input: InputStream<TMessage>, context| scene_bridge_proxy_program(input, context, move || {
+            let address = address.clone();
+
+            async move {
+                let socket = TcpStream::connect(address).await?;
+                socket.set_nodelay(true).ok();
+
+                let peer                = SocketPeer { address: socket.peer_addr().ok().map(|addr| addr.to_string()), ..SocketPeer::default() };
+                let (reader, writer)    = socket.into_split();
+
+                Ok((reader, writer, peer))
+            }
+        },
+        backoff,
+        max_input_waiting), max_input_waiting);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor;
+
+    #[test]
+    fn read_frames_split_across_blocks() {
+        let bytes   = write_bridge_frames(stream::iter(vec![BridgeFrame::Message(0, serde_json::json!({ "a": 1 })), BridgeFrame::Delivered(0)]).boxed());
+        let bytes   = executor::block_on(bytes.collect::<Vec<_>>()).concat();
+
+        // Split the bytes into small blocks, and add a line that isn't a frame
+        let mut blocks = bytes.chunks(3).map(|block| block.to_vec()).collect::<Vec<_>>();
+        blocks.insert(0, b"not a frame\n".to_vec());
+
+        let frames = executor::block_on(read_bridge_frames(stream::iter(blocks).boxed()).collect::<Vec<_>>());
+
+        assert!(frames == vec![Ok(BridgeFrame::Message(0, serde_json::json!({ "a": 1 }))), Ok(BridgeFrame::Delivered(0))], "{:?}", frames);
+    }
+
+    #[test]
+    fn read_many_frames_in_one_block() {
+        let expected    = (0..100).map(BridgeFrame::Delivered).collect::<Vec<_>>();
+        let bytes       = write_bridge_frames(stream::iter(expected.clone()).boxed());
+        let bytes       = executor::block_on(bytes.collect::<Vec<_>>()).concat();
+
+        let frames = executor::block_on(read_bridge_frames(stream::iter(vec![bytes]).boxed()).collect::<Vec<_>>());
+
+        assert!(frames == expected.into_iter().map(Ok).collect::<Vec<_>>(), "{:?}", frames);
+    }
+
+    #[test]
+    fn reject_long_frame() {
+        let mut blocks = vec![b"{\"Delivered\":0}\n".to_vec()];
+        blocks.extend((0..10).map(|_| vec![b' '; 16]));
+
+        let frames = executor::block_on(read_bridge_frames_with_max_size(64)(stream::iter(blocks).boxed()).collect::<Vec<_>>());
+
+        assert!(frames.len() == 2, "{:?}", frames);
+        assert!(frames[0] == Ok(BridgeFrame::Delivered(0)), "{:?}", frames);
+        assert!(matches!(frames[1], Err(ConnectionError::IoError(_))), "{:?}", frames);
+    }
+
+    #[test]
+    fn reject_long_frame_in_one_block() {
+        let mut bytes = vec![b' '; 100];
+        bytes.push(b'\n');
+
+        let frames = executor::block_on(read_bridge_frames_with_max_size(64)(stream::iter(vec![bytes]).boxed()).collect::<Vec<_>>());
+
+        assert!(frames.len() == 1, "{:?}", frames);
+        assert!(matches!(frames[0], Err(ConnectionError::IoError(_))), "{:?}", frames);
+    }
+}
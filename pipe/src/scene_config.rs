@@ -0,0 +1,315 @@
+//!
+//! # Declarative scene configuration
+//!
+//! A `SceneConfig` describes a set of subprograms and the connections between them, and can be loaded from a RON file
+//! so that the topology of a scene can be changed without recompiling. Subprograms are created by name from a
+//! `SceneProgramRegistry`, and connections are specified by program name and by the `message_type_name()` of the
+//! stream that is being connected.
+//!
+//! ```text
+//! (
+//!     subprograms: [
+//!         (name: "logger",    program: "log_writer", max_input_waiting: 20),
+//!         (name: "producer",  program: "producer"),
+//!     ],
+//!     connections: [
+//!         (source: Some("producer"), target: Some("logger"), message_type: "my_crate::LogMessage"),
+//!     ],
+//! )
+//! ```
+//!
+
+use flo_scene::*;
+use flo_scene::programs::*;
+
+use futures::prelude::*;
+use serde::*;
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path};
+use std::sync::*;
+
+/// Function that creates a subprogram from a registry entry
+type ProgramFactoryFn = Arc<dyn Send + Sync + Fn(SubProgramId, usize) -> SceneProgramFn>;
+
+///
+/// A subprogram that should be started as part of a scene configuration
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize)]
+pub struct SubProgramConfig {
+    /// The name of the subprogram (which is used to generate its `SubProgramId` via `SubProgramId::called()`)
+    pub name: String,
+
+    /// The name of the program in the `SceneProgramRegistry` that should be used to create this subprogram
+    pub program: String,
+
+    /// The maximum number of messages that can be waiting in the input stream of this program
+    #[serde(default)]
+    pub max_input_waiting: usize,
+}
+
+///
+/// A connection that should be made as part of a scene configuration
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize)]
+pub struct ConnectionConfig {
+    /// The name of the program whose output should be connected, or `None` to connect the output of all programs
+    #[serde(default)]
+    pub source: Option<String>,
+
+    /// The name of the program that should receive the messages, or `None` to reconnect to the default target for the message type
+    #[serde(default)]
+    pub target: Option<String>,
+
+    /// The `message_type_name()` of the stream that is being connected
+    pub message_type: String,
+}
+
+///
+/// Describes the subprograms and connections that make up a scene
+///
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[derive(Serialize, Deserialize)]
+pub struct SceneConfig {
+    /// The subprograms to start in the scene
+    #[serde(default)]
+    pub subprograms: Vec<SubProgramConfig>,
+
+    /// The connections to make in the scene (these are applied after all of the subprograms have been started)
+    #[serde(default)]
+    pub connections: Vec<ConnectionConfig>,
+}
+
+///
+/// The set of named programs and message types that can be referred to by a `SceneConfig`
+///
+#[derive(Clone, Default)]
+pub struct SceneProgramRegistry {
+    /// The functions that can create subprograms, by name
+    programs: HashMap<String, ProgramFactoryFn>,
+
+    /// The stream IDs for the message types that are known to the registry, indexed by message type name
+    message_types: HashMap<String, StreamId>,
+}
+
+impl SceneProgramRegistry {
+    ///
+    /// Creates a new registry with no programs in it
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Adds a program to this registry that can be started by a configuration using the specified name
+    ///
+    /// The input message type for the program is also registered so it can be used in connections.
+    ///
+    pub fn with_program<TProgramFn, TInputMessage, TFuture>(mut self, name: impl Into<String>, program: TProgramFn) -> Self
+    where
+        TFuture:        'static + Send + Future<Output=()>,
+        TInputMessage:  'static + SceneMessage,
+        TProgramFn:     'static + Send + Sync + Fn(InputStream<TInputMessage>, SceneContext) -> TFuture,
+    {
+        let program                         = Arc::new(program);
+        let factory: ProgramFactoryFn       = Arc::new(move |program_id, max_input_waiting| {
+            let program = program.clone();
+            SceneProgramFn::new(program_id, move |input, context| (*program)(input, context), max_input_waiting)
+        });
+
+        self.programs.insert(name.into(), factory);
+        self.with_message_type::<TInputMessage>()
+    }
+
+    ///
+    /// Registers a message type so that it can be used in the connections for a configuration
+    ///
+    /// Messages that have been installed as serializable types don't need to be registered, and the input types of any
+    /// program added by `with_program()` are registered automatically.
+    ///
+    pub fn with_message_type<TMessageType>(mut self) -> Self
+    where
+        TMessageType: 'static + SceneMessage,
+    {
+        self.message_types.insert(TMessageType::message_type_name(), StreamId::with_message_type::<TMessageType>());
+        self
+    }
+
+    ///
+    /// Retrieves the stream ID for a message type name, if it's known
+    ///
+    pub fn stream_id_for_message_type(&self, message_type_name: &str) -> Option<StreamId> {
+        self.message_types.get(message_type_name)
+            .cloned()
+            .or_else(|| StreamId::with_serialization_type(message_type_name))
+    }
+
+    ///
+    /// Creates the function that will start a program from this registry
+    ///
+    pub fn create_program(&self, name: &str, program_id: SubProgramId, max_input_waiting: usize) -> Option<SceneProgramFn> {
+        self.programs.get(name)
+            .map(|factory| (factory)(program_id, max_input_waiting))
+    }
+}
+
+impl ConnectionConfig {
+    ///
+    /// Returns the source, target and stream for this connection
+    ///
+    pub fn connection(&self, registry: &SceneProgramRegistry) -> Result<(StreamSource, StreamTarget, StreamId), ConnectionError> {
+        let stream_id   = registry.stream_id_for_message_type(&self.message_type).ok_or(ConnectionError::StreamNotKnown)?;
+        let source      = match &self.source {
+            Some(name)  => StreamSource::Program(SubProgramId::called(name)),
+            None        => StreamSource::All,
+        };
+        let target      = match &self.target {
+            Some(name)  => StreamTarget::Program(SubProgramId::called(name)),
+            None        => StreamTarget::Any,
+        };
+
+        Ok((source, target, stream_id))
+    }
+}
+
+impl SceneConfig {
+    ///
+    /// Reads a scene configuration from a string in RON format
+    ///
+    pub fn from_ron(config: &str) -> Result<SceneConfig, ConnectionError> {
+        ron::from_str(config)
+            .map_err(|err| ConnectionError::InvalidConfiguration(format!("{}", err)))
+    }
+
+    ///
+    /// Loads a scene configuration from a file in RON format
+    ///
+    pub fn load(path: impl AsRef<Path>) -> Result<SceneConfig, ConnectionError> {
+        let config = std::fs::read_to_string(path)
+            .map_err(|io_err| ConnectionError::IoError(format!("{}", io_err)))?;
+
+        Self::from_ron(&config)
+    }
+
+    ///
+    /// Converts this configuration into a string in RON format
+    ///
+    pub fn to_ron(&self) -> Result<String, ConnectionError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| ConnectionError::InvalidConfiguration(format!("{}", err)))
+    }
+
+    ///
+    /// Checks that this configuration can be applied using the specified registry
+    ///
+    pub fn validate(&self, registry: &SceneProgramRegistry) -> Result<(), ConnectionError> {
+        let mut names = HashSet::new();
+
+        for subprogram in self.subprograms.iter() {
+            // Each subprogram must only be started once
+            if !names.insert(subprogram.name.as_str()) {
+                return Err(ConnectionError::InvalidConfiguration(format!("Subprogram '{}' is defined more than once", subprogram.name)));
+            }
+
+            // The program must be in the registry
+            if !registry.programs.contains_key(&subprogram.program) {
+                return Err(ConnectionError::InvalidConfiguration(format!("Program '{}' (used by subprogram '{}') is not in the registry", subprogram.program, subprogram.name)));
+            }
+        }
+
+        for connection in self.connections.iter() {
+            // All of the message types must be known
+            if registry.stream_id_for_message_type(&connection.message_type).is_none() {
+                return Err(ConnectionError::StreamNotKnown);
+            }
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Generates the scene control messages that will set up the subprograms and connections in this configuration
+    ///
+    /// The configuration is validated first, so either all of the messages are returned or none of them are.
+    ///
+    pub fn scene_control_messages(&self, registry: &SceneProgramRegistry) -> Result<Vec<SceneControl>, ConnectionError> {
+        self.validate(registry)?;
+
+        let mut messages = vec![];
+
+        // Start the subprograms first
+        for subprogram in self.subprograms.iter() {
+            let program_id  = SubProgramId::called(&subprogram.name);
+            let start_fn    = registry.create_program(&subprogram.program, program_id, subprogram.max_input_waiting).ok_or(ConnectionError::TargetNotAvailable)?;
+
+            messages.push(SceneControl::Start(start_fn));
+        }
+
+        // Then connect them together
+        for connection in self.connections.iter() {
+            let (source, target, stream_id) = connection.connection(registry)?;
+
+            messages.push(SceneControl::Connect(source, target, stream_id));
+        }
+
+        Ok(messages)
+    }
+
+    ///
+    /// Creates a `SceneTopology` from this configuration, which can be sent to the scene control program using `SceneControl::ApplyTopology`
+    ///
+    /// This can be used to reconfigure a running scene: programs that are no longer in the configuration are stopped, and the
+    /// connections are changed as a single operation.
+    ///
+    pub fn topology(&self, registry: &SceneProgramRegistry) -> Result<SceneTopology, ConnectionError> {
+        self.validate(registry)?;
+
+        let mut topology = SceneTopology::new();
+
+        for subprogram in self.subprograms.iter() {
+            let program_id  = SubProgramId::called(&subprogram.name);
+            let start_fn    = registry.create_program(&subprogram.program, program_id, subprogram.max_input_waiting).ok_or(ConnectionError::TargetNotAvailable)?;
+
+            topology = topology.with_program_fn(program_id, start_fn);
+        }
+
+        for connection in self.connections.iter() {
+            let (source, target, stream_id) = connection.connection(registry)?;
+
+            topology = topology.with_connection(source, target, stream_id);
+        }
+
+        Ok(topology)
+    }
+
+    ///
+    /// Applies this configuration to a scene directly (this is useful for setting up a scene before it's running)
+    ///
+    pub fn apply_to_scene(&self, scene: &Scene, registry: &SceneProgramRegistry) -> Result<(), ConnectionError> {
+        for message in self.scene_control_messages(registry)? {
+            match message {
+                SceneControl::Start(start_fn)                   => { start_fn.add_to_scene(scene); }
+                SceneControl::Connect(source, target, stream)   => { scene.connect_programs(source, target, stream)?; }
+                _                                               => { }
+            }
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Applies this configuration to a running scene by sending messages to the scene control program
+    ///
+    pub async fn apply(&self, context: &SceneContext, registry: &SceneProgramRegistry) -> Result<(), ConnectionError> {
+        let messages        = self.scene_control_messages(registry)?;
+        let mut control     = context.send::<SceneControl>(())?;
+
+        for message in messages {
+            control.send(message).await?;
+        }
+
+        Ok(())
+    }
+}
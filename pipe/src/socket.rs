@@ -2,9 +2,14 @@ use flo_scene::*;
 use flo_scene::programs::*;
 
 use futures::prelude::{Stream, Future};
+use futures::channel::{mpsc};
+use futures::future;
+use futures::future::{AbortHandle, AbortRegistration, Abortable, Either};
 use futures::stream;
 use futures::stream::{BoxStream, StreamExt};
-use futures::{pin_mut};
+use futures::task::{Context, Poll, Waker};
+use futures::{pin_mut, FutureExt, SinkExt};
+use futures_timer::{Delay};
 
 use tokio::io::*;
 
@@ -12,8 +17,162 @@ use serde::*;
 use serde::de::{Error as DeError};
 use serde::ser::{Error as SeError};
 
+use std::collections::{HashMap, VecDeque};
+use std::io::{IoSlice};
+use std::pin::{Pin};
 use std::result::{Result};
 use std::sync::*;
+use std::time::{Duration, Instant};
+
+///
+/// Information about the process at the other end of a socket connection
+///
+/// The fields are only filled in when the socket type can supply them: for example, Unix domain sockets can read the credentials
+/// of the connecting process, TCP sockets know the address of the peer, but internal sockets have no peer information.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SocketPeer {
+    /// The address of the other end of the connection (for example, the IP address and port for a TCP connection)
+    pub address: Option<String>,
+
+    /// The user ID of the process that made the connection
+    pub user_id: Option<u32>,
+
+    /// The group ID of the process that made the connection
+    pub group_id: Option<u32>,
+
+    /// The process ID of the process that made the connection
+    pub process_id: Option<i32>,
+
+    /// The DER-encoded certificate that the client supplied to identify itself (TLS sockets that request client certificates only)
+    pub client_certificate: Option<Vec<u8>>,
+}
+
+///
+/// Options that control how data is read from and written to the sockets created by a socket program
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// The maximum number of bytes that are read from a socket at once
+    read_buffer_size: usize,
+
+    /// The maximum number of blocks of output that are combined into a single write
+    max_write_blocks: usize,
+
+    /// How long a connection can go without receiving any data before it's closed (None to leave idle connections open)
+    idle_timeout: Option<Duration>,
+
+    /// The maximum number of connections a listener will have open at once (None for no limit)
+    max_connections: Option<usize>,
+
+    /// True if the connections that are open when a listener is stopped should be left to finish, false if they should be closed
+    drain_connections: bool,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        SocketOptions {
+            read_buffer_size:   16384,
+            max_write_blocks:   64,
+            idle_timeout:       None,
+            max_connections:    None,
+            drain_connections:  false,
+        }
+    }
+}
+
+impl SocketOptions {
+    ///
+    /// Creates the default set of socket options
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Sets the maximum number of bytes that are read from a socket at once (this is the largest block that is passed to the
+    /// `create_input_messages` function)
+    ///
+    pub fn with_read_buffer_size(mut self, read_buffer_size: usize) -> Self {
+        self.read_buffer_size = read_buffer_size.max(1);
+        self
+    }
+
+    ///
+    /// Sets the maximum number of blocks from the output stream that can be sent to the socket in a single write
+    ///
+    pub fn with_max_write_blocks(mut self, max_write_blocks: usize) -> Self {
+        self.max_write_blocks = max_write_blocks.max(1);
+        self
+    }
+
+    ///
+    /// Sets how long a connection can go without receiving any data before it's closed by the listener
+    ///
+    /// The timeout runs from when data was last read from the connection, so a connection that the receiving program isn't reading
+    /// from is also closed once it has been left for this long.
+    ///
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    ///
+    /// Sets the maximum number of connections that a listener will have open at once. Any connections made while the listener is
+    /// at the limit are closed immediately, and reported with a `SocketEvent::Rejected` message.
+    ///
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = Some(max_connections);
+        self
+    }
+
+    ///
+    /// Sets whether or not the connections that are open when a listener is stopped are left to finish
+    ///
+    /// A listener stops when its input stream is closed (for example, with `SceneControl::Close(program_id)`). It stops accepting
+    /// connections immediately: by default, any connections that are still open are closed at the same time, but if this is set to
+    /// true, the listener program will instead wait for them to close by themselves before finishing.
+    ///
+    pub fn with_drain_connections(mut self, drain_connections: bool) -> Self {
+        self.drain_connections = drain_connections;
+        self
+    }
+
+    ///
+    /// Returns the maximum number of bytes that are read from a socket at once
+    ///
+    pub fn read_buffer_size(&self) -> usize {
+        self.read_buffer_size
+    }
+
+    ///
+    /// Returns the maximum number of blocks from the output stream that are sent to the socket in a single write
+    ///
+    pub fn max_write_blocks(&self) -> usize {
+        self.max_write_blocks
+    }
+
+    ///
+    /// Returns how long a connection can go without receiving any data before it's closed
+    ///
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    ///
+    /// Returns the maximum number of connections that a listener will have open at once
+    ///
+    pub fn max_connections(&self) -> Option<usize> {
+        self.max_connections
+    }
+
+    ///
+    /// Returns true if the connections that are open when a listener is stopped are left to finish
+    ///
+    pub fn drain_connections(&self) -> bool {
+        self.drain_connections
+    }
+}
 
 ///
 /// Represents an incoming socket connection. When a socket is connected, we retrieve an input stream, and need to respond with an output stream.
@@ -29,6 +188,9 @@ pub struct SocketConnection<TInputMessage, TOutputMessage> {
 
     /// Sends the output of a stream as the response to a socket (set to None once the socket is created)
     create_output_stream: Option<Box<dyn Send + FnOnce(&SceneContext, BoxStream<'static, TOutputMessage>) -> ()>>,
+
+    /// Information about the process that made this connection
+    peer: SocketPeer,
 }
 
 ///
@@ -55,6 +217,58 @@ impl<TInputMessage: 'static, TOutputMessage: 'static> SceneMessage for SocketMes
 }
 
 
+///
+/// The reasons that a connection to a socket listener can close
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SocketCloseReason {
+    /// The other end of the connection closed it
+    ClosedByPeer,
+
+    /// No data was received for longer than the listener's idle timeout
+    IdleTimeout,
+
+    /// Reading from the connection failed
+    Error(String),
+
+    /// The program that was reading from the connection stopped reading from it
+    Dropped,
+
+    /// The listener was stopped, and closed the connection
+    ListenerStopped,
+}
+
+///
+/// Events sent by a socket listener program as connections are opened and closed
+///
+/// Each connection is given an ID that's unique to the listener, and each event includes the number of connections that the listener has
+/// open after the event. These events are discarded unless a program is connected to receive them, for example with
+/// `scene.connect_programs(socket_program, event_program, StreamId::with_message_type::<SocketEvent>())`.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SocketEvent {
+    /// A new connection was accepted
+    Connected { connection_id: usize, peer: SocketPeer, open_connections: usize },
+
+    /// A connection was closed
+    Closed { connection_id: usize, reason: SocketCloseReason, open_connections: usize },
+
+    /// A connection was closed immediately because the listener already had the maximum number of connections open
+    Rejected { peer: SocketPeer, open_connections: usize },
+
+    /// The listener has stopped accepting connections (the listening socket is closed at this point)
+    StoppedListening { open_connections: usize },
+
+    /// The listening socket could not be set up, so the listener program stopped without accepting any connections
+    ListenFailed { error: ConnectionError },
+}
+
+impl SceneMessage for SocketEvent {
+    fn message_type_name() -> String { "flo_scene_pipe::SocketEvent".into() }
+
+    fn default_target() -> StreamTarget { StreamTarget::None }
+}
+
 impl<TInputMessage, TOutputMessage> Serialize for SocketMessage<TInputMessage, TOutputMessage> {
     fn serialize<S>(&self, _: S) -> Result<S::Ok, S::Error>
     where
@@ -86,9 +300,25 @@ where
             context:                context.clone(),
             input_stream:           Some(input.fuse().boxed()),
             create_output_stream:   Some(Box::new(send_output)),
+            peer:                   SocketPeer::default(),
         }
     }
 
+    ///
+    /// Sets the information about the process that made this connection
+    ///
+    pub fn with_peer(mut self, peer: SocketPeer) -> Self {
+        self.peer = peer;
+        self
+    }
+
+    ///
+    /// Returns the information about the process that made this connection
+    ///
+    pub fn peer(&self) -> &SocketPeer {
+        &self.peer
+    }
+
     ///
     /// Sets the stream that will send the resulting output to the socket, and returns the input stream that can be used to read incoming data
     ///
@@ -105,37 +335,393 @@ where
     }
 }
 
+///
+/// Creates a stream that reads blocks of data from an AsyncRead, ending after the first error
+///
+fn read_socket_blocks(reader: impl 'static + Send + AsyncRead, options: &SocketOptions) -> impl Stream<Item=Result<Vec<u8>, std::io::Error>> {
+    let reader              = Box::pin(reader);
+    let read_buffer_size    = options.read_buffer_size;
+
+    stream::unfold(Some(reader), move |reader| async move {
+        // The reader is set to None once the stream has finished
+        let mut reader = reader?;
+
+        // Read directly into a new buffer, which becomes the next block in the stream
+        let mut buf     = Vec::with_capacity(read_buffer_size);
+        let next_read   = reader.read_buf(&mut buf).await;
+
+        // Return the next set of bytes we read from the input stream if available (or close the stream if there's an error or the end of stream is reached)
+        match next_read {
+            Ok(0)   => None,
+            Ok(_)   => Some((Ok(buf), Some(reader))),
+            Err(err)=> Some((Err(err), None)),
+        }
+    })
+}
+
 ///
 /// Creates a stream that reads blocks of data from an AsyncRead
 ///
-pub (crate) fn create_reader_stream(reader: impl 'static + Send + AsyncRead) -> impl Stream<Item=Vec<u8>> {
-    let reader = Box::pin(reader);
-    let reader = Arc::new(Mutex::new(Some(reader)));
+/// Each block is up to `read_buffer_size` bytes long. The reader is only read from when the stream is polled, so a program that
+/// stops reading from the stream (for example, because the `InputStream` it's sending to is full) also stops the socket from
+/// being read, which in turn will eventually stop the process at the other end from writing.
+///
+pub (crate) fn create_reader_stream(reader: impl 'static + Send + AsyncRead, options: &SocketOptions) -> impl Stream<Item=Vec<u8>> {
+    read_socket_blocks(reader, options)
+        .filter_map(|block| std::future::ready(block.ok()))
+}
 
-    stream::unfold([0u8; 64], move |mut buf| {
-        // Create a copy of the reader to use in the async block
-        let reader = Arc::clone(&reader);
+///
+/// The state of a connection accepted by a socket listener, shared between the listener and the stream that reads from the connection
+///
+/// The listener can close the connection whether or not the stream is being read from, so a connection is still stopped or timed out
+/// when the program it was sent to isn't reading from it.
+///
+struct MonitoredConnection {
+    /// The ID of the connection, or None if the close event has already been sent
+    connection_id: Option<usize>,
 
-        async move {
-            // Take the reader from the mutex and read the next value
-            let mut borrowed_reader     = reader.lock().unwrap().take().unwrap();
-            let next_read               = borrowed_reader.read(&mut buf).await;
-            *(reader.lock().unwrap())   = Some(borrowed_reader);
-
-            // Return the next set of bytes we read from the input stream if available (or close the stream if there's an error or the end of stream is reached)
-            match next_read {
-                Ok(0)           => None,
-                Ok(num_read)    => Some((buf[0..num_read].into(), buf)),
-                Err(_)          => None,
+    /// The blocks read from the connection (None once the connection has closed)
+    reader: Option<BoxStream<'static, Result<Vec<u8>, std::io::Error>>>,
+
+    /// When data was last read from the connection (or when it was accepted, if nothing has been read yet)
+    last_read: Instant,
+
+    /// The waker for the stream, if it's waiting for data
+    waker: Option<Waker>,
+
+    /// Used to stop writing to the connection when it is closed by the listener
+    abort_writer: AbortHandle,
+
+    /// Closes the writer for the connection (whether or not anything has started writing to it)
+    close_writer: Box<dyn Send + Fn()>,
+
+    /// Where to send the reason that the connection closed
+    closed: mpsc::UnboundedSender<(usize, SocketCloseReason)>,
+}
+
+impl MonitoredConnection {
+    ///
+    /// Closes the connection, reporting the reason to the listener if it hasn't already been reported
+    ///
+    fn close(&mut self, reason: SocketCloseReason) {
+        self.reader = None;
+
+        if let Some(connection_id) = self.connection_id.take() {
+            self.closed.unbounded_send((connection_id, reason)).ok();
+        }
+
+        // The stream won't be woken by the reader now it's gone
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+///
+/// The listener's handle on a connection that it has accepted
+///
+/// The connection is stopped if this is dropped while it's still open (for example, because the listener program was dropped).
+///
+struct ListenerConnection(Arc<Mutex<MonitoredConnection>>);
+
+impl ListenerConnection {
+    ///
+    /// Closes the connection and stops writing to it, if it's still open
+    ///
+    fn stop(&self, reason: SocketCloseReason) {
+        let mut connection = self.0.lock().unwrap();
+
+        if connection.connection_id.is_some() {
+            connection.abort_writer.abort();
+            (connection.close_writer)();
+            connection.close(reason);
+        }
+    }
+
+    ///
+    /// Returns the time when this connection will become idle, if it's still open
+    ///
+    fn idle_at(&self, idle_timeout: Duration) -> Option<Instant> {
+        let connection = self.0.lock().unwrap();
+
+        if connection.connection_id.is_some() {
+            Some(connection.last_read + idle_timeout)
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for ListenerConnection {
+    fn drop(&mut self) {
+        self.stop(SocketCloseReason::ListenerStopped);
+    }
+}
+
+///
+/// Stream that reads from a connection accepted by a socket listener, and reports to the listener when the connection closes
+///
+struct MonitoredReaderStream {
+    connection: Arc<Mutex<MonitoredConnection>>,
+}
+
+impl MonitoredReaderStream {
+    ///
+    /// Creates a new monitored reader stream, and the handle that the listener uses to close the connection
+    ///
+    fn new(connection_id: usize, reader: impl 'static + Send + Stream<Item=Result<Vec<u8>, std::io::Error>>, abort_writer: AbortHandle, close_writer: impl 'static + Send + Fn(), closed: mpsc::UnboundedSender<(usize, SocketCloseReason)>) -> (Self, ListenerConnection) {
+        let connection = MonitoredConnection {
+            connection_id:  Some(connection_id),
+            reader:         Some(reader.boxed()),
+            last_read:      Instant::now(),
+            waker:          None,
+            close_writer:   Box::new(close_writer),
+            abort_writer,
+            closed,
+        };
+        let connection = Arc::new(Mutex::new(connection));
+
+        (MonitoredReaderStream { connection: Arc::clone(&connection) }, ListenerConnection(connection))
+    }
+}
+
+impl Stream for MonitoredReaderStream {
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<Vec<u8>>> {
+        let mut connection  = self.connection.lock().unwrap();
+        let reader          = if let Some(reader) = connection.reader.as_mut() { reader } else { return Poll::Ready(None); };
+
+        match reader.poll_next_unpin(context) {
+            Poll::Ready(Some(Ok(bytes))) => {
+                // The connection stops being idle whenever some data arrives
+                connection.last_read = Instant::now();
+
+                Poll::Ready(Some(bytes))
+            }
+
+            Poll::Ready(Some(Err(err))) => {
+                connection.close(SocketCloseReason::Error(err.to_string()));
+                Poll::Ready(None)
+            }
+
+            Poll::Ready(None) => {
+                connection.close(SocketCloseReason::ClosedByPeer);
+                Poll::Ready(None)
+            }
+
+            Poll::Pending => {
+                connection.waker = Some(context.waker().clone());
+                Poll::Pending
             }
         }
-    })
+    }
+}
+
+impl Drop for MonitoredReaderStream {
+    fn drop(&mut self) {
+        let mut connection = self.connection.lock().unwrap();
+
+        if connection.connection_id.is_some() {
+            connection.close(SocketCloseReason::Dropped);
+        }
+    }
+}
+
+///
+/// Writes to a connection accepted by a socket listener, which the listener can close before anything has started writing to it
+///
+struct ListenerWriter<TWriter>(Arc<Mutex<Option<Pin<Box<TWriter>>>>>);
+
+impl<TWriter> ListenerWriter<TWriter>
+where
+    TWriter: 'static + Send,
+{
+    ///
+    /// Creates a new listener writer, and a function that closes it
+    ///
+    fn new(writer: TWriter) -> (Self, impl 'static + Send + Fn()) {
+        let writer          = Arc::new(Mutex::new(Some(Box::pin(writer))));
+        let close_writer    = Arc::clone(&writer);
+
+        (ListenerWriter(writer), move || { close_writer.lock().unwrap().take(); })
+    }
+}
+
+impl<TWriter> AsyncWrite for ListenerWriter<TWriter>
+where
+    TWriter: AsyncWrite,
+{
+    fn poll_write(self: Pin<&mut Self>, context: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        match self.0.lock().unwrap().as_mut() {
+            Some(writer)    => writer.as_mut().poll_write(context, buf),
+            None            => Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, context: &mut Context<'_>, bufs: &[IoSlice<'_>]) -> Poll<std::io::Result<usize>> {
+        match self.0.lock().unwrap().as_mut() {
+            Some(writer)    => writer.as_mut().poll_write_vectored(context, bufs),
+            None            => Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.lock().unwrap().as_ref().map(|writer| writer.is_write_vectored()).unwrap_or(false)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.0.lock().unwrap().as_mut() {
+            Some(writer)    => writer.as_mut().poll_flush(context),
+            None            => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.0.lock().unwrap().as_mut() {
+            Some(writer)    => writer.as_mut().poll_shutdown(context),
+            None            => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+///
+/// Waits until the first of the open connections becomes idle, or forever if there's no idle timeout
+///
+fn next_idle_connection<'a>(connections: impl Iterator<Item=&'a ListenerConnection>, idle_timeout: Option<Duration>) -> impl Future<Output=()> {
+    let idle_at = idle_timeout.and_then(|idle_timeout| connections.flat_map(|connection| connection.idle_at(idle_timeout)).min());
+
+    if let Some(idle_at) = idle_at {
+        Either::Left(Delay::new(idle_at.saturating_duration_since(Instant::now())))
+    } else {
+        Either::Right(future::pending())
+    }
+}
+
+///
+/// Writes the blocks of bytes from a stream to an `AsyncWrite`
+///
+/// Blocks that are waiting at the same time are combined into a single vectored write (up to the limit set in the options), and
+/// the writer is flushed after each write. The future completes when the stream finishes or the writer returns an error.
+///
+pub (crate) async fn write_byte_stream(output_byte_stream: BoxStream<'static, Vec<u8>>, async_writer: impl Send + AsyncWrite, options: &SocketOptions) {
+    let mut output_blocks   = output_byte_stream.ready_chunks(options.max_write_blocks);
+    let async_writer        = async_writer;
+    pin_mut!(async_writer);
+
+    while let Some(blocks) = output_blocks.next().await {
+        // Write the blocks that were waiting, keeping track of how much of the first block has been written
+        let mut blocks      = blocks.into_iter().filter(|block| !block.is_empty()).collect::<VecDeque<_>>();
+        let mut write_pos   = 0;
+
+        while !blocks.is_empty() {
+            let slices = blocks.iter().enumerate()
+                .map(|(idx, block)| if idx == 0 { IoSlice::new(&block[write_pos..]) } else { IoSlice::new(block) })
+                .collect::<Vec<_>>();
+
+            let mut num_written = match async_writer.write_vectored(&slices).await {
+                Ok(0) | Err(_)  => { return; }
+                Ok(num_written) => num_written,
+            };
+
+            // Remove the blocks that were completely written
+            while let Some(first_block) = blocks.front() {
+                let remaining = first_block.len() - write_pos;
+
+                if num_written >= remaining {
+                    num_written -= remaining;
+                    write_pos   = 0;
+                    blocks.pop_front();
+                } else {
+                    write_pos += num_written;
+                    break;
+                }
+            }
+        }
+
+        // Some writers (such as WebSockets) buffer their output until they are flushed
+        if async_writer.flush().await.is_err() {
+            break;
+        }
+    }
+}
+
+///
+/// Creates a socket connection that reads from a stream of bytes and writes to an `AsyncWrite`
+///
+/// The output is written by a subprogram that's started when the connection is connected (so the main 'scene' program must be running).
+/// If an abort registration is supplied, the writer will stop when it's aborted.
+///
+#[allow(clippy::too_many_arguments)]     // Internal function shared by the different kinds of socket
+pub (crate) fn create_socket_connection<TWriteStream, TInputStream, TOutputMessage>(
+    context:                &SceneContext,
+    reader_stream:          BoxStream<'static, Vec<u8>>,
+    async_writer:           TWriteStream,
+    peer:                   SocketPeer,
+    options:                &SocketOptions,
+    abort_writer:           Option<AbortRegistration>,
+    create_input_messages:  &(impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> TInputStream),
+    create_output_messages: Arc<impl 'static + Send + Sync + Fn(BoxStream<'static, TOutputMessage>) -> BoxStream<'static, Vec<u8>>>) -> SocketConnection<TInputStream::Item, TOutputMessage>
+where
+    TWriteStream:   'static + Send + AsyncWrite,
+    TInputStream:   'static + Send + Stream,
+    TOutputMessage: 'static + Send,
+{
+    let reader_stream   = create_input_messages(reader_stream);
+    let options         = options.clone();
+
+    SocketConnection::<TInputStream::Item, TOutputMessage>::new(context, reader_stream, move |context, output_stream| {
+        // Create a stream that converts to bytes
+        let output_byte_stream = create_output_messages(output_stream);
+
+        // Future to write the bytes
+        let byte_writer = async move {
+            let write_bytes = write_byte_stream(output_byte_stream, async_writer, &options);
+
+            if let Some(abort_writer) = abort_writer {
+                Abortable::new(write_bytes, abort_writer).await.ok();
+            } else {
+                write_bytes.await;
+            }
+        };
+
+        // Ask the scene to create a subprogram that writes the output (won't work if the main 'scene' program isn't running)
+        let output_program = SubProgramId::new();
+        let output_program = SceneControl::start_program(output_program, move |_: InputStream<()>, _| byte_writer, 0);
+
+        let mut control = context.send(()).unwrap();
+        control.send_immediate(output_program).ok();
+    }).with_peer(peer)
+}
+
+///
+/// The events processed by a socket listener subprogram
+///
+enum ListenerEvent<TReadStream, TWriteStream> {
+    /// A new connection has been accepted
+    Accepted(TReadStream, TWriteStream, SocketPeer),
+
+    /// The connection with the specified ID has closed
+    Closed(usize, SocketCloseReason),
+
+    /// The input stream for the listener program has closed
+    Stop,
+
+    /// One or more of the open connections may have become idle
+    IdleTimeout,
+
+    /// The listener could not accept any more connections
+    AcceptFailed,
 }
 
 ///
 /// Runs a socket listener suprogram. This accepts 'Subscribe' messages from subprograms that wish to receive connections (subscription messages are sent in a round-robin fashion),
 /// and calls the 'accept_message' function to receive incoming connections
 ///
+/// The 'accept_connection' function returns the reader and writer for the connection. Use `socket_listener_subprogram_with_peer()` to
+/// supply information about the process at the other end of each connection. `SocketEvent` messages are sent as connections are opened and closed.
+///
 pub async fn socket_listener_subprogram<TFutureStream, TReadStream, TWriteStream, TInputStream, TOutputMessage>(
     context:                SceneContext, 
     accept_connection:      impl 'static + Send + Fn() -> TFutureStream,
@@ -147,12 +733,58 @@ where
     TWriteStream:   'static + Send + AsyncWrite,
     TInputStream:   'static + Send + Stream,
     TOutputMessage: 'static + Send ,
+{
+    // Nothing is known about the peer for these connections
+    let accept_connection = move || accept_connection().map(|result| result.map(|(reader, writer)| (reader, writer, SocketPeer::default())));
+
+    socket_listener_subprogram_with_peer(context, accept_connection, create_input_messages, create_output_messages).await
+}
+
+///
+/// As for `socket_listener_subprogram()`, except the 'accept_connection' function also returns any information that's available about
+/// the process at the other end of the connection
+///
+pub async fn socket_listener_subprogram_with_peer<TFutureStream, TReadStream, TWriteStream, TInputStream, TOutputMessage>(
+    context:                SceneContext, 
+    accept_connection:      impl 'static + Send + Fn() -> TFutureStream,
+    create_input_messages:  impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> TInputStream,
+    create_output_messages: impl 'static + Send + Sync + Fn(BoxStream<'static, TOutputMessage>) -> BoxStream<'static, Vec<u8>>)
+where
+    TFutureStream:  Send + Future<Output=Result<(TReadStream, TWriteStream, SocketPeer), ConnectionError>>,
+    TReadStream:    'static + Send + AsyncRead,
+    TWriteStream:   'static + Send + AsyncWrite,
+    TInputStream:   'static + Send + Stream,
+    TOutputMessage: 'static + Send ,
+{
+    socket_listener_subprogram_with_options(stream::pending::<()>(), context, SocketOptions::default(), accept_connection, create_input_messages, create_output_messages).await
+}
+
+///
+/// As for `socket_listener_subprogram_with_peer()`, with options that control how the data is read from and written to each connection, how long
+/// connections can stay idle and how many connections can be open at once
+///
+/// The listener stops when the `input` stream finishes (this is usually the input stream for the listener program, so it will stop
+/// when that's closed). The `accept_connection` function is dropped at this point, which should close the listening socket.
+///
+pub async fn socket_listener_subprogram_with_options<TFutureStream, TReadStream, TWriteStream, TInputStream, TOutputMessage>(
+    input:                  impl Send + Stream,
+    context:                SceneContext, 
+    options:                SocketOptions,
+    accept_connection:      impl 'static + Send + Fn() -> TFutureStream,
+    create_input_messages:  impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> TInputStream,
+    create_output_messages: impl 'static + Send + Sync + Fn(BoxStream<'static, TOutputMessage>) -> BoxStream<'static, Vec<u8>>)
+where
+    TFutureStream:  Send + Future<Output=Result<(TReadStream, TWriteStream, SocketPeer), ConnectionError>>,
+    TReadStream:    'static + Send + AsyncRead,
+    TWriteStream:   'static + Send + AsyncWrite,
+    TInputStream:   'static + Send + Stream,
+    TOutputMessage: 'static + Send ,
 {
     // Wrap functions that get shared in a reference
     let accept_connection       = Arc::new(accept_connection);
     let create_output_messages  = Arc::new(create_output_messages);
 
-    // Combine the subscription and the acceptance streams
+    // Stream of accepted connections (this owns the accept_connection function, so dropping it will stop the listener)
     let accept_messages = stream::unfold(0, move |_| {
         let accept_connection = Arc::clone(&accept_connection);
 
@@ -162,64 +794,240 @@ where
 
             // Continue until we get an error
             match next_connection {
-                Ok(next_connection) => Some((next_connection, 0)),
-                _                   => None,
+                Ok((reader, writer, peer))  => Some((ListenerEvent::Accepted(reader, writer, peer), 0)),
+                _                           => None,
             }
         }
     });
+    let mut accept_messages = Some(Box::pin(accept_messages));
+
+    // The connections report when they're closed via another stream, and the listener stops when the input stream is closed
+    let (send_closed, recv_closed)  = mpsc::unbounded();
+    let closed_messages             = recv_closed.map(|(connection_id, reason)| ListenerEvent::Closed(connection_id, reason));
+    let stop_messages               = input.filter_map(|_| future::ready(None)).chain(stream::once(future::ready(ListenerEvent::Stop)));
 
-    pin_mut!(accept_messages);
-    let mut input = accept_messages;
+    let other_events = stream::select(closed_messages, stop_messages);
+    pin_mut!(other_events);
+
+    // Events are discarded unless something is connected to receive them
+    let mut events              = context.send::<SocketEvent>(StreamTarget::None).unwrap();
+    let mut open_connections    = 0;
+    let mut next_connection_id  = 0;
+    let mut stop_connections    = HashMap::new();
+
+    // Run the socket listener until it has stopped and there are no more connections
+    while accept_messages.is_some() || open_connections > 0 {
+        // Connections are closed by the listener when they've been idle for too long, whether or not anything is reading from them
+        let next_other_event = future::select(other_events.next(), Box::pin(next_idle_connection(stop_connections.values(), options.idle_timeout)))
+            .map(|next_event| match next_event {
+                Either::Left((event, _))    => event,
+                Either::Right(((), _))      => Some(ListenerEvent::IdleTimeout),
+            });
+
+        let next_event = if let Some(accept) = accept_messages.as_mut() {
+            match future::select(accept.next(), next_other_event).await {
+                Either::Left((Some(event), _))  => event,
+                Either::Left((None, _))         => ListenerEvent::AcceptFailed,
+                Either::Right((Some(event), _)) => event,
+                Either::Right((None, _))        => { break; }
+            }
+        } else if let Some(event) = next_other_event.await {
+            event
+        } else {
+            break;
+        };
 
-    // Run the socket listener
-    while let Some(next_event) = input.next().await {
         match next_event {
-            (async_reader, async_writer) => {
-                // Create the socket connection from the reader
-                let reader_stream = create_reader_stream(async_reader);
-                let reader_stream = create_input_messages(reader_stream.boxed());
-
-                let create_output_messages  = Arc::clone(&create_output_messages);
-                let socket_connection       = SocketConnection::<TInputStream::Item, TOutputMessage>::new(&context, reader_stream, move |context, output_stream| {
-                    // Create a stream that converts to bytes
-                    let mut output_byte_stream = create_output_messages(output_stream);
-
-                    // Future to write the bytes
-                    let async_writer = Box::pin(async_writer);
-                    let byte_writer  = async move {
-                        // Write each block as it arrives from the output byte stream to the socket target
-                        let mut async_writer = async_writer;
-                        while let Some(bytes) = output_byte_stream.next().await {
-                            // Loop until we've written all of the bytes
-                            let mut write_pos = 0;
-
-                            while write_pos < bytes.len() {
-                                match async_writer.write(&bytes[write_pos..(bytes.len())]).await {
-                                    Ok(0)           => break,
-                                    Err(_)          => break,
-                                    Ok(num_written) => {
-                                        write_pos += num_written;
-                                        if write_pos >= bytes.len() {
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    };
+            ListenerEvent::Accepted(async_reader, async_writer, peer) => {
+                if options.max_connections.map(|max_connections| open_connections >= max_connections).unwrap_or(false) {
+                    // Close the connection immediately if there are too many open
+                    drop(async_reader);
+                    drop(async_writer);
 
-                    // Ask the scene to create a subprogram that writes the output (won't work if the main 'scene' program isn't running)
-                    let output_program = SubProgramId::new();
-                    let output_program = SceneControl::start_program(output_program, move |_: InputStream<()>, _| byte_writer, 0);
+                    events.send(SocketEvent::Rejected { peer, open_connections }).await.ok();
+                    continue;
+                }
 
-                    let mut control = context.send(()).unwrap();
-                    control.send_immediate(output_program).ok();
-                });
+                let connection_id = next_connection_id;
+                next_connection_id  += 1;
+                open_connections    += 1;
+
+                events.send(SocketEvent::Connected { connection_id, peer: peer.clone(), open_connections }).await.ok();
+
+                // Create the socket connection from the reader
+                let (abort_writer, abort_registration)  = AbortHandle::new_pair();
+                let (async_writer, close_writer)        = ListenerWriter::new(async_writer);
+                let reader_stream                       = read_socket_blocks(async_reader, &options);
+                let (reader_stream, listener_connection)= MonitoredReaderStream::new(connection_id, reader_stream, abort_writer, close_writer, send_closed.clone());
+                let socket_connection                   = create_socket_connection(&context, reader_stream.boxed(), async_writer, peer, &options, Some(abort_registration), &create_input_messages, Arc::clone(&create_output_messages));
+
+                stop_connections.insert(connection_id, listener_connection);
 
                 // Send the connection to whoever is connected to this socket listener
                 let socket_connection = SocketMessage::Connection(socket_connection);
                 context.send_message(socket_connection).await.ok();
             }
+
+            ListenerEvent::Closed(connection_id, reason) => {
+                open_connections -= 1;
+                stop_connections.remove(&connection_id);
+
+                events.send(SocketEvent::Closed { connection_id, reason, open_connections }).await.ok();
+            }
+
+            ListenerEvent::Stop => {
+                // Dropping the accept stream closes the listening socket
+                accept_messages = None;
+
+                if !options.drain_connections {
+                    // Stop reading from and writing to the connections immediately
+                    for (_, connection) in stop_connections.drain() {
+                        connection.stop(SocketCloseReason::ListenerStopped);
+                    }
+                }
+
+                events.send(SocketEvent::StoppedListening { open_connections }).await.ok();
+            }
+
+            ListenerEvent::IdleTimeout => {
+                // Close any connection that has had no data for the idle timeout (they'll report that they're closed as usual)
+                if let Some(idle_timeout) = options.idle_timeout {
+                    let now = Instant::now();
+
+                    for connection in stop_connections.values() {
+                        if connection.idle_at(idle_timeout).map(|idle_at| idle_at <= now).unwrap_or(false) {
+                            connection.stop(SocketCloseReason::IdleTimeout);
+                        }
+                    }
+                }
+            }
+
+            ListenerEvent::AcceptFailed => {
+                // The connections that are already open are left to finish if the listening socket fails
+                accept_messages = None;
+
+                events.send(SocketEvent::StoppedListening { open_connections }).await.ok();
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor;
+    use futures::future;
+
+    use std::io;
+
+    #[test]
+    fn read_blocks_up_to_buffer_size() {
+        let data    = (0..1000).map(|idx| (idx % 256) as u8).collect::<Vec<_>>();
+        let options = SocketOptions::default().with_read_buffer_size(100);
+        let blocks  = executor::block_on(create_reader_stream(io::Cursor::new(data.clone()), &options).collect::<Vec<_>>());
+
+        assert!(blocks.iter().all(|block| block.len() <= 100), "{:?}", blocks.iter().map(|block| block.len()).collect::<Vec<_>>());
+        assert!(blocks.concat() == data);
+    }
+
+    #[test]
+    fn write_blocks_with_partial_writes() {
+        // The duplex buffer is smaller than the blocks, so the writer has to deal with partial writes
+        let blocks                  = (0..20).map(|idx| vec![idx as u8; 37]).collect::<Vec<_>>();
+        let (writer, mut reader)    = duplex(16);
+        let options                 = SocketOptions::default().with_max_write_blocks(4);
+
+        let write_blocks            = stream::iter(blocks.clone()).boxed();
+        let written                 = executor::block_on(future::join(
+            async move { write_byte_stream(write_blocks, writer, &options).await; },
+            async move { let mut written = vec![]; reader.read_to_end(&mut written).await.unwrap(); written }));
+
+        assert!(written.1 == blocks.concat());
+    }
+
+    #[test]
+    fn report_closed_by_peer() {
+        let (send_closed, mut recv_closed)  = mpsc::unbounded();
+        let (abort_writer, _)               = AbortHandle::new_pair();
+        let blocks                          = stream::iter(vec![Ok(vec![1, 2, 3])]);
+        let (reader, _connection)           = MonitoredReaderStream::new(1, blocks, abort_writer, || { }, send_closed);
+
+        let read = executor::block_on(reader.collect::<Vec<_>>());
+
+        assert!(read == vec![vec![1, 2, 3]]);
+        assert!(recv_closed.try_next().unwrap() == Some((1, SocketCloseReason::ClosedByPeer)));
+    }
+
+    #[test]
+    fn report_read_error() {
+        let (send_closed, mut recv_closed)  = mpsc::unbounded();
+        let (abort_writer, _)               = AbortHandle::new_pair();
+        let blocks                          = stream::iter(vec![Ok(vec![1]), Err(io::Error::other("Failed"))]);
+        let (reader, _connection)           = MonitoredReaderStream::new(2, blocks, abort_writer, || { }, send_closed);
+
+        let read = executor::block_on(reader.collect::<Vec<_>>());
+
+        assert!(read == vec![vec![1]]);
+        assert!(recv_closed.try_next().unwrap() == Some((2, SocketCloseReason::Error("Failed".into()))));
+    }
+
+    #[test]
+    fn report_dropped_reader() {
+        let (send_closed, mut recv_closed)  = mpsc::unbounded();
+        let (abort_writer, _)               = AbortHandle::new_pair();
+        let (reader, _connection)           = MonitoredReaderStream::new(3, stream::pending(), abort_writer, || { }, send_closed);
+
+        drop(reader);
+
+        assert!(recv_closed.try_next().unwrap() == Some((3, SocketCloseReason::Dropped)));
+    }
+
+    #[test]
+    fn close_idle_connection() {
+        let (send_closed, mut recv_closed)  = mpsc::unbounded();
+        let (abort_writer, abort_reg)       = AbortHandle::new_pair();
+        let idle_timeout                    = Duration::from_millis(50);
+        let blocks                          = stream::iter(vec![Ok(vec![1])]).chain(stream::pending());
+        let (mut reader, connection)        = MonitoredReaderStream::new(4, blocks, abort_writer, || { }, send_closed);
+
+        // The listener waits for the connection to become idle, then closes it (nothing is reading from the connection at this point)
+        executor::block_on(next_idle_connection(vec![&connection].into_iter(), Some(idle_timeout)));
+        assert!(connection.idle_at(idle_timeout).unwrap() <= Instant::now());
+
+        connection.stop(SocketCloseReason::IdleTimeout);
+
+        // The reader should have no more data, and the writer should be aborted
+        let read    = executor::block_on(reader.next());
+        let writer  = executor::block_on(Abortable::new(future::pending::<()>(), abort_reg));
+
+        assert!(read.is_none());
+        assert!(writer.is_err());
+        assert!(recv_closed.try_next().unwrap() == Some((4, SocketCloseReason::IdleTimeout)));
+        assert!(connection.idle_at(idle_timeout).is_none());
+    }
+
+    #[test]
+    fn close_when_listener_stops() {
+        let (send_closed, mut recv_closed)  = mpsc::unbounded();
+        let (abort_writer, abort_reg)       = AbortHandle::new_pair();
+        let blocks                          = stream::iter(vec![Ok(vec![1])]).chain(stream::pending());
+        let (mut reader, connection)        = MonitoredReaderStream::new(5, blocks, abort_writer, || { }, send_closed);
+
+        executor::block_on(async {
+            assert!(reader.next().await == Some(vec![1]));
+
+            // Wait for the next block, then drop the listener's side of the connection (as happens if the listener stops)
+            let mut next_block = reader.next();
+            assert!(futures::poll!(&mut next_block).is_pending());
+
+            drop(connection);
+            assert!(next_block.await.is_none());
+        });
+
+        let writer = executor::block_on(Abortable::new(future::pending::<()>(), abort_reg));
+
+        assert!(writer.is_err());
+        assert!(recv_closed.try_next().unwrap() == Some((5, SocketCloseReason::ListenerStopped)));
+    }
+}
@@ -0,0 +1,251 @@
+use flo_scene::*;
+
+use futures::prelude::*;
+use futures::stream::{BoxStream};
+use serde::*;
+
+///
+/// The largest frame that `read_socket_frames()` will accept (16MB)
+///
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+///
+/// The number of bytes used for each of the length fields in a frame
+///
+const LENGTH_SIZE: usize = 4;
+
+///
+/// A frame sent over a socket using the binary framing protocol
+///
+/// On the wire, a frame is a 32-bit big-endian length of the rest of the frame, followed by a 32-bit big-endian length of the message
+/// type name, the UTF-8 bytes of the type name and finally the payload.
+///
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SocketFrame {
+    /// The name of the type of the message in the payload
+    pub type_name: String,
+
+    /// The data for this frame
+    pub payload: Vec<u8>,
+}
+
+impl SocketFrame {
+    ///
+    /// Creates a new frame containing a payload of the specified type
+    ///
+    pub fn new(type_name: impl Into<String>, payload: impl Into<Vec<u8>>) -> Self {
+        SocketFrame {
+            type_name:  type_name.into(),
+            payload:    payload.into(),
+        }
+    }
+
+    ///
+    /// Creates a frame containing a message serialized as JSON, using its serialization name as the type name
+    ///
+    pub fn from_message<TMessage>(message: &TMessage) -> Result<Self, serde_json::Error>
+    where
+        TMessage: SceneMessage + Serialize,
+    {
+        Ok(SocketFrame::new(TMessage::message_type_name(), serde_json::to_vec(message)?))
+    }
+
+    ///
+    /// If this frame contains a JSON payload for a serializable message type, returns it as a serialized message that can be sent with `send_serialized()`
+    ///
+    pub fn to_serialized_json(&self) -> Option<SerializedMessage<serde_json::Value>> {
+        let message_type    = StreamId::with_serialization_type(self.type_name.clone())?.message_type();
+        let value           = serde_json::from_slice(&self.payload).ok()?;
+
+        Some(SerializedMessage(value, message_type))
+    }
+
+    ///
+    /// Encodes this frame as bytes ready to be written to a socket
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let frame_len   = LENGTH_SIZE + self.type_name.len() + self.payload.len();
+        let mut bytes   = Vec::with_capacity(LENGTH_SIZE + frame_len);
+
+        bytes.extend((frame_len as u32).to_be_bytes());
+        bytes.extend((self.type_name.len() as u32).to_be_bytes());
+        bytes.extend(self.type_name.bytes());
+        bytes.extend(&self.payload);
+
+        bytes
+    }
+}
+
+///
+/// Reads a 32-bit big-endian length from the start of a slice
+///
+#[inline]
+fn read_length(bytes: &[u8]) -> usize {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+}
+
+///
+/// Decodes the contents of a frame (the part following the frame length)
+///
+fn decode_frame(bytes: &[u8]) -> Result<SocketFrame, ConnectionError> {
+    if bytes.len() < LENGTH_SIZE {
+        return Err(ConnectionError::IoError("Frame is too short to contain a message type".into()));
+    }
+
+    let name_len = read_length(bytes);
+    if name_len > bytes.len() - LENGTH_SIZE {
+        return Err(ConnectionError::IoError("Message type name is longer than the frame".into()));
+    }
+
+    let type_name   = String::from_utf8(bytes[LENGTH_SIZE..(LENGTH_SIZE + name_len)].to_vec())
+        .map_err(|_| ConnectionError::IoError("Message type name is not valid UTF-8".into()))?;
+    let payload     = bytes[(LENGTH_SIZE + name_len)..].to_vec();
+
+    Ok(SocketFrame { type_name, payload })
+}
+
+///
+/// Returns a function that reads frames from a socket, rejecting any frame that's larger than the specified size
+///
+/// The stream ends after the first error, as it's not possible to find the start of the next frame once the framing is lost
+///
+pub fn read_socket_frames_with_max_size(max_frame_size: usize) -> impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> BoxStream<'static, Result<SocketFrame, ConnectionError>> {
+    move |input| {
+        // State is the input stream, the buffered bytes and the position of the start of the next frame in the buffer
+        stream::unfold(Some((input, vec![], 0)), move |state| async move {
+            let (mut input, mut buffer, mut pos): (BoxStream<'static, Vec<u8>>, Vec<u8>, usize) = state?;
+
+            loop {
+                // Decode the next frame if there's enough data in the buffer
+                let available = buffer.len() - pos;
+
+                if available >= LENGTH_SIZE {
+                    let frame_len = read_length(&buffer[pos..]);
+
+                    if frame_len > max_frame_size {
+                        return Some((Err(ConnectionError::IoError(format!("Frame of {} bytes is larger than the maximum size of {} bytes", frame_len, max_frame_size))), None));
+                    }
+
+                    if available >= LENGTH_SIZE + frame_len {
+                        let frame_start = pos + LENGTH_SIZE;
+                        let frame_end   = frame_start + frame_len;
+
+                        return match decode_frame(&buffer[frame_start..frame_end]) {
+                            Ok(frame)   => Some((Ok(frame), Some((input, buffer, frame_end)))),
+                            Err(err)    => Some((Err(err), None)),
+                        };
+                    }
+                }
+
+                // Need to read more data: discard the frames that have already been read from the buffer first
+                buffer.drain(0..pos);
+                pos = 0;
+
+                match input.next().await {
+                    Some(bytes) => { buffer.extend(bytes); }
+                    None        => {
+                        if buffer.is_empty() {
+                            return None;
+                        } else {
+                            return Some((Err(ConnectionError::IoError("Connection closed partway through a frame".into())), None));
+                        }
+                    }
+                }
+            }
+        }).boxed()
+    }
+}
+
+///
+/// Reads frames from a socket using the binary framing protocol
+///
+/// This can be used as the `create_input_messages` function for any of the socket programs, for example
+/// `start_unix_socket_program(&scene, program_id, path, read_socket_frames, write_socket_frames)`. Frames larger than
+/// `DEFAULT_MAX_FRAME_SIZE` are rejected: use `read_socket_frames_with_max_size()` to choose a different limit. Framing errors are
+/// reported as `ConnectionError::IoError` and end the stream.
+///
+pub fn read_socket_frames(input: BoxStream<'static, Vec<u8>>) -> BoxStream<'static, Result<SocketFrame, ConnectionError>> {
+    read_socket_frames_with_max_size(DEFAULT_MAX_FRAME_SIZE)(input)
+}
+
+///
+/// Writes frames to a socket using the binary framing protocol
+///
+pub fn write_socket_frames(input: BoxStream<'static, SocketFrame>) -> BoxStream<'static, Vec<u8>> {
+    input.map(|frame| frame.to_bytes()).boxed()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor;
+
+    fn read_frames(chunks: Vec<Vec<u8>>, max_frame_size: usize) -> Vec<Result<SocketFrame, ConnectionError>> {
+        let input = stream::iter(chunks).boxed();
+
+        executor::block_on(read_socket_frames_with_max_size(max_frame_size)(input).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn encode_frame() {
+        let bytes = SocketFrame::new("test", vec![1, 2, 3]).to_bytes();
+
+        assert!(bytes == vec![0, 0, 0, 11, 0, 0, 0, 4, b't', b'e', b's', b't', 1, 2, 3], "{:?}", bytes);
+    }
+
+    #[test]
+    fn read_several_frames_from_one_chunk() {
+        let frames  = vec![SocketFrame::new("one", vec![1]), SocketFrame::new("two", vec![]), SocketFrame::new("three", vec![3; 100])];
+        let bytes   = frames.iter().flat_map(|frame| frame.to_bytes()).collect::<Vec<_>>();
+
+        let read    = read_frames(vec![bytes], DEFAULT_MAX_FRAME_SIZE);
+        assert!(read == frames.into_iter().map(Ok).collect::<Vec<_>>(), "{:?}", read);
+    }
+
+    #[test]
+    fn read_frames_one_byte_at_a_time() {
+        let frames  = vec![SocketFrame::new("one", vec![1, 2, 3]), SocketFrame::new("two", vec![4, 5, 6])];
+        let bytes   = frames.iter().flat_map(|frame| frame.to_bytes()).map(|byte| vec![byte]).collect::<Vec<_>>();
+
+        let read    = read_frames(bytes, DEFAULT_MAX_FRAME_SIZE);
+        assert!(read == frames.into_iter().map(Ok).collect::<Vec<_>>(), "{:?}", read);
+    }
+
+    #[test]
+    fn reject_large_frame() {
+        let bytes   = SocketFrame::new("large", vec![0; 100]).to_bytes();
+        let read    = read_frames(vec![bytes], 50);
+
+        assert!(read.len() == 1, "{:?}", read);
+        assert!(matches!(read[0], Err(ConnectionError::IoError(_))), "{:?}", read);
+    }
+
+    #[test]
+    fn error_for_truncated_frame() {
+        let mut bytes = SocketFrame::new("test", vec![1, 2, 3]).to_bytes();
+        bytes.pop();
+
+        let read = read_frames(vec![bytes], DEFAULT_MAX_FRAME_SIZE);
+
+        assert!(read.len() == 1, "{:?}", read);
+        assert!(matches!(read[0], Err(ConnectionError::IoError(_))), "{:?}", read);
+    }
+
+    #[test]
+    fn error_for_invalid_type_name_length() {
+        let bytes   = vec![0, 0, 0, 5, 0, 0, 0, 10, b'x'];
+        let read    = read_frames(vec![bytes], DEFAULT_MAX_FRAME_SIZE);
+
+        assert!(read.len() == 1, "{:?}", read);
+        assert!(matches!(read[0], Err(ConnectionError::IoError(_))), "{:?}", read);
+    }
+
+    #[test]
+    fn write_frames() {
+        let frames  = vec![SocketFrame::new("one", vec![1]), SocketFrame::new("two", vec![2])];
+        let bytes   = executor::block_on(write_socket_frames(stream::iter(frames.clone()).boxed()).concat());
+
+        assert!(bytes == frames.iter().flat_map(|frame| frame.to_bytes()).collect::<Vec<_>>());
+    }
+}
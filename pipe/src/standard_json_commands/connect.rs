@@ -37,6 +37,21 @@ pub struct ConnectArguments {
     stream_type_name: String,
 }
 
+impl ConnectArguments {
+    ///
+    /// The subprograms that are named as the source or the target of the connection
+    ///
+    pub fn targets(&self) -> Vec<StreamTarget> {
+        [&self.source_program, &self.target_program].into_iter()
+            .flat_map(|connection| match connection {
+                Connection::Program(prog_id)    => Some(StreamTarget::Program(*prog_id)),
+                Connection::None                |
+                Connection::Any                 => None,
+            })
+            .collect()
+    }
+}
+
 ///
 ///
 ///
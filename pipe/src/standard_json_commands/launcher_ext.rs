@@ -8,6 +8,7 @@ use super::send::*;
 use super::subscribe::*;
 use crate::commands::*;
 
+use flo_scene::*;
 use flo_scene::commands::*;
 
 ///
@@ -33,3 +34,24 @@ impl StandardCommandsLauncherExt for CommandLauncher<JsonParameter, CommandRespo
             .with_json_command("subscribe", command_subscribe)
     }
 }
+
+///
+/// Returns the subprograms that are named in the argument of one of the standard commands
+///
+/// Commands such as `send` and `connect` can reach subprograms other than the one they're run on, so a command session checks these
+/// targets against its permissions as well as the target of the command itself. Commands that aren't part of the standard set, or
+/// that have arguments that can't be read, return no targets.
+///
+pub fn standard_command_targets(command: &str, argument: &serde_json::Value) -> Vec<StreamTarget> {
+    fn targets<TArgs: for<'de> serde::Deserialize<'de>>(argument: &serde_json::Value, targets: impl FnOnce(TArgs) -> Vec<StreamTarget>) -> Vec<StreamTarget> {
+        serde_json::from_value(argument.clone()).map(targets).unwrap_or_default()
+    }
+
+    match command {
+        "connect"   => targets(argument, |args: ConnectArguments| args.targets()),
+        "query"     => targets(argument, |args: QueryArguments| args.targets()),
+        "send"      => targets(argument, |args: SendArguments| args.targets()),
+        "subscribe" => targets(argument, |args: SubscribeArguments| args.targets()),
+        _           => vec![],
+    }
+}
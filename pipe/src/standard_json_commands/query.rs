@@ -24,6 +24,18 @@ pub enum QueryArguments {
     },
 }
 
+impl QueryArguments {
+    ///
+    /// The subprogram that is named as the target of the query, if there is one
+    ///
+    pub fn targets(&self) -> Vec<StreamTarget> {
+        match self {
+            QueryArguments::Type(_)                     => vec![],
+            QueryArguments::SubProgram { program, .. }  => vec![StreamTarget::Program(*program)],
+        }
+    }
+}
+
 ///
 /// The `query` command, which runs a query and returns the results
 ///
@@ -19,6 +19,18 @@ pub enum SendArguments {
     Type(String),
 }
 
+impl SendArguments {
+    ///
+    /// The subprograms that the messages will be sent to
+    ///
+    pub fn targets(&self) -> Vec<StreamTarget> {
+        match self {
+            SendArguments::SubProgram(subprogram_id)    => vec![StreamTarget::Program(*subprogram_id)],
+            SendArguments::Type(type_name)              => StreamId::with_serialization_type(type_name.clone()).map(|stream_id| stream_id.default_target()).into_iter().collect(),
+        }
+    }
+}
+
 ///
 /// The `send` command, which sends messags to a subprogram in a scene
 ///
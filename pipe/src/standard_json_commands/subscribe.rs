@@ -24,6 +24,18 @@ pub enum SubscribeArguments {
     },
 }
 
+impl SubscribeArguments {
+    ///
+    /// The subprogram that is named as the source of the subscription, if there is one
+    ///
+    pub fn targets(&self) -> Vec<StreamTarget> {
+        match self {
+            SubscribeArguments::Type(_)                     => vec![],
+            SubscribeArguments::SubProgram { program, .. }  => vec![StreamTarget::Program(*program)],
+        }
+    }
+}
+
 ///
 /// The `subscribe` command, which opens a background stream to events from a source subprogram
 ///
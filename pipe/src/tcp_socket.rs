@@ -5,9 +5,120 @@ use flo_scene::*;
 use futures::prelude::*;
 use futures::stream::{BoxStream};
 
-use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::net::{TcpListener};
 
-use ::desync::*;
+use std::net::{ToSocketAddrs};
+use std::sync::*;
+
+#[cfg(any(feature="tls", feature="websocket"))] use futures::channel::{mpsc};
+#[cfg(any(feature="tls", feature="websocket"))] use futures::future::{BoxFuture, Either};
+#[cfg(any(feature="tls", feature="websocket"))] use futures_timer::{Delay};
+#[cfg(any(feature="tls", feature="websocket"))] use flo_scene::programs::*;
+#[cfg(any(feature="tls", feature="websocket"))] use tokio::net::{TcpStream};
+#[cfg(any(feature="tls", feature="websocket"))] use std::net::{SocketAddr};
+#[cfg(any(feature="tls", feature="websocket"))] use std::time::{Duration};
+
+///
+/// How long a client has to finish the handshake for a protocol like TLS before its connection is dropped
+///
+#[cfg(any(feature="tls", feature="websocket"))]
+pub (crate) const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+///
+/// Binds a TCP listener to an address, ready to be used by a socket program
+///
+/// The socket programs bind their address when they are started so that errors (such as the address being in use) can be returned
+/// to the caller. The listener is converted to a tokio listener once the program is running in the tokio runtime.
+///
+pub (crate) fn bind_tcp_listener(address: impl ToSocketAddrs) -> Result<std::net::TcpListener, ConnectionError> {
+    let listener = std::net::TcpListener::bind(address)
+        .map_err(|err| ConnectionError::IoError(format!("{}", err)))?;
+    listener.set_nonblocking(true)
+        .map_err(|err| ConnectionError::IoError(format!("{}", err)))?;
+
+    Ok(listener)
+}
+
+///
+/// Converts a listener created by `bind_tcp_listener()` to a tokio listener, from within a socket program
+///
+/// This has to happen in the tokio runtime, so it can't be done before the program starts. If the listener can't be converted, a
+/// `SocketEvent::ListenFailed` message is sent and `None` is returned: the program should stop at this point.
+///
+pub (crate) async fn tokio_tcp_listener(context: &SceneContext, listener: std::net::TcpListener) -> Option<TcpListener> {
+    match TcpListener::from_std(listener) {
+        Ok(listener)    => Some(listener),
+        Err(err)        => {
+            if let Ok(mut events) = context.send::<SocketEvent>(StreamTarget::None) {
+                events.send(SocketEvent::ListenFailed { error: ConnectionError::IoError(format!("{}", err)) }).await.ok();
+            }
+
+            None
+        }
+    }
+}
+
+///
+/// Creates an 'accept' function for `socket_listener_subprogram_with_peer()` that performs a handshake on each TCP connection before passing it on
+///
+/// The handshake for each connection runs in its own subprogram, so a client that stalls during the handshake doesn't stop any other
+/// connections from being accepted. Connections where the handshake fails (returns `None`) or takes longer than `HANDSHAKE_TIMEOUT`
+/// are dropped.
+///
+#[cfg(any(feature="tls", feature="websocket"))]
+pub (crate) fn accept_with_handshake<TConnection, THandshakeFuture>(
+        context:    &SceneContext, 
+        listener:   TcpListener, 
+        handshake:  impl 'static + Send + Sync + Fn(TcpStream, SocketAddr) -> THandshakeFuture
+    ) -> impl 'static + Send + Fn() -> BoxFuture<'static, Result<TConnection, ConnectionError>>
+where
+    TConnection:        'static + Send,
+    THandshakeFuture:   'static + Send + Future<Output=Option<TConnection>>,
+{
+    // Connections are sent back to the listener once their handshake has finished
+    let (send_connection, recv_connection)  = mpsc::unbounded();
+    let scene_control                       = context.send::<SceneControl>(()).unwrap();
+    let handshake                           = Arc::new(handshake);
+
+    // The listener is taken while a connection is being accepted (the accept future needs to run in the tokio runtime)
+    let listener = Arc::new(Mutex::new(Some((listener, recv_connection, scene_control))));
+
+    move || {
+        let listener                                                = Arc::clone(&listener);
+        let handshake                                               = Arc::clone(&handshake);
+        let send_connection                                         = send_connection.clone();
+        let (our_listener, mut recv_connection, mut scene_control)  = listener.lock().unwrap().take().unwrap();
+
+        async move {
+            let connection = loop {
+                match future::select(Box::pin(our_listener.accept()), recv_connection.next()).await {
+                    Either::Left((Ok((socket, addr)), _)) => {
+                        // Start a subprogram to perform the handshake for the new connection
+                        socket.set_nodelay(true).ok();
+
+                        let handshake       = handshake(socket, addr);
+                        let send_connection = send_connection.clone();
+                        let handshake       = SceneControl::start_program(SubProgramId::new(), move |_: InputStream<()>, _| async move {
+                            if let Either::Left((Some(connection), _)) = future::select(Box::pin(handshake), Delay::new(HANDSHAKE_TIMEOUT)).await {
+                                send_connection.unbounded_send(connection).ok();
+                            }
+                        }, 0);
+
+                        scene_control.send(handshake).await.ok();
+                    }
+
+                    Either::Left((Err(err), _))             => { break Err(err.into()); }
+                    Either::Right((Some(connection), _))    => { break Ok(connection); }
+                    Either::Right((None, _))                => { break Err(ConnectionError::Cancelled); }
+                }
+            };
+
+            *listener.lock().unwrap() = Some((our_listener, recv_connection, scene_control));
+
+            connection
+        }.boxed()
+    }
+}
 
 ///
 /// Starts a sub-program that accepts unencrypted connections on a TCP socket.
@@ -16,10 +127,13 @@ use ::desync::*;
 /// message. Typically, there's only one subscriber but in the event multiple are connected, they are informed of connections in
 /// a round-robin fashion.
 ///
+/// The address is bound before this returns, so an error is returned if it's not available. The program stops listening when its
+/// input stream is closed (for example, by sending `SceneControl::Close(program_id)`).
+///
 pub fn start_unencrpted_tcp_socket<TInputStream, TOutputMessage>(
         scene:                  &Scene, 
         program_id:             SubProgramId, 
-        address:                impl 'static + Send + ToSocketAddrs, 
+        address:                impl ToSocketAddrs, 
         create_input_messages:  impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> TInputStream,
         create_output_messages: impl 'static + Send + Sync + Fn(BoxStream<'static, TOutputMessage>) -> BoxStream<'static, Vec<u8>>
     ) -> Result<(), ConnectionError> 
@@ -27,24 +141,55 @@ where
     TInputStream:   'static + Send + Stream,
     TOutputMessage: 'static + Send,
 {
-    scene.add_subprogram(program_id, move |_input: InputStream<()>, context| async move {
-        // The listener requires an await to start, so we create it as part of the program
-        let listener = TcpListener::bind(address).await
-            .map_err(|tokio_err| ConnectionError::IoError(format!("{}", tokio_err)))
-            .unwrap();
-
-        // Add a socket runner subprogram. We don't use the address for anything, ie we accept all connections here
-        let listener = Desync::new(listener);
-
-        socket_listener_subprogram(context, move || 
-            listener.future_desync(|listener| async {
-                listener.accept().await
-                    .map(|(socket, _addr)| {
-                        socket.set_nodelay(true).ok();
-                        socket.into_split()
-                    })
-                    .map_err(|tokio_err| tokio_err.into())
-            }).map_ok_or_else(|_cancelled| Err(ConnectionError::Cancelled), |ok| ok),
+    start_unencrpted_tcp_socket_with_options(scene, program_id, address, SocketOptions::default(), create_input_messages, create_output_messages)
+}
+
+///
+/// As for `start_unencrpted_tcp_socket()`, with options that control how the connections are read and written, and limits on how
+/// many connections can be open and how long they can stay idle
+///
+pub fn start_unencrpted_tcp_socket_with_options<TInputStream, TOutputMessage>(
+        scene:                  &Scene, 
+        program_id:             SubProgramId, 
+        address:                impl ToSocketAddrs, 
+        options:                SocketOptions,
+        create_input_messages:  impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> TInputStream,
+        create_output_messages: impl 'static + Send + Sync + Fn(BoxStream<'static, TOutputMessage>) -> BoxStream<'static, Vec<u8>>
+    ) -> Result<(), ConnectionError> 
+where
+    TInputStream:   'static + Send + Stream,
+    TOutputMessage: 'static + Send,
+{
+    let listener = bind_tcp_listener(address)?;
+
+    scene.add_subprogram(program_id, move |input: InputStream<()>, context| async move {
+        // The tokio listener has to be created in the tokio runtime, so we create it as part of the program
+        let listener = if let Some(listener) = tokio_tcp_listener(&context, listener).await { listener } else { return; };
+
+        // Add a socket runner subprogram. We accept all connections here, and pass the address on as part of the peer information
+        let listener = Arc::new(Mutex::new(Some(listener)));
+
+        socket_listener_subprogram_with_options(input, context, options, move || {
+                // The listener is taken while a connection is being accepted (the accept future needs to run in the tokio runtime)
+                let listener        = Arc::clone(&listener);
+                let our_listener    = listener.lock().unwrap().take().unwrap();
+
+                async move {
+                    let connection = our_listener.accept().await
+                        .map(|(socket, addr)| {
+                            socket.set_nodelay(true).ok();
+                            let (reader, writer)    = socket.into_split();
+                            let peer                = SocketPeer { address: Some(addr.to_string()), ..SocketPeer::default() };
+
+                            (reader, writer, peer)
+                        })
+                        .map_err(|tokio_err| tokio_err.into());
+
+                    *listener.lock().unwrap() = Some(our_listener);
+
+                    connection
+                }
+            },
             create_input_messages,
             create_output_messages).await;
         }, 0);
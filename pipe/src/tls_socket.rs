@@ -0,0 +1,188 @@
+use super::socket::*;
+use super::tcp_socket::*;
+
+use flo_scene::*;
+
+use futures::prelude::*;
+use futures::stream::{BoxStream};
+
+use tokio_rustls::{TlsAcceptor};
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::{WebPkiClientVerifier};
+
+use std::fs;
+use std::path::{Path};
+use std::net::{ToSocketAddrs};
+use std::sync::*;
+
+///
+/// The certificates used by a TLS socket, and how it verifies its clients
+///
+#[derive(Debug)]
+pub struct TlsSocketConfig {
+    /// The certificate chain that the server presents to its clients
+    certificates: Vec<CertificateDer<'static>>,
+
+    /// The private key for the server certificate
+    private_key: PrivateKeyDer<'static>,
+
+    /// The root certificates used to verify client certificates (or None if client certificates are not requested)
+    client_roots: Option<Vec<CertificateDer<'static>>>,
+
+    /// True if clients must supply a certificate, false if they are optional
+    client_certificate_required: bool,
+}
+
+impl TlsSocketConfig {
+    ///
+    /// Creates a TLS configuration from a certificate chain and its private key
+    ///
+    pub fn new(certificates: Vec<CertificateDer<'static>>, private_key: PrivateKeyDer<'static>) -> Self {
+        TlsSocketConfig { certificates, private_key, client_roots: None, client_certificate_required: false }
+    }
+
+    ///
+    /// Creates a TLS configuration from a PEM-encoded certificate chain and private key
+    ///
+    pub fn from_pem(certificates_pem: &[u8], private_key_pem: &[u8]) -> Result<Self, ConnectionError> {
+        let certificates    = read_pem_certificates(certificates_pem)?;
+        let private_key     = rustls_pemfile::private_key(&mut &*private_key_pem)
+            .map_err(|err| ConnectionError::IoError(format!("{}", err)))?
+            .ok_or_else(|| ConnectionError::IoError("No private key was found".into()))?;
+
+        Ok(Self::new(certificates, private_key))
+    }
+
+    ///
+    /// Creates a TLS configuration by reading a PEM-encoded certificate chain and private key from files
+    ///
+    pub fn from_pem_files(certificates_path: impl AsRef<Path>, private_key_path: impl AsRef<Path>) -> Result<Self, ConnectionError> {
+        let certificates_pem    = fs::read(certificates_path).map_err(|err| ConnectionError::IoError(format!("{}", err)))?;
+        let private_key_pem     = fs::read(private_key_path).map_err(|err| ConnectionError::IoError(format!("{}", err)))?;
+
+        Self::from_pem(&certificates_pem, &private_key_pem)
+    }
+
+    ///
+    /// Requests certificates from clients, which are verified against the specified root certificates
+    ///
+    /// If `required` is true, clients that don't supply a valid certificate are refused. Otherwise, clients can connect without a
+    /// certificate, but any certificate they do supply must be valid. The certificate supplied by a client can be read from the
+    /// `client_certificate` field of the `SocketPeer` for the connection.
+    ///
+    pub fn with_client_certificates(mut self, root_certificates: Vec<CertificateDer<'static>>, required: bool) -> Self {
+        self.client_roots                   = Some(root_certificates);
+        self.client_certificate_required    = required;
+        self
+    }
+
+    ///
+    /// As for `with_client_certificates()`, except the root certificates are PEM-encoded
+    ///
+    pub fn with_client_certificates_pem(self, root_certificates_pem: &[u8], required: bool) -> Result<Self, ConnectionError> {
+        let root_certificates = read_pem_certificates(root_certificates_pem)?;
+
+        Ok(self.with_client_certificates(root_certificates, required))
+    }
+
+    ///
+    /// Creates the rustls server configuration for this TLS configuration
+    ///
+    fn server_config(self) -> Result<ServerConfig, ConnectionError> {
+        let provider    = Arc::new(ring::default_provider());
+        let builder     = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .map_err(|err| ConnectionError::IoError(format!("{}", err)))?;
+
+        let builder = if let Some(client_roots) = self.client_roots {
+            // Verify client certificates using the supplied roots
+            let mut roots = RootCertStore::empty();
+            for root in client_roots {
+                roots.add(root).map_err(|err| ConnectionError::IoError(format!("{}", err)))?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if self.client_certificate_required { verifier } else { verifier.allow_unauthenticated() };
+            let verifier = verifier.build().map_err(|err| ConnectionError::IoError(format!("{}", err)))?;
+
+            builder.with_client_cert_verifier(verifier)
+        } else {
+            builder.with_no_client_auth()
+        };
+
+        builder.with_single_cert(self.certificates, self.private_key)
+            .map_err(|err| ConnectionError::IoError(format!("{}", err)))
+    }
+}
+
+///
+/// Reads the certificates from a PEM file
+///
+fn read_pem_certificates(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>, ConnectionError> {
+    let certificates = rustls_pemfile::certs(&mut &*pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| ConnectionError::IoError(format!("{}", err)))?;
+
+    if certificates.is_empty() {
+        Err(ConnectionError::IoError("No certificates were found".into()))
+    } else {
+        Ok(certificates)
+    }
+}
+
+///
+/// Starts a sub-program that accepts TLS connections on a TCP socket (requires the `tls` feature)
+///
+/// This works like `start_unencrpted_tcp_socket()`, except that the streams are encrypted using the certificates in the supplied
+/// configuration. Connections that fail the TLS handshake (for example, because they don't supply a valid client certificate when
+/// one is required) or that don't finish it within 10 seconds are dropped without being sent to the subscribers. Each handshake is
+/// performed separately, so a slow client doesn't hold up other connections.
+///
+pub fn start_tls_tcp_socket<TInputStream, TOutputMessage>(
+        scene:                  &Scene,
+        program_id:             SubProgramId,
+        address:                impl ToSocketAddrs,
+        tls_config:             TlsSocketConfig,
+        create_input_messages:  impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> TInputStream,
+        create_output_messages: impl 'static + Send + Sync + Fn(BoxStream<'static, TOutputMessage>) -> BoxStream<'static, Vec<u8>>
+    ) -> Result<(), ConnectionError>
+where
+    TInputStream:   'static + Send + Stream,
+    TOutputMessage: 'static + Send,
+{
+    // Errors in the configuration are reported immediately
+    let acceptor = TlsAcceptor::from(Arc::new(tls_config.server_config()?));
+
+    let listener = bind_tcp_listener(address)?;
+
+    scene.add_subprogram(program_id, move |input: InputStream<()>, context| async move {
+        // The tokio listener has to be created in the tokio runtime, so we create it as part of the program
+        let listener = if let Some(listener) = tokio_tcp_listener(&context, listener).await { listener } else { return; };
+
+        // The TLS handshake is performed for each connection before it's passed on to the subscribers
+        let accept_connection = accept_with_handshake(&context, listener, move |socket, addr| {
+            let accept = acceptor.accept(socket);
+
+            async move {
+                let tls_stream = accept.await.ok()?;
+
+                // The client certificate identifies the client if one was requested
+                let (_, connection)     = tls_stream.get_ref();
+                let client_certificate  = connection.peer_certificates()
+                    .and_then(|certificates| certificates.first())
+                    .map(|certificate| certificate.to_vec());
+                let peer                = SocketPeer { address: Some(addr.to_string()), client_certificate, ..SocketPeer::default() };
+
+                let (reader, writer) = tokio::io::split(tls_stream);
+                Some((reader, writer, peer))
+            }
+        });
+
+        socket_listener_subprogram_with_options(input, context, SocketOptions::default(), accept_connection, create_input_messages, create_output_messages).await;
+    }, 0);
+
+    // Success
+    Ok(())
+}
@@ -6,21 +6,58 @@ use futures::prelude::*;
 use futures::stream::{BoxStream};
 
 #[cfg(unix)]
-use tokio::net::{UnixListener};
+use tokio::net::{UnixListener, UnixStream};
 
+#[cfg(unix)]
+use std::fs;
 use std::path::*;
 use std::sync::*;
 
+///
+/// A listener for a unix domain socket, which removes the socket file when it is dropped
+///
+#[cfg(unix)]
+struct UnixSocketListener {
+    /// The listener for this socket
+    listener: UnixListener,
+
+    /// The path where the socket is bound
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl Drop for UnixSocketListener {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+///
+/// Reads the credentials of the process at the other end of a unix domain socket
+///
+#[cfg(unix)]
+pub (crate) fn unix_socket_peer(socket: &UnixStream) -> SocketPeer {
+    socket.peer_cred()
+        .map(|credentials| SocketPeer { user_id: Some(credentials.uid()), group_id: Some(credentials.gid()), process_id: credentials.pid(), ..SocketPeer::default() })
+        .unwrap_or_default()
+}
+
 ///
 /// Starts a sub-program in a sceme that accepts connections on a unix domain socket that binds at a specified path
 ///
 /// To use this subprogram, the scene must be running inside a tokio runtime. The program will accept no connections if this
 /// crate was not compiled for UNIX.
 ///
+/// The `SocketConnection` for each connection has a `SocketPeer` containing the user, group and process IDs of the connecting process,
+/// which can be used to decide whether or not to accept the connection.
+///
 /// The program will wait for subscribers (the `Subscribe` message) to the `SocketMessage<TInputStream::Item, TOutputStream::Item>`
 /// message. Typically, there's only one subscriber but in the event multiple are connected, they are informed of connections in
 /// a round-robin fashion.
 ///
+/// Closing the program's input stream (for example, by sending `SceneControl::Close(program_id)`) stops the listener and removes
+/// the socket file.
+///
 pub fn start_unix_socket_program<TInputStream, TOutputMessage>(
         scene:                  &Scene, 
         program_id:             SubProgramId, 
@@ -31,22 +68,50 @@ pub fn start_unix_socket_program<TInputStream, TOutputMessage>(
 where
     TInputStream:   'static + Send + Stream,
     TOutputMessage: 'static + Send,
+{
+    start_unix_socket_program_with_options(scene, program_id, path, SocketOptions::default(), create_input_messages, create_output_messages)
+}
+
+///
+/// As for `start_unix_socket_program()`, with options that control how data is read from and written to each connection
+///
+/// For example, `SocketOptions::default().with_read_buffer_size(65536)` can be used to improve the throughput for sockets that are
+/// used to transfer large amounts of data.
+///
+pub fn start_unix_socket_program_with_options<TInputStream, TOutputMessage>(
+        scene:                  &Scene, 
+        program_id:             SubProgramId, 
+        path:                   impl AsRef<Path>, 
+        options:                SocketOptions,
+        create_input_messages:  impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> TInputStream,
+        create_output_messages: impl 'static + Send + Sync + Fn(BoxStream<'static, TOutputMessage>) -> BoxStream<'static, Vec<u8>>
+    ) -> Result<(), ConnectionError> 
+where
+    TInputStream:   'static + Send + Stream,
+    TOutputMessage: 'static + Send,
 {
     #[cfg(unix)]
     {
-        // Create the listener for this program
-        let listener = UnixListener::bind(path)
+        // Create the listener for this program (the socket file is removed when the listener is dropped)
+        let path        = path.as_ref().to_path_buf();
+        let listener    = UnixListener::bind(&path)
             .map_err(|tokio_err| ConnectionError::IoError(format!("{}", tokio_err)))?;
-        let listener = Arc::new(Mutex::new(Some(listener)));
+        let listener    = Arc::new(Mutex::new(Some(UnixSocketListener { listener, path })));
 
         // Add a socket runner subprogram. We don't use the address for anything, ie we accept all connections here
-        scene.add_subprogram(program_id, move |_input: InputStream<()>, context| socket_listener_subprogram(context, move || {
+        scene.add_subprogram(program_id, move |input: InputStream<()>, context| socket_listener_subprogram_with_options(input, context, options, move || {
                 let listener        = Arc::clone(&listener);
                 let our_listener    = listener.lock().unwrap().take().unwrap();
 
                 async move {
-                    let connection = our_listener.accept().await
-                        .map(|(socket, _addr)| socket.into_split())
+                    let connection = our_listener.listener.accept().await
+                        .map(|(socket, _addr)| {
+                            // Unix sockets can tell us which process is at the other end of the connection
+                            let peer                = unix_socket_peer(&socket);
+                            let (reader, writer)    = socket.into_split();
+
+                            (reader, writer, peer)
+                        })
                         .map_err(|tokio_err| tokio_err.into());
 
                     *listener.lock().unwrap() = Some(our_listener);
@@ -63,6 +128,8 @@ where
 
     #[cfg(not(unix))]
     {
+        let _ = options;
+
         // If we're not on Unix, this creates a program that ignores its messages (we can't create any UNIX sockets)
         scene.add_subprogram(program_id, move |input: InputStream<()>, _context| async move {
             let mut input = input;
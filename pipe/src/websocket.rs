@@ -0,0 +1,140 @@
+use super::socket::*;
+use super::tcp_socket::*;
+
+use flo_scene::*;
+
+use futures::prelude::*;
+use futures::stream::{BoxStream, SplitSink, SplitStream};
+use futures::task::{Context, Poll};
+use futures::ready;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::{WebSocketStream};
+use tokio_tungstenite::tungstenite::{Message};
+
+use std::io;
+use std::pin::{Pin};
+use std::net::{ToSocketAddrs};
+
+///
+/// Reads the data from the text and binary frames received from a WebSocket
+///
+struct WebSocketReader<TStream> {
+    /// The frames received from the WebSocket
+    frames: SplitStream<WebSocketStream<TStream>>,
+
+    /// The data from the last frame that was received
+    buffer: Vec<u8>,
+
+    /// The position of the next byte to read from the buffer
+    pos: usize,
+}
+
+///
+/// Writes data to a WebSocket, sending a frame for each write
+///
+/// Writes that contain valid UTF-8 are sent as text frames, and anything else is sent as a binary frame.
+///
+struct WebSocketWriter<TStream> {
+    /// The sink that sends frames to the WebSocket
+    frames: SplitSink<WebSocketStream<TStream>, Message>,
+}
+
+impl<TStream> AsyncRead for WebSocketReader<TStream>
+where
+    TStream: Unpin + AsyncRead + AsyncWrite,
+{
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        loop {
+            // Read from the last frame if there's any data left in it
+            if self.pos < self.buffer.len() {
+                let num_read = (self.buffer.len() - self.pos).min(buf.remaining());
+
+                buf.put_slice(&self.buffer[self.pos..(self.pos + num_read)]);
+                self.pos += num_read;
+
+                return Poll::Ready(Ok(()));
+            }
+
+            // Wait for the next frame containing data (the stream ends when the WebSocket is closed)
+            match ready!(self.frames.poll_next_unpin(cx)) {
+                Some(Ok(Message::Text(text)))       => { self.buffer = text.into_bytes(); self.pos = 0; }
+                Some(Ok(Message::Binary(data)))     => { self.buffer = data; self.pos = 0; }
+                Some(Ok(Message::Close(_))) | None  => { return Poll::Ready(Ok(())); }
+                Some(Ok(_))                         => { }
+                Some(Err(err))                      => { return Poll::Ready(Err(io::Error::other(err))); }
+            }
+        }
+    }
+}
+
+impl<TStream> AsyncWrite for WebSocketWriter<TStream>
+where
+    TStream: Unpin + AsyncRead + AsyncWrite,
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        ready!(self.frames.poll_ready_unpin(cx)).map_err(io::Error::other)?;
+
+        let message = match String::from_utf8(buf.to_vec()) {
+            Ok(text)    => Message::Text(text),
+            Err(err)    => Message::Binary(err.into_bytes()),
+        };
+
+        self.frames.start_send_unpin(message).map_err(io::Error::other)?;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.frames.poll_flush_unpin(cx).map_err(io::Error::other)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.frames.poll_close_unpin(cx).map_err(io::Error::other)
+    }
+}
+
+///
+/// Starts a sub-program that accepts WebSocket connections on a TCP socket (requires the `websocket` feature)
+///
+/// This works like `start_unencrpted_tcp_socket()`, except that clients connect using the WebSocket protocol (any request path is
+/// accepted). The data from text and binary frames is passed on to the input stream, and each block of output is sent as a frame:
+/// for a command socket this means that every response and every message from a background stream is pushed to the client as a
+/// text frame. Connections that fail the WebSocket handshake or that don't finish it within 10 seconds are dropped without being sent
+/// to the subscribers. Each handshake is performed separately, so a slow client doesn't hold up other connections.
+///
+/// The program will wait for subscribers (the `Subscribe` message) to the `SocketMessage<TInputStream::Item, TOutputStream::Item>`
+/// message, so it can be connected to a program like `command_connection_program()`.
+///
+pub fn start_websocket_program<TInputStream, TOutputMessage>(
+        scene:                  &Scene,
+        program_id:             SubProgramId,
+        address:                impl ToSocketAddrs,
+        create_input_messages:  impl 'static + Send + Sync + Fn(BoxStream<'static, Vec<u8>>) -> TInputStream,
+        create_output_messages: impl 'static + Send + Sync + Fn(BoxStream<'static, TOutputMessage>) -> BoxStream<'static, Vec<u8>>
+    ) -> Result<(), ConnectionError>
+where
+    TInputStream:   'static + Send + Stream,
+    TOutputMessage: 'static + Send,
+{
+    let listener = bind_tcp_listener(address)?;
+
+    scene.add_subprogram(program_id, move |input: InputStream<()>, context| async move {
+        // The tokio listener has to be created in the tokio runtime, so we create it as part of the program
+        let listener = if let Some(listener) = tokio_tcp_listener(&context, listener).await { listener } else { return; };
+
+        // The WebSocket handshake is performed for each connection before it's passed on to the subscribers
+        let accept_connection = accept_with_handshake(&context, listener, |socket, addr| async move {
+            let websocket           = tokio_tungstenite::accept_async(socket).await.ok()?;
+            let (writer, reader)    = websocket.split();
+            let peer                = SocketPeer { address: Some(addr.to_string()), ..SocketPeer::default() };
+
+            Some((WebSocketReader { frames: reader, buffer: vec![], pos: 0 }, WebSocketWriter { frames: writer }, peer))
+        });
+
+        socket_listener_subprogram_with_options(input, context, SocketOptions::default(), accept_connection, create_input_messages, create_output_messages).await;
+    }, 0);
+
+    // Success
+    Ok(())
+}
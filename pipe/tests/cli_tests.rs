@@ -0,0 +1,120 @@
+#![cfg(unix)]
+
+use flo_scene::*;
+use flo_scene_pipe::*;
+use flo_scene_pipe::commands::*;
+
+use std::env;
+use std::fs;
+use std::io::{Write};
+use std::path::{PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+///
+/// Starts a scene with a command socket at a temporary path on a separate thread, returning the path of the socket
+///
+fn start_command_socket_scene(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("flo_scene_cli_{}_{}", name, std::process::id()));
+    fs::remove_file(&path).ok();
+
+    let (send_ready, recv_ready)    = mpsc::channel();
+    let socket_path                 = path.clone();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default()
+                .with_standard_json_commands();
+
+            let command_program = SubProgramId::new();
+            scene.add_subprogram(command_program, |input, context| command_connection_program(input, context, ()), 0);
+
+            let socket_program = SubProgramId::new();
+            start_unix_socket_program(&scene, socket_program, &socket_path, read_command_data, write_command_data).unwrap();
+            scene.connect_programs(socket_program, command_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+
+            send_ready.send(()).unwrap();
+            scene.run_scene().await;
+        });
+    });
+
+    recv_ready.recv().unwrap();
+    path
+}
+
+///
+/// Runs the CLI with the specified arguments and stdin
+///
+fn run_cli(args: &[&str], stdin: &str) -> Output {
+    let mut cli = Command::new(env!("CARGO_BIN_EXE_flo_scene_cli"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    cli.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    cli.wait_with_output().unwrap()
+}
+
+#[test]
+fn run_one_shot_command() {
+    let path    = start_command_socket_scene("one_shot");
+    let output  = run_cli(&["--unix", path.to_str().unwrap(), "-c", "echo \"Hello\""], "");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.code() == Some(0), "{:?}", output);
+    assert!(stdout.contains("Hello"), "{:?}", stdout);
+}
+
+#[test]
+fn command_error_sets_exit_code() {
+    let path    = start_command_socket_scene("error");
+    let output  = run_cli(&["--unix", path.to_str().unwrap(), "-c", "not::a::command"], "");
+
+    assert!(output.status.code() == Some(1), "{:?}", output);
+    assert!(!output.stderr.is_empty(), "{:?}", output);
+}
+
+#[test]
+fn print_raw_json() {
+    let path    = start_command_socket_scene("raw_json");
+    let output  = run_cli(&["--unix", path.to_str().unwrap(), "--raw", "-c", "[1, 2]"], "");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.code() == Some(0), "{:?}", output);
+    assert!(stdout.trim() == "[1,2]", "{:?}", stdout);
+}
+
+#[test]
+fn pretty_print_json() {
+    let path    = start_command_socket_scene("pretty_json");
+    let output  = run_cli(&["--unix", path.to_str().unwrap(), "-c", "[1, 2]"], "");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.code() == Some(0), "{:?}", output);
+    assert!(stdout.trim() == "[\n  1,\n  2\n]", "{:?}", stdout);
+}
+
+#[test]
+fn read_commands_from_stdin() {
+    let path    = start_command_socket_scene("stdin");
+    let output  = run_cli(&["--unix", path.to_str().unwrap()], "echo \"One\"\nif true {\n    echo \"Two\"\n}\n");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.code() == Some(0), "{:?}", output);
+    assert!(stdout.contains("One"), "{:?}", stdout);
+    assert!(stdout.contains("Two"), "{:?}", stdout);
+}
+
+#[test]
+fn connection_failure_exit_code() {
+    let path    = env::temp_dir().join(format!("flo_scene_cli_missing_{}", std::process::id()));
+    let output  = run_cli(&["--unix", path.to_str().unwrap(), "-c", "echo \"Hello\""], "");
+
+    assert!(output.status.code() == Some(2), "{:?}", output);
+}
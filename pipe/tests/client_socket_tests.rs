@@ -0,0 +1,197 @@
+#![cfg(unix)]
+
+use flo_scene::*;
+use flo_scene_pipe::*;
+use flo_scene_pipe::commands::*;
+
+use futures::prelude::*;
+
+use std::env;
+use std::fs;
+use std::path::{PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration};
+
+///
+/// Returns a path for a unix socket in the temporary directory
+///
+fn socket_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("flo_scene_client_{}_{}", name, std::process::id()));
+    fs::remove_file(&path).ok();
+
+    path
+}
+
+///
+/// Starts a scene with a command socket at the specified path on a separate thread
+///
+fn start_command_socket_scene(path: PathBuf) {
+    let (send_ready, recv_ready) = mpsc::channel();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default().with_standard_json_commands();
+
+            let command_program = SubProgramId::new();
+            scene.add_subprogram(command_program, |input, context| command_connection_program(input, context, ()), 0);
+
+            let socket_program = SubProgramId::new();
+            start_unix_socket_program(&scene, socket_program, &path, read_command_data, write_command_data).unwrap();
+            scene.connect_programs(socket_program, command_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+
+            send_ready.send(()).unwrap();
+            scene.run_scene().await;
+        });
+    });
+
+    recv_ready.recv().unwrap();
+}
+
+///
+/// Starts a scene that connects to a command socket at the specified path on a separate thread
+///
+/// Each time a connection is made, the client sends `echo "Connection <n>"` and then closes its side of the connection. The output
+/// received from each connection is sent to the returned receiver.
+///
+fn start_client_scene(path: PathBuf, backoff: ReconnectBackoff) -> mpsc::Receiver<String> {
+    let (send_output, recv_output) = mpsc::channel();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default();
+
+            let client_program = SubProgramId::new();
+            scene.add_subprogram(client_program, move |input: InputStream<CommandProgramSocketMessage>, _context| async move {
+                let mut input               = input;
+                let mut connection_count    = 0;
+
+                while let Some(SocketMessage::Connection(connection)) = input.next().await {
+                    connection_count += 1;
+
+                    let command         = CommandData(format!("echo \"Connection {}\"\n", connection_count).into_bytes());
+                    let mut responses   = connection.connect(stream::iter(vec![command]));
+
+                    let mut output = vec![];
+                    while let Some(CommandData(data)) = responses.next().await {
+                        output.extend(data);
+                    }
+
+                    send_output.send(String::from_utf8_lossy(&output).to_string()).ok();
+                }
+            }, 0);
+
+            let socket_program = SubProgramId::new();
+            start_unix_socket_client_program(&scene, socket_program, &path, backoff, read_command_data, write_command_data).unwrap();
+            scene.connect_programs(socket_program, client_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+
+            scene.run_scene().await;
+        });
+    });
+
+    recv_output
+}
+
+#[test]
+fn send_command_to_other_scene() {
+    let path = socket_path("send_command");
+
+    start_command_socket_scene(path.clone());
+    let output = start_client_scene(path, ReconnectBackoff::new(Duration::from_millis(10), Duration::from_millis(100)));
+
+    let first_output = output.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert!(first_output.contains("Connection 1"), "{:?}", first_output);
+}
+
+#[test]
+fn reconnect_when_connection_closes() {
+    let path = socket_path("reconnect");
+
+    start_command_socket_scene(path.clone());
+    let output = start_client_scene(path, ReconnectBackoff::new(Duration::from_millis(10), Duration::from_millis(100)));
+
+    let first_output    = output.recv_timeout(Duration::from_secs(10)).unwrap();
+    let second_output   = output.recv_timeout(Duration::from_secs(10)).unwrap();
+
+    assert!(first_output.contains("Connection 1"), "{:?}", first_output);
+    assert!(second_output.contains("Connection 2"), "{:?}", second_output);
+}
+
+#[test]
+fn retry_until_socket_is_available() {
+    let path = socket_path("retry");
+
+    // The client starts before there's anything to connect to
+    let output = start_client_scene(path.clone(), ReconnectBackoff::new(Duration::from_millis(10), Duration::from_millis(50)));
+    thread::sleep(Duration::from_millis(200));
+
+    start_command_socket_scene(path);
+
+    let first_output = output.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert!(first_output.contains("Connection 1"), "{:?}", first_output);
+}
+
+#[test]
+fn give_up_after_max_attempts() {
+    let path = socket_path("give_up");
+
+    // The client gives up before the socket is available, so no connection is ever made
+    let output = start_client_scene(path.clone(), ReconnectBackoff::new(Duration::from_millis(10), Duration::from_millis(10)).with_max_attempts(3));
+    thread::sleep(Duration::from_millis(200));
+
+    start_command_socket_scene(path);
+
+    assert!(output.recv_timeout(Duration::from_millis(500)).is_err());
+}
+
+#[test]
+fn connect_to_tcp_socket() {
+    let port                        = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let (send_output, recv_output)  = mpsc::channel();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default().with_standard_json_commands();
+
+            // Command socket listening on a TCP port
+            let command_program = SubProgramId::new();
+            scene.add_subprogram(command_program, |input, context| command_connection_program(input, context, ()), 0);
+
+            let listener_program = SubProgramId::new();
+            start_unencrpted_tcp_socket(&scene, listener_program, ("127.0.0.1", port), read_command_data, write_command_data).unwrap();
+            scene.connect_programs(listener_program, command_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+
+            // Client that connects to the same port
+            let client_program = SubProgramId::new();
+            scene.add_subprogram(client_program, move |input: InputStream<CommandProgramSocketMessage>, _context| async move {
+                let mut input = input;
+
+                if let Some(SocketMessage::Connection(connection)) = input.next().await {
+                    let mut responses = connection.connect(stream::iter(vec![CommandData(b"echo \"Over TCP\"\n".to_vec())]));
+
+                    let mut output = vec![];
+                    while let Some(CommandData(data)) = responses.next().await {
+                        output.extend(data);
+                    }
+
+                    send_output.send(String::from_utf8_lossy(&output).to_string()).ok();
+                }
+            }, 0);
+
+            let client_socket_program = SubProgramId::new();
+            start_tcp_socket_client_program(&scene, client_socket_program, ("127.0.0.1", port), ReconnectBackoff::new(Duration::from_millis(10), Duration::from_millis(100)), read_command_data, write_command_data).unwrap();
+            scene.connect_programs(client_socket_program, client_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+
+            scene.run_scene().await;
+        });
+    });
+
+    let output = recv_output.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert!(output.contains("Over TCP"), "{:?}", output);
+}
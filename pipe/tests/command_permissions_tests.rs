@@ -0,0 +1,330 @@
+use flo_scene::*;
+use flo_scene::programs::*;
+use flo_scene_pipe::*;
+use flo_scene_pipe::commands::*;
+
+use futures::prelude::*;
+use serde::*;
+use tokio::io::*;
+
+use std::sync::*;
+
+/// TestSucceeded message is used to indicate when a test has passed
+#[derive(Serialize, Deserialize, Debug)]
+struct TestSucceeded { message: String }
+impl SceneMessage for TestSucceeded {
+    fn message_type_name() -> String { "test::TestSucceeded".into() }
+}
+
+///
+/// Creates an internal socket program in a scene that can be used to send commands to an authenticated command program
+///
+fn create_internal_command_socket(scene: &Scene, internal_socket_id: SubProgramId, authenticator: impl 'static + CommandAuthenticator) {
+    // The command connection program receives connections from sockets
+    let command_program = SubProgramId::new();
+    let authenticator   = Arc::new(Mutex::new(Some(authenticator)));
+    scene.add_subprogram(command_program, move |input, context| {
+        let authenticator = authenticator.lock().unwrap().take().unwrap();
+        authenticated_command_connection_program(input, context, (), authenticator)
+    }, 0);
+
+    // The internal socket program lets us receive connections and send messages to the command program as streams of data
+    start_internal_socket_program(scene, internal_socket_id, read_command_data, write_command_data).unwrap();
+
+    // Connect the internal socket program to the command program
+    scene.connect_programs(internal_socket_id, command_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+}
+
+///
+/// Adds a subprogram that runs some commands using the internal socket program
+///
+fn add_command_runner<TFuture>(scene: &Scene, internal_socket_id: SubProgramId, commands: impl Into<String>, process_results: impl 'static + Send + Fn(String, SceneContext) -> TFuture) 
+where
+    TFuture: 'static + Send + Future<Output=()>
+{
+    // Create an arbitrary program ID
+    let program_id  = SubProgramId::called("command_runner");
+    let commands    = commands.into();
+
+    scene.add_subprogram(program_id, move |_: InputStream<()>, context| async move {
+        context.wait_for_idle(100).await;
+
+        // Create a connection via the internal socket
+        let (our_side, their_side)          = duplex(1024);
+        let (command_input, command_output) = split(their_side);
+        let (read_result, write_command)    = split(our_side);
+
+        let mut socket_program = context.send(internal_socket_id).unwrap();
+        socket_program.send(InternalSocketMessage::CreateInternalSocket(Box::new(command_input), Box::new(command_output))).await.ok().unwrap();
+
+        let context = &context;
+
+        // Future that writes the commands
+        let write_side = async move {
+            println!("In: {}", commands);
+
+            // Send the commands to the write side and then stop
+            let mut write_command = write_command;
+
+            write_command.write_all(&commands.bytes().collect::<Vec<u8>>()).await.unwrap();
+
+            println!("Sent all");
+
+            context.wait_for_idle(100).await;
+
+            write_command.flush().await.unwrap();
+            write_command.shutdown().await.unwrap();
+
+            println!("Finished sending");
+        };
+
+        // Future that reads the results and processes them
+        let read_side = async move {
+            let mut bytes = vec![];
+
+            let mut read_result = read_result;
+            let mut buf = vec![];
+            while let Ok(len) = read_result.read_buf(&mut buf).await {
+                println!("{:?}", String::from_utf8_lossy(&buf));
+                bytes.extend(&buf);
+                buf.drain(..);
+
+                if len == 0 {
+                    break;
+                }
+            }
+
+            let string_result = String::from_utf8_lossy(&bytes);
+            println!("\nOut: {}", string_result);
+            process_results(string_result.into(), context.clone()).await;
+        };
+
+        // Wait for both futures together to run the socket
+        future::join(write_side, read_side).await;
+    }, 0)
+}
+
+#[test]
+fn only_run_allowed_commands() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("allowed_commands_internal_socket");
+    let test_program    = SubProgramId::called("allowed_commands_test_program");
+
+    create_internal_command_socket(&scene, internal_socket, |_: &CommandCredentials| Ok(CommandPermissions::all().with_commands(["echo"])));
+    add_command_runner(&scene, internal_socket, 
+        r#"echo "Allowed"
+        list_subprograms
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains("Allowed"), "{}", msg);
+            assert!(msg.contains("Cannot run `list_subprograms` (TargetPermissionRefused)"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn watch_needs_permission() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("watch_permission_internal_socket");
+    let test_program    = SubProgramId::called("watch_permission_test_program");
+
+    create_internal_command_socket(&scene, internal_socket, |_: &CommandCredentials| Ok(CommandPermissions::all().with_commands(["echo"])));
+    add_command_runner(&scene, internal_socket, 
+        r#":value = 1
+        watch :value
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains("Cannot run `watch` (TargetPermissionRefused)"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn only_send_to_allowed_targets() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("allowed_targets_internal_socket");
+    let test_program    = SubProgramId::called("allowed_targets_test_program");
+
+    create_internal_command_socket(&scene, internal_socket, |_: &CommandCredentials| Ok(CommandPermissions::all().with_targets([SubProgramId::called("allowed_targets::allowed")])));
+    add_command_runner(&scene, internal_socket, 
+        r#"echo "Default target"
+        @allowed_targets::not_allowed echo "Not allowed"
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains("Default target"), "{}", msg);
+            assert!(msg.contains("Cannot run `echo` (TargetPermissionRefused)"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn send_needs_permission_for_its_target() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("send_targets_internal_socket");
+    let test_program    = SubProgramId::called("send_targets_test_program");
+
+    // Sending to a subprogram by name, or sending scene control messages to the control program, needs permission for that target
+    create_internal_command_socket(&scene, internal_socket, |_: &CommandCredentials| Ok(CommandPermissions::all().with_targets([SubProgramId::called("send_targets::allowed")])));
+    add_command_runner(&scene, internal_socket, 
+        r#"send { "SubProgram": { "Named": "send_targets::not_allowed" } }
+        send { "Type": "flo_scene::SceneControl" }
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.matches("Cannot run `send` (TargetPermissionRefused)").count() == 2, "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn connect_needs_permission_for_its_programs() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("connect_targets_internal_socket");
+    let test_program    = SubProgramId::called("connect_targets_test_program");
+
+    // Both the source and the target of a connection need to be allowed
+    create_internal_command_socket(&scene, internal_socket, |_: &CommandCredentials| Ok(CommandPermissions::all().with_targets([SubProgramId::called("connect_targets::allowed")])));
+    add_command_runner(&scene, internal_socket, 
+        r#"connect { "source_program": { "Program": { "Named": "connect_targets::allowed" } }, "target_program": { "Program": { "Named": "connect_targets::not_allowed" } }, "stream_type_name": "test::TestSucceeded" }
+        connect { "source_program": { "Program": { "Named": "connect_targets::not_allowed" } }, "target_program": "None", "stream_type_name": "test::TestSucceeded" }
+        connect { "source_program": { "Program": { "Named": "connect_targets::allowed" } }, "target_program": "None", "stream_type_name": "test::TestSucceeded" }
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.matches("Cannot run `connect` (TargetPermissionRefused)").count() == 2, "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn authenticate_with_shared_token() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("shared_token_internal_socket");
+    let test_program    = SubProgramId::called("shared_token_test_program");
+
+    create_internal_command_socket(&scene, internal_socket, SharedTokenAuthenticator::new("secret", CommandPermissions::all()));
+    add_command_runner(&scene, internal_socket, 
+        r#"echo "Before"
+        authenticate "wrong"
+        authenticate "secret"
+        echo "After"
+        "#, 
+        move |msg, context| async move {
+            assert!(!msg.contains("Before"), "{}", msg);
+            assert!(msg.contains("Cannot run `echo` (TargetPermissionRefused)"), "{}", msg);
+            assert!(msg.contains("Authentication failed (TargetPermissionRefused)"), "{}", msg);
+            assert!(msg.contains("Authenticated"), "{}", msg);
+            assert!(msg.contains("After"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::os::unix::fs::{MetadataExt};
+    use std::os::unix::net::{UnixStream};
+    use std::path::{PathBuf};
+    use std::sync::mpsc;
+    use std::thread;
+
+    ///
+    /// Starts a scene with an authenticated unix command socket that will accept commands from processes running as the user IDs returned by a function
+    ///
+    fn start_unix_command_socket(name: &str, user_ids: impl 'static + Send + Fn(u32) -> Vec<u32>) -> PathBuf {
+        let path = env::temp_dir().join(format!("flo_scene_permissions_{}_{}", name, std::process::id()));
+        fs::remove_file(&path).ok();
+
+        let (send_ready, recv_ready)    = mpsc::channel();
+        let socket_path                 = path.clone();
+
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+
+            runtime.block_on(async move {
+                let scene           = Scene::default().with_standard_json_commands();
+                let socket_program  = SubProgramId::new();
+                start_unix_socket_program(&scene, socket_program, &socket_path, read_command_data, write_command_data).unwrap();
+
+                // The socket file is created by this process, so its owner is the user ID that the tests are running as
+                let our_user_id     = fs::metadata(&socket_path).unwrap().uid();
+                let authenticator   = Arc::new(Mutex::new(Some(PeerUserAuthenticator::new(user_ids(our_user_id), CommandPermissions::all()))));
+
+                let command_program = SubProgramId::new();
+                scene.add_subprogram(command_program, move |input, context| {
+                    let authenticator = authenticator.lock().unwrap().take().unwrap();
+                    authenticated_command_connection_program(input, context, (), authenticator)
+                }, 0);
+                scene.connect_programs(socket_program, command_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+
+                send_ready.send(()).unwrap();
+                scene.run_scene().await;
+            });
+        });
+
+        recv_ready.recv().unwrap();
+        path
+    }
+
+    ///
+    /// Sends a command to a unix socket, and returns the output once the socket is closed
+    ///
+    fn send_command(path: &PathBuf, command: &str) -> String {
+        let mut stream = UnixStream::connect(path).unwrap();
+        stream.write_all(command.as_bytes()).unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+
+        let mut output = String::new();
+        stream.read_to_string(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn accept_peer_user() {
+        let path    = start_unix_command_socket("accept_peer_user", |our_user_id| vec![our_user_id]);
+        let output  = send_command(&path, "echo \"Hello\"\n");
+
+        assert!(output.contains("Hello"), "{}", output);
+    }
+
+    #[test]
+    fn refuse_peer_user() {
+        let path    = start_unix_command_socket("refuse_peer_user", |our_user_id| vec![our_user_id.wrapping_add(1)]);
+        let output  = send_command(&path, "echo \"Hello\"\n");
+
+        assert!(!output.contains("Hello"), "{}", output);
+        assert!(output.contains("TargetPermissionRefused"), "{}", output);
+    }
+}
@@ -0,0 +1,291 @@
+use flo_scene::*;
+use flo_scene::programs::*;
+use flo_scene_pipe::*;
+use flo_scene_pipe::commands::*;
+
+use futures::prelude::*;
+use serde::*;
+use tokio::io::*;
+
+use std::env;
+use std::fs;
+use std::path::{PathBuf};
+use std::thread;
+use std::time::{Duration};
+
+/// TestSucceeded message is used to indicate when a test has passed
+#[derive(Serialize, Deserialize, Debug)]
+struct TestSucceeded { message: String }
+impl SceneMessage for TestSucceeded {
+    fn message_type_name() -> String { "test::TestSucceeded".into() }
+}
+
+///
+/// Returns an empty directory that can be used to store the profiles for a test
+///
+fn profile_directory(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("flo_scene_profile_tests_{}_{}", name, std::process::id()));
+    fs::remove_dir_all(&directory).ok();
+    fs::create_dir_all(&directory).unwrap();
+
+    directory
+}
+
+///
+/// Creates an internal socket program in a scene that can be used to send commands to a command program that uses a set of profiles
+///
+fn create_internal_command_socket(scene: &Scene, internal_socket_id: SubProgramId, profiles: CommandProfiles) {
+    // The command connection program receives connections from sockets
+    let command_program = SubProgramId::new();
+    scene.add_subprogram(command_program, move |input, context| profile_command_connection_program(input, context, (), profiles.clone()), 0);
+
+    // The internal socket program lets us receive connections and send messages to the command program as streams of data
+    start_internal_socket_program(scene, internal_socket_id, read_command_data, write_command_data).unwrap();
+
+    // Connect the internal socket program to the command program
+    scene.connect_programs(internal_socket_id, command_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+}
+
+///
+/// Adds a subprogram that runs some commands using the internal socket program
+///
+fn add_command_runner<TFuture>(scene: &Scene, internal_socket_id: SubProgramId, commands: impl Into<String>, process_results: impl 'static + Send + Fn(String, SceneContext) -> TFuture) 
+where
+    TFuture: 'static + Send + Future<Output=()>
+{
+    // Create an arbitrary program ID
+    let program_id  = SubProgramId::called("command_runner");
+    let commands    = commands.into();
+
+    scene.add_subprogram(program_id, move |_: InputStream<()>, context| async move {
+        context.wait_for_idle(100).await;
+
+        // Create a connection via the internal socket
+        let (our_side, their_side)          = duplex(1024);
+        let (command_input, command_output) = split(their_side);
+        let (read_result, write_command)    = split(our_side);
+
+        let mut socket_program = context.send(internal_socket_id).unwrap();
+        socket_program.send(InternalSocketMessage::CreateInternalSocket(Box::new(command_input), Box::new(command_output))).await.ok().unwrap();
+
+        let context = &context;
+
+        // Future that writes the commands
+        let write_side = async move {
+            println!("In: {}", commands);
+
+            // Send the commands to the write side and then stop
+            let mut write_command = write_command;
+
+            write_command.write_all(&commands.bytes().collect::<Vec<u8>>()).await.unwrap();
+
+            println!("Sent all");
+
+            context.wait_for_idle(100).await;
+
+            write_command.flush().await.unwrap();
+            write_command.shutdown().await.unwrap();
+
+            println!("Finished sending");
+        };
+
+        // Future that reads the results and processes them
+        let read_side = async move {
+            let mut bytes = vec![];
+
+            let mut read_result = read_result;
+            let mut buf = vec![];
+            while let Ok(len) = read_result.read_buf(&mut buf).await {
+                println!("{:?}", String::from_utf8_lossy(&buf));
+                bytes.extend(&buf);
+                buf.drain(..);
+
+                if len == 0 {
+                    break;
+                }
+            }
+
+            let string_result = String::from_utf8_lossy(&bytes);
+            println!("\nOut: {}", string_result);
+            process_results(string_result.into(), context.clone()).await;
+        };
+
+        // Wait for both futures together to run the socket
+        future::join(write_side, read_side).await;
+    }, 0)
+}
+
+#[test]
+fn list_export_and_clear_variables() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("vars_internal_socket");
+    let test_program    = SubProgramId::called("vars_test_program");
+
+    create_internal_command_socket(&scene, internal_socket, CommandProfiles::new(profile_directory("vars")));
+    add_command_runner(&scene, internal_socket, 
+        r#":greeting = "Hello"
+        :count = 3
+        vars
+        vars "export"
+        vars "clear"
+        vars
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains(":count = 3"), "{}", msg);
+            assert!(msg.contains(r#":greeting = "Hello""#), "{}", msg);
+            assert!(msg.contains(r#"":count": 3"#), "{}", msg);
+            assert!(msg.contains("Cleared 2 variables"), "{}", msg);
+            assert!(msg.contains("No variables are defined"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn save_and_load_profile() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("save_load_internal_socket");
+    let test_program    = SubProgramId::called("save_load_test_program");
+
+    create_internal_command_socket(&scene, internal_socket, CommandProfiles::new(profile_directory("save_load")));
+    add_command_runner(&scene, internal_socket, 
+        r#":answer = 42
+        vars { "save": "saved" }
+        vars "clear"
+        vars { "load": "saved" }
+        echo :answer
+        vars { "load": "../not_a_profile" }
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains("Variables saved to profile `saved`"), "{}", msg);
+            assert!(msg.contains("Variables loaded from profile `saved`"), "{}", msg);
+            assert!(msg.contains("42"), "{}", msg);
+            assert!(msg.contains("Could not load profile `../not_a_profile`"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn cannot_use_another_users_peer_profile() {
+    let directory       = profile_directory("other_user");
+    let profiles        = CommandProfiles::new(&directory).with_peer_profiles(true);
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("other_user_internal_socket");
+    let test_program    = SubProgramId::called("other_user_test_program");
+
+    let mut variables = std::collections::HashMap::new();
+    variables.insert(":secret".to_string(), serde_json::json!("password"));
+    profiles.save_profile("user-0", &variables).unwrap();
+
+    // Internal sockets have no user ID, so they can't use the profile for any user
+    create_internal_command_socket(&scene, internal_socket, profiles.clone());
+    add_command_runner(&scene, internal_socket, 
+        r#"vars { "load": "user-0" }
+        vars { "save": "user-0" }
+        echo :secret
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains("Could not load profile `user-0`"), "{}", msg);
+            assert!(msg.contains("Could not save profile `user-0`"), "{}", msg);
+            assert!(!msg.contains("password"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+
+    // The profile should not have been overwritten
+    assert!(profiles.load_profile("user-0").unwrap() == variables);
+
+    fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn variables_are_saved_when_session_disconnects() {
+    let directory   = profile_directory("disconnect");
+    let profiles    = CommandProfiles::new(&directory);
+
+    // The first session makes the profile active and then changes a variable
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("disconnect_internal_socket");
+    let test_program    = SubProgramId::called("disconnect_test_program");
+
+    create_internal_command_socket(&scene, internal_socket, profiles.clone());
+    add_command_runner(&scene, internal_socket, 
+        r#"vars { "save": "persisted" }
+        :answer = 42
+        "#, 
+        move |_msg, context| async move {
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+
+    // The session saves the profile after the socket has closed
+    for _ in 0..100 {
+        if profiles.load_profile("persisted").map(|variables| variables.contains_key(":answer")).unwrap_or(false) { break; }
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let variables = profiles.load_profile("persisted").unwrap();
+    assert!(variables.get(":answer") == Some(&serde_json::json!(42)), "{:?}", variables);
+
+    // A new session can load the variable again
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("disconnect_internal_socket_2");
+    let test_program    = SubProgramId::called("disconnect_test_program_2");
+
+    create_internal_command_socket(&scene, internal_socket, profiles);
+    add_command_runner(&scene, internal_socket, 
+        r#"vars { "load": "persisted" }
+        echo :answer
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains("42"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn run_startup_file() {
+    let directory       = profile_directory("startup");
+    let startup_file    = directory.join("startup.cmd");
+    fs::write(&startup_file, "// Set up the session\n:greeting = \"Hello from the startup file\"\nnot_a_command\n").unwrap();
+
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("startup_internal_socket");
+    let test_program    = SubProgramId::called("startup_test_program");
+
+    create_internal_command_socket(&scene, internal_socket, CommandProfiles::new(&directory).with_startup_file(&startup_file));
+    add_command_runner(&scene, internal_socket, 
+        r#"echo :greeting
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains("Hello from the startup file"), "{}", msg);
+            assert!(msg.contains("Startup file:"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
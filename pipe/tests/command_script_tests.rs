@@ -0,0 +1,195 @@
+use flo_scene::*;
+use flo_scene::programs::*;
+use flo_scene_pipe::*;
+use flo_scene_pipe::commands::*;
+
+use futures::prelude::*;
+use futures::channel::mpsc;
+use serde::*;
+
+/// Message sent to the test program when a script has finished running
+#[derive(Serialize, Deserialize, Debug)]
+struct ScriptFinished { exit_code: i32, error: String, output: String }
+impl SceneMessage for ScriptFinished {
+    fn message_type_name() -> String { "test::ScriptFinished".into() }
+}
+
+///
+/// Adds a subprogram that runs a script and sends the result to the test program
+///
+fn add_script_runner(scene: &Scene, test_program: SubProgramId, script: &str) {
+    let script = script.as_bytes().to_vec();
+
+    scene.add_subprogram(SubProgramId::new(), move |_: InputStream<()>, context| async move {
+        // Run the script, collecting the output
+        let (send_output, recv_output) = mpsc::channel(1);
+
+        let run_script  = run_command_script(&context, stream::iter(vec![script]), (), send_output.sink_map_err(|_| ()));
+        let read_output = recv_output.map(|CommandData(data)| data).concat();
+
+        let (result, output) = future::join(run_script, read_output).await;
+        let output      = String::from_utf8_lossy(&output).to_string();
+        let exit_code   = result.as_ref().map(|_| 0).unwrap_or_else(|err| err.exit_code());
+        let error       = result.err().map(|err| format!("{:?}", err)).unwrap_or_default();
+
+        println!("{}", output);
+
+        context.send(test_program).unwrap().send(ScriptFinished { exit_code, error, output }).await.unwrap();
+    }, 0);
+}
+
+#[test]
+fn foreach_over_variable() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let test_program    = SubProgramId::new();
+
+    add_script_runner(&scene, test_program, r#"
+        :list = [ "one", "two", "three" ]
+        foreach :item in :list {
+            echo :item
+        }
+        "#);
+
+    TestBuilder::new()
+        .expect_message(|ScriptFinished { exit_code, error, output }| {
+            if exit_code != 0 { return Err(format!("Unexpected error: {}", error)); }
+            if !output.contains("one\n   two\n   three") { return Err(format!("Unexpected output: {:?}", output)); }
+            if output.contains("> ") { return Err(format!("Prompt in output: {:?}", output)); }
+            Ok(())
+        })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn if_else() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let test_program    = SubProgramId::new();
+
+    add_script_runner(&scene, test_program, r#"
+        :empty = [ ]
+        if :empty {
+            echo "Not empty"
+        } else if true {
+            echo "Empty"
+        } else {
+            echo "Unreachable"
+        }
+        "#);
+
+    TestBuilder::new()
+        .expect_message(|ScriptFinished { exit_code, error, output }| {
+            if exit_code != 0 { return Err(format!("Unexpected error: {}", error)); }
+            if output.contains("Not empty") || output.contains("Unreachable") || !output.contains("Empty") { return Err(format!("Unexpected output: {:?}", output)); }
+            Ok(())
+        })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn stop_on_first_error() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let test_program    = SubProgramId::new();
+
+    add_script_runner(&scene, test_program, r#"
+        echo "Before"
+        not::a::command
+        echo "After"
+        "#);
+
+    TestBuilder::new()
+        .expect_message(|ScriptFinished { exit_code, error, output }| {
+            if exit_code != 1 || !error.starts_with("CommandFailed") { return Err(format!("Unexpected error: {} ({})", error, exit_code)); }
+            if !output.contains("Before") || output.contains("After") { return Err(format!("Unexpected output: {:?}", output)); }
+            Ok(())
+        })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn continue_on_marked_error() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let test_program    = SubProgramId::new();
+
+    add_script_runner(&scene, test_program, r#"
+        echo "Before"
+        -not::a::command
+        echo "After"
+        "#);
+
+    TestBuilder::new()
+        .expect_message(|ScriptFinished { exit_code, error, output }| {
+            if exit_code != 0 { return Err(format!("Unexpected error: {}", error)); }
+            if !output.contains("Before") || !output.contains("After") { return Err(format!("Unexpected output: {:?}", output)); }
+            Ok(())
+        })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn unclosed_block_is_parse_error() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let test_program    = SubProgramId::new();
+
+    add_script_runner(&scene, test_program, r#"
+        if true {
+            echo "Never closed"
+        "#);
+
+    TestBuilder::new()
+        .expect_message(|ScriptFinished { exit_code, error, .. }| {
+            if exit_code != 2 || error != format!("{:?}", CommandScriptError::ParseError(CommandParseError::UnclosedBlock)) { return Err(format!("Unexpected error: {} ({})", error, exit_code)); }
+            Ok(())
+        })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn truncated_command_is_parse_error() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let test_program    = SubProgramId::new();
+
+    add_script_runner(&scene, test_program, r#"
+        echo "Before"
+        foreach :item"#);
+
+    TestBuilder::new()
+        .expect_message(|ScriptFinished { exit_code, error, .. }| {
+            if exit_code != 2 || error != format!("{:?}", CommandScriptError::ParseError(CommandParseError::ExpectedMoreInput)) { return Err(format!("Unexpected error: {} ({})", error, exit_code)); }
+            Ok(())
+        })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn trailing_comment_is_end_of_script() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let test_program    = SubProgramId::new();
+
+    add_script_runner(&scene, test_program, r#"
+        echo "Before"
+
+        // Nothing else to do"#);
+
+    TestBuilder::new()
+        .expect_message(|ScriptFinished { exit_code, error, output }| {
+            if exit_code != 0 { return Err(format!("Unexpected error: {}", error)); }
+            if !output.contains("Before") { return Err(format!("Unexpected output: {:?}", output)); }
+            Ok(())
+        })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn script_binary_exits_with_parse_error() {
+    let path = std::env::temp_dir().join(format!("flo_scene_script_truncated_{}.cmd", std::process::id()));
+    std::fs::write(&path, "echo \"Before\"\nforeach :item").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_flo_scene_script"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).ok();
+
+    assert!(output.status.code() == Some(2), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Before"), "{:?}", output);
+}
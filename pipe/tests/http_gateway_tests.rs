@@ -0,0 +1,186 @@
+use flo_scene::*;
+use flo_scene::commands::*;
+use flo_scene_pipe::*;
+use flo_scene_pipe::commands::*;
+
+use futures::prelude::*;
+
+use std::io::{Read, Write};
+use std::net::{TcpStream};
+use std::thread;
+use std::time::{Duration};
+
+///
+/// Finds a TCP port that is not in use
+///
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+///
+/// Starts a scene with a HTTP command gateway on a separate thread, returning the port that it's listening on
+///
+/// The scene has a `test::add_one` command that adds one to a number, and a `test::count_to` command that returns a background
+/// stream of numbers.
+///
+fn start_http_gateway() -> u16 {
+    let port = free_port();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default().with_standard_json_commands();
+
+            let launcher = CommandLauncher::json()
+                .with_json_command("test::add_one", |value: i64, _context| async move {
+                    CommandResponse::Json(serde_json::json!(value + 1))
+                })
+                .with_json_command("test::count_to", |value: i64, _context| async move {
+                    CommandResponse::BackgroundStream(stream::iter((1..=value).map(|num| serde_json::json!(num))).boxed())
+                });
+            scene.add_subprogram(SubProgramId::new(), launcher.to_subprogram(), 0);
+
+            let gateway_program = SubProgramId::new();
+            scene.add_subprogram(gateway_program, |input, context| http_command_gateway_program(input, context, ()), 0);
+
+            let socket_program = SubProgramId::new();
+            start_unencrpted_tcp_socket(&scene, socket_program, ("127.0.0.1", port), read_http_requests, write_http_responses).unwrap();
+            scene.connect_programs(socket_program, gateway_program, StreamId::with_message_type::<HttpSocketMessage>()).unwrap();
+
+            scene.run_scene().await;
+        });
+    });
+
+    port
+}
+
+///
+/// Connects to the gateway, waiting for it to start
+///
+fn connect(port: u16) -> TcpStream {
+    for _ in 0..100 {
+        if let Ok(connection) = TcpStream::connect(("127.0.0.1", port)) {
+            connection.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+            return connection;
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    panic!("Could not connect to the HTTP gateway");
+}
+
+///
+/// Sends some raw requests to the gateway, and returns everything it sends back until the connection is closed
+///
+fn send_requests(port: u16, requests: &str) -> String {
+    let mut connection = connect(port);
+    connection.write_all(requests.as_bytes()).unwrap();
+
+    let mut response = vec![];
+    connection.read_to_end(&mut response).unwrap();
+
+    String::from_utf8_lossy(&response).to_string()
+}
+
+///
+/// Creates a POST request for a command that closes the connection afterwards
+///
+fn post(command: &str, body: &str) -> String {
+    format!("POST /commands/{} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", command, body.len(), body)
+}
+
+///
+/// Returns the body of a response containing a single JSON value
+///
+fn json_body(response: &str) -> serde_json::Value {
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    serde_json::from_str(body).unwrap()
+}
+
+#[test]
+fn post_command() {
+    let port        = start_http_gateway();
+    let response    = send_requests(port, &post("test::add_one", "41"));
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{:?}", response);
+    assert!(response.contains("Content-Type: application/json\r\n"), "{:?}", response);
+    assert!(json_body(&response) == serde_json::json!([{ "Json": 42 }]), "{:?}", response);
+}
+
+#[test]
+fn post_command_with_message_responses() {
+    let port        = start_http_gateway();
+    let response    = send_requests(port, &post("echo", "[\"Hello\", \"World\"]"));
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{:?}", response);
+    assert!(json_body(&response) == serde_json::json!([{ "Message": "Hello" }, { "Message": "World" }]), "{:?}", response);
+}
+
+#[test]
+fn list_commands() {
+    let port        = start_http_gateway();
+    let response    = send_requests(port, "GET /commands HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{:?}", response);
+    assert!(response.contains("\"test::add_one\""), "{:?}", response);
+    assert!(response.contains("\"echo\""), "{:?}", response);
+}
+
+#[test]
+fn unknown_command_is_not_found() {
+    let port        = start_http_gateway();
+    let response    = send_requests(port, &post("not::a::command", ""));
+
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"), "{:?}", response);
+}
+
+#[test]
+fn invalid_json_is_bad_request() {
+    let port        = start_http_gateway();
+    let response    = send_requests(port, &post("test::add_one", "{ not json"));
+
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{:?}", response);
+}
+
+#[test]
+fn background_stream_as_server_sent_events() {
+    let port        = start_http_gateway();
+    let response    = send_requests(port, &post("test::count_to", "3"));
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{:?}", response);
+    assert!(response.contains("Content-Type: text/event-stream\r\n"), "{:?}", response);
+    assert!(response.ends_with("\r\n\r\ndata: 1\n\ndata: 2\n\ndata: 3\n\n"), "{:?}", response);
+}
+
+#[test]
+fn keep_alive() {
+    let port = start_http_gateway();
+
+    // The first request leaves the connection open, so the second request is processed on the same connection
+    let first_request   = "POST /commands/test::add_one HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1\r\n\r\n1";
+    let second_request  = post("test::add_one", "2");
+    let response        = send_requests(port, &format!("{}{}", first_request, second_request));
+
+    assert!(response.matches("HTTP/1.1 200 OK\r\n").count() == 2, "{:?}", response);
+    assert!(response.contains("Connection: keep-alive\r\n"), "{:?}", response);
+    assert!(response.contains("[{\"Json\":2}]"), "{:?}", response);
+    assert!(response.ends_with("[{\"Json\":3}]"), "{:?}", response);
+}
+
+#[test]
+fn unknown_path_is_not_found() {
+    let port        = start_http_gateway();
+    let response    = send_requests(port, "GET /index.html HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"), "{:?}", response);
+}
+
+#[test]
+fn body_too_large() {
+    let port        = start_http_gateway();
+    let response    = send_requests(port, "POST /commands/echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1000000000\r\n\r\n\"Hello\"");
+
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"), "{:?}", response);
+}
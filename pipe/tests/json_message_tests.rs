@@ -0,0 +1,102 @@
+use flo_scene::*;
+use flo_scene::programs::*;
+use flo_scene_pipe::*;
+
+use futures::prelude::*;
+use futures::channel::mpsc;
+use tokio::io::*;
+use serde::*;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct JsonTestMessage {
+    value: usize,
+}
+
+impl SceneMessage for JsonTestMessage {
+    fn message_type_name() -> String { "test::JsonTestMessage".into() }
+}
+
+type JsonTestSocketMessage = SocketMessage<SerializedMessage<serde_json::Value>, SerializedMessage<serde_json::Value>>;
+
+///
+/// Accepts connections from a JSON message socket, and sends the messages to the JsonTestMessage stream, echoing them back to the connection
+///
+async fn json_echo_program(input: InputStream<JsonTestSocketMessage>, context: SceneContext) {
+    let mut input = input;
+    while let Some(SocketMessage::Connection(connection)) = input.next().await {
+        context.send_message(SceneControl::start_program(SubProgramId::new(), move |_: InputStream<()>, context| async move {
+            let (mut send_output, recv_output) = mpsc::channel(5);
+            let mut messages = connection.connect(recv_output);
+            let mut target   = context.send_serialized::<serde_json::Value>(StreamId::with_message_type::<JsonTestMessage>()).unwrap();
+
+            while let Some(message) = messages.next().await {
+                target.send(message.0.clone()).await.ok().unwrap();
+                send_output.send(message).await.ok();
+            }
+        }, 0)).await.ok();
+    }
+}
+
+#[test]
+fn send_json_messages_over_socket() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+
+    #[derive(Serialize, Deserialize)]
+    struct EchoedLines(Vec<String>);
+    impl SceneMessage for EchoedLines { }
+
+    // The echo program receives connections from the socket
+    let echo_program = SubProgramId::new();
+    scene.add_subprogram(echo_program, json_echo_program, 0);
+
+    // The socket program reads JsonTestMessages from a socket
+    let socket_program = SubProgramId::new();
+    start_internal_socket_program(&scene, socket_program, read_json_messages::<JsonTestMessage>, write_json_messages).unwrap();
+    scene.connect_programs(socket_program, echo_program, StreamId::with_message_type::<JsonTestSocketMessage>()).unwrap();
+
+    // The messages that are deserialized are sent to the test program
+    scene.connect_programs((), test_program, StreamId::with_message_type::<JsonTestMessage>()).unwrap();
+
+    // Write some messages to the socket, one split across writes, and read back the echoed lines
+    scene.add_subprogram(SubProgramId::new(), move |_input: InputStream<()>, context| async move {
+        let (our_side, their_side)          = duplex(1024);
+        let (socket_input, socket_output)   = split(their_side);
+        let (read_result, write_messages)   = split(our_side);
+
+        let mut socket_program = context.send(socket_program).unwrap();
+        socket_program.send(InternalSocketMessage::CreateInternalSocket(Box::new(socket_input), Box::new(socket_output))).await.ok().unwrap();
+
+        let mut write_messages = write_messages;
+        write_messages.write_all(b"{ \"value\": 1 }\n{ \"val").await.unwrap();
+        write_messages.write_all(b"ue\": 2 }\n").await.unwrap();
+        write_messages.write_all(b"not json\n{ \"value\": 3 }\n").await.unwrap();
+        write_messages.shutdown().await.unwrap();
+
+        let mut read_result = BufReader::new(read_result);
+        let mut lines       = vec![];
+        let mut line        = String::new();
+        while lines.len() < 3 && read_result.read_line(&mut line).await.unwrap() > 0 {
+            lines.push(line.clone());
+            line.clear();
+        }
+
+        context.send_message(EchoedLines(lines)).await.ok();
+    }, 0);
+
+    TestBuilder::new()
+        .expect_message(|msg: JsonTestMessage| if msg.value == 1 { Ok(()) } else { Err(format!("{:?}", msg)) })
+        .expect_message(|msg: JsonTestMessage| if msg.value == 2 { Ok(()) } else { Err(format!("{:?}", msg)) })
+        .expect_message(|msg: JsonTestMessage| if msg.value == 3 { Ok(()) } else { Err(format!("{:?}", msg)) })
+        .expect_message(|EchoedLines(lines)| if lines == vec!["{\"value\":1}\n", "{\"value\":2}\n", "{\"value\":3}\n"] { Ok(()) } else { Err(format!("{:?}", lines)) })
+        .run_in_scene_with_threads(&scene, test_program, 5);
+}
+
+#[test]
+fn read_json_messages_for_named_type() {
+    // The message type has to be registered before it can be found by name
+    let _scene = Scene::default().with_serializer::<serde_json::Value>().with_serializable_type::<JsonTestMessage>();
+
+    assert!(read_json_messages_for_type("test::JsonTestMessage").is_some());
+    assert!(read_json_messages_for_type("test::NotAMessageType").is_none());
+}
@@ -0,0 +1,278 @@
+use flo_scene::*;
+use flo_scene::commands::*;
+use flo_scene::programs::*;
+use flo_scene_pipe::*;
+use flo_scene_pipe::commands::*;
+
+use futures::prelude::*;
+use serde::*;
+use tokio::io::*;
+
+/// TestSucceeded message is used to indicate when a test has passed
+#[derive(Serialize, Deserialize, Debug)]
+struct TestSucceeded { message: String }
+impl SceneMessage for TestSucceeded {
+    fn message_type_name() -> String { "test::TestSucceeded".into() }
+}
+
+///
+/// Creates an internal socket program in a scene that can be used to send commands, using either the standard or the JSON-RPC connection program
+///
+fn create_internal_command_socket(scene: &Scene, internal_socket_id: SubProgramId, json_rpc: bool) {
+    // The command connection program receives connections from sockets
+    let command_program = SubProgramId::new();
+    if json_rpc {
+        scene.add_subprogram(command_program, |input, context| json_rpc_connection_program(input, context, ()), 0);
+    } else {
+        scene.add_subprogram(command_program, |input, context| command_connection_program(input, context, ()), 0);
+    }
+
+    // The internal socket program lets us receive connections and send messages to the command program as streams of data
+    start_internal_socket_program(scene, internal_socket_id, read_command_data, write_command_data).unwrap();
+
+    // Connect the internal socket program to the command program
+    scene.connect_programs(internal_socket_id, command_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+}
+
+///
+/// Adds a subprogram that runs some commands using the internal socket program
+///
+fn add_command_runner<TFuture>(scene: &Scene, internal_socket_id: SubProgramId, commands: impl Into<String>, process_results: impl 'static + Send + Fn(String, SceneContext) -> TFuture) 
+where
+    TFuture: 'static + Send + Future<Output=()>
+{
+    // Create an arbitrary program ID
+    let program_id  = SubProgramId::called("command_runner");
+    let commands    = commands.into();
+
+    scene.add_subprogram(program_id, move |_: InputStream<()>, context| async move {
+        context.wait_for_idle(100).await;
+
+        // Create a connection via the internal socket
+        let (our_side, their_side)          = duplex(1024);
+        let (command_input, command_output) = split(their_side);
+        let (read_result, write_command)    = split(our_side);
+
+        let mut socket_program = context.send(internal_socket_id).unwrap();
+        socket_program.send(InternalSocketMessage::CreateInternalSocket(Box::new(command_input), Box::new(command_output))).await.ok().unwrap();
+
+        let context = &context;
+
+        // Future that writes the commands
+        let write_side = async move {
+            println!("In: {}", commands);
+
+            // Send the commands to the write side and then stop
+            let mut write_command = write_command;
+
+            write_command.write_all(&commands.bytes().collect::<Vec<u8>>()).await.unwrap();
+
+            println!("Sent all");
+
+            context.wait_for_idle(100).await;
+
+            write_command.flush().await.unwrap();
+            write_command.shutdown().await.unwrap();
+
+            println!("Finished sending");
+        };
+
+        // Future that reads the results and processes them
+        let read_side = async move {
+            let mut bytes = vec![];
+
+            let mut read_result = read_result;
+            let mut buf = vec![];
+            while let Ok(len) = read_result.read_buf(&mut buf).await {
+                println!("{:?}", String::from_utf8_lossy(&buf));
+                bytes.extend(&buf);
+                buf.drain(..);
+
+                if len == 0 {
+                    break;
+                }
+            }
+
+            let string_result = String::from_utf8_lossy(&bytes);
+            println!("\nOut: {}", string_result);
+            process_results(string_result.into(), context.clone()).await;
+        };
+
+        // Wait for both futures together to run the socket
+        future::join(write_side, read_side).await;
+    }, 0)
+}
+
+///
+/// Parses the JSON-RPC objects from the output of a command socket
+///
+fn json_rpc_objects(output: &str) -> Vec<serde_json::Value> {
+    output.lines()
+        .filter(|line| line.starts_with('{'))
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+///
+/// Adds a launcher with some commands that return JSON values
+///
+fn add_json_commands(scene: &Scene) {
+    let launcher = CommandLauncher::json()
+        .with_json_command("test::add_one", |value: i64, _context| async move {
+            CommandResponse::Json(serde_json::json!(value + 1))
+        })
+        .with_json_command("test::count_to", |value: i64, _context| async move {
+            CommandResponse::BackgroundStream(stream::iter((1..=value).map(|num| serde_json::json!(num))).boxed())
+        });
+
+    scene.add_subprogram(SubProgramId::called("json_rpc_test_commands"), launcher.to_subprogram(), 0);
+}
+
+#[test]
+fn json_rpc_request() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("json_rpc_internal_socket");
+    let test_program    = SubProgramId::called("json_rpc_test_program");
+
+    add_json_commands(&scene);
+
+    create_internal_command_socket(&scene, internal_socket, true);
+    add_command_runner(&scene, internal_socket, 
+        r#"{ "jsonrpc": "2.0", "id": 1, "method": "test::add_one", "params": 41 }
+        { "jsonrpc": "2.0", "id": "two", "method": "echo", "params": "Hello" }
+        "#, 
+        move |msg, context| async move {
+            let objects = json_rpc_objects(&msg);
+
+            // Every line of output is a JSON-RPC object
+            assert!(msg.lines().all(|line| line.is_empty() || line.starts_with('{')), "{}", msg);
+            assert!(objects.contains(&serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": [ 42 ] })), "{}", msg);
+            assert!(objects.contains(&serde_json::json!({ "jsonrpc": "2.0", "method": "message", "params": { "id": "two", "message": "Hello" } })), "{}", msg);
+            assert!(objects.contains(&serde_json::json!({ "jsonrpc": "2.0", "id": "two", "result": [] })), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn json_rpc_errors() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("json_rpc_errors_internal_socket");
+    let test_program    = SubProgramId::called("json_rpc_errors_test_program");
+
+    create_internal_command_socket(&scene, internal_socket, true);
+    add_command_runner(&scene, internal_socket, 
+        r#"{ "jsonrpc": "2.0", "id": 1, "method": "not::a::command" }
+        { "jsonrpc": "2.0", "id": 2 }
+        { "jsonrpc": "2.0", "id": 3, "method": "echo", "params": "Still running" }
+        "#, 
+        move |msg, context| async move {
+            let objects = json_rpc_objects(&msg);
+            let error_1 = objects.iter().find(|obj| obj["id"] == 1).unwrap();
+            let error_2 = objects.iter().find(|obj| obj["id"] == 2).unwrap();
+
+            assert!(error_1["error"]["code"] == JSON_RPC_COMMAND_ERROR, "{}", msg);
+            assert!(error_2["error"]["code"] == JSON_RPC_INVALID_REQUEST, "{}", msg);
+            assert!(objects.contains(&serde_json::json!({ "jsonrpc": "2.0", "id": 3, "result": [] })), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn negotiate_json_rpc() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("negotiate_internal_socket");
+    let test_program    = SubProgramId::called("negotiate_test_program");
+
+    add_json_commands(&scene);
+
+    // The first request is a JSON-RPC request, so the standard command program should switch to JSON-RPC mode
+    create_internal_command_socket(&scene, internal_socket, false);
+    add_command_runner(&scene, internal_socket, 
+        r#"{ "jsonrpc": "2.0", "id": 1, "method": "test::add_one", "params": 1 }
+        { "jsonrpc": "2.0", "id": 2, "method": "test::add_one", "params": 2 }
+        "#, 
+        move |msg, context| async move {
+            let objects = json_rpc_objects(&msg);
+
+            assert!(objects.contains(&serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": [ 2 ] })), "{}", msg);
+            assert!(objects.contains(&serde_json::json!({ "jsonrpc": "2.0", "id": 2, "result": [ 3 ] })), "{}", msg);
+
+            // Only the first prompt is displayed
+            assert!(msg.matches("> ").count() == 1, "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn json_rpc_background_stream() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("json_rpc_stream_internal_socket");
+    let test_program    = SubProgramId::called("json_rpc_stream_test_program");
+
+    add_json_commands(&scene);
+
+    create_internal_command_socket(&scene, internal_socket, true);
+    add_command_runner(&scene, internal_socket, 
+        r#"{ "jsonrpc": "2.0", "id": 1, "method": "test::count_to", "params": 2 }
+        "#, 
+        move |msg, context| async move {
+            let objects = json_rpc_objects(&msg);
+
+            let stream_start    = objects.iter().find(|obj| obj["method"] == "stream_start").unwrap();
+            let stream_id       = stream_start["params"]["stream"].clone();
+
+            assert!(stream_start["params"]["id"] == 1, "{}", msg);
+            assert!(objects.contains(&serde_json::json!({ "jsonrpc": "2.0", "method": "stream", "params": { "stream": stream_id, "value": 1 } })), "{}", msg);
+            assert!(objects.contains(&serde_json::json!({ "jsonrpc": "2.0", "method": "stream", "params": { "stream": stream_id, "value": 2 } })), "{}", msg);
+            assert!(objects.contains(&serde_json::json!({ "jsonrpc": "2.0", "method": "stream_end", "params": { "stream": stream_id } })), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn json_rpc_eval() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("json_rpc_eval_internal_socket");
+    let test_program    = SubProgramId::called("json_rpc_eval_test_program");
+
+    add_json_commands(&scene);
+
+    create_internal_command_socket(&scene, internal_socket, true);
+    add_command_runner(&scene, internal_socket, 
+        r#"{ "jsonrpc": "2.0", "id": 1, "method": "flo_scene::eval", "params": "test::add_one (test::add_one 40)" }
+        { "jsonrpc": "2.0", "id": 2, "method": "flo_scene::eval", "params": "test::add_one [" }
+        "#, 
+        move |msg, context| async move {
+            let objects = json_rpc_objects(&msg);
+            let error_2 = objects.iter().find(|obj| obj["id"] == 2).unwrap();
+
+            assert!(objects.contains(&serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": [ 42 ] })), "{}", msg);
+            assert!(error_2["error"]["code"] == JSON_RPC_INVALID_PARAMS, "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
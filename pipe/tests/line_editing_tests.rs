@@ -0,0 +1,181 @@
+use flo_scene::*;
+use flo_scene::programs::*;
+use flo_scene_pipe::*;
+use flo_scene_pipe::commands::*;
+
+use futures::prelude::*;
+use serde::*;
+use tokio::io::*;
+
+use std::fs;
+use std::path::{PathBuf};
+
+/// TestSucceeded message is used to indicate when a test has passed
+#[derive(Serialize, Deserialize, Debug)]
+struct TestSucceeded { message: String }
+impl SceneMessage for TestSucceeded {
+    fn message_type_name() -> String { "test::TestSucceeded".into() }
+}
+
+///
+/// Creates an internal socket program in a scene that can be used to send commands to a line editing session
+///
+fn create_internal_command_socket(scene: &Scene, internal_socket_id: SubProgramId, history_file: Option<PathBuf>) {
+    // The command connection program receives connections from sockets
+    let command_program = SubProgramId::new();
+    scene.add_subprogram(command_program, move |input, context| line_editing_connection_program(input, context, (), history_file), 0);
+
+    // The internal socket program lets us receive connections and send messages to the command program as streams of data
+    start_internal_socket_program(scene, internal_socket_id, read_command_data, write_command_data).unwrap();
+
+    // Connect the internal socket program to the command program
+    scene.connect_programs(internal_socket_id, command_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+}
+
+///
+/// Adds a subprogram that runs some commands using the internal socket program
+///
+fn add_command_runner<TFuture>(scene: &Scene, internal_socket_id: SubProgramId, commands: impl Into<String>, process_results: impl 'static + Send + Fn(String, SceneContext) -> TFuture) 
+where
+    TFuture: 'static + Send + Future<Output=()>
+{
+    // Create an arbitrary program ID
+    let program_id  = SubProgramId::called("command_runner");
+    let commands    = commands.into();
+
+    scene.add_subprogram(program_id, move |_: InputStream<()>, context| async move {
+        context.wait_for_idle(100).await;
+
+        // Create a connection via the internal socket
+        let (our_side, their_side)          = duplex(1024);
+        let (command_input, command_output) = split(their_side);
+        let (read_result, write_command)    = split(our_side);
+
+        let mut socket_program = context.send(internal_socket_id).unwrap();
+        socket_program.send(InternalSocketMessage::CreateInternalSocket(Box::new(command_input), Box::new(command_output))).await.ok().unwrap();
+
+        let context = &context;
+
+        // Future that writes the commands
+        let write_side = async move {
+            println!("In: {}", commands);
+
+            // Send the commands to the write side and then stop
+            let mut write_command = write_command;
+
+            write_command.write_all(&commands.bytes().collect::<Vec<u8>>()).await.unwrap();
+
+            println!("Sent all");
+
+            context.wait_for_idle(100).await;
+
+            write_command.flush().await.unwrap();
+            write_command.shutdown().await.unwrap();
+
+            println!("Finished sending");
+        };
+
+        // Future that reads the results and processes them
+        let read_side = async move {
+            let mut bytes = vec![];
+
+            let mut read_result = read_result;
+            let mut buf = vec![];
+            while let Ok(len) = read_result.read_buf(&mut buf).await {
+                println!("{:?}", String::from_utf8_lossy(&buf));
+                bytes.extend(&buf);
+                buf.drain(..);
+
+                if len == 0 {
+                    break;
+                }
+            }
+
+            let string_result = String::from_utf8_lossy(&bytes);
+            println!("\nOut: {}", string_result);
+            process_results(string_result.into(), context.clone()).await;
+        };
+
+        // Wait for both futures together to run the socket
+        future::join(write_side, read_side).await;
+    }, 0)
+}
+
+#[test]
+fn tab_complete_command() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("tab_complete_internal_socket");
+    let test_program    = SubProgramId::called("tab_complete_test_program");
+
+    create_internal_command_socket(&scene, internal_socket, None);
+    add_command_runner(&scene, internal_socket, 
+        "ec\t \"Hello\"\r", 
+        move |msg, context| async move {
+            // The socket should switch to 'edit' mode, complete the command and run it
+            assert!(msg.contains("<< EDIT <<"), "{:?}", msg);
+            assert!(msg.contains("echo \"Hello\"\r\n"), "{:?}", msg);
+            assert!(msg.contains("   Hello\r\n"), "{:?}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn tab_complete_subprogram() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("tab_complete_subprogram_internal_socket");
+    let test_program    = SubProgramId::called("tab_complete_subprogram_test_program");
+
+    // Subprograms are only listed while they're running, so this waits for input that never arrives
+    scene.add_subprogram(SubProgramId::called("tab_complete_unique_name"), |mut input: InputStream<()>, _| async move { input.next().await; }, 0);
+
+    create_internal_command_socket(&scene, internal_socket, None);
+    add_command_runner(&scene, internal_socket, 
+        "@tab_complete_u\t\r", 
+        move |msg, context| async move {
+            assert!(msg.contains("@tab_complete_unique_name"), "{:?}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn save_history_to_file() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("history_internal_socket");
+    let test_program    = SubProgramId::called("history_test_program");
+    let history_file    = std::env::temp_dir().join(format!("flo_scene_history_{}", std::process::id()));
+
+    fs::write(&history_file, "echo \"previous\"\n").unwrap();
+
+    // Up arrow should recall the previous line from the file
+    create_internal_command_socket(&scene, internal_socket, Some(history_file.clone()));
+    add_command_runner(&scene, internal_socket, 
+        "echo \"new\"\r\x1b[A\x1b[A\r", 
+        move |msg, context| {
+            let history_file = history_file.clone();
+
+            async move {
+                let history = fs::read_to_string(&history_file).unwrap();
+                fs::remove_file(&history_file).ok();
+
+                assert!(msg.contains("   new\r\n"), "{:?}", msg);
+                assert!(msg.contains("   previous\r\n"), "{:?}", msg);
+                assert!(history == "echo \"previous\"\necho \"new\"\necho \"previous\"\n", "{:?}", history);
+
+                context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+            }
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
@@ -0,0 +1,240 @@
+use flo_scene::*;
+use flo_scene::commands::*;
+use flo_scene::programs::*;
+use flo_scene_pipe::*;
+use flo_scene_pipe::commands::*;
+
+use futures::prelude::*;
+use serde::*;
+use tokio::io::*;
+
+/// TestSucceeded message is used to indicate when a test has passed
+#[derive(Serialize, Deserialize, Debug)]
+struct TestSucceeded { message: String }
+impl SceneMessage for TestSucceeded {
+    fn message_type_name() -> String { "test::TestSucceeded".into() }
+}
+
+/// Message sent by the event source in these tests
+#[derive(Serialize, Deserialize, Debug)]
+struct ReactiveTestMessage { value: i64 }
+impl SceneMessage for ReactiveTestMessage {
+    fn message_type_name() -> String { "test::ReactiveTestMessage".into() }
+}
+
+///
+/// Creates an internal socket program in a scene that can be used to send commands
+///
+fn create_internal_command_socket(scene: &Scene, internal_socket_id: SubProgramId) {
+    // The command connection program receives connections from sockets
+    let command_program = SubProgramId::new();
+    scene.add_subprogram(command_program, |input, context| command_connection_program(input, context, ()), 0);
+
+    // The internal socket program lets us receive connections and send messages to the command program as streams of data
+    start_internal_socket_program(scene, internal_socket_id, read_command_data, write_command_data).unwrap();
+
+    // Connect the internal socket program to the command program
+    scene.connect_programs(internal_socket_id, command_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+}
+
+///
+/// Adds a program that sends a `ReactiveTestMessage` for each of the specified values to anything that subscribes to it
+///
+fn add_event_source(scene: &Scene, values: Vec<i64>) {
+    let source_program = SubProgramId::new();
+
+    scene.add_subprogram(source_program, move |input, context| async move {
+        let mut input       = input;
+        let mut subscribers = EventSubscribers::<ReactiveTestMessage>::new();
+
+        while let Some(req) = input.next().await {
+            let req: Subscribe<ReactiveTestMessage> = req;
+            subscribers.subscribe(&context, req.target());
+
+            for value in values.iter() {
+                subscribers.send_round_robin(ReactiveTestMessage { value: *value }).await.ok();
+            }
+        }
+    }, 0);
+
+    scene.connect_programs((), source_program, StreamId::with_message_type::<Subscribe<ReactiveTestMessage>>()).unwrap();
+}
+
+///
+/// Adds a subprogram that sends each of a list of commands to a command session, waiting for the scene to become idle after each one
+///
+fn add_command_runner<TFuture>(scene: &Scene, internal_socket_id: SubProgramId, commands: Vec<&'static str>, process_results: impl 'static + Send + Fn(String, SceneContext) -> TFuture)
+where
+    TFuture: 'static + Send + Future<Output=()>
+{
+    scene.add_subprogram(SubProgramId::new(), move |_: InputStream<()>, context| async move {
+        context.wait_for_idle(100).await;
+
+        // Create a connection via the internal socket
+        let (our_side, their_side)          = duplex(1024);
+        let (command_input, command_output) = split(their_side);
+        let (read_result, write_command)    = split(our_side);
+
+        let mut socket_program = context.send(internal_socket_id).unwrap();
+        socket_program.send(InternalSocketMessage::CreateInternalSocket(Box::new(command_input), Box::new(command_output))).await.ok().unwrap();
+
+        let context = &context;
+
+        // Future that writes the commands, letting each one finish before sending the next
+        let write_side = async move {
+            let mut write_command = write_command;
+
+            for command in commands {
+                write_command.write_all(command.as_bytes()).await.unwrap();
+                write_command.flush().await.unwrap();
+
+                context.wait_for_idle(100).await;
+            }
+
+            write_command.shutdown().await.unwrap();
+        };
+
+        // Future that reads the results and processes them
+        let read_side = async move {
+            let mut read_result = read_result;
+            let mut bytes       = vec![];
+            read_result.read_to_end(&mut bytes).await.unwrap();
+
+            let string_result = String::from_utf8_lossy(&bytes);
+            println!("\nOut: {}", string_result);
+            process_results(string_result.into(), context.clone()).await;
+        };
+
+        future::join(write_side, read_side).await;
+    }, 0)
+}
+
+#[test]
+fn assign_background_stream_to_variable() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("reactive_assign_internal_socket");
+    let test_program    = SubProgramId::called("reactive_assign_test_program");
+
+    add_event_source(&scene, vec![1, 2, 3]);
+    create_internal_command_socket(&scene, internal_socket);
+
+    // The variable should have the most recent value once all the events have arrived
+    add_command_runner(&scene, internal_socket,
+        vec![
+            ":state = subscribe { \"Type\": \"test::ReactiveTestMessage\" }\n",
+            "echo { \"latest\": :state }\n",
+        ],
+        move |msg, context| async move {
+            assert!(msg.contains("`:state` will be updated as new values arrive"), "{}", msg);
+            assert!(msg.contains("\"value\": 3"), "{}", msg);
+            assert!(!msg.contains("\"value\": 2"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn watch_variable() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("watch_internal_socket");
+    let test_program    = SubProgramId::called("watch_test_program");
+
+    create_internal_command_socket(&scene, internal_socket);
+
+    // The watched expression is re-evaluated when `:a` changes, but not when `:b` changes
+    add_command_runner(&scene, internal_socket,
+        vec![
+            ":a = \"first\"\n",
+            ":b = \"unwatched\"\n",
+            "watch [\"watched\", :a]\n",
+            ":a = \"second\"\n",
+            ":b = \"still unwatched\"\n",
+        ],
+        move |msg, context| async move {
+            assert!(msg.contains("\"first\""), "{}", msg);
+            assert!(msg.contains("\"second\""), "{}", msg);
+            assert!(msg.matches("\"watched\"").count() == 2, "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn watch_requires_a_variable() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("watch_error_internal_socket");
+    let test_program    = SubProgramId::called("watch_error_test_program");
+
+    create_internal_command_socket(&scene, internal_socket);
+    add_command_runner(&scene, internal_socket,
+        vec!["watch 1\n"],
+        move |msg, context| async move {
+            assert!(msg.contains("`watch` requires an expression that uses at least one variable"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn subscribe_to_variable_changes() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("subscribe_variable_internal_socket");
+    let observer        = SubProgramId::called("subscribe_variable_observer");
+    let test_program    = SubProgramId::called("subscribe_variable_test_program");
+
+    create_internal_command_socket(&scene, internal_socket);
+
+    // The observer opens a session, subscribes to its variables, and then changes one of them
+    scene.add_subprogram(observer, move |input: InputStream<CommandVariable>, context| async move {
+        context.wait_for_idle(100).await;
+
+        // Open a connection (the session stays open as long as our side of it exists)
+        let (our_side, their_side)          = duplex(1024);
+        let (command_input, command_output) = split(their_side);
+
+        let mut socket_program = context.send(internal_socket).unwrap();
+        socket_program.send(InternalSocketMessage::CreateInternalSocket(Box::new(command_input), Box::new(command_output))).await.ok().unwrap();
+        context.wait_for_idle(100).await;
+
+        // Find the session that was started for the connection
+        let mut command_session = None;
+        let mut updates         = context.spawn_query(ReadCommand::default(), Query::<SceneUpdate>::with_no_target(), *SCENE_CONTROL_PROGRAM).unwrap();
+
+        while let Some(update) = updates.next().await {
+            if let SceneUpdate::Started(program_id, input_stream) = update {
+                if input_stream.message_type() == StreamId::with_message_type::<CommandSessionRequest>().message_type() {
+                    command_session = Some(program_id);
+                }
+            }
+        }
+
+        let command_session = command_session.unwrap();
+        context.send(command_session).unwrap().send(subscribe::<CommandVariable>(observer)).await.ok().unwrap();
+        context.wait_for_idle(100).await;
+
+        context.send(command_session).unwrap().send(CommandSessionRequest::SetVariable(":observed".into(), serde_json::json!(42))).await.unwrap();
+
+        let mut input = input;
+        let change = input.next().await;
+        assert!(change == Some(CommandVariable(":observed".into(), serde_json::json!(42))), "{:?}", change);
+
+        context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        drop(our_side);
+    }, 0);
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
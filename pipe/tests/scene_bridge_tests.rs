@@ -0,0 +1,242 @@
+#![cfg(unix)]
+
+use flo_scene::*;
+use flo_scene_pipe::*;
+
+use futures::prelude::*;
+use futures::channel::oneshot;
+use serde::*;
+
+use std::env;
+use std::fs;
+use std::path::{PathBuf};
+use std::sync::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct BridgeTestMessage(usize);
+
+impl SceneMessage for BridgeTestMessage {
+    fn message_type_name() -> String { "test::BridgeTestMessage".into() }
+}
+
+///
+/// Returns a path for a unix socket in the temporary directory
+///
+fn socket_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("flo_scene_bridge_{}_{}", name, std::process::id()));
+    fs::remove_file(&path).ok();
+
+    path
+}
+
+///
+/// Starts a scene that publishes a target program on a unix socket. The target program waits for the 'start_reading' future before
+/// it reads its input, and sends the messages it receives to the returned receiver
+///
+fn start_publisher_scene(path: PathBuf, publish_subprogram: bool, start_reading: impl 'static + Send + Future<Output=()>) -> mpsc::Receiver<BridgeTestMessage> {
+    let (send_ready, recv_ready)        = mpsc::channel();
+    let (send_received, recv_received)  = mpsc::channel();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default();
+
+            let target_program = SubProgramId::new();
+            scene.add_subprogram(target_program, move |input: InputStream<BridgeTestMessage>, _context| async move {
+                start_reading.await;
+
+                let mut input = input;
+                while let Some(message) = input.next().await {
+                    send_received.send(message).ok();
+                }
+            }, 0);
+            scene.connect_programs((), target_program, StreamId::with_message_type::<BridgeTestMessage>()).unwrap();
+
+            let publisher_program   = SubProgramId::new();
+            let target              = if publish_subprogram { SerializedStreamTarget::from(target_program) } else { SerializedStreamTarget::from(StreamId::with_message_type::<BridgeTestMessage>()) };
+            scene.add_subprogram(publisher_program, move |input, context| scene_bridge_publisher_program(input, context, target), 0);
+
+            let socket_program = SubProgramId::new();
+            start_unix_socket_program(&scene, socket_program, &path, read_bridge_frames, write_bridge_frames).unwrap();
+            scene.connect_programs(socket_program, publisher_program, StreamId::with_message_type::<BridgeSocketMessage>()).unwrap();
+
+            send_ready.send(()).unwrap();
+            scene.run_scene().await;
+        });
+    });
+
+    recv_ready.recv().unwrap();
+
+    recv_received
+}
+
+///
+/// Starts a scene with a proxy for the target published at the specified path, which sends a number of messages to the proxy. The
+/// number of messages that have been sent so far is stored in the returned counter
+///
+fn start_proxy_scene(path: PathBuf, num_messages: usize, max_input_waiting: usize) -> Arc<AtomicUsize> {
+    let num_sent        = Arc::new(AtomicUsize::new(0));
+    let sender_num_sent = Arc::clone(&num_sent);
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default();
+
+            let proxy_program = SubProgramId::new();
+            start_unix_scene_bridge_proxy::<BridgeTestMessage>(&scene, proxy_program, &path, ReconnectBackoff::new(Duration::from_millis(10), Duration::from_millis(50)), max_input_waiting).unwrap();
+
+            let sender_program = SubProgramId::new();
+            scene.add_subprogram(sender_program, move |_: InputStream<()>, context| async move {
+                let mut proxy = context.send::<BridgeTestMessage>(proxy_program).unwrap();
+
+                for num in 0..num_messages {
+                    proxy.send(BridgeTestMessage(num)).await.unwrap();
+                    sender_num_sent.fetch_add(1, Ordering::SeqCst);
+                }
+            }, 0);
+
+            scene.run_scene().await;
+        });
+    });
+
+    num_sent
+}
+
+///
+/// Reads the specified number of messages from a receiver
+///
+fn receive_messages(received: &mpsc::Receiver<BridgeTestMessage>, num_messages: usize) -> Vec<BridgeTestMessage> {
+    (0..num_messages)
+        .map(|_| received.recv_timeout(Duration::from_secs(10)).unwrap())
+        .collect()
+}
+
+#[test]
+fn send_to_published_stream() {
+    let path = socket_path("stream");
+
+    let received = start_publisher_scene(path.clone(), false, future::ready(()));
+    start_proxy_scene(path, 10, 4);
+
+    let messages = receive_messages(&received, 10);
+    assert!(messages == (0..10).map(BridgeTestMessage).collect::<Vec<_>>(), "{:?}", messages);
+}
+
+#[test]
+fn send_to_published_subprogram() {
+    let path = socket_path("subprogram");
+
+    let received = start_publisher_scene(path.clone(), true, future::ready(()));
+    start_proxy_scene(path, 10, 4);
+
+    let messages = receive_messages(&received, 10);
+    assert!(messages == (0..10).map(BridgeTestMessage).collect::<Vec<_>>(), "{:?}", messages);
+}
+
+#[test]
+fn proxy_waits_for_publisher() {
+    let path = socket_path("wait_for_publisher");
+
+    // The proxy starts before there's anything to connect to
+    let num_sent = start_proxy_scene(path.clone(), 10, 2);
+    thread::sleep(Duration::from_millis(200));
+
+    // Only the messages that fit in the proxy's input stream can be sent while it's disconnected
+    assert!(num_sent.load(Ordering::SeqCst) <= 3, "{}", num_sent.load(Ordering::SeqCst));
+
+    let received = start_publisher_scene(path, false, future::ready(()));
+
+    let messages = receive_messages(&received, 10);
+    assert!(messages == (0..10).map(BridgeTestMessage).collect::<Vec<_>>(), "{:?}", messages);
+}
+
+#[test]
+fn proxy_waits_for_busy_target() {
+    let path = socket_path("busy_target");
+
+    // The target doesn't read anything until we tell it to
+    let (start_reading, wait_for_start) = oneshot::channel::<()>();
+    let received                        = start_publisher_scene(path.clone(), false, wait_for_start.map(|_| ()));
+    let num_sent                        = start_proxy_scene(path, 100, 2);
+
+    // The sender should only be able to send a few messages before the proxy stops accepting them
+    thread::sleep(Duration::from_millis(500));
+    let sent_while_busy = num_sent.load(Ordering::SeqCst);
+    assert!(sent_while_busy > 0 && sent_while_busy < 10, "{}", sent_while_busy);
+
+    // All of the messages arrive once the target starts reading
+    start_reading.send(()).unwrap();
+
+    let messages = receive_messages(&received, 100);
+    assert!(messages == (0..100).map(BridgeTestMessage).collect::<Vec<_>>(), "{:?}", messages);
+}
+
+#[test]
+fn report_undelivered_messages() {
+    let path = socket_path("undelivered");
+
+    // The publisher's target is not running, so the messages from the proxy can't be delivered to it
+    let (send_ready, recv_ready)    = mpsc::channel();
+    let publisher_path              = path.clone();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default();
+
+            let target_program      = SubProgramId::new();
+            let publisher_program   = SubProgramId::new();
+            scene.add_subprogram(publisher_program, move |input, context| scene_bridge_publisher_program(input, context, target_program), 0);
+
+            let socket_program = SubProgramId::new();
+            start_unix_socket_program(&scene, socket_program, &publisher_path, read_bridge_frames, write_bridge_frames).unwrap();
+            scene.connect_programs(socket_program, publisher_program, StreamId::with_message_type::<BridgeSocketMessage>()).unwrap();
+
+            send_ready.send(()).unwrap();
+            scene.run_scene().await;
+        });
+    });
+
+    recv_ready.recv().unwrap();
+
+    // The proxy reports the errors as events
+    let (send_event, recv_event) = mpsc::channel();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default();
+
+            let proxy_program = SubProgramId::new();
+            start_unix_scene_bridge_proxy::<BridgeTestMessage>(&scene, proxy_program, &path, ReconnectBackoff::new(Duration::from_millis(10), Duration::from_millis(50)), 4).unwrap();
+
+            let event_program = SubProgramId::new();
+            scene.add_subprogram(event_program, move |input: InputStream<SceneBridgeEvent>, _context| async move {
+                let mut input = input;
+                while let Some(event) = input.next().await {
+                    send_event.send(event).ok();
+                }
+            }, 0);
+            scene.connect_programs(proxy_program, event_program, StreamId::with_message_type::<SceneBridgeEvent>()).unwrap();
+
+            let sender_program = SubProgramId::new();
+            scene.add_subprogram(sender_program, move |_: InputStream<()>, context| async move {
+                context.send::<BridgeTestMessage>(proxy_program).unwrap().send(BridgeTestMessage(1)).await.unwrap();
+            }, 0);
+
+            scene.run_scene().await;
+        });
+    });
+
+    let event = recv_event.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert!(matches!(event, SceneBridgeEvent::NotDelivered { sequence: 0, .. }), "{:?}", event);
+}
@@ -0,0 +1,163 @@
+use flo_scene::*;
+use flo_scene::programs::*;
+use flo_scene_pipe::*;
+
+use futures::prelude::*;
+use serde::*;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ConfigRequest(String);
+
+impl SceneMessage for ConfigRequest {
+    fn message_type_name() -> String { "flo_scene_pipe::test::ConfigRequest".into() }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ConfigResponse(String);
+
+impl SceneMessage for ConfigResponse { }
+
+fn test_registry() -> SceneProgramRegistry {
+    SceneProgramRegistry::new()
+        .with_program("relay", |input: InputStream<ConfigRequest>, context| async move {
+            let mut input = input;
+
+            while let Some(ConfigRequest(msg)) = input.next().await {
+                context.send_message(ConfigResponse(msg)).await.unwrap();
+            }
+        })
+        .with_program("sender", |_: InputStream<()>, context| async move {
+            context.send_message(ConfigRequest("Hello".into())).await.unwrap();
+        })
+}
+
+#[test]
+fn read_config_from_ron() {
+    let config = SceneConfig::from_ron(r#"
+        (
+            subprograms: [
+                (name: "relay", program: "relay", max_input_waiting: 20),
+                (name: "sender", program: "sender"),
+            ],
+            connections: [
+                (source: Some("sender"), target: Some("relay"), message_type: "flo_scene_pipe::test::ConfigRequest"),
+            ],
+        )
+    "#).unwrap();
+
+    assert!(config.subprograms.len() == 2);
+    assert!(config.subprograms[0].max_input_waiting == 20);
+    assert!(config.subprograms[1].max_input_waiting == 0);
+    assert!(config.connections.len() == 1);
+    assert!(config.connections[0].target == Some("relay".into()));
+
+    let round_trip = SceneConfig::from_ron(&config.to_ron().unwrap()).unwrap();
+    assert!(round_trip == config);
+}
+
+#[test]
+fn reject_invalid_ron() {
+    let config = SceneConfig::from_ron("( subprograms: [ (name: 42) ] )");
+
+    assert!(matches!(config, Err(ConnectionError::InvalidConfiguration(_))), "{:?}", config);
+}
+
+#[test]
+fn reject_unknown_program() {
+    let config = SceneConfig::from_ron(r#"( subprograms: [ (name: "relay", program: "not_a_program") ] )"#).unwrap();
+
+    assert!(matches!(config.validate(&test_registry()), Err(ConnectionError::InvalidConfiguration(_))));
+}
+
+#[test]
+fn reject_duplicate_subprogram() {
+    let config = SceneConfig::from_ron(r#"( subprograms: [ (name: "relay", program: "relay"), (name: "relay", program: "relay") ] )"#).unwrap();
+
+    assert!(matches!(config.validate(&test_registry()), Err(ConnectionError::InvalidConfiguration(_))));
+}
+
+#[test]
+fn reject_unknown_message_type() {
+    let config = SceneConfig::from_ron(r#"( connections: [ (target: Some("relay"), message_type: "not::a::MessageType") ] )"#).unwrap();
+
+    assert!(config.validate(&test_registry()) == Err(ConnectionError::StreamNotKnown));
+    assert!(config.scene_control_messages(&test_registry()).is_err());
+}
+
+#[test]
+fn apply_config_to_scene() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+
+    let config = SceneConfig::from_ron(r#"
+        (
+            subprograms: [
+                (name: "relay", program: "relay", max_input_waiting: 20),
+                (name: "sender", program: "sender"),
+            ],
+            connections: [
+                (source: Some("sender"), target: Some("relay"), message_type: "flo_scene_pipe::test::ConfigRequest"),
+            ],
+        )
+    "#).unwrap();
+
+    config.apply_to_scene(&scene, &test_registry()).unwrap();
+
+    TestBuilder::new()
+        .expect_message(|ConfigResponse(msg)| if msg == "Hello" { Ok(()) } else { Err(format!("Unexpected message: {:?}", msg)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn apply_config_via_scene_control() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+
+    let config = SceneConfig::from_ron(r#"
+        (
+            subprograms: [
+                (name: "relay_2", program: "relay"),
+                (name: "sender_2", program: "sender"),
+            ],
+            connections: [
+                (source: Some("sender_2"), target: Some("relay_2"), message_type: "flo_scene_pipe::test::ConfigRequest"),
+            ],
+        )
+    "#).unwrap();
+
+    // Apply the configuration from a subprogram running in the scene
+    scene.add_subprogram(SubProgramId::new(), move |_: InputStream<()>, context| async move {
+        config.apply(&context, &test_registry()).await.unwrap();
+    }, 0);
+
+    TestBuilder::new()
+        .expect_message(|ConfigResponse(msg)| if msg == "Hello" { Ok(()) } else { Err(format!("Unexpected message: {:?}", msg)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn apply_config_as_topology() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+
+    let config = SceneConfig::from_ron(r#"
+        (
+            subprograms: [
+                (name: "relay_3", program: "relay"),
+                (name: "sender_3", program: "sender"),
+            ],
+            connections: [
+                (source: Some("sender_3"), target: Some("relay_3"), message_type: "flo_scene_pipe::test::ConfigRequest"),
+            ],
+        )
+    "#).unwrap();
+    let topology = config.topology(&test_registry()).unwrap();
+
+    assert!(topology.programs().count() == 2);
+    assert!(topology.connections().count() == 1);
+
+    TestBuilder::new()
+        .send_message(SceneControl::ApplyTopology(topology))
+        .expect_message(|ConfigResponse(msg)| if msg == "Hello" { Ok(()) } else { Err(format!("Unexpected message: {:?}", msg)) })
+        .run_in_scene(&scene, test_program);
+}
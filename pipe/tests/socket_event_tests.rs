@@ -0,0 +1,225 @@
+use flo_scene::*;
+use flo_scene::programs::*;
+use flo_scene_pipe::*;
+
+use futures::prelude::*;
+use futures::channel::mpsc;
+use futures::stream::{BoxStream};
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, SocketAddr};
+use std::sync::mpsc as std_mpsc;
+use std::thread;
+use std::time::{Duration};
+
+type ByteSocketMessage = SocketMessage<Vec<u8>, Vec<u8>>;
+
+///
+/// Passes the bytes read from a socket straight through
+///
+fn raw_bytes(input: BoxStream<'static, Vec<u8>>) -> BoxStream<'static, Vec<u8>> {
+    input
+}
+
+///
+/// Finds a free TCP port on the local machine
+///
+fn free_address() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
+
+///
+/// Sends everything that's received on a connection back to the sender
+///
+async fn echo_program(input: InputStream<ByteSocketMessage>, context: SceneContext) {
+    let mut input = input;
+    while let Some(SocketMessage::Connection(connection)) = input.next().await {
+        context.send_message(SceneControl::start_program(SubProgramId::new(), move |_: InputStream<()>, _context| async move {
+            let (send_output, recv_output)  = mpsc::channel(16);
+            let input                       = connection.connect(recv_output);
+
+            input.map(Ok).forward(send_output).await.ok();
+        }, 0)).await.ok();
+    }
+}
+
+///
+/// Accepts connections but never reads from them
+///
+async fn unread_program(input: InputStream<ByteSocketMessage>, _context: SceneContext) {
+    let mut input       = input;
+    let mut connections = vec![];
+
+    while let Some(SocketMessage::Connection(connection)) = input.next().await {
+        connections.push(connection);
+    }
+}
+
+///
+/// Starts a scene with an echoing TCP socket using the specified options, returning the socket address and a receiver for the socket events
+///
+fn start_echo_scene(options: SocketOptions) -> (SocketAddr, std_mpsc::Receiver<SocketEvent>) {
+    start_socket_scene(options, echo_program)
+}
+
+///
+/// Starts a scene with a TCP socket that sends its connections to the specified program, returning the socket address and a receiver for the socket events
+///
+fn start_socket_scene<TFuture>(options: SocketOptions, connection_program: impl 'static + Send + FnOnce(InputStream<ByteSocketMessage>, SceneContext) -> TFuture) -> (SocketAddr, std_mpsc::Receiver<SocketEvent>) 
+where
+    TFuture: 'static + Send + Future<Output=()>,
+{
+    let address                     = free_address();
+    let (send_ready, recv_ready)    = std_mpsc::channel();
+    let (send_event, recv_event)    = std_mpsc::channel();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default();
+
+            let echo_program_id = SubProgramId::new();
+            scene.add_subprogram(echo_program_id, connection_program, 0);
+
+            // The event program relays the socket events back to the test thread
+            let event_program_id = SubProgramId::new();
+            scene.add_subprogram(event_program_id, move |mut events: InputStream<SocketEvent>, _context| {
+                let send_event = send_event.clone();
+
+                async move {
+                    while let Some(event) = events.next().await {
+                        send_event.send(event).ok();
+                    }
+                }
+            }, 0);
+
+            let socket_program = SubProgramId::new();
+            start_unencrpted_tcp_socket_with_options(&scene, socket_program, address, options, raw_bytes, raw_bytes).unwrap();
+            scene.connect_programs(socket_program, echo_program_id, StreamId::with_message_type::<ByteSocketMessage>()).unwrap();
+            scene.connect_programs(socket_program, event_program_id, StreamId::with_message_type::<SocketEvent>()).unwrap();
+
+            send_ready.send(()).unwrap();
+            scene.run_scene().await;
+        });
+    });
+
+    recv_ready.recv().unwrap();
+
+    (address, recv_event)
+}
+
+///
+/// Connects to a TCP socket, retrying while the socket program starts up
+///
+fn connect(address: SocketAddr) -> TcpStream {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(address) {
+            stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+            return stream;
+        }
+
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    panic!("Could not connect to {}", address);
+}
+
+fn next_event(events: &std_mpsc::Receiver<SocketEvent>) -> SocketEvent {
+    events.recv_timeout(Duration::from_secs(10)).unwrap()
+}
+
+#[test]
+fn connected_and_closed_events() {
+    let (address, events)   = start_echo_scene(SocketOptions::default());
+    let mut client          = connect(address);
+    let client_address      = client.local_addr().unwrap().to_string();
+
+    // The peer address should be the address of our end of the connection
+    match next_event(&events) {
+        SocketEvent::Connected { peer, open_connections: 1, .. }    => { assert!(peer.address == Some(client_address), "{:?}", peer); }
+        other                                                       => { panic!("{:?}", other); }
+    }
+
+    // Check that the connection works, then close it
+    client.write_all(b"Hello").unwrap();
+    let mut echoed = [0u8; 5];
+    client.read_exact(&mut echoed).unwrap();
+    assert!(&echoed == b"Hello");
+
+    client.shutdown(std::net::Shutdown::Write).unwrap();
+
+    match next_event(&events) {
+        SocketEvent::Closed { reason: SocketCloseReason::ClosedByPeer, open_connections: 0, .. }    => { }
+        other                                                                                       => { panic!("{:?}", other); }
+    }
+}
+
+#[test]
+fn idle_connections_are_closed() {
+    let (address, events)   = start_echo_scene(SocketOptions::default().with_idle_timeout(Duration::from_millis(200)));
+    let mut client          = connect(address);
+
+    assert!(matches!(next_event(&events), SocketEvent::Connected { .. }));
+
+    // Send nothing: the listener should close the connection after the timeout
+    let mut remaining = vec![];
+    client.read_to_end(&mut remaining).unwrap();
+    assert!(remaining.is_empty());
+
+    match next_event(&events) {
+        SocketEvent::Closed { reason: SocketCloseReason::IdleTimeout, open_connections: 0, .. }     => { }
+        other                                                                                       => { panic!("{:?}", other); }
+    }
+}
+
+#[test]
+fn idle_connections_are_closed_when_not_read() {
+    let (address, events)   = start_socket_scene(SocketOptions::default().with_idle_timeout(Duration::from_millis(200)), unread_program);
+    let mut client          = connect(address);
+
+    assert!(matches!(next_event(&events), SocketEvent::Connected { .. }));
+
+    // Nothing is sent, and nothing is reading from the connection, but the listener should still close it after the timeout
+    let mut remaining = vec![];
+    client.read_to_end(&mut remaining).unwrap();
+    assert!(remaining.is_empty());
+
+    match next_event(&events) {
+        SocketEvent::Closed { reason: SocketCloseReason::IdleTimeout, open_connections: 0, .. }     => { }
+        other                                                                                       => { panic!("{:?}", other); }
+    }
+}
+
+#[test]
+fn reject_connections_over_limit() {
+    let (address, events)   = start_echo_scene(SocketOptions::default().with_max_connections(1));
+    let mut first_client    = connect(address);
+
+    assert!(matches!(next_event(&events), SocketEvent::Connected { open_connections: 1, .. }));
+
+    // The second connection should be closed immediately
+    let mut second_client = connect(address);
+
+    match next_event(&events) {
+        SocketEvent::Rejected { open_connections: 1, peer } => { assert!(peer.address == Some(second_client.local_addr().unwrap().to_string()), "{:?}", peer); }
+        other                                               => { panic!("{:?}", other); }
+    }
+
+    let mut remaining = vec![];
+    second_client.read_to_end(&mut remaining).ok();
+    assert!(remaining.is_empty());
+
+    // The first connection should still work
+    first_client.write_all(b"Still open").unwrap();
+    let mut echoed = [0u8; 10];
+    first_client.read_exact(&mut echoed).unwrap();
+    assert!(&echoed == b"Still open");
+
+    // Once it's closed, new connections can be made again
+    first_client.shutdown(std::net::Shutdown::Write).unwrap();
+    assert!(matches!(next_event(&events), SocketEvent::Closed { open_connections: 0, .. }));
+
+    let _third_client = connect(address);
+    assert!(matches!(next_event(&events), SocketEvent::Connected { open_connections: 1, .. }));
+}
@@ -0,0 +1,131 @@
+use flo_scene::*;
+use flo_scene::programs::*;
+use flo_scene_pipe::*;
+
+use futures::prelude::*;
+use futures::channel::mpsc;
+use tokio::io::{duplex, split, AsyncReadExt, AsyncWriteExt};
+use serde::*;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct FrameTestMessage {
+    value: usize,
+}
+
+impl SceneMessage for FrameTestMessage {
+    fn message_type_name() -> String { "test::FrameTestMessage".into() }
+}
+
+type FrameSocketMessage = SocketMessage<Result<SocketFrame, ConnectionError>, SocketFrame>;
+
+///
+/// Accepts connections from a framed socket, and sends the JSON messages in the frames to their default streams, sending back an
+/// empty 'ok' or 'error' frame for each frame that's received
+///
+async fn frame_bridge_program(input: InputStream<FrameSocketMessage>, context: SceneContext) {
+    let mut input = input;
+    while let Some(SocketMessage::Connection(connection)) = input.next().await {
+        context.send_message(SceneControl::start_program(SubProgramId::new(), move |_: InputStream<()>, context| async move {
+            let (mut send_output, recv_output) = mpsc::channel(5);
+            let mut frames = connection.connect(recv_output);
+
+            while let Some(frame) = frames.next().await {
+                let reply = match frame {
+                    Ok(frame) => {
+                        let message = frame.to_serialized_json().unwrap();
+                        let target  = StreamId::with_serialization_type(frame.type_name.clone()).unwrap();
+
+                        context.send_serialized::<serde_json::Value>(target).unwrap().send(message.0).await.ok().unwrap();
+                        SocketFrame::new("ok", vec![])
+                    }
+
+                    Err(err) => SocketFrame::new("error", format!("{:?}", err)),
+                };
+
+                send_output.send(reply).await.ok();
+            }
+        }, 0)).await.ok();
+    }
+}
+
+///
+/// Sends some bytes to a framed socket, checking the messages that are received and the type names of the frames that are sent back
+///
+fn run_frame_test(scene: &Scene, test_program: SubProgramId, max_frame_size: usize, bytes: Vec<Vec<u8>>, expected_messages: Vec<usize>, expected_replies: Vec<&'static str>) {
+    #[derive(Serialize, Deserialize)]
+    struct Replies(Vec<String>);
+    impl SceneMessage for Replies { }
+
+    let bridge_program = SubProgramId::new();
+    scene.add_subprogram(bridge_program, frame_bridge_program, 0);
+
+    let socket_program = SubProgramId::new();
+    start_internal_socket_program(scene, socket_program, read_socket_frames_with_max_size(max_frame_size), write_socket_frames).unwrap();
+    scene.connect_programs(socket_program, bridge_program, StreamId::with_message_type::<FrameSocketMessage>()).unwrap();
+    scene.connect_programs((), test_program, StreamId::with_message_type::<FrameTestMessage>()).unwrap();
+
+    scene.add_subprogram(SubProgramId::new(), move |_input: InputStream<()>, context| async move {
+        let (our_side, their_side)          = duplex(1024);
+        let (socket_input, socket_output)   = split(their_side);
+        let (read_replies, write_frames)    = split(our_side);
+
+        let mut socket_program = context.send(socket_program).unwrap();
+        socket_program.send(InternalSocketMessage::CreateInternalSocket(Box::new(socket_input), Box::new(socket_output))).await.ok().unwrap();
+
+        // The socket stops reading after a framing error, so writes can fail
+        let mut write_frames = write_frames;
+        for block in bytes {
+            write_frames.write_all(&block).await.ok();
+        }
+        write_frames.shutdown().await.ok();
+
+        // Read the replies using the framing protocol
+        let mut read_replies    = read_replies;
+        let mut reply_bytes     = vec![];
+        read_replies.read_to_end(&mut reply_bytes).await.ok();
+
+        let replies = read_socket_frames(stream::iter(vec![reply_bytes]).boxed())
+            .map(|frame| frame.unwrap().type_name)
+            .collect::<Vec<_>>().await;
+
+        context.send_message(Replies(replies)).await.ok();
+    }, 0);
+
+    let mut test = TestBuilder::new();
+    for expected in expected_messages {
+        test = test.expect_message(move |msg: FrameTestMessage| if msg.value == expected { Ok(()) } else { Err(format!("{:?}", msg)) });
+    }
+
+    test.expect_message(move |Replies(replies)| if replies == expected_replies { Ok(()) } else { Err(format!("{:?}", replies)) })
+        .run_in_scene_with_threads(scene, test_program, 5);
+}
+
+#[test]
+fn send_frames_over_socket() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    scene.with_serializer::<serde_json::Value>().with_serializable_type::<FrameTestMessage>();
+
+    // Send three frames, with the second one split across several writes
+    let frame_1 = SocketFrame::from_message(&FrameTestMessage { value: 1 }).unwrap().to_bytes();
+    let frame_2 = SocketFrame::from_message(&FrameTestMessage { value: 2 }).unwrap().to_bytes();
+    let frame_3 = SocketFrame::from_message(&FrameTestMessage { value: 3 }).unwrap().to_bytes();
+
+    let bytes = vec![frame_1, frame_2[0..3].to_vec(), frame_2[3..10].to_vec(), frame_2[10..].to_vec(), frame_3];
+
+    run_frame_test(&scene, test_program, DEFAULT_MAX_FRAME_SIZE, bytes, vec![1, 2, 3], vec!["ok", "ok", "ok"]);
+}
+
+#[test]
+fn reject_oversized_frame() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    scene.with_serializer::<serde_json::Value>().with_serializable_type::<FrameTestMessage>();
+
+    // The second frame is too big, so the connection should stop reading after it
+    let frame_1 = SocketFrame::from_message(&FrameTestMessage { value: 1 }).unwrap().to_bytes();
+    let frame_2 = SocketFrame::new("test::FrameTestMessage", vec![b' '; 1000]).to_bytes();
+    let frame_3 = SocketFrame::from_message(&FrameTestMessage { value: 3 }).unwrap().to_bytes();
+
+    run_frame_test(&scene, test_program, 100, vec![frame_1, frame_2, frame_3], vec![1], vec!["ok", "error"]);
+}
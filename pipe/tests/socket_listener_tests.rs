@@ -0,0 +1,252 @@
+use flo_scene::*;
+use flo_scene::programs::*;
+use flo_scene_pipe::*;
+
+use futures::prelude::*;
+use futures::channel::mpsc;
+use futures::stream::{BoxStream};
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, SocketAddr};
+use std::sync::*;
+use std::sync::mpsc as std_mpsc;
+use std::thread;
+use std::time::{Duration};
+
+type ByteSocketMessage = SocketMessage<Vec<u8>, Vec<u8>>;
+
+///
+/// Passes the bytes read from a socket straight through
+///
+fn raw_bytes(input: BoxStream<'static, Vec<u8>>) -> BoxStream<'static, Vec<u8>> {
+    input
+}
+
+///
+/// Finds a free TCP port on the local machine
+///
+fn free_address() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
+
+///
+/// Sends everything that's received on a connection back to the sender
+///
+async fn echo_program(input: InputStream<ByteSocketMessage>, context: SceneContext) {
+    let mut input = input;
+    while let Some(SocketMessage::Connection(connection)) = input.next().await {
+        context.send_message(SceneControl::start_program(SubProgramId::new(), move |_: InputStream<()>, _context| async move {
+            let (send_output, recv_output)  = mpsc::channel(16);
+            let input                       = connection.connect(recv_output);
+
+            input.map(Ok).forward(send_output).await.ok();
+        }, 0)).await.ok();
+    }
+}
+
+///
+/// Starts a scene with an echoing socket program created by `start_socket`, returning a receiver for the socket events and a sender
+/// that closes the socket program's input stream
+///
+fn start_echo_scene(start_socket: impl 'static + Send + FnOnce(&Scene, SubProgramId)) -> (std_mpsc::Receiver<SocketEvent>, mpsc::UnboundedSender<()>) {
+    let (send_ready, recv_ready)    = std_mpsc::channel();
+    let (send_event, recv_event)    = std_mpsc::channel();
+    let (send_stop, mut recv_stop)  = mpsc::unbounded();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default();
+
+            let echo_program_id = SubProgramId::new();
+            scene.add_subprogram(echo_program_id, echo_program, 0);
+
+            // The event program relays the socket events back to the test thread
+            let event_program_id = SubProgramId::new();
+            scene.add_subprogram(event_program_id, move |mut events: InputStream<SocketEvent>, _context| {
+                let send_event = send_event.clone();
+
+                async move {
+                    while let Some(event) = events.next().await {
+                        send_event.send(event).ok();
+                    }
+                }
+            }, 0);
+
+            let socket_program = SubProgramId::new();
+            start_socket(&scene, socket_program);
+            scene.connect_programs(socket_program, echo_program_id, StreamId::with_message_type::<ByteSocketMessage>()).unwrap();
+            scene.connect_programs(socket_program, event_program_id, StreamId::with_message_type::<SocketEvent>()).unwrap();
+
+            // The stop program closes the socket program when the test asks it to
+            scene.add_subprogram(SubProgramId::new(), move |_: InputStream<()>, context| async move {
+                if recv_stop.next().await.is_some() {
+                    context.send_message(SceneControl::Close(socket_program)).await.unwrap();
+                }
+            }, 0);
+
+            send_ready.send(()).unwrap();
+            scene.run_scene().await;
+        });
+    });
+
+    recv_ready.recv().unwrap();
+
+    (recv_event, send_stop)
+}
+
+///
+/// Starts an echoing TCP socket with the specified options
+///
+fn start_tcp_echo_scene(options: SocketOptions) -> (SocketAddr, std_mpsc::Receiver<SocketEvent>, mpsc::UnboundedSender<()>) {
+    let address         = free_address();
+    let (events, stop)  = start_echo_scene(move |scene, socket_program| {
+        start_unencrpted_tcp_socket_with_options(scene, socket_program, address, options, raw_bytes, raw_bytes).unwrap();
+    });
+
+    (address, events, stop)
+}
+
+///
+/// Connects to a TCP socket
+///
+fn connect(address: SocketAddr) -> TcpStream {
+    let stream = TcpStream::connect(address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+
+    stream
+}
+
+fn next_event(events: &std_mpsc::Receiver<SocketEvent>) -> SocketEvent {
+    events.recv_timeout(Duration::from_secs(10)).unwrap()
+}
+
+#[test]
+fn listen_without_peer_information() {
+    let address         = free_address();
+    let (events, _stop) = start_echo_scene(move |scene, socket_program| {
+        // The accept function only returns the reader and writer for each connection
+        let listener = TcpListener::bind(address).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let listener = Arc::new(tokio::net::TcpListener::from_std(listener).unwrap());
+
+        scene.add_subprogram(socket_program, move |_: InputStream<()>, context| socket_listener_subprogram(context, move || {
+            let listener = listener.clone();
+
+            async move {
+                let (socket, _) = listener.accept().await?;
+                Ok(socket.into_split())
+            }
+        }, raw_bytes, raw_bytes), 0);
+    });
+
+    let mut client = connect(address);
+    client.write_all(b"Hello").unwrap();
+
+    let mut echoed = [0u8; 5];
+    client.read_exact(&mut echoed).unwrap();
+    assert!(&echoed == b"Hello");
+
+    match next_event(&events) {
+        SocketEvent::Connected { peer, .. } => { assert!(peer == SocketPeer::default(), "{:?}", peer); }
+        other                               => { panic!("{:?}", other); }
+    }
+}
+
+#[test]
+fn report_tcp_bind_error() {
+    // Bind the address so the socket program can't use it
+    let existing_listener   = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address             = existing_listener.local_addr().unwrap();
+
+    let scene   = Scene::default();
+    let result  = start_unencrpted_tcp_socket(&scene, SubProgramId::new(), address, raw_bytes, raw_bytes);
+
+    assert!(matches!(result, Err(ConnectionError::IoError(_))), "{:?}", result);
+}
+
+#[test]
+fn stop_tcp_listener() {
+    let (address, events, stop) = start_tcp_echo_scene(SocketOptions::default());
+    let mut client              = connect(address);
+
+    assert!(matches!(next_event(&events), SocketEvent::Connected { open_connections: 1, .. }));
+
+    // Stopping the listener should close the connection that's already open
+    stop.unbounded_send(()).unwrap();
+
+    match next_event(&events) {
+        SocketEvent::StoppedListening { open_connections: 1 }   => { }
+        other                                                   => { panic!("{:?}", other); }
+    }
+
+    match next_event(&events) {
+        SocketEvent::Closed { reason: SocketCloseReason::ListenerStopped, open_connections: 0, .. } => { }
+        other                                                                                       => { panic!("{:?}", other); }
+    }
+
+    let mut remaining = vec![];
+    client.read_to_end(&mut remaining).ok();
+    assert!(remaining.is_empty());
+
+    // The address should no longer accept connections
+    assert!(TcpStream::connect(address).is_err());
+}
+
+#[test]
+fn drain_connections_when_stopping() {
+    let (address, events, stop) = start_tcp_echo_scene(SocketOptions::default().with_drain_connections(true));
+    let mut client              = connect(address);
+
+    assert!(matches!(next_event(&events), SocketEvent::Connected { open_connections: 1, .. }));
+
+    stop.unbounded_send(()).unwrap();
+    assert!(matches!(next_event(&events), SocketEvent::StoppedListening { open_connections: 1 }));
+
+    // No new connections are accepted, but the existing connection should still work
+    assert!(TcpStream::connect(address).is_err());
+
+    client.write_all(b"Still open").unwrap();
+    let mut echoed = [0u8; 10];
+    client.read_exact(&mut echoed).unwrap();
+    assert!(&echoed == b"Still open");
+
+    client.shutdown(std::net::Shutdown::Write).unwrap();
+
+    match next_event(&events) {
+        SocketEvent::Closed { reason: SocketCloseReason::ClosedByPeer, open_connections: 0, .. }    => { }
+        other                                                                                       => { panic!("{:?}", other); }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn remove_unix_socket_file_when_stopped() {
+    use std::os::unix::net::{UnixStream};
+
+    let path = std::env::temp_dir().join(format!("flo_scene_stop_listener_{}", std::process::id()));
+    std::fs::remove_file(&path).ok();
+
+    let socket_path     = path.clone();
+    let (events, stop)  = start_echo_scene(move |scene, socket_program| {
+        start_unix_socket_program(scene, socket_program, &socket_path, raw_bytes, raw_bytes).unwrap();
+    });
+
+    // Check that the socket works before it's stopped
+    let mut client = UnixStream::connect(&path).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    assert!(matches!(next_event(&events), SocketEvent::Connected { .. }));
+
+    client.write_all(b"Hello").unwrap();
+    let mut echoed = [0u8; 5];
+    client.read_exact(&mut echoed).unwrap();
+    assert!(&echoed == b"Hello");
+
+    // The socket file is removed when the listener stops
+    stop.unbounded_send(()).unwrap();
+    assert!(matches!(next_event(&events), SocketEvent::StoppedListening { .. }));
+
+    assert!(!path.exists());
+    assert!(UnixStream::connect(&path).is_err());
+}
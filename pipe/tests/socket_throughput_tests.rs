@@ -0,0 +1,178 @@
+#![cfg(unix)]
+
+use flo_scene::*;
+use flo_scene::programs::*;
+use flo_scene_pipe::*;
+
+use futures::prelude::*;
+use futures::channel::mpsc;
+use futures::future;
+use futures::stream::{BoxStream};
+use serde::*;
+
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixStream};
+use std::path::{PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type ByteSocketMessage = SocketMessage<Vec<u8>, Vec<u8>>;
+
+#[derive(Serialize, Deserialize)]
+struct Block(Vec<u8>);
+
+impl SceneMessage for Block { }
+
+///
+/// Returns a path for a unix socket in the temporary directory
+///
+fn socket_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("flo_scene_throughput_{}_{}", name, std::process::id()));
+    fs::remove_file(&path).ok();
+
+    path
+}
+
+///
+/// Passes the bytes read from a socket straight through
+///
+fn raw_bytes(input: BoxStream<'static, Vec<u8>>) -> BoxStream<'static, Vec<u8>> {
+    input
+}
+
+///
+/// Starts a scene on a separate thread with a unix socket that uses the specified options, and a program that deals with its connections
+///
+fn start_socket_scene<TFuture>(path: PathBuf, options: SocketOptions, connection_program: impl 'static + Send + Sync + Fn(InputStream<ByteSocketMessage>, SceneContext) -> TFuture)
+where
+    TFuture: 'static + Send + Future<Output=()>,
+{
+    let (send_ready, recv_ready) = std::sync::mpsc::channel();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default();
+
+            let connection_program_id = SubProgramId::new();
+            scene.add_subprogram(connection_program_id, connection_program, 0);
+
+            let socket_program = SubProgramId::new();
+            start_unix_socket_program_with_options(&scene, socket_program, &path, options, raw_bytes, raw_bytes).unwrap();
+            scene.connect_programs(socket_program, connection_program_id, StreamId::with_message_type::<ByteSocketMessage>()).unwrap();
+
+            send_ready.send(()).unwrap();
+            scene.run_scene().await;
+        });
+    });
+
+    recv_ready.recv().unwrap();
+}
+
+///
+/// Sends everything that's received on a connection back to the sender
+///
+async fn echo_program(input: InputStream<ByteSocketMessage>, context: SceneContext) {
+    let mut input = input;
+    while let Some(SocketMessage::Connection(connection)) = input.next().await {
+        context.send_message(SceneControl::start_program(SubProgramId::new(), move |_: InputStream<()>, _context| async move {
+            let (send_output, recv_output)  = mpsc::channel(16);
+            let input                       = connection.connect(recv_output);
+
+            input.map(Ok).forward(send_output).await.ok();
+        }, 0)).await.ok();
+    }
+}
+
+///
+/// Sends some data to an echo socket with the specified options, and returns how long it took for all of it to come back
+///
+fn time_echo(name: &str, options: SocketOptions, num_bytes: usize) -> Duration {
+    let path = socket_path(name);
+    start_socket_scene(path.clone(), options, echo_program);
+
+    let data        = (0..num_bytes).map(|idx| (idx % 251) as u8).collect::<Vec<_>>();
+    let start       = Instant::now();
+    let mut reader  = UnixStream::connect(&path).unwrap();
+    let mut writer  = reader.try_clone().unwrap();
+
+    let write_data  = data.clone();
+    let writer      = thread::spawn(move || {
+        for block in write_data.chunks(65536) {
+            writer.write_all(block).unwrap();
+        }
+        writer.shutdown(std::net::Shutdown::Write).unwrap();
+    });
+
+    let mut echoed = vec![];
+    reader.read_to_end(&mut echoed).unwrap();
+    writer.join().unwrap();
+
+    let elapsed = start.elapsed();
+    assert!(echoed.len() == data.len(), "{} != {}", echoed.len(), data.len());
+    assert!(echoed == data);
+
+    elapsed
+}
+
+#[test]
+#[ignore = "benchmark: run with --ignored --nocapture to see the results"]
+fn benchmark_socket_buffer_sizes() {
+    // Compare the original 64-byte reads and single-block writes with the default options
+    let num_bytes   = 4 * 1024 * 1024;
+    let small       = time_echo("small", SocketOptions::default().with_read_buffer_size(64).with_max_write_blocks(1), num_bytes);
+    let default     = time_echo("default", SocketOptions::default(), num_bytes);
+    let large       = time_echo("large", SocketOptions::default().with_read_buffer_size(256 * 1024), num_bytes);
+
+    let mb_per_sec  = |time: Duration| (num_bytes as f64 / (1024.0 * 1024.0)) / time.as_secs_f64();
+    println!("64 byte reads:    {:?} ({:.1} MB/s)", small, mb_per_sec(small));
+    println!("Default options:  {:?} ({:.1} MB/s)", default, mb_per_sec(default));
+    println!("256k reads:       {:?} ({:.1} MB/s)", large, mb_per_sec(large));
+}
+
+#[test]
+fn backpressure_stops_socket_reads() {
+    let path = socket_path("backpressure");
+
+    // The connection program forwards everything to a program that never reads its input
+    start_socket_scene(path.clone(), SocketOptions::default(), |input, context| async move {
+        let stalled_program = SubProgramId::new();
+        context.send_message(SceneControl::start_program(stalled_program, |input: InputStream<Block>, _context| async move {
+            future::pending::<()>().await;
+            drop(input);
+        }, 1)).await.unwrap();
+
+        let mut input = input;
+        while let Some(SocketMessage::Connection(connection)) = input.next().await {
+            let mut stalled_program = context.send::<Block>(stalled_program).unwrap();
+
+            tokio::spawn(async move {
+                let mut input = connection.connect(stream::pending());
+
+                while let Some(bytes) = input.next().await {
+                    stalled_program.send(Block(bytes)).await.ok();
+                }
+            });
+        }
+    });
+
+    // Writing should stall once the program's input and the socket's buffers are full
+    let mut socket = UnixStream::connect(&path).unwrap();
+    socket.set_write_timeout(Some(Duration::from_millis(500))).unwrap();
+
+    let block           = vec![0u8; 65536];
+    let max_bytes       = 256 * 1024 * 1024;
+    let mut num_written = 0;
+
+    while num_written < max_bytes {
+        match socket.write(&block) {
+            Ok(len) => { num_written += len; }
+            Err(_)  => { break; }
+        }
+    }
+
+    assert!(num_written < max_bytes, "Wrote {} bytes without blocking", num_written);
+}
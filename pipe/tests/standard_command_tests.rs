@@ -1,9 +1,12 @@
 use flo_scene::*;
+use flo_scene::commands::*;
 use flo_scene::programs::*;
 use flo_scene_pipe::*;
 use flo_scene_pipe::commands::*;
 
 use futures::prelude::*;
+use futures::channel::mpsc;
+use futures::channel::oneshot;
 use serde::*;
 use tokio::io::*;
 
@@ -312,3 +315,209 @@ fn query_command() {
     TestBuilder::new()
         .run_in_scene(&scene, test_program);
 }
+
+///
+/// Adds a command launcher with a `test::add` command, which adds its argument to every number piped into it
+///
+fn add_pipeline_commands(scene: &Scene) {
+    let launcher = CommandLauncher::json()
+        .with_json_command("test::add", |amount: i64, context| async move {
+            let (send_values, recv_values)  = mpsc::channel(16);
+            let (send_input, recv_input)    = oneshot::channel();
+
+            // Open an IO stream to receive the numbers
+            context.send_message(CommandResponse::IoStream(Box::new(move |input_stream| {
+                send_input.send(input_stream).ok();
+                recv_values.boxed()
+            }))).await.unwrap();
+
+            // Add to each of the values
+            let mut input_stream    = recv_input.await.unwrap();
+            let mut send_values     = send_values;
+
+            while let Some(value) = input_stream.next().await {
+                send_values.send(serde_json::json!(value.as_i64().unwrap() + amount)).await.unwrap();
+            }
+
+            CommandResponse::Message("Added".into())
+        });
+
+    scene.add_subprogram(SubProgramId::new(), launcher.to_subprogram(), 0);
+}
+
+#[test]
+fn pipe_json_to_send() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("pipe_internal_socket");
+    let test_program    = SubProgramId::called("pipe_test_program");
+
+    // The JSON value should be used as the input to the 'send' command
+    create_internal_command_socket(&scene, internal_socket);
+    add_command_runner(&scene, internal_socket, 
+        r#"{ "message": "piped" } | send { "Type": "test::TestSucceeded" }
+        "#, 
+        |_, _| async { });
+
+    TestBuilder::new()
+        .expect_message(|TestSucceeded { message }| if message == "piped" { Ok(()) } else { Err(format!("Unexpected message: {}", message)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn pipe_through_several_stages() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("pipe_internal_socket");
+    let test_program    = SubProgramId::called("pipe_test_program");
+
+    add_pipeline_commands(&scene);
+
+    // Each stage adds to the value from the previous stage
+    create_internal_command_socket(&scene, internal_socket);
+    add_command_runner(&scene, internal_socket, 
+        r#"1 | test::add 10 | test::add 100 | test::add 1000
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains("\n1111\n"), "{}", msg);
+            assert!(!msg.contains("!!!"), "{}", msg);
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn pipe_reports_failing_stage() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("pipe_internal_socket");
+    let test_program    = SubProgramId::called("pipe_test_program");
+
+    add_pipeline_commands(&scene);
+
+    // 'echo' doesn't read its input, so the third stage should fail
+    create_internal_command_socket(&scene, internal_socket);
+    add_command_runner(&scene, internal_socket, 
+        r#"1 | test::add 10 | echo "Hello"
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains("!!! Pipeline stage 3 (`echo`): command does not accept piped input"), "{}", msg);
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+///
+/// Adds two launchers, both of which declare a `test::whoami` command
+///
+fn add_duplicate_commands(scene: &Scene) {
+    for name in ["launcher_a", "launcher_b"] {
+        let launcher = CommandLauncher::json()
+            .with_json_command("test::whoami", move |_: serde_json::Value, _context| async move {
+                CommandResponse::Json(serde_json::Value::String(name.into()))
+            });
+
+        scene.add_subprogram(SubProgramId::called(name), launcher.to_subprogram(), 0);
+    }
+}
+
+#[test]
+fn command_for_target() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("target_internal_socket");
+    let test_program    = SubProgramId::called("target_test_program");
+
+    add_duplicate_commands(&scene);
+
+    // Both launchers implement the same command, so we use '@' to pick one of them
+    create_internal_command_socket(&scene, internal_socket);
+    add_command_runner(&scene, internal_socket, 
+        r#"@launcher_b test::whoami
+        @launcher_a test::whoami
+        @launcher_b test::whoami
+        "#, 
+        move |msg, context| async move {
+            let results = msg.lines().filter(|line| line.contains("launcher_")).collect::<Vec<_>>();
+            assert!(results.len() == 3, "{}", msg);
+            assert!(results[0].contains("launcher_b"), "{}", msg);
+            assert!(results[1].contains("launcher_a"), "{}", msg);
+            assert!(results[2].contains("launcher_b"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn command_for_missing_target() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("target_internal_socket");
+    let test_program    = SubProgramId::called("target_test_program");
+
+    // Sending a command to a program that isn't running is an error
+    create_internal_command_socket(&scene, internal_socket);
+    add_command_runner(&scene, internal_socket, 
+        r#"@not_a_program echo "Hello"
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains("!!! "), "{}", msg);
+            assert!(!msg.contains("Hello"), "{}", msg);
+
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn substitute_command_result() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("substitution_internal_socket");
+    let test_program    = SubProgramId::called("substitution_test_program");
+
+    add_duplicate_commands(&scene);
+
+    // The result of the command in brackets is used as part of the argument to 'echo'
+    create_internal_command_socket(&scene, internal_socket);
+    add_command_runner(&scene, internal_socket, 
+        r#"echo [ "Hello", (@launcher_a test::whoami), ("nested (brackets)") ]
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains("   Hello\n   launcher_a\n   nested (brackets)\n"), "{}", msg);
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn substitution_error() {
+    let scene           = Scene::default().with_standard_json_commands();
+    let internal_socket = SubProgramId::called("substitution_internal_socket");
+    let test_program    = SubProgramId::called("substitution_test_program");
+
+    // Errors in the substituted command stop the outer command from running
+    create_internal_command_socket(&scene, internal_socket);
+    add_command_runner(&scene, internal_socket, 
+        r#"echo [ "Hello", (test::not_a_command) ]
+        "#, 
+        move |msg, context| async move {
+            assert!(msg.contains("!!! `(test::not_a_command)`: "), "{}", msg);
+            assert!(!msg.contains("Hello"), "{}", msg);
+            context.send(test_program).unwrap().send(TestSucceeded { message: "Ok".into() }).await.unwrap();
+        });
+
+    TestBuilder::new()
+        .expect_message(|_: TestSucceeded| Ok(()))
+        .run_in_scene(&scene, test_program);
+}
@@ -0,0 +1,182 @@
+#![cfg(feature = "tls")]
+
+use flo_scene::*;
+use flo_scene_pipe::*;
+use flo_scene_pipe::commands::*;
+
+use tokio::io::*;
+use tokio::net::{TcpStream};
+use tokio_rustls::{TlsConnector};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+
+use std::sync::*;
+use std::thread;
+use std::time::{Duration};
+
+///
+/// Finds a TCP port that is not in use
+///
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+///
+/// Starts a scene with a TLS command socket on a separate thread, returning the port that it's listening on
+///
+fn start_tls_command_socket(tls_config: TlsSocketConfig, authenticator: impl 'static + CommandAuthenticator) -> u16 {
+    let port = free_port();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene           = Scene::default().with_standard_json_commands();
+            let authenticator   = Arc::new(Mutex::new(Some(authenticator)));
+
+            let command_program = SubProgramId::new();
+            scene.add_subprogram(command_program, move |input, context| {
+                let authenticator = authenticator.lock().unwrap().take().unwrap();
+                authenticated_command_connection_program(input, context, (), authenticator)
+            }, 0);
+
+            let socket_program = SubProgramId::new();
+            start_tls_tcp_socket(&scene, socket_program, ("127.0.0.1", port), tls_config, read_command_data, write_command_data).unwrap();
+            scene.connect_programs(socket_program, command_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+
+            scene.run_scene().await;
+        });
+    });
+
+    port
+}
+
+///
+/// Connects to a TLS command socket, sends a command and returns the output (or an empty string if the connection fails)
+///
+fn run_tls_client(port: u16, server_certificate: CertificateDer<'static>, client_certificate: Option<(CertificateDer<'static>, PrivateKeyDer<'static>)>, command: &str) -> String {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    runtime.block_on(async move {
+        // Trust the server's self-signed certificate
+        let mut roots = RootCertStore::empty();
+        roots.add(server_certificate).unwrap();
+
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions().unwrap()
+            .with_root_certificates(roots);
+        let config = if let Some((certificate, key)) = client_certificate {
+            config.with_client_auth_cert(vec![certificate], key).unwrap()
+        } else {
+            config.with_no_client_auth()
+        };
+
+        // The listener starts in the background, so retry until the connection succeeds
+        let mut socket = None;
+        for _ in 0..100 {
+            if let Ok(connection) = TcpStream::connect(("127.0.0.1", port)).await {
+                socket = Some(connection);
+                break;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let connector   = TlsConnector::from(Arc::new(config));
+        let tls_stream  = connector.connect(ServerName::try_from("localhost").unwrap(), socket.unwrap()).await;
+        let mut stream  = if let Ok(stream) = tls_stream { stream } else { return String::new(); };
+
+        if stream.write_all(command.as_bytes()).await.is_err() { return String::new(); }
+        if stream.shutdown().await.is_err() { return String::new(); }
+
+        let mut output = vec![];
+        stream.read_to_end(&mut output).await.ok();
+
+        String::from_utf8_lossy(&output).to_string()
+    })
+}
+
+///
+/// Creates a self-signed certificate and its private key
+///
+fn self_signed_certificate(name: &str) -> (rcgen::Certificate, rcgen::KeyPair) {
+    let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+    (cert, key_pair)
+}
+
+#[test]
+fn run_command_over_tls() {
+    let (server_cert, server_key)   = self_signed_certificate("localhost");
+    let tls_config                  = TlsSocketConfig::from_pem(server_cert.pem().as_bytes(), server_key.serialize_pem().as_bytes()).unwrap();
+    let port                        = start_tls_command_socket(tls_config, |_: &CommandCredentials| Ok(CommandPermissions::all()));
+
+    let output = run_tls_client(port, server_cert.der().clone(), None, "echo \"Hello\"\n");
+
+    assert!(output.contains("Hello"), "{:?}", output);
+}
+
+#[test]
+fn identify_client_certificate() {
+    let (server_cert, server_key)   = self_signed_certificate("localhost");
+    let (client_cert, client_key)   = self_signed_certificate("client");
+    let tls_config                  = TlsSocketConfig::from_pem(server_cert.pem().as_bytes(), server_key.serialize_pem().as_bytes()).unwrap()
+        .with_client_certificates_pem(client_cert.pem().as_bytes(), true).unwrap();
+
+    // Only the client with the expected certificate is allowed to run commands
+    let expected_certificate    = client_cert.der().to_vec();
+    let port                    = start_tls_command_socket(tls_config, move |credentials: &CommandCredentials| {
+        if credentials.peer.client_certificate.as_ref() == Some(&expected_certificate) {
+            Ok(CommandPermissions::all())
+        } else {
+            Err(ConnectionError::TargetPermissionRefused)
+        }
+    });
+
+    let client_key  = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(client_key.serialize_der()));
+    let output      = run_tls_client(port, server_cert.der().clone(), Some((client_cert.der().clone(), client_key)), "echo \"Hello\"\n");
+
+    assert!(output.contains("Hello"), "{:?}", output);
+}
+
+#[test]
+fn refuse_client_without_certificate() {
+    let (server_cert, server_key)   = self_signed_certificate("localhost");
+    let (client_cert, _client_key)  = self_signed_certificate("client");
+    let tls_config                  = TlsSocketConfig::from_pem(server_cert.pem().as_bytes(), server_key.serialize_pem().as_bytes()).unwrap()
+        .with_client_certificates_pem(client_cert.pem().as_bytes(), true).unwrap();
+    let port                        = start_tls_command_socket(tls_config, |_: &CommandCredentials| Ok(CommandPermissions::all()));
+
+    let output = run_tls_client(port, server_cert.der().clone(), None, "echo \"Hello\"\n");
+
+    assert!(!output.contains("Hello"), "{:?}", output);
+}
+
+#[test]
+fn stalled_handshake_does_not_block_other_clients() {
+    let (server_cert, server_key)   = self_signed_certificate("localhost");
+    let tls_config                  = TlsSocketConfig::from_pem(server_cert.pem().as_bytes(), server_key.serialize_pem().as_bytes()).unwrap();
+    let port                        = start_tls_command_socket(tls_config, |_: &CommandCredentials| Ok(CommandPermissions::all()));
+
+    // Open a connection that never starts the TLS handshake
+    let stalled_client = loop {
+        if let Ok(connection) = std::net::TcpStream::connect(("127.0.0.1", port)) {
+            break connection;
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    // Another client should still be able to connect and run commands
+    let output = run_tls_client(port, server_cert.der().clone(), None, "echo \"Hello\"\n");
+
+    assert!(output.contains("Hello"), "{:?}", output);
+    drop(stalled_client);
+}
+
+#[test]
+fn invalid_certificate_pem() {
+    let (_server_cert, server_key) = self_signed_certificate("localhost");
+
+    assert!(TlsSocketConfig::from_pem(b"Not a certificate", server_key.serialize_pem().as_bytes()).is_err());
+}
@@ -0,0 +1,164 @@
+#![cfg(feature = "websocket")]
+
+use flo_scene::*;
+use flo_scene::commands::*;
+use flo_scene_pipe::*;
+use flo_scene_pipe::commands::*;
+
+use futures::prelude::*;
+use tokio::net::{TcpStream};
+use tokio_tungstenite::tungstenite::{Message};
+
+use std::thread;
+use std::time::{Duration};
+
+///
+/// Finds a TCP port that is not in use
+///
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+///
+/// Starts a scene with a WebSocket command socket on a separate thread, returning the port that it's listening on
+///
+/// The scene also has a `test::count_to` command that returns a background stream of numbers
+///
+fn start_websocket_command_socket() -> u16 {
+    let port = free_port();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default().with_standard_json_commands();
+
+            let command_program = SubProgramId::new();
+            scene.add_subprogram(command_program, |input, context| command_connection_program(input, context, ()), 0);
+
+            let launcher = CommandLauncher::json()
+                .with_json_command("test::count_to", |value: i64, _context| async move {
+                    CommandResponse::BackgroundStream(stream::iter((1..=value).map(|num| serde_json::json!({ "count": num }))).boxed())
+                });
+            scene.add_subprogram(SubProgramId::new(), launcher.to_subprogram(), 0);
+
+            let socket_program = SubProgramId::new();
+            start_websocket_program(&scene, socket_program, ("127.0.0.1", port), read_command_data, write_command_data).unwrap();
+            scene.connect_programs(socket_program, command_program, StreamId::with_message_type::<CommandProgramSocketMessage>()).unwrap();
+
+            scene.run_scene().await;
+        });
+    });
+
+    port
+}
+
+///
+/// Connects to a WebSocket command socket, sends a command in a text frame, and returns the text frames that are received until
+/// one of them contains the expected text
+///
+fn run_websocket_client(port: u16, command: &str, expected: &str) -> Vec<String> {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    runtime.block_on(async move {
+        // The listener starts in the background, so retry until the connection succeeds
+        let mut socket = None;
+        for _ in 0..100 {
+            if let Ok(connection) = TcpStream::connect(("127.0.0.1", port)).await {
+                socket = Some(connection);
+                break;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let (mut websocket, _) = tokio_tungstenite::client_async(format!("ws://127.0.0.1:{}/", port), socket.unwrap()).await.unwrap();
+
+        websocket.send(Message::Text(command.into())).await.unwrap();
+
+        let mut frames = vec![];
+        while let Some(Ok(frame)) = websocket.next().await {
+            if let Message::Text(text) = frame {
+                let found = text.contains(expected);
+                frames.push(text);
+
+                if found { break; }
+            }
+        }
+
+        websocket.close(None).await.ok();
+
+        frames
+    })
+}
+
+#[test]
+fn run_command_over_websocket() {
+    let port    = start_websocket_command_socket();
+    let frames  = run_websocket_client(port, "echo \"Hello, WebSocket\"\n", "Hello, WebSocket");
+
+    assert!(frames.last().map(|frame| frame.contains("Hello, WebSocket")).unwrap_or(false), "{:?}", frames);
+}
+
+#[test]
+fn background_stream_over_websocket() {
+    let port    = start_websocket_command_socket();
+    let frames  = run_websocket_client(port, "test::count_to 3\n", "\"count\": 3");
+
+    assert!(frames.last().map(|frame| frame.contains("\"count\": 3")).unwrap_or(false), "{:?}", frames);
+}
+
+#[test]
+fn refuse_non_websocket_connection() {
+    use tokio::io::*;
+
+    let port = start_websocket_command_socket();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let output  = runtime.block_on(async move {
+        let mut socket = None;
+        for _ in 0..100 {
+            if let Ok(connection) = TcpStream::connect(("127.0.0.1", port)).await {
+                socket = Some(connection);
+                break;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // Sending a command without a handshake should not run it
+        let mut socket = socket.unwrap();
+        socket.write_all(b"echo \"Not a websocket\"\n\n").await.unwrap();
+
+        let mut output = vec![];
+        socket.read_to_end(&mut output).await.ok();
+
+        String::from_utf8_lossy(&output).to_string()
+    });
+
+    assert!(!output.contains("Not a websocket"), "{:?}", output);
+
+    // The listener should still accept WebSocket connections afterwards
+    let frames = run_websocket_client(port, "echo \"Still working\"\n", "Still working");
+    assert!(frames.last().map(|frame| frame.contains("Still working")).unwrap_or(false), "{:?}", frames);
+}
+
+#[test]
+fn stalled_handshake_does_not_block_other_clients() {
+    let port = start_websocket_command_socket();
+
+    // Open a connection that never starts the WebSocket handshake
+    let stalled_client = loop {
+        if let Ok(connection) = std::net::TcpStream::connect(("127.0.0.1", port)) {
+            break connection;
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    // Another client should still be able to connect and run commands
+    let frames = run_websocket_client(port, "echo \"Hello, WebSocket\"\n", "Hello, WebSocket");
+
+    assert!(frames.last().map(|frame| frame.contains("Hello, WebSocket")).unwrap_or(false), "{:?}", frames);
+    drop(stalled_client);
+}
@@ -5,6 +5,7 @@ use super::error::*;
 use crate::input_stream::*;
 use crate::scene_context::*;
 use crate::scene_message::*;
+use crate::stream_id::*;
 use crate::subprogram_id::*;
 use crate::programs::*;
 
@@ -43,6 +44,9 @@ where
     let mut commands    = HashMap::<String, (CommandDescription, SubProgramId)>::new();
     let mut subprograms = HashSet::<SubProgramId>::new();
 
+    // Only programs that accept RunCommand requests can list commands (sending to a program that has stopped will wait for it to start again, so we need to avoid that)
+    let command_message_type = StreamId::with_message_type::<RunCommand<TParameter, TResponse>>().message_type();
+
     // Wait for requests to run commands
     let mut input = input;
     while let Some(next_command) = input.next().await {
@@ -81,7 +85,11 @@ where
                 .filter(|old_program| !active_subprograms.contains(old_program))
                 .copied()
                 .collect::<HashSet<_>>();
-            let added_subprograms = active_subprograms.iter()
+            let command_subprograms = scene_status.iter().flat_map(|update| match update {
+                SceneUpdate::Started(program_id, input_stream_id) if input_stream_id.message_type() == command_message_type  => Some(*program_id),
+                _                                                                                                           => None,
+            }).collect::<HashSet<SubProgramId>>();
+            let added_subprograms = command_subprograms.iter()
                 .filter(|new_program| !subprograms.contains(new_program))
                 .copied()
                 .collect::<HashSet<_>>();
@@ -81,6 +81,9 @@ pub enum ConnectionError {
 
     /// An operation could not be completed because of an I/O problem
     IoError(String),
+
+    /// A configuration for a scene could not be read or refers to something that doesn't exist
+    InvalidConfiguration(String),
 }
 
 ///
@@ -321,6 +321,13 @@ where
         self.closed
     }
 
+    ///
+    /// True if the input stream that reads from this core has been dropped (so no more messages will be read from it)
+    ///
+    pub (crate) fn is_dropped(&self) -> bool {
+        self.dropped
+    }
+
     ///
     /// True if this input stream is blocked and shouldn't accept any more messages
     ///
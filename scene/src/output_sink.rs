@@ -1,6 +1,8 @@
 use crate::error::*;
 use crate::input_stream::*;
 use crate::scene_core::*;
+use crate::scene_message::*;
+use crate::stream_target::*;
 use crate::subprogram_id::*;
 
 use futures::prelude::*;
@@ -9,7 +11,21 @@ use futures::task::{Poll, Waker};
 use std::pin::*;
 use std::sync::*;
 
-// TODO: close the sink when the target program finishes
+///
+/// What an output stream should do when the program that it's sending messages to finishes
+///
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub enum TargetEndedPolicy {
+    /// Messages are returned to the sender using `SceneSendError::TargetProgramEnded` until the stream is connected again
+    #[default]
+    Error,
+
+    /// Messages wait until a program with the same ID is started again (or the stream is connected somewhere else)
+    WaitForRestart,
+
+    /// The stream is reconnected to a different target (which can be `StreamTarget::None` to discard any further messages)
+    Fallback(StreamTarget),
+}
 
 ///
 /// The target of an output sink
@@ -26,6 +42,9 @@ pub (crate) enum OutputSinkTarget<TMessage: 'static + Send> {
 
     /// Same as 'Input', except the stream is closed when this output sink target is dropped
     CloseWhenDropped(Weak<Mutex<InputStreamCore<TMessage>>>),
+
+    /// Indicates an output whose target program has finished, which will return an error for any message sent to it
+    Ended,
 }
 
 ///
@@ -37,6 +56,15 @@ pub (crate) struct OutputSinkCore<TMessage: 'static + Send> {
 
     /// Waker that is notified when the target is changed
     pub (crate) when_target_changed: Option<Waker>,
+
+    /// What this sink should do when its target program finishes
+    pub (crate) target_ended_policy: TargetEndedPolicy,
+
+    /// The number of batches of connections that are being changed that affect this sink (it won't send any messages while this is non-zero)
+    pub (crate) paused: usize,
+
+    /// The filter that this sink was sending to when it was paused: messages already sent to this filter are delivered before any new messages are sent
+    pub (crate) draining_filter: Option<Weak<Mutex<InputStreamCore<TMessage>>>>,
 }
 
 ///
@@ -73,6 +101,7 @@ where
         match self {
             Disconnected                => Disconnected,
             Discard                     => Discard,
+            Ended                       => Ended,
             Input(input)                => Input(Weak::clone(input)),
             CloseWhenDropped(input)     => Input(Weak::clone(input)),           // Only the original output sink target will close when dropped
         }
@@ -112,6 +141,84 @@ where
         OutputSinkCore {
             target:                 target,
             when_target_changed:    None,
+            target_ended_policy:    TargetEndedPolicy::default(),
+            paused:                 0,
+            draining_filter:        None,
+        }
+    }
+
+    ///
+    /// Stops this sink from sending messages until `resume()` is called
+    ///
+    /// The target can be changed while the sink is paused. If the sink was sending to a filter, it will also wait for that filter
+    /// to finish delivering its messages before it starts sending to a new target, so that messages can't arrive out of order.
+    ///
+    pub (crate) fn pause(&mut self) {
+        if self.paused == 0 && self.draining_filter.is_none() {
+            if let OutputSinkTarget::CloseWhenDropped(filter_input) = &self.target {
+                self.draining_filter = Some(Weak::clone(filter_input));
+            }
+        }
+
+        self.paused += 1;
+    }
+
+    ///
+    /// Reverses a call to `pause()`, returning the waker for anything waiting to send a message if the sink is no longer paused
+    ///
+    pub (crate) fn resume(&mut self) -> Option<Waker> {
+        self.paused = self.paused.saturating_sub(1);
+
+        if self.paused == 0 {
+            self.when_target_changed.take()
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// Returns true if this sink is paused or is waiting for its old filter to finish sending messages, and will wake the context when it's ready to continue
+    ///
+    fn wait_before_sending(&mut self, context: &mut std::task::Context<'_>) -> bool {
+        if self.paused > 0 {
+            self.when_target_changed = Some(context.waker().clone());
+            return true;
+        }
+
+        if let Some(draining_filter) = self.draining_filter.take() {
+            // Nothing to wait for if the filter is still the target (eg, because the connection was rolled back)
+            let still_target = match &self.target {
+                OutputSinkTarget::Input(input_core)             |
+                OutputSinkTarget::CloseWhenDropped(input_core)  => Weak::ptr_eq(input_core, &draining_filter),
+                _                                               => false,
+            };
+
+            if !still_target {
+                if let Some(filter_input) = draining_filter.upgrade() {
+                    // The filter drops its input stream once it has sent all of its messages, which wakes anything waiting for a slot
+                    let mut filter_input = filter_input.lock().unwrap();
+
+                    if !filter_input.is_dropped() {
+                        filter_input.wake_when_slots_available(context);
+                        self.draining_filter = Some(draining_filter);
+
+                        return true;
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    ///
+    /// Returns true if the program that this core was sending to has finished
+    ///
+    pub (crate) fn target_has_ended(&self) -> bool {
+        match &self.target {
+            OutputSinkTarget::Input(input_core)             |
+            OutputSinkTarget::CloseWhenDropped(input_core)  => input_core.strong_count() == 0,
+            _                                               => false,
         }
     }
 
@@ -120,8 +227,11 @@ where
     ///
     pub fn target_program_id(core: &Arc<Mutex<Self>>) -> Option<SubProgramId> {
         let input_core = match &core.lock().unwrap().target {
-            OutputSinkTarget::Disconnected      | OutputSinkTarget::Discard                         => None,
-            OutputSinkTarget::Input(input_core) | OutputSinkTarget::CloseWhenDropped(input_core)    => input_core.upgrade(),
+            OutputSinkTarget::Disconnected                  |
+            OutputSinkTarget::Discard                       |
+            OutputSinkTarget::Ended                         => None,
+            OutputSinkTarget::Input(input_core)             |
+            OutputSinkTarget::CloseWhenDropped(input_core)  => input_core.upgrade(),
         }?;
 
         let program_id = input_core.lock().unwrap().target_program_id();
@@ -129,6 +239,39 @@ where
     }
 }
 
+impl<TMessage> OutputSinkCore<TMessage> 
+where
+    TMessage: 'static + SceneMessage
+{
+    ///
+    /// If the program that an output sink core is sending to has finished, changes its target according to its 'target ended' policy
+    ///
+    /// Returns the waker for anything that's waiting for the target to change
+    ///
+    pub (crate) fn apply_target_ended_policy(core: &Arc<Mutex<Self>>, scene_core: &Arc<Mutex<SceneCore>>, source_program: SubProgramId) -> Option<Waker> {
+        let policy = {
+            let core = core.lock().unwrap();
+            if !core.target_has_ended() { return None; }
+
+            core.target_ended_policy.clone()
+        };
+
+        // Work out the new target (without the core locked, as this might need the scene core)
+        let new_target = match policy {
+            TargetEndedPolicy::WaitForRestart   => OutputSinkTarget::Disconnected,
+            TargetEndedPolicy::Error            => OutputSinkTarget::Ended,
+            TargetEndedPolicy::Fallback(target) => SceneCore::sink_for_target(scene_core, &source_program, target).unwrap_or(OutputSinkTarget::Disconnected),
+        };
+
+        // Update the core, provided that it wasn't reconnected while we were finding the new target
+        let mut core = core.lock().unwrap();
+        if !core.target_has_ended() { return None; }
+
+        core.target = new_target;
+        core.when_target_changed.take()
+    }
+}
+
 impl<TMessage> OutputSink<TMessage> 
 where
     TMessage: Send
@@ -161,6 +304,37 @@ where
         }
     }
 
+    ///
+    /// Sets what this output sink will do if the program it's sending to finishes
+    ///
+    /// This applies to every output sink for the same stream in the program that owns this sink. The default policy is 
+    /// `TargetEndedPolicy::Error`. Restarting a program with the same ID will reconnect the stream to the 
+    /// new program, whichever policy is in use.
+    ///
+    pub fn set_target_ended_policy(&self, policy: TargetEndedPolicy) {
+        self.core.lock().unwrap().target_ended_policy = policy;
+    }
+
+    ///
+    /// Updates the target of this sink when it's found that the program it was sending to has finished
+    ///
+    fn target_ended(&self) {
+        // The scene applies the policy when the target program stops, but the sink might notice that the target has gone first
+        if let Some(scene_core) = self.scene_core.upgrade() {
+            SceneCore::apply_target_ended_policies(&scene_core);
+        }
+
+        // If the scene couldn't update the target (eg, because this sink is not part of a subprogram), use the policy as best we can
+        let mut core = self.core.lock().unwrap();
+
+        if core.target_has_ended() {
+            core.target = match core.target_ended_policy {
+                TargetEndedPolicy::Error    => OutputSinkTarget::Ended,
+                _                           => OutputSinkTarget::Disconnected,
+            };
+        }
+    }
+
     ///
     /// Returns true if this output sink is still attached to a target program
     ///
@@ -170,6 +344,7 @@ where
         let maybe_input_core = match &self.core.lock().unwrap().target {
             OutputSinkTarget::Discard                   => { return true; },
             OutputSinkTarget::Disconnected              => { return false; },
+            OutputSinkTarget::Ended                     => { return false; },
             OutputSinkTarget::Input(input)              |
             OutputSinkTarget::CloseWhenDropped(input)   => input.upgrade()
         };
@@ -226,11 +401,17 @@ where
             if let Err(message) = self.try_send_immediate(message) {
                 // If we still can't send the message, overfill the target buffer
                 let source = self.program_id;
-                let target = self.core.lock().unwrap().target.clone();
+                let target = {
+                    let core = self.core.lock().unwrap();
+
+                    // Paused sinks act as if they're disconnected
+                    if core.paused > 0 { OutputSinkTarget::Disconnected } else { core.target.clone() }
+                };
 
                 match &target {
                     OutputSinkTarget::Discard                   => Ok(()),
                     OutputSinkTarget::Disconnected              => Err(SceneSendError::StreamDisconnected(message)),
+                    OutputSinkTarget::Ended                     => Err(SceneSendError::TargetProgramEnded(message)),
                     OutputSinkTarget::Input(input)              |
                     OutputSinkTarget::CloseWhenDropped(input)   => {
                         if let Some(input) = input.upgrade() {
@@ -266,14 +447,21 @@ where
     /// buffered.
     ///
     pub fn try_send_immediate(&mut self, message: TMessage) -> Result<(), TMessage> {
+        use std::mem;
+
         // Fetch the input core that we'll be sending the message to
         let program_id       = self.program_id;
-        let maybe_input_core = match &self.core.lock().unwrap().target {
+        let core             = self.core.lock().unwrap();
+        if core.paused > 0 { return Err(message); }
+
+        let maybe_input_core = match &core.target {
             OutputSinkTarget::Discard                   => { return Ok(()); },
             OutputSinkTarget::Disconnected              => None,
+            OutputSinkTarget::Ended                     => None,
             OutputSinkTarget::Input(input)              |
             OutputSinkTarget::CloseWhenDropped(input)   => input.upgrade()
         };
+        mem::drop(core);
 
         // We're disconnected if the core is 'None'
         if let Some(input_core) = maybe_input_core {
@@ -317,6 +505,7 @@ where
         let maybe_input_core = match &self.core.lock().unwrap().target {
             OutputSinkTarget::Discard                   => None,
             OutputSinkTarget::Disconnected              => None,
+            OutputSinkTarget::Ended                     => None,
             OutputSinkTarget::Input(input)              |
             OutputSinkTarget::CloseWhenDropped(input)   => {
                 input.upgrade()
@@ -351,6 +540,8 @@ where
     type Error = SceneSendError<TMessage>;
 
     fn poll_ready(mut self: Pin<&mut Self>, context: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        use std::mem;
+
         // Say we're waiting if there's an input value waiting
         if self.waiting_message.is_some() {
             // Wait for the message to finish sending
@@ -360,21 +551,26 @@ where
             // Always say that we're ready (we store the message in the sink while we're flushing instead)
             let mut core = self.core.lock().unwrap();
 
+            if core.wait_before_sending(context) {
+                return Poll::Pending;
+            }
+
             match &core.target {
                 OutputSinkTarget::Disconnected => {
                     core.when_target_changed = Some(context.waker().clone());
                     Poll::Pending
                 },
                 OutputSinkTarget::Discard => Poll::Ready(Ok(())),
+                OutputSinkTarget::Ended => Poll::Ready(Ok(())),         // Sending will return the message as an error
 
                 OutputSinkTarget::Input(input_core)               |
                 OutputSinkTarget::CloseWhenDropped(input_core)    => {
                     if input_core.upgrade().is_none() {
-                        // Downgrade to a disconnected core so the sending can be retried
-                        core.target = OutputSinkTarget::Disconnected;
+                        // The target program is not running any more: apply the policy for this stream and try again
+                        mem::drop(core);
+                        self.target_ended();
 
-                        // Error if the target program is not running any more
-                        Poll::Ready(Err(SceneSendError::TargetProgramEndedBeforeReady))
+                        self.poll_ready(context)
                     } else {
                         // Can send the message
                         Poll::Ready(Ok(()))
@@ -389,7 +585,15 @@ where
 
         self.yield_after_sending = false;
 
-        let mut core = self.core.lock().unwrap();
+        let core = self.core.lock().unwrap();
+
+        if core.paused > 0 || core.draining_filter.is_some() {
+            // The message is sent when the sink is flushed
+            mem::drop(core);
+            self.waiting_message = Some(item);
+            return Ok(());
+        }
+
         match &core.target {
             OutputSinkTarget::Disconnected                  => {
                 mem::drop(core);
@@ -404,6 +608,11 @@ where
                 Ok(())
             },
 
+            OutputSinkTarget::Ended                         => {
+                // Return the message to the sender
+                Err(SceneSendError::TargetProgramEnded(item))
+            },
+
             OutputSinkTarget::Input(input_core)             |
             OutputSinkTarget::CloseWhenDropped(input_core)  => {
                 if let Some(input_core) = input_core.upgrade() {
@@ -462,11 +671,11 @@ where
                         }
                     } 
                 } else {
-                    // Downgrade to a disconnected core so the sending can be retried
-                    core.target = OutputSinkTarget::Disconnected;
+                    // The target program is not running any more: apply the policy for this stream and try again
+                    mem::drop(core);
+                    self.target_ended();
 
-                    // Target program is not available
-                    Err(SceneSendError::TargetProgramEnded(item))
+                    self.start_send(item)
                 }
             }
         }
@@ -497,6 +706,11 @@ where
 
         // Action depends on the state of the target
         let mut core = self.core.lock().unwrap();
+
+        if core.wait_before_sending(context) {
+            return Poll::Pending;
+        }
+
         match &core.target {
             OutputSinkTarget::Disconnected => {
                 // Wait for the target to change
@@ -512,6 +726,18 @@ where
                 Poll::Ready(Ok(()))
             },
 
+            OutputSinkTarget::Ended => {
+                // Return the waiting message to the sender
+                mem::drop(core);
+                if let Some(when_message_sent) = self.when_message_sent.take() { when_message_sent.wake(); }
+
+                if let Some(message) = self.waiting_message.take() {
+                    Poll::Ready(Err(SceneSendError::TargetProgramEnded(message)))
+                } else {
+                    Poll::Ready(Ok(()))
+                }
+            },
+
             OutputSinkTarget::Input(input_core)             |
             OutputSinkTarget::CloseWhenDropped(input_core)  => {
                 // Try to send to the attached core
@@ -556,12 +782,11 @@ where
                         Poll::Ready(Ok(()))
                     }
                 } else {
-                    // Downgrade to a disconnected core so the sending can be retried
-                    core.target = OutputSinkTarget::Disconnected;
+                    // The target program has terminated: apply the policy for this stream and try again
+                    mem::drop(core);
+                    self.target_ended();
 
-                    // When the core is released during a send, the target program has terminated, so we generate an error
-                    core.when_target_changed    = Some(context.waker().clone());
-                    Poll::Ready(Err(SceneSendError::TargetProgramEndedBeforeReady))
+                    self.poll_flush(context)
                 }
             }
         }
@@ -589,10 +814,7 @@ mod test {
         /// Creates a new output sink that belongs to the specified sub-program
         ///
         pub (crate) fn new(program_id: SubProgramId, scene_core: &Arc<Mutex<SceneCore>>) -> OutputSink<TMessage> {
-            let core = OutputSinkCore {
-                target:                 OutputSinkTarget::Disconnected,
-                when_target_changed:    None,
-            };
+            let core = OutputSinkCore::new(OutputSinkTarget::Disconnected);
 
             OutputSink {
                 program_id:             program_id,
@@ -13,6 +13,7 @@ use crate::subprogram_id::*;
 use super::idle_request::*;
 use super::subscription::*;
 use super::query::*;
+use super::topology::*;
 
 use futures::prelude::*;
 use futures::future::{poll_fn};
@@ -60,6 +61,14 @@ pub enum SceneControl {
     ///
     Connect(StreamSource, StreamTarget, StreamId),
 
+    ///
+    /// Makes a set of connections as a single operation
+    ///
+    /// Either all of the connections are made or none of them are, and the `SceneUpdate`s for the connections are sent together.
+    /// If any of the connections is invalid, a `FailedConnection` update is sent for it and no other connections are changed.
+    ///
+    ConnectAll(Vec<(StreamSource, StreamTarget, StreamId)>),
+
     ///
     /// Marks the input stream for a subprogram as 'closed', which will usually cause it to shut down
     ///
@@ -101,6 +110,16 @@ pub enum SceneControl {
     /// sometimes return programs that haven't yet sent their notifications to subscribers.
     ///
     Query(StreamTarget),
+
+    ///
+    /// Changes the programs and connections in the scene to match a topology
+    ///
+    /// This is compared against the programs and connections in the scene: new programs are started, programs from the previous
+    /// topology that are no longer in the topology are closed, and the connections that differ from the topology are changed
+    /// together so that no messages are sent using a partially updated set of connections. If any of the connections can't be made, the scene is rolled back to the previous topology
+    /// and a `FailedConnection` update is sent.
+    ///
+    ApplyTopology(SceneTopology),
 }
 
 // TODO: make the scene updates serializable (needs StreamId to be serializable first)
@@ -237,6 +256,13 @@ impl SceneControl {
         SceneControl::Connect(source.into(), target.into(), stream_id.into())
     }
 
+    ///
+    /// Creates a 'connect all' message from a list of connections
+    ///
+    pub fn connect_all(connections: impl IntoIterator<Item=(StreamSource, StreamTarget, StreamId)>) -> Self {
+        SceneControl::ConnectAll(connections.into_iter().collect())
+    }
+
     ///
     /// Runs the scene control program
     ///
@@ -246,6 +272,10 @@ impl SceneControl {
         let mut started_subprograms = HashSet::<SubProgramId>::new();
         let mut active_connections  = HashMap::<(SubProgramId, StreamId), SubProgramId>::new();
 
+        // The programs and connections that are in the topology that was most recently applied using ApplyTopology
+        let mut topology_programs       = HashSet::<SubProgramId>::new();
+        let mut topology_connections    = HashSet::<(StreamSource, StreamId)>::new();
+
         // Most of the scene control program's functionality is performed by manipulating the scene core directly
         let scene_core              = context.scene_core();
         let mut update_subscribers  = EventSubscribers::new();
@@ -293,25 +323,39 @@ impl SceneControl {
                     }
                 },
 
+                Control(ConnectAll(connections)) => {
+                    if let Some(scene_core) = scene_core.upgrade() {
+                        // Try to make all the connections, and send an update for the connection that failed if there's an error
+                        match SceneCore::connect_many(&scene_core, connections.clone()) {
+                            Ok(_)                       => { }
+                            Err((failed_idx, error))    => {
+                                let (source, target, stream) = connections[failed_idx].clone();
+                                update_subscribers.send(SceneUpdate::FailedConnection(error, source, target, stream)).await;
+                            }
+                        }
+                    } else {
+                        break;
+                    }
+                },
+
                 Control(Close(sub_program_id)) => {
                     // Try to close the input stream for a subprogram
                     if let Some(scene_core) = scene_core.upgrade() {
-                        let waker = {
-                            let program     = scene_core.lock().unwrap().get_sub_program(sub_program_id);
-                            let input_core  = scene_core.lock().unwrap().get_input_stream_core(sub_program_id);
-
-                            if let (Some(program), Some(input_core)) = (program, input_core) {
-                                let input_stream_id = program.lock().unwrap().input_stream_id();
+                        Self::close_subprogram(&scene_core, sub_program_id);
+                    }
+                },
 
-                                input_stream_id.close_input(&input_core)
-                            } else {
-                                Ok(None)
+                Control(ApplyTopology(topology)) => {
+                    if let Some(scene_core) = scene_core.upgrade() {
+                        // Try to apply the topology, and send an update if it could not be applied
+                        match Self::apply_topology(&scene_core, topology, &mut topology_programs, &mut topology_connections) {
+                            Ok(())                                      => { }
+                            Err((error, source, target, stream))        => {
+                                update_subscribers.send(SceneUpdate::FailedConnection(error, source, target, stream)).await;
                             }
-                        };
-
-                        if let Ok(Some(waker)) = waker {
-                            waker.wake()
                         }
+                    } else {
+                        break;
                     }
                 },
 
@@ -447,6 +491,124 @@ impl SceneControl {
             }
         }
     }
+
+    ///
+    /// Closes the input stream of a subprogram, which will usually cause it to stop
+    ///
+    fn close_subprogram(scene_core: &Arc<Mutex<SceneCore>>, sub_program_id: SubProgramId) {
+        let waker = {
+            let program     = scene_core.lock().unwrap().get_sub_program(sub_program_id);
+            let input_core  = scene_core.lock().unwrap().get_input_stream_core(sub_program_id);
+
+            if let (Some(program), Some(input_core)) = (program, input_core) {
+                let input_stream_id = program.lock().unwrap().input_stream_id();
+
+                input_stream_id.close_input(&input_core)
+            } else {
+                Ok(None)
+            }
+        };
+
+        if let Ok(Some(waker)) = waker {
+            waker.wake()
+        }
+    }
+
+    ///
+    /// Changes the scene from the current topology to a new one, returning the connection that failed if the topology can't be applied
+    ///
+    /// The programs and connections from the previous topology are the ones that are stopped or removed if they're not in the new
+    /// topology, but they're compared with the programs and connections that are actually in the scene, so anything that has been
+    /// changed since the previous topology was applied (eg, by a `Connect` request) is changed back to match the new topology.
+    ///
+    /// New programs are started first, so that the connections to them can be checked. The connections are then changed together
+    /// using `connect_many()`, which keeps the affected outputs paused until they've all been made or rolled back. Nothing from the
+    /// previous topology is removed or stopped until this has succeeded, so a topology that can't be applied only has to stop the
+    /// programs that it started.
+    ///
+    #[allow(clippy::type_complexity, clippy::result_large_err)]     // The error is the same as the data in a FailedConnection update
+    fn apply_topology(scene_core: &Arc<Mutex<SceneCore>>, topology: SceneTopology, current_programs: &mut HashSet<SubProgramId>, current_connections: &mut HashSet<(StreamSource, StreamId)>) -> Result<(), (ConnectionError, StreamSource, StreamTarget, StreamId)> {
+        let SceneTopology { programs, connections } = topology;
+
+        // Work out which programs are being added and removed
+        let new_programs        = programs.iter().map(|(program_id, _)| *program_id).collect::<HashSet<_>>();
+        let removed_programs    = current_programs.iter()
+            .filter(|program_id| !new_programs.contains(program_id))
+            .filter(|program_id| scene_core.lock().unwrap().get_sub_program(**program_id).is_some())
+            .copied()
+            .collect::<Vec<_>>();
+
+        // Connections are compared in their standard form, as that's how they're stored in the core (connections that can't be converted are left for connect_many to report)
+        let new_connections     = connections.into_iter()
+            .map(|(source, target, stream)| {
+                let (source, target) = SceneCore::normalize_connection(source.clone(), target.clone(), &stream).unwrap_or((source, target));
+                ((source, stream), target)
+            })
+            .collect::<HashMap<_, _>>();
+
+        // Work out which connections are being changed (connections that are removed from the topology revert to the target they would have without the connection)
+        let mut changes         = vec![];
+        let mut removed         = vec![];
+
+        {
+            let mut core = scene_core.lock().unwrap();
+
+            for ((source, stream), target) in new_connections.iter() {
+                if core.connection_target(source, stream) != Some(target) {
+                    changes.push((source.clone(), target.clone(), stream.clone()));
+                }
+            }
+
+            for (source, stream) in current_connections.iter() {
+                if !new_connections.contains_key(&(source.clone(), stream.clone())) && core.connection_target(source, stream).is_some() {
+                    let fallback = core.fallback_target_for_connection(source, stream)
+                        .map_err(|err| (err, source.clone(), StreamTarget::Any, stream.clone()))?;
+
+                    changes.push((source.clone(), fallback, stream.clone()));
+                    removed.push((source.clone(), stream.clone()));
+                }
+            }
+        }
+
+        // Start any program that's not already running
+        let mut started = vec![];
+        for (program_id, start_fn) in programs {
+            let is_running = scene_core.lock().unwrap().get_sub_program(program_id).is_some();
+
+            if !is_running {
+                (start_fn.0)(Arc::clone(scene_core));
+                started.push(program_id);
+            }
+        }
+
+        // Change all of the connections in one operation
+        if let Err((failed_idx, error)) = SceneCore::connect_many(scene_core, changes.clone()) {
+            // Roll back by stopping the programs we started (connect_many will have restored the previous connections)
+            for program_id in started {
+                Self::close_subprogram(scene_core, program_id);
+            }
+
+            let (source, target, stream) = changes.swap_remove(failed_idx);
+            return Err((error, source, target, stream));
+        }
+
+        // The new connections are in place, so the parts of the previous topology that aren't needed any more can be removed
+        // (this must not happen before connect_many succeeds, as there's no way to restart a program that's been stopped)
+        for (source, stream) in removed {
+            scene_core.lock().unwrap().remove_connection(&source, &stream);
+        }
+
+        // Stop the programs that aren't in the new topology
+        for program_id in removed_programs {
+            Self::close_subprogram(scene_core, program_id);
+        }
+
+        // The new topology is now the current one
+        *current_programs       = new_programs;
+        *current_connections    = new_connections.into_keys().collect();
+
+        Ok(())
+    }
 }
 
 ///
@@ -455,6 +617,7 @@ impl SceneControl {
 #[derive(Serialize, Deserialize)]
 enum SerializedSceneControl {
     Connect(StreamSource, StreamTarget, StreamId),
+    ConnectAll(Vec<(StreamSource, StreamTarget, StreamId)>),
     Close(SubProgramId),
     StopSceneWhenIdle,
     StopScene,
@@ -469,12 +632,14 @@ impl Serialize for SceneControl {
     {
         let intermediate = match self {
             SceneControl::Connect(source, target, stream)   => Ok(SerializedSceneControl::Connect(source.clone(), target.clone(), stream.clone())),
+            SceneControl::ConnectAll(connections)           => Ok(SerializedSceneControl::ConnectAll(connections.clone())),
             SceneControl::Close(program)                    => Ok(SerializedSceneControl::Close(*program)),
             SceneControl::StopSceneWhenIdle                 => Ok(SerializedSceneControl::StopSceneWhenIdle),
             SceneControl::StopScene                         => Ok(SerializedSceneControl::StopScene),
             SceneControl::Subscribe(target)                 => Ok(SerializedSceneControl::Subscribe(target.clone())),
             SceneControl::Query(target)                     => Ok(SerializedSceneControl::Query(target.clone())),
-            SceneControl::Start(_)                          => Err(S::Error::custom("SceneControl::Start cannot be serialized (uses a function)"))
+            SceneControl::Start(_)                          => Err(S::Error::custom("SceneControl::Start cannot be serialized (uses a function)")),
+            SceneControl::ApplyTopology(_)                  => Err(S::Error::custom("SceneControl::ApplyTopology cannot be serialized (uses functions)")),
         }?;
 
         intermediate.serialize(serializer)
@@ -490,6 +655,7 @@ impl<'a> Deserialize<'a> for SceneControl {
 
         match intermediate {
             SerializedSceneControl::Connect(source, target, stream) => Ok(SceneControl::Connect(source, target, stream)),
+            SerializedSceneControl::ConnectAll(connections)         => Ok(SceneControl::ConnectAll(connections)),
             SerializedSceneControl::Close(program)                  => Ok(SceneControl::Close(program)),
             SerializedSceneControl::StopSceneWhenIdle               => Ok(SceneControl::StopSceneWhenIdle),
             SerializedSceneControl::StopScene                       => Ok(SceneControl::StopScene),
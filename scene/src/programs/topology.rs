@@ -0,0 +1,107 @@
+use super::control::*;
+
+use crate::input_stream::*;
+use crate::scene_context::*;
+use crate::scene_message::*;
+use crate::stream_id::*;
+use crate::stream_source::*;
+use crate::stream_target::*;
+use crate::subprogram_id::*;
+
+use futures::prelude::*;
+
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+
+///
+/// Describes a desired set of subprograms and connections for a scene, which can be applied using `SceneControl::ApplyTopology`
+///
+/// When a topology is applied, it's compared to the programs and connections in the scene: programs that are in the new topology
+/// but are not running are started, programs that were in the previous topology but are not in the new one are closed, and
+/// any connections that don't match the new topology (including ones changed since the previous topology was applied) are
+/// changed in one step.
+///
+pub struct SceneTopology {
+    /// The programs that should be running in the scene, and the functions to start them if they are not running
+    pub (crate) programs: Vec<(SubProgramId, SceneProgramFn)>,
+
+    /// The connections that should be made in the scene
+    pub (crate) connections: Vec<(StreamSource, StreamTarget, StreamId)>,
+}
+
+impl SceneTopology {
+    ///
+    /// Creates an empty topology
+    ///
+    pub fn new() -> Self {
+        SceneTopology {
+            programs:       vec![],
+            connections:    vec![],
+        }
+    }
+
+    ///
+    /// Adds a program to this topology. The program will be started if it's not already running when the topology is applied
+    ///
+    pub fn with_program<TProgramFn, TInputMessage, TFuture>(self, program_id: SubProgramId, program: TProgramFn, max_input_waiting: usize) -> Self
+    where
+        TFuture:        'static + Send + Future<Output=()>,
+        TInputMessage:  'static + SceneMessage,
+        TProgramFn:     'static + Send + FnOnce(InputStream<TInputMessage>, SceneContext) -> TFuture,
+    {
+        self.with_program_fn(program_id, SceneProgramFn::new(program_id, program, max_input_waiting))
+    }
+
+    ///
+    /// Adds a program to this topology using a start function (which must start the program with the specified ID)
+    ///
+    pub fn with_program_fn(mut self, program_id: SubProgramId, start_fn: SceneProgramFn) -> Self {
+        self.programs.retain(|(existing_id, _)| existing_id != &program_id);
+        self.programs.push((program_id, start_fn));
+
+        self
+    }
+
+    ///
+    /// Adds a connection to this topology
+    ///
+    pub fn with_connection(mut self, source: impl Into<StreamSource>, target: impl Into<StreamTarget>, stream_id: impl Into<StreamId>) -> Self {
+        let source      = source.into();
+        let stream_id   = stream_id.into();
+
+        // Only one connection can be made for each source and stream
+        self.connections.retain(|(existing_source, _, existing_stream)| existing_source != &source || existing_stream != &stream_id);
+        self.connections.push((source, target.into(), stream_id));
+
+        self
+    }
+
+    ///
+    /// Returns the IDs of the programs in this topology
+    ///
+    pub fn programs(&self) -> impl '_ + Iterator<Item=SubProgramId> {
+        self.programs.iter().map(|(program_id, _)| *program_id)
+    }
+
+    ///
+    /// Returns the connections in this topology
+    ///
+    pub fn connections(&self) -> impl '_ + Iterator<Item=&(StreamSource, StreamTarget, StreamId)> {
+        self.connections.iter()
+    }
+}
+
+impl Default for SceneTopology {
+    fn default() -> Self {
+        SceneTopology::new()
+    }
+}
+
+impl Debug for SceneTopology {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SceneTopology")
+            .field("programs", &self.programs().collect::<Vec<_>>())
+            .field("connections", &self.connections)
+            .finish()
+    }
+}
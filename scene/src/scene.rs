@@ -158,6 +158,11 @@ impl Scene {
     /// using `StreamId::with_message_type::<SomeMessage>()` to indicate all outgoing streams of that type from `source`, or 
     /// `StreamId::with_message_type::<SomeMessage>().for_target(target)` to indicate an outgoing stream with a specific destination.
     ///
+    /// If the target program is in the scene and can't accept the messages, and there's no filter that can convert them, 
+    /// `ConnectionError::WrongInputType` is returned and the connection is not made (a source filter that converts the messages
+    /// needs to be added first). Connections to programs that haven't been added to the scene yet are stored and made when the
+    /// program starts: `scene.validate()` can be used to check for any of these that can't be made.
+    ///
     /// Examples:
     ///
     /// ```
@@ -206,6 +211,47 @@ impl Scene {
         SceneCore::connect_programs(&self.core, source, target, stream)
     }
 
+    ///
+    /// Checks that every connection configured in this scene can be made
+    ///
+    /// Each connection is checked against the input stream of its target program, taking account of any filters that can convert
+    /// between the two message types. Connections to programs that have not started yet are not errors, as they're made when the
+    /// target program starts. The connections that can't be made are returned along with the reason why.
+    ///
+    pub fn validate(&self) -> Result<(), Vec<(StreamSource, StreamTarget, StreamId, ConnectionError)>> {
+        let failed_connections = SceneCore::validate_connections(&self.core);
+
+        if failed_connections.is_empty() {
+            Ok(())
+        } else {
+            Err(failed_connections)
+        }
+    }
+
+    ///
+    /// Makes a set of connections as a single operation
+    ///
+    /// The connections are made in order, and if one can't be made, the connections before it are rolled back so none of the
+    /// connections are changed. The outputs that are being reconnected are paused until all of the connections have been made,
+    /// so no message is sent using a partially applied set of connections, and the `SceneUpdate`s for the connections are sent 
+    /// together once they've all been made. No updates are sent if the connections can't be made.
+    ///
+    /// ```
+    /// # use flo_scene::*;
+    /// # let scene         = Scene::default();
+    /// # let program_1     = SubProgramId::new();
+    /// # let program_2     = SubProgramId::new();
+    /// scene.connect_many([
+    ///     (program_1.into(), program_2.into(), StreamId::with_message_type::<String>()),
+    ///     (program_2.into(), program_1.into(), StreamId::with_message_type::<usize>()),
+    /// ]).unwrap();
+    /// ```
+    ///
+    pub fn connect_many(&self, connections: impl IntoIterator<Item=(StreamSource, StreamTarget, StreamId)>) -> Result<Vec<ConnectionResult>, ConnectionError> {
+        SceneCore::connect_many(&self.core, connections.into_iter().collect())
+            .map_err(|(_, err)| err)
+    }
+
     ///
     /// Creates a stream that can be used to send messages into this scene from elsewhere
     ///
@@ -205,6 +205,11 @@ impl SceneCore {
                     let old_input_core      = core.sub_program_inputs[handle].take();
                     core.next_subprogram    = core.next_subprogram.min(handle);
 
+                    // The handle can be re-used by the next program to start, so this program's ID should no longer refer to it
+                    if core.program_indexes.get(&program_id) == Some(&handle) {
+                        core.program_indexes.remove(&program_id);
+                    }
+
                     // Drop in order: first release the core lock, then drop the subprograms (which may re-take it)
                     mem::drop(core);
 
@@ -215,6 +220,9 @@ impl SceneCore {
                     mem::drop(old_input_core);
                     mem::drop(old_sub_program);
 
+                    // Any outputs that were sending to this program need to be updated according to their policy
+                    SceneCore::apply_target_ended_policies(&process_core);
+
                     // Core might be idle now the program has finished
                     SceneCore::check_if_idle(&process_core);
                 }
@@ -334,12 +342,9 @@ impl SceneCore {
     }
 
     ///
-    /// Adds or updates a program connection in this core
+    /// Checks the filters for a connection and converts it into its 'standard' form
     ///
-    pub (crate) fn connect_programs(core: &Arc<Mutex<SceneCore>>, source: StreamSource, target: StreamTarget, stream_id: StreamId) -> Result<ConnectionResult, ConnectionError> {
-        // Make sure the target stream ID type  is initialised
-        Self::initialise_message_type(core, stream_id.clone());
-
+    pub (crate) fn normalize_connection(source: StreamSource, target: StreamTarget, stream_id: &StreamId) -> Result<(StreamSource, StreamTarget), ConnectionError> {
         // Check source/target filter streams
         match (&source, &target) {
             (StreamSource::Filtered(source_filter), StreamTarget::Filtered(target_filter, _)) => {
@@ -384,15 +389,211 @@ impl SceneCore {
             (source, target) => (source, target),
         };
 
+        Ok((source, target))
+    }
+
+    ///
+    /// Adds or updates a program connection in this core
+    ///
+    pub (crate) fn connect_programs(core: &Arc<Mutex<SceneCore>>, source: StreamSource, target: StreamTarget, stream_id: StreamId) -> Result<ConnectionResult, ConnectionError> {
+        // Make sure the target stream ID type  is initialised
+        Self::initialise_message_type(core, stream_id.clone());
+
+        // Check the filters and convert to the standard form
+        let (source, target) = Self::normalize_connection(source, target, &stream_id)?;
+
+        // Check that the target can accept this stream (targets that aren't in the scene yet are checked when they start), then make the connection
+        let mut scene_updates   = vec![];
+        let result              = SceneCore::validate_connection_target(core, &target, &stream_id)
+            .and_then(|_| SceneCore::apply_connection(core, source.clone(), target.clone(), stream_id.clone(), &mut scene_updates));
+
+        SceneCore::send_scene_updates(core, scene_updates);
+
+        // Send an update if there's an error
+        if let Err(err) = &result {
+            let update = SceneUpdate::FailedConnection(err.clone(), source, target, stream_id);
+            SceneCore::send_scene_updates(core, vec![update]);
+        }
+
+        result
+    }
+
+    ///
+    /// Applies a set of connections as a single operation
+    ///
+    /// The connections are made in order, and each target is checked just before its connection is made (so a connection can rely on
+    /// a filter set up earlier in the same batch). If any connection fails, the connections made before it are rolled back, so the
+    /// scene is left unchanged. Every output affected by the batch is paused until all of the connections have been made (or rolled 
+    /// back), and an output that was sending to a filter waits for that filter to deliver the messages it has already received before
+    /// it starts sending to its new target, so no message is sent to a target that is part-way through being reconfigured and messages
+    /// don't arrive out of order. The scene updates are sent together once all of the connections have been made.
+    ///
+    /// On failure, the index of the connection that failed is returned alongside the error.
+    ///
+    pub (crate) fn connect_many(core: &Arc<Mutex<SceneCore>>, connections: Vec<(StreamSource, StreamTarget, StreamId)>) -> Result<Vec<ConnectionResult>, (usize, ConnectionError)> {
+        // Check the filters for all of the connections before making any changes
+        let mut normalized = Vec::with_capacity(connections.len());
+
+        for (idx, (source, target, stream_id)) in connections.into_iter().enumerate() {
+            Self::initialise_message_type(core, stream_id.clone());
+
+            let (source, target) = Self::normalize_connection(source, target, &stream_id).map_err(|err| (idx, err))?;
+            normalized.push((source, target, stream_id));
+        }
+
+        // Remember the existing connections so we can roll back if there's a failure
+        let previous_connections = {
+            let core = core.lock().unwrap();
+
+            normalized.iter()
+                .map(|(source, _, stream_id)| core.connections.get(&(source.clone(), stream_id.clone())).cloned())
+                .collect::<Vec<_>>()
+        };
+
+        // Pause all of the affected outputs so they wait for the whole batch to be applied
+        let paused_outputs = Self::pause_outputs_for_connections(core, &normalized);
+
+        // Make the connections
+        let mut scene_updates   = vec![];
+        let mut results         = vec![];
+        let mut failure         = None;
+
+        for (idx, (source, target, stream_id)) in normalized.iter().enumerate() {
+            let result = SceneCore::validate_connection_target(core, target, stream_id)
+                .and_then(|_| SceneCore::apply_connection(core, source.clone(), target.clone(), stream_id.clone(), &mut scene_updates));
+
+            match result {
+                Ok(result)  => { results.push(result); }
+                Err(err)    => { failure = Some((idx, err)); break; }
+            }
+        }
+
+        if let Some((failed_idx, _)) = &failure {
+            // Restore the previous connections for everything we tried to change, undoing the most recent change first
+            let mut rollback_updates = vec![];
+
+            for ((source, _, stream_id), previous) in normalized.into_iter().zip(previous_connections).take(failed_idx+1).rev() {
+                let previous_target = previous.clone().unwrap_or(StreamTarget::Any);
+                SceneCore::apply_connection(core, source.clone(), previous_target, stream_id.clone(), &mut rollback_updates).ok();
+
+                if previous.is_none() {
+                    core.lock().unwrap().connections.remove(&(source, stream_id));
+                }
+            }
+        }
+
+        // Resume the paused outputs now the batch is finished, whether or not it succeeded
+        for (stream_id, output_sink) in paused_outputs {
+            if let Ok(Some(waker)) = stream_id.resume_output(&output_sink) {
+                waker.wake();
+            }
+        }
+
+        if let Some(failure) = failure {
+            return Err(failure);
+        }
+
+        // Send the updates for the whole batch
+        SceneCore::send_scene_updates(core, scene_updates);
+
+        Ok(results)
+    }
+
+    ///
+    /// Pauses every output sink whose target might be changed by a set of connections, returning the paused sinks
+    ///
+    fn pause_outputs_for_connections(core: &Arc<Mutex<SceneCore>>, connections: &[(StreamSource, StreamTarget, StreamId)]) -> Vec<(StreamId, Arc<dyn Send + Sync + Any>)> {
+        let (subprograms, filter_conversions) = {
+            let core = core.lock().unwrap();
+
+            (core.sub_programs.iter().flatten().cloned().collect::<Vec<_>>(), core.filter_conversions.keys().cloned().collect::<Vec<_>>())
+        };
+
+        let mut paused_outputs = vec![];
+
+        for subprogram in subprograms.iter() {
+            let subprogram = subprogram.lock().unwrap();
+
+            for (output_stream_id, output_sink) in subprogram.output_streams() {
+                let affected = connections.iter().any(|(source, target, stream_id)| {
+                    // Outputs that are connected directly (including the streams for a specific target that apply_connection() updates for a filter)
+                    let connected_directly = source.matches_subprogram(&subprogram.id) && (output_stream_id == stream_id || match target {
+                        StreamTarget::Filtered(_, target_program_id)    => stream_id.target_program().is_none() && output_stream_id == &stream_id.for_target(*target_program_id),
+                        _                                               => false,
+                    });
+
+                    // Outputs that can be converted to the stream type by a source filter, which are reconnected if they're waiting for a connection
+                    let converted = filter_conversions.iter()
+                        .any(|(convert_from, convert_to)| convert_to.message_type() == stream_id.message_type() && convert_from == output_stream_id);
+
+                    connected_directly || converted
+                });
+
+                if affected && output_stream_id.pause_output(output_sink).is_ok() {
+                    paused_outputs.push((output_stream_id.clone(), Arc::clone(output_sink)));
+                }
+            }
+        }
+
+        paused_outputs
+    }
+
+    ///
+    /// Checks that the target of a connection can accept messages of the specified stream type
+    ///
+    /// The target's input stream must match the stream type, or there must be a filter conversion that can map between the
+    /// two types. Targets that are not running yet are accepted, as the connection will be made when they start.
+    ///
+    pub (crate) fn validate_connection_target(core: &Arc<Mutex<SceneCore>>, target: &StreamTarget, stream_id: &StreamId) -> Result<(), ConnectionError> {
+        match target {
+            StreamTarget::None | StreamTarget::Any => Ok(()),
+
+            StreamTarget::Program(program_id) => {
+                match core.lock().unwrap().get_target_input(program_id, stream_id) {
+                    Ok(_)                                   => Ok(()),
+                    Err(ConnectionError::TargetNotInScene)  => Ok(()),
+                    Err(err)                                => Err(err),
+                }
+            }
+
+            StreamTarget::Filtered(filter_handle, program_id) => {
+                let input_stream_id = filter_handle.target_stream_id(*program_id)?;
+                core.lock().unwrap().get_target_input(program_id, &input_stream_id)?;
+
+                Ok(())
+            }
+        }
+    }
+
+    ///
+    /// Checks every connection that's configured in a scene core, returning the connections that can't be made along with the reason why
+    ///
+    pub (crate) fn validate_connections(core: &Arc<Mutex<SceneCore>>) -> Vec<(StreamSource, StreamTarget, StreamId, ConnectionError)> {
+        let connections = core.lock().unwrap().connections.clone();
+
+        connections.into_iter()
+            .flat_map(|((source, stream_id), target)| {
+                match SceneCore::validate_connection_target(core, &target, &stream_id) {
+                    Ok(())      => None,
+                    Err(err)    => Some((source, target, stream_id, err)),
+                }
+            })
+            .collect()
+    }
+
+    ///
+    /// Makes a connection that has been converted to its standard form, adding the updates generated to the list of scene updates
+    ///
+    fn apply_connection(core: &Arc<Mutex<SceneCore>>, source: StreamSource, target: StreamTarget, stream_id: StreamId, scene_updates: &mut Vec<SceneUpdate>) -> Result<ConnectionResult, ConnectionError> {
         // Call finish_connecting_programs to determine the result of the connection
-        let result = SceneCore::finish_connecting_programs(core, source.clone(), target.clone(), stream_id.clone());
+        let result = SceneCore::finish_connecting_programs(core, source.clone(), target.clone(), stream_id.clone(), scene_updates);
 
         // If successful and the target is a filter, connect the specific stream for the target as well as the 'all' stream
         if result.is_ok() {
             // Reconnect any streams that specifically target the filter as well
             if let StreamTarget::Filtered(_, target_program_id) = target {
                 if stream_id.target_program().is_none() {
-                    SceneCore::finish_connecting_programs(core, source.clone(), target.clone(), stream_id.for_target(target_program_id)).ok();
+                    SceneCore::finish_connecting_programs(core, source.clone(), target.clone(), stream_id.for_target(target_program_id), scene_updates).ok();
                 }
             }
 
@@ -424,20 +625,14 @@ impl SceneCore {
             }
         }
 
-        // Send an update if there's an error
-        if let Err(err) = &result {
-            let update = SceneUpdate::FailedConnection(err.clone(), source, target, stream_id);
-            SceneCore::send_scene_updates(core, vec![update]);
-        }
-
         result
     }
 
     ///
-    /// Finishes a program connection, sending updates if successful
+    /// Finishes a program connection, adding the updates to send to the list of scene updates if successful
     ///
     #[allow(clippy::type_complexity)]   // Creating a type for reconnect_subprogram just looks super goofy and is a lifetime nightmare
-    fn finish_connecting_programs(core: &Arc<Mutex<SceneCore>>, source: StreamSource, target: StreamTarget, stream_id: StreamId) -> Result<ConnectionResult, ConnectionError> {
+    fn finish_connecting_programs(core: &Arc<Mutex<SceneCore>>, source: StreamSource, target: StreamTarget, stream_id: StreamId, scene_updates: &mut Vec<SceneUpdate>) -> Result<ConnectionResult, ConnectionError> {
         // If the source is a filter source, then add to the list of available filter programs
         if let StreamSource::Filtered(source_filter) = &source {
             // Get the source and target streams IDs, with no target
@@ -516,8 +711,8 @@ impl SceneCore {
             },
         };
 
-        // TODO: pause the inputs of all the sub-programs matching the source, so the update is atomic?
         // TODO: if there's a filtered connection we really should wait for the filter program to stop before starting new input to avoid situations where some values can arrive out-of-order
+        // (connect_many() does this: it pauses the affected outputs, and outputs that were sending to a filter wait for the filter to finish before sending to the new target)
 
         let sub_programs = {
             let mut core = core.lock().unwrap();
@@ -530,7 +725,6 @@ impl SceneCore {
         };
 
         // Update the existing connections
-        let mut scene_updates = vec![];
         let target_program_id = target.target_sub_program();
 
         for sub_program in sub_programs.iter().flatten() {
@@ -554,11 +748,8 @@ impl SceneCore {
             }
         }
 
-        // Send the updates on how the connections have changed
-        SceneCore::send_scene_updates(core, scene_updates);
-
         if target_program_id.is_some() {
-            // TODO: determine if the target program can accept connections of this type
+            // The target was checked against the stream type when the connection was validated
             Ok(ConnectionResult::Ready)
         } else {
             Ok(ConnectionResult::TargetNotReady)
@@ -822,6 +1013,35 @@ impl SceneCore {
         }
     }
 
+    ///
+    /// Returns the target that's currently configured for a source and a stream, if there is one
+    ///
+    pub (crate) fn connection_target(&self, source: &StreamSource, stream_id: &StreamId) -> Option<&StreamTarget> {
+        self.connections.get(&(source.clone(), stream_id.clone()))
+    }
+
+    ///
+    /// Returns the target that a source would use for a stream if the connection for that source and stream were removed
+    ///
+    pub (crate) fn fallback_target_for_connection(&mut self, source: &StreamSource, stream_id: &StreamId) -> Result<StreamTarget, ConnectionError> {
+        let key             = (source.clone(), stream_id.clone());
+        let connection      = self.connections.remove(&key);
+        let fallback_target = self.mapped_target_for_connection(source, &StreamTarget::Any, stream_id);
+
+        if let Some(connection) = connection {
+            self.connections.insert(key, connection);
+        }
+
+        fallback_target
+    }
+
+    ///
+    /// Removes the connection for a source and a stream (the outputs will continue to use their current targets until they are reconnected)
+    ///
+    pub (crate) fn remove_connection(&mut self, source: &StreamSource, stream_id: &StreamId) -> Option<StreamTarget> {
+        self.connections.remove(&(source.clone(), stream_id.clone()))
+    }
+
     ///
     /// Returns the output sink target configured for a particular stream
     ///
@@ -1224,6 +1444,35 @@ impl SceneCore {
         }
     }
 
+    ///
+    /// Finds any output sinks whose target program has finished, and updates them according to their `TargetEndedPolicy`
+    ///
+    pub (crate) fn apply_target_ended_policies(scene_core: &Arc<Mutex<SceneCore>>) {
+        // Fetch the outputs for all of the subprograms (the subprograms can't stay locked while the policies are applied, as this can lock the scene core)
+        let subprograms = scene_core.lock().unwrap().sub_programs.iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let outputs = subprograms.into_iter()
+            .flat_map(|subprogram| {
+                let subprogram = subprogram.lock().unwrap();
+                let program_id = subprogram.id;
+
+                subprogram.outputs.iter()
+                    .map(|(stream_id, output_sink_core)| (program_id, stream_id.clone(), output_sink_core.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        // Update the outputs, then wake anything that was waiting for them
+        let wakers = outputs.into_iter()
+            .flat_map(|(program_id, stream_id, output_sink_core)| stream_id.apply_target_ended_policy(scene_core, &output_sink_core, program_id).ok().flatten())
+            .collect::<Vec<_>>();
+
+        wakers.into_iter().for_each(|waker| waker.wake());
+    }
+
     ///
     /// After a program has started, finds any connections that are targetting it and remakes them
     ///
@@ -1448,6 +1697,12 @@ pub (crate) fn run_core(core: &Arc<Mutex<SceneCore>>) -> impl Future<Output=()>
                         if !core.awake_processes.contains(&next_process_idx) {
                             core.awake_processes.push_back(next_process_idx);
                         }
+
+                        // Yield to the executor running the scene before polling again. Some executors (such as tokio) limit how much work a task
+                        // can do before yielding, and their futures will wake themselves without making progress until the scene does this
+                        mem::drop(core);
+                        ctxt.waker().wake_by_ref();
+                        return Poll::Pending;
                     }
                 } else {
                     // This process has been terminated: remove it from the list
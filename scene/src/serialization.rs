@@ -336,6 +336,7 @@ impl Scene {
 ///
 /// Targets for a serialized stream
 ///
+#[derive(Clone, Debug)]
 pub enum SerializedStreamTarget {
     /// Send by deserializing to the input stream of the specified subprogram
     SubProgram(SubProgramId),
@@ -23,12 +23,15 @@ static STREAM_TYPE_FUNCTIONS: Lazy<RwLock<HashMap<TypeId, StreamTypeFunctions>>>
 type ConnectOutputToInputFn     = Arc<dyn Send + Sync + Fn(&Arc<dyn Send + Sync + Any>, &Arc<dyn Send + Sync + Any>, bool) -> Result<Option<Waker>, ConnectionError>>;
 type ConnectOutputToDiscardFn   = Arc<dyn Send + Sync + Fn(&Arc<dyn Send + Sync + Any>) -> Result<Option<Waker>, ConnectionError>>;
 type DisconnectOutputFn         = Arc<dyn Send + Sync + Fn(&Arc<dyn Send + Sync + Any>) -> Result<Option<Waker>, ConnectionError>>;
+type PauseOutputFn              = Arc<dyn Send + Sync + Fn(&Arc<dyn Send + Sync + Any>) -> Result<(), ConnectionError>>;
+type ResumeOutputFn             = Arc<dyn Send + Sync + Fn(&Arc<dyn Send + Sync + Any>) -> Result<Option<Waker>, ConnectionError>>;
 type CloseInputFn               = Arc<dyn Send + Sync + Fn(&Arc<dyn Send + Sync + Any>) -> Result<Option<Waker>, ConnectionError>>;
 type IsIdleFn                   = Arc<dyn Send + Sync + Fn(&Arc<dyn Send + Sync + Any>) -> Result<bool, ConnectionError>>;
 type WaitingForIdleFn           = Arc<dyn Send + Sync + Fn(&Arc<dyn Send + Sync + Any>, usize) -> Result<IdleInputStreamCore, ConnectionError>>;
 type DefaultTargetFn            = Arc<dyn Send + Sync + Fn() -> StreamTarget>;
 type ActiveTargetFn             = Arc<dyn Send + Sync + Fn(&Arc<dyn Send + Sync + Any>) -> Result<StreamTarget, ConnectionError>>;
 type ReconnectSinkFn            = Arc<dyn Send + Sync + Fn(&Arc<Mutex<SceneCore>>, &Arc<dyn Send + Sync + Any>, SubProgramId, StreamTarget) -> Result<Option<Waker>, ConnectionError>>;
+type TargetEndedFn              = Arc<dyn Send + Sync + Fn(&Arc<Mutex<SceneCore>>, &Arc<dyn Send + Sync + Any>, SubProgramId) -> Result<Option<Waker>, ConnectionError>>;
 type InitialiseFn               = Arc<dyn Send + Sync + Fn(&Scene)>;
 
 ///
@@ -44,6 +47,12 @@ struct StreamTypeFunctions {
     /// Disconnects an OutputSinkCore, causing it to wait for a new connection to be made
     disconnect_output: DisconnectOutputFn,
 
+    /// Stops an OutputSinkCore from sending messages while its connections are changed
+    pause_output: PauseOutputFn,
+
+    /// Reverses a call to pause_output
+    resume_output: ResumeOutputFn,
+
     /// Closes the input to a stream
     close_input: CloseInputFn,
 
@@ -62,6 +71,9 @@ struct StreamTypeFunctions {
     /// Reconnects an output sink core to an input stream
     reconnect_sink: ReconnectSinkFn,
 
+    /// Applies the 'target ended' policy to an output sink core if its target program has finished
+    target_ended: TargetEndedFn,
+
     /// Initialises the message type inside a scene
     initialise: InitialiseFn,
 }
@@ -166,6 +178,20 @@ impl StreamTypeFunctions {
                 Ok(waker)
             }),
 
+            pause_output: Arc::new(|output_sink_any| {
+                let output_sink = output_sink_any.clone().downcast::<Mutex<OutputSinkCore<TMessageType>>>().map_err(|_| ConnectionError::UnexpectedConnectionType)?;
+                output_sink.lock().unwrap().pause();
+
+                Ok(())
+            }),
+
+            resume_output: Arc::new(|output_sink_any| {
+                let output_sink = output_sink_any.clone().downcast::<Mutex<OutputSinkCore<TMessageType>>>().map_err(|_| ConnectionError::UnexpectedConnectionType)?;
+                let waker       = output_sink.lock().unwrap().resume();
+
+                Ok(waker)
+            }),
+
             close_input: Arc::new(|input_stream_any| {
                 let input_stream    = input_stream_any.clone().downcast::<Mutex<InputStreamCore<TMessageType>>>().map_err(|_| ConnectionError::UnexpectedConnectionType)?;
                 let waker           = input_stream.lock().unwrap().close();
@@ -197,6 +223,7 @@ impl StreamTypeFunctions {
 
                 match &output_sink_target {
                     OutputSinkTarget::Disconnected                  => Ok(StreamTarget::Any),
+                    OutputSinkTarget::Ended                         => Ok(StreamTarget::Any),
                     OutputSinkTarget::Discard                       => Ok(StreamTarget::None),
                     OutputSinkTarget::Input(input_core)             |
                     OutputSinkTarget::CloseWhenDropped(input_core)  => {
@@ -230,6 +257,12 @@ impl StreamTypeFunctions {
                 Ok(waker)
             }),
 
+            target_ended: Arc::new(|scene_core, output_sink_core_any, source_program| {
+                let output_sink = output_sink_core_any.clone().downcast::<Mutex<OutputSinkCore<TMessageType>>>().map_err(|_| ConnectionError::UnexpectedConnectionType)?;
+
+                Ok(OutputSinkCore::apply_target_ended_policy(&output_sink, scene_core, source_program))
+            }),
+
             initialise: Arc::new(move |scene| {
                 use std::mem;
 
@@ -310,6 +343,20 @@ impl StreamTypeFunctions {
             .map(|all_functions| Arc::clone(&all_functions.disconnect_output))
     }
 
+    pub fn pause_output(type_id: &TypeId) -> Option<PauseOutputFn> {
+        let stream_type_functions = STREAM_TYPE_FUNCTIONS.read().unwrap();
+
+        stream_type_functions.get(type_id)
+            .map(|all_functions| Arc::clone(&all_functions.pause_output))
+    }
+
+    pub fn resume_output(type_id: &TypeId) -> Option<ResumeOutputFn> {
+        let stream_type_functions = STREAM_TYPE_FUNCTIONS.read().unwrap();
+
+        stream_type_functions.get(type_id)
+            .map(|all_functions| Arc::clone(&all_functions.resume_output))
+    }
+
     pub fn close_input(type_id: &TypeId) -> Option<CloseInputFn> {
         let stream_type_functions = STREAM_TYPE_FUNCTIONS.read().unwrap();
 
@@ -352,6 +399,13 @@ impl StreamTypeFunctions {
             .map(|all_functions| Arc::clone(&all_functions.reconnect_sink))
     }
 
+    pub fn target_ended(type_id: &TypeId) -> Option<TargetEndedFn> {
+        let stream_type_functions = STREAM_TYPE_FUNCTIONS.read().unwrap();
+
+        stream_type_functions.get(type_id)
+            .map(|all_functions| Arc::clone(&all_functions.target_ended))
+    }
+
     pub fn initialise(type_id: &TypeId) -> Option<InitialiseFn> {
         let stream_type_functions = STREAM_TYPE_FUNCTIONS.read().unwrap();
 
@@ -500,6 +554,34 @@ impl StreamId {
         }
     }
 
+    ///
+    /// Given an output sink (an 'Any' that maps to an OutputSinkCore of the same type as this stream ID), stops it from sending messages until `resume_output()` is called
+    ///
+    pub (crate) fn pause_output(&self, output_sink: &Arc<dyn Send + Sync + Any>) -> Result<(), ConnectionError> {
+        let message_type = self.message_type();
+
+        if let Some(pause_output) = StreamTypeFunctions::pause_output(&message_type) {
+            (pause_output)(output_sink)
+        } else {
+            // Shouldn't happen: the stream type was not registered correctly
+            Err(ConnectionError::UnexpectedConnectionType)
+        }
+    }
+
+    ///
+    /// Reverses a call to `pause_output()`, returning a waker for the sink if it can send messages again
+    ///
+    pub (crate) fn resume_output(&self, output_sink: &Arc<dyn Send + Sync + Any>) -> Result<Option<Waker>, ConnectionError> {
+        let message_type = self.message_type();
+
+        if let Some(resume_output) = StreamTypeFunctions::resume_output(&message_type) {
+            (resume_output)(output_sink)
+        } else {
+            // Shouldn't happen: the stream type was not registered correctly
+            Err(ConnectionError::UnexpectedConnectionType)
+        }
+    }
+
     ///
     /// Closes an input stream (an 'Any' that maps to an InputStreamCore of the same type as this stream ID) 
     ///
@@ -591,6 +673,20 @@ impl StreamId {
             Err(ConnectionError::UnexpectedConnectionType)
         }
     }
+
+    ///
+    /// If the target of an output sink core has finished, updates it according to the sink's `TargetEndedPolicy`
+    ///
+    pub (crate) fn apply_target_ended_policy(&self, scene_core: &Arc<Mutex<SceneCore>>, output_sink_core: &Arc<dyn Send + Sync + Any>, source_program: SubProgramId) -> Result<Option<Waker>, ConnectionError> {
+        let message_type = self.message_type();
+
+        if let Some(target_ended) = StreamTypeFunctions::target_ended(&message_type) {
+            (target_ended)(scene_core, output_sink_core, source_program)
+        } else {
+            // Shouldn't happen: the stream type was not registered correctly
+            Err(ConnectionError::UnexpectedConnectionType)
+        }
+    }
 }
 
 mod serialization {
@@ -3,6 +3,7 @@ use crate::process_core::*;
 use crate::scene_core::*;
 use crate::scene_message::*;
 use crate::stream_id::*;
+use crate::stream_target::*;
 use crate::subprogram_id::*;
 
 use futures::task::{ArcWake, Waker, waker};
@@ -130,14 +131,16 @@ impl SubProgramCore {
     }
 
     ///
-    /// Attempts to reconnect any output sinks that are 
+    /// Attempts to reconnect any output sinks that are not attached to a target
+    ///
+    /// Sinks that are already sending to an input stream are left alone: these may have been attached directly to an
+    /// input core (as happens for the output of a command), which would be lost if the sink was reconnected.
     ///
     pub (crate) fn reconnect_disconnected_outputs(program_core: &Arc<Mutex<SubProgramCore>>, scene_core: &Arc<Mutex<SceneCore>>, reconnect_stream_id: &StreamId) -> Option<Waker> {
         // Get the disconnected output sinks that match this
         let (output_sink_cores, program_id) = {
             let core = program_core.lock().unwrap();
 
-            // TODO: filter out output sinks that are already attached, we only want disconnected ones here
             let output_sink_cores = core.outputs
                 .iter()
                 .filter(|(output_stream_id, _)| reconnect_stream_id.message_type() == output_stream_id.message_type())
@@ -155,8 +158,10 @@ impl SubProgramCore {
             // Get the active target for this sink
             let target = reconnect_stream_id.active_target_for_output_sink(&core);
 
-            // Try to reconnect it
+            // Try to reconnect it, if it's not already sending to a program
             if let Ok(target) = target {
+                if let StreamTarget::Program(_) = target { continue; }
+
                 let waker = reconnect_stream_id.reconnect_output_sink(scene_core, &core, program_id, target);
                 if let Ok(Some(waker)) = waker { wakers.push(waker) }
             }
@@ -111,6 +111,16 @@ impl SubProgramId {
         }
     }
 
+    ///
+    /// Returns the name of this subprogram, if it was created with `SubProgramId::called()`
+    ///
+    pub fn name(&self) -> Option<String> {
+        match &self.0 {
+            SubProgramIdValue::Named(name_num)  => name_for_id(*name_num),
+            _                                   => None,
+        }
+    }
+
     ///
     /// Returns true if this program is a subtask of another program
     ///
@@ -83,3 +83,34 @@ fn query_command() {
         .run_query(ReadCommand::default(), Query::<SceneUpdate>::with_no_target(), *SCENE_CONTROL_PROGRAM, |output| if output.len() == 0 { Err(format!("Unexpected command output: {:?}", output)) } else { Ok(()) })
         .run_in_scene_with_threads(&scene, test_program, 5);
 }
+
+#[test]
+fn command_output_survives_new_message_type() {
+    use serde::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+    struct CommandOutputMessage(usize);
+
+    impl SceneMessage for CommandOutputMessage { }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct FirstUseMessage;
+
+    impl SceneMessage for FirstUseMessage { }
+
+    let scene = Scene::default();
+
+    // Using a message type for the first time installs its serialization filters, which shouldn't disconnect the output of the command
+    let test_command = FnCommand::<(), CommandOutputMessage>::new(|_input, context| async move {
+        let mut output = context.send::<CommandOutputMessage>(()).unwrap();
+        output.send(CommandOutputMessage(1)).await.unwrap();
+
+        let _first_use = context.send::<FirstUseMessage>(()).unwrap();
+        output.send(CommandOutputMessage(2)).await.unwrap();
+    });
+
+    let test_program = SubProgramId::new();
+    TestBuilder::new()
+        .run_command(test_command, vec![], |output| if output != vec![CommandOutputMessage(1), CommandOutputMessage(2)] { Err(format!("Unexpected command output: {:?}", output)) } else { Ok(()) })
+        .run_in_scene_with_threads(&scene, test_program, 5);
+}
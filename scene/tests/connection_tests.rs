@@ -41,8 +41,12 @@ use flo_scene::*;
 use flo_scene::programs::*;
 
 use futures::prelude::*;
+use futures_timer::*;
 use serde::*;
 
+use std::sync::*;
+use std::time::{Duration};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 struct TestResult(String);
@@ -174,9 +178,9 @@ pub fn connect_two_subprograms_using_filter_then_all_no_delay() {
         }
     }, 0);
 
-    // Connect testmessages and strings to program 2, filtering the test messages as we go
+    // Connect testmessages and strings to program 2, filtering the test messages as we go (TestMessage can't be connected directly as there's no filter for it)
     scene.connect_programs((), program_2, StreamId::with_message_type::<String>()).unwrap();
-    scene.connect_programs((), program_2, StreamId::with_message_type::<TestMessage>()).unwrap();
+    assert!(matches!(scene.connect_programs((), program_2, StreamId::with_message_type::<TestMessage>()), Err(ConnectionError::WrongInputType(_, _))));
     scene.connect_programs((), StreamTarget::Filtered(test_string_filter, program_2), StreamId::with_message_type::<TestMessage>()).unwrap();
 
     // Check that we receive the test message
@@ -227,7 +231,8 @@ pub fn connect_default_using_source_filter_after_all() {
     }, 0);
 
     // Connect testmessages and strings to program 2, filtering the test messages as we go
-    scene.connect_programs((), program_2, StreamId::with_message_type::<TestMessage>()).unwrap();
+    // (TestMessage can't be connected directly as there's no filter for it yet: the source filter sends it wherever strings go)
+    assert!(matches!(scene.connect_programs((), program_2, StreamId::with_message_type::<TestMessage>()), Err(ConnectionError::WrongInputType(_, _))));
     scene.connect_programs((), program_2, StreamId::with_message_type::<String>()).unwrap();
 
     // Check that we receive the test message
@@ -275,7 +280,8 @@ pub fn connect_default_using_source_filter_after_all_no_delay() {
     }, 0);
 
     // Connect testmessages and strings to program 2, filtering the test messages as we go
-    scene.connect_programs((), program_2, StreamId::with_message_type::<TestMessage>()).unwrap();
+    // (TestMessage can't be connected directly as there's no filter for it yet: the source filter sends it wherever strings go)
+    assert!(matches!(scene.connect_programs((), program_2, StreamId::with_message_type::<TestMessage>()), Err(ConnectionError::WrongInputType(_, _))));
     scene.connect_programs((), program_2, StreamId::with_message_type::<String>()).unwrap();
     scene.connect_programs(StreamSource::Filtered(test_string_filter), (), StreamId::with_message_type::<TestMessage>()).unwrap();
 
@@ -743,9 +749,10 @@ pub fn connect_two_subprograms_using_source_filter_later() {
         }
     }, 0);
 
-    // Connect program 1 to program 2, then say that we can filter TestMessages
-    scene.connect_programs(program_1, program_2, StreamId::with_message_type::<TestMessage>()).unwrap();
+    // Program 1 can't be connected to program 2 until there's a way to filter TestMessages
+    assert!(matches!(scene.connect_programs(program_1, program_2, StreamId::with_message_type::<TestMessage>()), Err(ConnectionError::WrongInputType(_, _))));
     scene.connect_programs(StreamSource::Filtered(test_string_filter), (), StreamId::with_message_type::<TestMessage>()).unwrap();
+    scene.connect_programs(program_1, program_2, StreamId::with_message_type::<TestMessage>()).unwrap();
 
     // Check that we receive the test message
     TestBuilder::new()
@@ -1201,3 +1208,291 @@ fn connect_single_source_to_single_target_before_creation() {
 // TODO: both these tests set up the connection before the connection is made, we also need to test making the connection later on
 // TODO: `connect_single_source_to_single_target` but with source and target filters
 
+
+#[test]
+pub fn connect_many_subprograms() {
+    // Scene with three programs: program_1 sends strings to program_2, which sends usizes to program_3
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let program_1       = SubProgramId::new();
+    let program_2       = SubProgramId::new();
+    let program_3       = SubProgramId::new();
+
+    scene.add_subprogram(program_1, |_: InputStream<()>, context| {
+        async move {
+            let mut send_strings = context.send(()).unwrap();
+            send_strings.send("Test".to_string()).await.unwrap();
+        }
+    }, 0);
+
+    scene.add_subprogram(program_2, |input: InputStream<String>, context| {
+        async move {
+            let mut send_lengths = context.send(()).unwrap();
+
+            let mut input = input;
+            while let Some(input) = input.next().await {
+                send_lengths.send(input.len()).await.unwrap();
+            }
+        }
+    }, 0);
+
+    scene.add_subprogram(program_3, move |input: InputStream<usize>, context| {
+        async move {
+            let mut test_program = context.send(test_program).unwrap();
+
+            let mut input = input;
+            while let Some(input) = input.next().await {
+                test_program.send(TestResult(format!("{}", input))).await.unwrap();
+            }
+        }
+    }, 0);
+
+    // Connect all the programs at once
+    scene.connect_many([
+        (program_1.into(), program_2.into(), StreamId::with_message_type::<String>()),
+        (program_2.into(), program_3.into(), StreamId::with_message_type::<usize>()),
+    ]).unwrap();
+
+    TestBuilder::new()
+        .expect_message(|TestResult(msg)| if msg == "4" { Ok(()) } else { Err(format!("Expected '4', got '{}'", msg)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+pub fn connect_many_makes_no_connections_if_one_is_invalid() {
+    // program_1 sends strings, which should end up at program_2 as the second connection is invalid
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let program_1       = SubProgramId::new();
+    let program_2       = SubProgramId::new();
+    let program_3       = SubProgramId::new();
+
+    scene.add_subprogram(program_1, |_: InputStream<()>, context| {
+        async move {
+            let mut send_strings = context.send(()).unwrap();
+            send_strings.send("Test".to_string()).await.unwrap();
+        }
+    }, 0);
+
+    scene.add_subprogram(program_2, move |input: InputStream<String>, context| {
+        async move {
+            let mut test_program = context.send(test_program).unwrap();
+
+            let mut input = input;
+            while let Some(input) = input.next().await {
+                test_program.send(TestResult(format!("program_2: {}", input))).await.unwrap();
+            }
+        }
+    }, 0);
+
+    scene.add_subprogram(program_3, |input: InputStream<usize>, _| {
+        async move {
+            let mut input = input;
+            while let Some(_) = input.next().await { }
+        }
+    }, 0);
+
+    scene.connect_programs(program_1, program_2, StreamId::with_message_type::<String>()).unwrap();
+
+    // The filter in the second connection reads 'usize' messages, so it can't be used for strings and this should leave the existing connection alone
+    let usize_to_usize  = FilterHandle::for_filter(|numbers: InputStream<usize>| numbers.map(|num| num));
+    let result          = scene.connect_many([
+        (program_2.into(), program_3.into(), StreamId::with_message_type::<usize>()),
+        (program_1.into(), StreamTarget::Filtered(usize_to_usize, program_3), StreamId::with_message_type::<String>()),
+    ]);
+
+    assert!(matches!(result, Err(ConnectionError::FilterTargetInputMustMatchStream)), "{:?}", result);
+
+    TestBuilder::new()
+        .expect_message(|TestResult(msg)| if msg == "program_2: Test" { Ok(()) } else { Err(format!("Expected 'program_2: Test', got '{}'", msg)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+pub fn connect_many_resumes_later_outputs_after_a_failure() {
+    // The second connection in the batch fails after the first one has been made, which should leave program_1 sending to program_2
+    let scene           = Arc::new(Scene::default());
+    let test_program    = SubProgramId::new();
+    let program_1       = SubProgramId::new();
+    let program_2       = SubProgramId::new();
+    let program_3       = SubProgramId::new();
+    let not_running     = SubProgramId::new();
+
+    scene.add_subprogram(program_2, move |input: InputStream<String>, context| {
+        async move {
+            let mut test_program = context.send(test_program).unwrap();
+
+            let mut input = input;
+            while let Some(input) = input.next().await {
+                test_program.send(TestResult(input)).await.unwrap();
+            }
+        }
+    }, 0);
+
+    scene.add_subprogram(program_3, |input: InputStream<usize>, _| {
+        async move {
+            let mut input = input;
+            while let Some(_) = input.next().await { }
+        }
+    }, 0);
+
+    // program_1 changes the connections once its output has been created, so its output is one of the ones that's paused
+    let batch_scene = Arc::clone(&scene);
+    scene.add_subprogram(program_1, move |_: InputStream<()>, context| {
+        async move {
+            let mut send_strings = context.send(()).unwrap();
+            send_strings.send("Before".to_string()).await.unwrap();
+
+            let result = batch_scene.connect_many([
+                (not_running.into(), program_2.into(), StreamId::with_message_type::<String>()),
+                (not_running.into(), program_3.into(), StreamId::with_message_type::<String>()),
+                (program_1.into(), program_2.into(), StreamId::with_message_type::<String>()),
+            ]);
+            assert!(matches!(result, Err(ConnectionError::WrongInputType(_, _))), "{:?}", result);
+
+            send_strings.send("After".to_string()).await.unwrap();
+        }
+    }, 0);
+
+    scene.connect_programs(program_1, program_2, StreamId::with_message_type::<String>()).unwrap();
+
+    TestBuilder::new()
+        .expect_message(|TestResult(msg)| if msg == "Before" { Ok(()) } else { Err(format!("Expected 'Before', got '{}'", msg)) })
+        .expect_message(|TestResult(msg)| if msg == "After" { Ok(()) } else { Err(format!("Expected 'After', got '{}'", msg)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+pub fn connect_many_keeps_filtered_messages_in_order() {
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct ReceivedNumbers(Vec<usize>);
+    impl SceneMessage for ReceivedNumbers { }
+
+    // The sender is moved from one filter to another while the receiver is still reading the messages sent through the first filter
+    let scene           = Arc::new(Scene::default());
+    let test_program    = SubProgramId::new();
+    let sender          = SubProgramId::new();
+    let receiver        = SubProgramId::new();
+    let reconnector     = SubProgramId::new();
+
+    let first_filter    = FilterHandle::for_filter(|numbers: InputStream<usize>| numbers.map(|num| num.to_string()));
+    let second_filter   = FilterHandle::for_filter(|numbers: InputStream<usize>| numbers.map(|num| num.to_string()));
+
+    scene.add_subprogram(receiver, move |input: InputStream<String>, context| {
+        async move {
+            let mut test_program    = context.send(test_program).unwrap();
+            let mut received        = vec![];
+
+            // Read slowly so that messages are waiting in the first filter when the connection changes
+            let mut input = input;
+            while received.len() < 20 {
+                let Some(num) = input.next().await else { break; };
+                Delay::new(Duration::from_millis(5)).await;
+
+                received.push(num.parse::<usize>().unwrap());
+            }
+
+            test_program.send(ReceivedNumbers(received)).await.unwrap();
+        }
+    }, 0);
+
+    scene.add_subprogram(sender, |_: InputStream<()>, context| {
+        async move {
+            let mut numbers = context.send::<usize>(()).unwrap();
+
+            for num in 0..20 {
+                numbers.send(num).await.unwrap();
+            }
+        }
+    }, 0);
+
+    let batch_scene = Arc::clone(&scene);
+    scene.add_subprogram(reconnector, move |_: InputStream<()>, _| {
+        async move {
+            Delay::new(Duration::from_millis(20)).await;
+
+            batch_scene.connect_many([
+                (sender.into(), StreamTarget::Filtered(second_filter, receiver), StreamId::with_message_type::<usize>()),
+            ]).unwrap();
+        }
+    }, 0);
+
+    scene.connect_programs(sender, StreamTarget::Filtered(first_filter, receiver), StreamId::with_message_type::<usize>()).unwrap();
+
+    TestBuilder::new()
+        .expect_message(|ReceivedNumbers(received)| if received == (0..20).collect::<Vec<_>>() { Ok(()) } else { Err(format!("Messages out of order: {:?}", received)) })
+        .run_in_scene_with_threads(&scene, test_program, 4);
+}
+
+#[test]
+pub fn connect_to_wrong_input_type() {
+    let scene           = Scene::default();
+    let program_1       = SubProgramId::new();
+
+    scene.add_subprogram(program_1, |input: InputStream<usize>, _| {
+        async move {
+            let mut input = input;
+            while let Some(_) = input.next().await { }
+        }
+    }, 0);
+
+    // program_1 only accepts usize messages, so can't be connected to a string stream (and the connection isn't stored)
+    let result = scene.connect_programs((), program_1, StreamId::with_message_type::<String>());
+    assert!(matches!(result, Err(ConnectionError::WrongInputType(_, _))), "{:?}", result);
+    assert!(scene.validate().is_ok());
+
+    // Connecting a stream of the right type is fine
+    let result = scene.connect_programs((), program_1, StreamId::with_message_type::<usize>());
+    assert!(result == Ok(ConnectionResult::Ready), "{:?}", result);
+}
+
+#[test]
+pub fn connect_to_input_type_with_filter_conversion() {
+    let scene           = Scene::default();
+    let program_1       = SubProgramId::new();
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct TestMessage;
+    impl SceneMessage for TestMessage { }
+
+    let test_string_filter = FilterHandle::for_filter(|messages| messages.map(|_: TestMessage| "Test".to_string()));
+
+    scene.add_subprogram(program_1, |input: InputStream<String>, _| {
+        async move {
+            let mut input = input;
+            while let Some(_) = input.next().await { }
+        }
+    }, 0);
+
+    // Once a source filter is set up, TestMessages can be sent to program_1
+    scene.connect_programs(StreamSource::Filtered(test_string_filter), (), StreamId::with_message_type::<TestMessage>()).unwrap();
+
+    let result = scene.connect_programs((), program_1, StreamId::with_message_type::<TestMessage>());
+    assert!(result == Ok(ConnectionResult::Ready), "{:?}", result);
+    assert!(scene.validate().is_ok());
+}
+
+#[test]
+pub fn validate_connection_made_before_program_started() {
+    let scene           = Scene::default();
+    let program_1       = SubProgramId::new();
+
+    // Connections to programs that aren't running can't be checked yet
+    scene.connect_programs((), program_1, StreamId::with_message_type::<String>()).unwrap();
+    assert!(scene.validate().is_ok());
+
+    scene.add_subprogram(program_1, |input: InputStream<usize>, _| {
+        async move {
+            let mut input = input;
+            while let Some(_) = input.next().await { }
+        }
+    }, 0);
+
+    // ... but once the program has started, the connection can be seen to be invalid
+    let failed = scene.validate().unwrap_err();
+
+    assert!(failed.len() == 1, "{:?}", failed);
+    assert!(failed[0].1 == StreamTarget::Program(program_1), "{:?}", failed);
+    assert!(failed[0].2 == StreamId::with_message_type::<String>(), "{:?}", failed);
+    assert!(matches!(failed[0].3, ConnectionError::WrongInputType(_, _)), "{:?}", failed);
+}
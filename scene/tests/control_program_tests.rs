@@ -387,6 +387,36 @@ fn query_control_program() {
         .run_in_scene_with_threads(&scene, test_program, 5);
 }
 
+#[test]
+fn query_does_not_include_stopped_programs() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let program_1       = SubProgramId::new();
+    let program_2       = SubProgramId::new();
+
+    #[derive(Serialize, Deserialize)]
+    struct Program2Message;
+    impl SceneMessage for Program2Message { }
+
+    // Program 1 stops immediately, and program 2 is started after it has finished (so it can re-use the space it was using in the scene)
+    scene.add_subprogram(program_1, move |_: InputStream<()>, _| async move { }, 0);
+
+    TestBuilder::new()
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| { Ok(()) })
+        .send_message(SceneControl::start_program(program_2, |mut input: InputStream<Program2Message>, _| async move { input.next().await; }, 0))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| { Ok(()) })
+        .run_query(ReadCommand::default(), Query::<SceneUpdate>::with_no_target(), *SCENE_CONTROL_PROGRAM, 
+            move |response| {
+                if response.iter().any(|update| matches!(update, SceneUpdate::Started(program_id, _) if *program_id == program_1)) { return Err(format!("Program 1 ({:?}) is in query response after stopping ({:?})", program_1, response)); }
+                if !response.iter().any(|update| update == &SceneUpdate::Started(program_2, StreamId::with_message_type::<Program2Message>())) { return Err(format!("Program 2 ({:?}) not in query response ({:?})", program_2, response)); }
+
+                Ok(()) 
+            })
+        .run_in_scene_with_threads(&scene, test_program, 5);
+}
+
 #[test]
 fn send_message_only_sends_one_connection_notification() {
     let scene           = Scene::default();
@@ -763,3 +793,82 @@ fn subscription_events_match_query_messages_times_100() {
         subscription_events_match_query_messages()
     }
 }
+
+#[test]
+fn ask_control_to_connect_all() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let sender          = SubProgramId::new();
+    let receiver        = SubProgramId::new();
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct Received(String);
+    impl SceneMessage for Received { }
+
+    scene.add_subprogram(receiver, move |input: InputStream<String>, context| async move {
+        let mut input = input;
+        while let Some(msg) = input.next().await {
+            context.send_message(Received(msg)).await.unwrap();
+        }
+    }, 0);
+
+    scene.add_subprogram(sender, |_: InputStream<()>, context| async move {
+        context.send_message("Hello".to_string()).await.unwrap();
+    }, 0);
+
+    TestBuilder::new()
+        .send_message(SceneControl::connect_all([(sender.into(), receiver.into(), StreamId::with_message_type::<String>())]))
+        .expect_message(|Received(msg)| if msg == "Hello" { Ok(()) } else { Err(format!("Unexpected message {:?}", msg)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn connect_all_sends_failed_connection() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let monitor         = SubProgramId::new();
+    let receiver        = SubProgramId::new();
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct MonitorResult(Result<(), String>);
+    impl SceneMessage for MonitorResult { }
+
+    scene.add_subprogram(receiver, |input: InputStream<String>, _| async move {
+        let mut input = input;
+        while let Some(_) = input.next().await { }
+    }, 0);
+
+    // The second connection is invalid, so neither connection should be made and the control program should report the failure
+    scene.add_subprogram(monitor, move |input: InputStream<SceneUpdate>, context| async move {
+        // Wait for the subscription to start before sending the request
+        let mut input = input;
+        context.send_message(subscribe::<SceneUpdate>(monitor)).await.unwrap();
+        input.next().await;
+
+        let usize_to_string = FilterHandle::for_filter(|numbers: InputStream<usize>| numbers.map(|num| num.to_string()));
+        context.send_message(SceneControl::connect_all([
+            (monitor.into(), receiver.into(), StreamId::with_message_type::<String>()),
+            (monitor.into(), StreamTarget::Filtered(usize_to_string, receiver), StreamId::with_message_type::<String>()),
+        ])).await.unwrap();
+
+        while let Some(update) = input.next().await {
+            match update {
+                SceneUpdate::FailedConnection(ConnectionError::FilterTargetInputMustMatchStream, _, _, _) => {
+                    context.send_message(MonitorResult(Ok(()))).await.unwrap();
+                    break;
+                }
+
+                SceneUpdate::Connected(source, target, _) if source == monitor && target == receiver => {
+                    context.send_message(MonitorResult(Err(format!("Unexpected connection {:?}", update)))).await.unwrap();
+                    break;
+                }
+
+                _ => { }
+            }
+        }
+    }, 0);
+
+    TestBuilder::new()
+        .expect_message(|MonitorResult(result)| result)
+        .run_in_scene(&scene, test_program);
+}
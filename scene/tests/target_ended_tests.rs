@@ -0,0 +1,148 @@
+//!
+//! When the program that an output stream is sending to finishes, the stream follows its `TargetEndedPolicy`: waiting
+//! for the program to restart, returning messages as errors, or reconnecting to a fallback target.
+//!
+
+use flo_scene::*;
+use flo_scene::programs::*;
+
+use futures::prelude::*;
+use serde::*;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct TestResult(String);
+
+impl SceneMessage for TestResult { }
+
+///
+/// A program that relays the first message it receives to the test program, then stops
+///
+fn one_shot_program(name: &'static str, test_program: SubProgramId) -> impl Send + FnOnce(InputStream<usize>, SceneContext) -> future::BoxFuture<'static, ()> {
+    move |input, context| async move {
+        let mut input = input;
+
+        if let Some(num) = input.next().await {
+            context.send(test_program).unwrap().send(TestResult(format!("{}: {}", name, num))).await.unwrap();
+        }
+    }.boxed()
+}
+
+///
+/// Creates a scene where 'sender' forwards the numbers it receives to 'target' using the specified policy
+///
+fn create_scene(policy: TargetEndedPolicy, test_program: SubProgramId, sender: SubProgramId, target: SubProgramId) -> Scene {
+    let scene = Scene::default();
+
+    scene.add_subprogram(sender, move |input: InputStream<usize>, context| async move {
+        let mut target_stream = context.send(target).unwrap();
+        let mut test_stream   = context.send(test_program).unwrap();
+        target_stream.set_target_ended_policy(policy);
+
+        let mut input = input;
+        while let Some(num) = input.next().await {
+            match target_stream.send(num).await {
+                Ok(())                                          => { }
+                Err(SceneSendError::TargetProgramEnded(num))    => { test_stream.send(TestResult(format!("ended: {}", num))).await.unwrap(); }
+                Err(err)                                        => { test_stream.send(TestResult(format!("error: {:?}", err))).await.unwrap(); }
+            }
+        }
+    }, 0);
+    scene.add_subprogram(target, one_shot_program("target", test_program), 0);
+
+    scene.connect_programs(test_program, sender, StreamId::with_message_type::<usize>()).unwrap();
+
+    scene
+}
+
+fn expect_result(expected: &'static str) -> impl 'static + Send + Fn(TestResult) -> Result<(), String> {
+    move |TestResult(msg)| if msg == expected { Ok(()) } else { Err(format!("Expected '{}', got '{}'", expected, msg)) }
+}
+
+#[test]
+fn wait_for_target_to_restart() {
+    let test_program    = SubProgramId::new();
+    let sender          = SubProgramId::new();
+    let target          = SubProgramId::new();
+    let scene           = create_scene(TargetEndedPolicy::WaitForRestart, test_program, sender, target);
+
+    TestBuilder::new()
+        .send_message(1usize)
+        .expect_message(expect_result("target: 1"))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(2usize)
+        .send_message(SceneControl::start_program(target, one_shot_program("restarted", test_program), 0))
+        .expect_message(expect_result("restarted: 2"))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn error_when_target_ended() {
+    let test_program    = SubProgramId::new();
+    let sender          = SubProgramId::new();
+    let target          = SubProgramId::new();
+    let scene           = create_scene(TargetEndedPolicy::Error, test_program, sender, target);
+
+    TestBuilder::new()
+        .send_message(1usize)
+        .expect_message(expect_result("target: 1"))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(2usize)
+        .expect_message(expect_result("ended: 2"))
+        .send_message(SceneControl::start_program(target, one_shot_program("restarted", test_program), 0))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(3usize)
+        .expect_message(expect_result("restarted: 3"))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn fallback_when_target_ended() {
+    let test_program    = SubProgramId::new();
+    let sender          = SubProgramId::new();
+    let target          = SubProgramId::new();
+    let fallback        = SubProgramId::new();
+    let scene           = create_scene(TargetEndedPolicy::Fallback(fallback.into()), test_program, sender, target);
+
+    scene.add_subprogram(fallback, one_shot_program("fallback", test_program), 0);
+
+    TestBuilder::new()
+        .send_message(1usize)
+        .expect_message(expect_result("target: 1"))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(2usize)
+        .expect_message(expect_result("fallback: 2"))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn fallback_is_replaced_when_target_restarts() {
+    let test_program    = SubProgramId::new();
+    let sender          = SubProgramId::new();
+    let target          = SubProgramId::new();
+    let scene           = create_scene(TargetEndedPolicy::Fallback(StreamTarget::None), test_program, sender, target);
+
+    // Messages are discarded while the target is stopped, then sent to the new target once it restarts
+    TestBuilder::new()
+        .send_message(1usize)
+        .expect_message(expect_result("target: 1"))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(2usize)
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(SceneControl::start_program(target, one_shot_program("restarted", test_program), 0))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(3usize)
+        .expect_message(expect_result("restarted: 3"))
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn error_is_default_policy() {
+    assert!(TargetEndedPolicy::default() == TargetEndedPolicy::Error);
+}
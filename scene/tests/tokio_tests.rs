@@ -0,0 +1,32 @@
+use flo_scene::*;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration};
+
+#[test]
+fn run_budgeted_futures_in_tokio() {
+    let (send_done, recv_done) = mpsc::channel();
+
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+
+        runtime.block_on(async move {
+            let scene = Scene::default();
+
+            // Tokio limits how many times a task can poll its futures before it has to yield: this program will only finish if the scene yields to tokio
+            // (block_on() for the multi-threaded runtime wakes a future that has run out of budget immediately instead of deferring the wake)
+            scene.add_subprogram(SubProgramId::new(), move |_: InputStream<()>, _context| async move {
+                for _ in 0..1000 {
+                    tokio::task::consume_budget().await;
+                }
+
+                send_done.send(()).unwrap();
+            }, 0);
+
+            scene.run_scene().await;
+        });
+    });
+
+    recv_done.recv_timeout(Duration::from_secs(10)).unwrap();
+}
@@ -0,0 +1,266 @@
+//!
+//! `SceneControl::ApplyTopology` changes the running programs and the connections in a scene to match a
+//! `SceneTopology`, starting and stopping programs as needed and rolling back if the topology can't be applied.
+//!
+
+use flo_scene::*;
+use flo_scene::programs::*;
+
+use futures::prelude::*;
+use serde::*;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct TopologyRequest(usize);
+
+impl SceneMessage for TopologyRequest { }
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct TopologyResponse(String, usize);
+
+impl SceneMessage for TopologyResponse { }
+
+///
+/// Creates a program that relays the requests it receives as responses with a particular name
+///
+fn relay_program(name: &'static str) -> impl Send + FnOnce(InputStream<TopologyRequest>, SceneContext) -> futures::future::BoxFuture<'static, ()> {
+    move |input, context| async move {
+        let mut input = input;
+
+        while let Some(TopologyRequest(num)) = input.next().await {
+            context.send_message(TopologyResponse(name.into(), num)).await.unwrap();
+        }
+    }.boxed()
+}
+
+#[test]
+fn apply_topology_starts_and_connects_programs() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let sender          = SubProgramId::new();
+    let receiver        = SubProgramId::new();
+
+    let topology = SceneTopology::new()
+        .with_program(receiver, relay_program("receiver"), 0)
+        .with_program(sender, |_: InputStream<()>, context| async move {
+            context.send_message(TopologyRequest(42)).await.unwrap();
+        }, 0)
+        .with_connection(sender, receiver, StreamId::with_message_type::<TopologyRequest>());
+
+    TestBuilder::new()
+        .send_message(SceneControl::ApplyTopology(topology))
+        .expect_message(|TopologyResponse(name, num)| if name == "receiver" && num == 42 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn apply_topology_rewires_connections() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let receiver_a      = SubProgramId::new();
+    let receiver_b      = SubProgramId::new();
+
+    let topology_a = SceneTopology::new()
+        .with_program(receiver_a, relay_program("a"), 0)
+        .with_connection(test_program, receiver_a, StreamId::with_message_type::<TopologyRequest>());
+    let topology_b = SceneTopology::new()
+        .with_program(receiver_b, relay_program("b"), 0)
+        .with_connection(test_program, receiver_b, StreamId::with_message_type::<TopologyRequest>());
+
+    TestBuilder::new()
+        .send_message(SceneControl::ApplyTopology(topology_a))
+        .send_message(TopologyRequest(1))
+        .expect_message(|TopologyResponse(name, num)| if name == "a" && num == 1 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .send_message(SceneControl::ApplyTopology(topology_b))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(TopologyRequest(2))
+        .expect_message(|TopologyResponse(name, num)| if name == "b" && num == 2 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn apply_topology_stops_removed_programs() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let receiver_a      = SubProgramId::new();
+
+    let topology_a = SceneTopology::new()
+        .with_program(receiver_a, |input: InputStream<TopologyRequest>, context| async move {
+            // Send a message once the input stream is closed
+            let mut input = input;
+            while let Some(_) = input.next().await { }
+
+            context.send_message(TopologyResponse("stopped".into(), 0)).await.unwrap();
+        }, 0);
+
+    TestBuilder::new()
+        .send_message(SceneControl::ApplyTopology(topology_a))
+        .send_message(SceneControl::ApplyTopology(SceneTopology::new()))
+        .expect_message(|TopologyResponse(name, _)| if name == "stopped" { Ok(()) } else { Err(format!("Unexpected response {:?}", name)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn failed_topology_rolls_back() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let receiver_a      = SubProgramId::new();
+    let receiver_b      = SubProgramId::new();
+
+    let topology_a = SceneTopology::new()
+        .with_program(receiver_a, relay_program("a"), 0)
+        .with_connection(test_program, receiver_a, StreamId::with_message_type::<TopologyRequest>());
+
+    // The filter reads 'usize' messages, so it can't be used for a stream of strings and this topology can't be applied
+    let usize_to_request    = FilterHandle::for_filter(|numbers: InputStream<usize>| numbers.map(TopologyRequest));
+    let topology_b          = SceneTopology::new()
+        .with_program(receiver_b, relay_program("b"), 0)
+        .with_connection(test_program, receiver_b, StreamId::with_message_type::<TopologyRequest>())
+        .with_connection(test_program, StreamTarget::Filtered(usize_to_request, receiver_b), StreamId::with_message_type::<String>());
+
+    TestBuilder::new()
+        .send_message(SceneControl::ApplyTopology(topology_a))
+        .send_message(TopologyRequest(1))
+        .expect_message(|TopologyResponse(name, num)| if name == "a" && num == 1 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .send_message(SceneControl::ApplyTopology(topology_b))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(TopologyRequest(2))
+        .expect_message(|TopologyResponse(name, num)| if name == "a" && num == 2 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn topology_with_wrong_input_type_rolls_back() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let receiver_a      = SubProgramId::new();
+    let receiver_b      = SubProgramId::new();
+
+    let topology_a = SceneTopology::new()
+        .with_program(receiver_a, relay_program("a"), 0)
+        .with_connection(test_program, receiver_a, StreamId::with_message_type::<TopologyRequest>());
+
+    // receiver_b can't accept 'usize' messages, so this topology can't be applied
+    let topology_b = SceneTopology::new()
+        .with_program(receiver_b, relay_program("b"), 0)
+        .with_connection(test_program, receiver_b, StreamId::with_message_type::<TopologyRequest>())
+        .with_connection(test_program, receiver_b, StreamId::with_message_type::<usize>());
+
+    TestBuilder::new()
+        .send_message(SceneControl::ApplyTopology(topology_a))
+        .send_message(TopologyRequest(1))
+        .expect_message(|TopologyResponse(name, num)| if name == "a" && num == 1 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .send_message(SceneControl::ApplyTopology(topology_b))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(TopologyRequest(2))
+        .expect_message(|TopologyResponse(name, num)| if name == "a" && num == 2 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn failed_topology_keeps_removed_program_connected() {
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct ForwardRequest(usize);
+
+    impl SceneMessage for ForwardRequest { }
+
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let forwarder       = SubProgramId::new();
+    let receiver_a      = SubProgramId::new();
+    let receiver_b      = SubProgramId::new();
+
+    // The forwarder's output is one of the outputs that's paused while the second topology is being applied
+    let forwarder_program = || |input: InputStream<ForwardRequest>, context: SceneContext| async move {
+        let mut requests    = context.send::<TopologyRequest>(()).unwrap();
+        let mut input       = input;
+
+        while let Some(ForwardRequest(num)) = input.next().await {
+            requests.send(TopologyRequest(num)).await.unwrap();
+        }
+    };
+
+    let topology_a = SceneTopology::new()
+        .with_program(forwarder, forwarder_program(), 0)
+        .with_program(receiver_a, relay_program("a"), 0)
+        .with_connection(test_program, forwarder, StreamId::with_message_type::<ForwardRequest>())
+        .with_connection(forwarder, receiver_a, StreamId::with_message_type::<TopologyRequest>());
+
+    // receiver_a is removed by this topology, but receiver_b can't accept 'usize' messages so it can't be applied
+    let topology_b = SceneTopology::new()
+        .with_program(forwarder, forwarder_program(), 0)
+        .with_program(receiver_b, relay_program("b"), 0)
+        .with_connection(test_program, forwarder, StreamId::with_message_type::<ForwardRequest>())
+        .with_connection(forwarder, receiver_b, StreamId::with_message_type::<TopologyRequest>())
+        .with_connection(test_program, receiver_b, StreamId::with_message_type::<usize>());
+
+    TestBuilder::new()
+        .send_message(SceneControl::ApplyTopology(topology_a))
+        .send_message(ForwardRequest(1))
+        .expect_message(|TopologyResponse(name, num)| if name == "a" && num == 1 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .send_message(SceneControl::ApplyTopology(topology_b))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(ForwardRequest(2))
+        .expect_message(|TopologyResponse(name, num)| if name == "a" && num == 2 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .send_message(ForwardRequest(3))
+        .expect_message(|TopologyResponse(name, num)| if name == "a" && num == 3 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn apply_topology_rewires_connections_changed_outside_topology() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let receiver_a      = SubProgramId::new();
+    let receiver_b      = SubProgramId::new();
+
+    // receiver_b is started outside of the topology
+    scene.add_subprogram(receiver_b, relay_program("b"), 0);
+
+    let topology = || SceneTopology::new()
+        .with_program(receiver_a, relay_program("a"), 0)
+        .with_connection(test_program, receiver_a, StreamId::with_message_type::<TopologyRequest>());
+
+    TestBuilder::new()
+        .send_message(SceneControl::ApplyTopology(topology()))
+        .send_message(TopologyRequest(1))
+        .expect_message(|TopologyResponse(name, num)| if name == "a" && num == 1 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .send_message(SceneControl::connect(test_program, receiver_b, StreamId::with_message_type::<TopologyRequest>()))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(TopologyRequest(2))
+        .expect_message(|TopologyResponse(name, num)| if name == "b" && num == 2 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .send_message(SceneControl::ApplyTopology(topology()))
+        .send_message(IdleRequest::WhenIdle(test_program))
+        .expect_message(|IdleNotification| Ok(()))
+        .send_message(TopologyRequest(3))
+        .expect_message(|TopologyResponse(name, num)| if name == "a" && num == 3 { Ok(()) } else { Err(format!("Unexpected response {:?} {:?}", name, num)) })
+        .run_in_scene(&scene, test_program);
+}
+
+#[test]
+fn apply_topology_stops_program_started_outside_topology() {
+    let scene           = Scene::default();
+    let test_program    = SubProgramId::new();
+    let receiver_a      = SubProgramId::new();
+
+    // receiver_a is already running when the topology is applied, so the topology uses it rather than starting a new program
+    scene.add_subprogram(receiver_a, |input: InputStream<TopologyRequest>, context| async move {
+        let mut input = input;
+        while let Some(_) = input.next().await { }
+
+        context.send_message(TopologyResponse("stopped".into(), 0)).await.unwrap();
+    }, 0);
+
+    let topology_a = SceneTopology::new()
+        .with_program(receiver_a, relay_program("not started"), 0);
+
+    TestBuilder::new()
+        .send_message(SceneControl::ApplyTopology(topology_a))
+        .send_message(SceneControl::ApplyTopology(SceneTopology::new()))
+        .expect_message(|TopologyResponse(name, _)| if name == "stopped" { Ok(()) } else { Err(format!("Unexpected response {:?}", name)) })
+        .run_in_scene(&scene, test_program);
+}